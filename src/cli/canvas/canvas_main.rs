@@ -1,5 +1,8 @@
 use clap::Parser;
-use rusty_bedrock_lib::nova::canvas;
+use rusty_bedrock_lib::cli::{ClientArgs, RetryArgs};
+use rusty_bedrock_lib::logging::{self, LogFormat};
+use rusty_bedrock_lib::nova::canvas::{self, json::ImageGenerationConfig, WeightedPrompt};
+use rusty_bedrock_lib::{progress, recording, redact, ClientOptions};
 
 /// Invokes Amazon's Canvas model on Bedrock
 ///
@@ -13,6 +16,7 @@ use rusty_bedrock_lib::nova::canvas;
 /// === Example usage ===
 ///
 ///     canvas --negative "birds, ducks" "Picture of a lake with wildlife, photorealistic"
+///     canvas --prompt "cat:1.2" --prompt "sunset:0.8" --negative "blur:1.5" --negative text
 ///
 /// For more information on Amazon Nova, read the user guide:
 ///     https://docs.aws.amazon.com/nova/latest/userguide/
@@ -28,8 +32,8 @@ struct CanvasCliArgs {
     /// AWS region and credentials are selected in the following sequence:
     ///
     /// 1/ Explicit Override:
-    /// When this --profile option is specified, the named profile will be read from
-    ///     ~/.aws/config and ~/.aws/credentials.
+    /// When this --profile option (or BEDROCK_PROFILE) is specified, the named profile
+    ///     will be read from ~/.aws/config and ~/.aws/credentials.
     ///
     /// 2/ Environment Variables, as described here:
     ///     https://docs.aws.amazon.com/cli/v1/userguide/cli-configure-envvars.html
@@ -41,51 +45,214 @@ struct CanvasCliArgs {
     ///   https://docs.aws.amazon.com/sdkref/latest/guide/file-format.html
     ///   https://docs.aws.amazon.com/sdk-for-rust/latest/dg/region.html
     ///   https://docs.aws.amazon.com/sdk-for-rust/latest/dg/credproviders.html
-    #[clap(long, verbatim_doc_comment)]
+    #[clap(long, env = "BEDROCK_PROFILE", verbatim_doc_comment)]
     aws_profile: Option<String>,
 
+    /// AWS region override, e.g. us-west-2
+    ///
+    /// Takes precedence over the region from --aws-profile or the environment.
+    #[clap(long, env = "BEDROCK_REGION")]
+    region: Option<String>,
+
+    #[clap(flatten)]
+    retry: RetryArgs,
+
+    #[clap(flatten)]
+    client: ClientArgs,
+
     /// prints request/response detail
     #[clap(short, long)]
     verbose: bool,
 
+    /// Log format on stderr: plain, or json (one object per line, for log collectors)
+    #[clap(long, arg_enum, default_value = "plain")]
+    log_format: LogFormat,
+
+    /// With --verbose, log request/response bodies in full
+    ///
+    /// By default base64 data is cut down to its length, and secret-like text (AWS
+    /// access key ids, api keys, private keys) is masked.
+    #[clap(long)]
+    no_redact: bool,
+
+    /// Save each response into this directory, keyed by a hash of the request
+    ///
+    /// Replay them later with --replay, e.g. for demos or tests without AWS access.
+    #[clap(long, conflicts_with = "replay")]
+    record: Option<String>,
+
+    /// Answer from responses saved by --record instead of calling Bedrock
+    #[clap(long)]
+    replay: Option<String>,
+
     /// Output directory
     #[clap(short, long, default_value = ".")]
     output: String,
 
-    /// Negative prompt
+    /// Negative prompt, optionally weighted as for --prompt (repeat for more)
     ///
     /// If provided, instructs Canvas what not to include.  Avoid negation words
     /// like "no" and "without"
+    #[clap(short, long, value_name = "NEGATIVE[:WEIGHT]")]
+    negative: Vec<WeightedPrompt>,
+
+    /// Request raw image bytes instead of base64 json
+    ///
+    /// Reduces the response payload size.  If the model doesn't return binary output,
+    /// falls back to the json/base64 response (with a warning), so images are still written.
+    #[clap(short, long)]
+    binary: bool,
+
+    /// Number of images to generate, 1 to 5
     #[clap(short, long)]
-    negative: Option<String>,
+    count: Option<u8>,
+
+    /// Also compose all returned images into a single grid image, <trace-id>-sheet.png
+    ///
+    /// Requires building with the `image` feature.
+    #[clap(long)]
+    contact_sheet: bool,
+
+    /// Number of images per row in the --contact-sheet (default: as square as possible)
+    #[clap(long, requires = "contact-sheet")]
+    columns: Option<u32>,
+
+    /// Embed the prompt, negative prompt, model id, and trace id in each image written
+    ///
+    /// Added as PNG text chunks, which image viewers and `exiftool` show.  Requires
+    /// building with the `image` feature.
+    #[clap(long)]
+    embed_metadata: bool,
 
     /// User prompt.
     ///
     /// Canvas isn't conversational.  Try to structure the prompt to be more like an image
     /// caption.  Avoid negation words ("no", "without"), as that will have the opposite effect.
     /// Instead, provide a negative prompt for exclusions.
-    prompt: String,
+    #[clap(required_unless_present = "prompts")]
+    prompt: Option<String>,
+
+    /// A weighted part of the prompt, e.g. "cat:1.2" (repeat for more)
+    ///
+    /// Weights run from 0.1 to 2, and default to 1.  The parts are sent as one caption,
+    /// heaviest first, after the positional prompt, with weights as "(cat:1.2)".
+    #[clap(long = "prompt", value_name = "PROMPT[:WEIGHT]")]
+    prompts: Vec<WeightedPrompt>,
 }
 
 #[tokio::main]
 async fn main() {
-    let cli: CanvasCliArgs = CanvasCliArgs::parse();
+    let cli: CanvasCliArgs = rusty_bedrock_lib::cli::parse_or_complete();
+
+    logging::init(cli.verbose, cli.log_format);
+    redact::set_enabled(!cli.no_redact);
 
-    let verbosity = if cli.verbose { 3 } else { 2 };
-    stderrlog::new().verbosity(verbosity).init().unwrap();
+    recording::set_mode(recording::Mode::from_args(
+        cli.record.clone(),
+        cli.replay.clone(),
+    ));
 
     // https://docs.rs/aws-sdk-bedrockruntime/latest/aws_sdk_bedrockruntime/
-    let client = rusty_bedrock_lib::new_runtime_client(cli.aws_profile).await;
+    let options = ClientOptions {
+        aws_profile: cli.aws_profile,
+        region: cli.region,
+        retry: cli.retry.options(),
+        ..cli.client.options()
+    };
+    let client = rusty_bedrock_lib::new_runtime_client(&options).await;
 
-    let (trace_id, images) = canvas::text_to_image(&client, cli.prompt, cli.negative).await;
+    let config = cli.count.map(|count| ImageGenerationConfig {
+        number_of_images: Some(count),
+    });
+    let prompt = cli
+        .prompt
+        .into_iter()
+        .chain([canvas::compose(&cli.prompts)])
+        .filter(|prompt| !prompt.is_empty())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let negative = Some(canvas::compose(&cli.negative)).filter(|negative| !negative.is_empty());
+    let provenance = cli
+        .embed_metadata
+        .then(|| (prompt.clone(), negative.clone()));
+    let generate = async {
+        if cli.binary {
+            canvas::text_to_image_binary(&client, prompt, negative, config).await
+        } else {
+            canvas::text_to_image(&client, prompt, negative, config)
+                .await
+                .map(|(trace_id, images)| {
+                    (
+                        trace_id,
+                        images.into_iter().map(|image| image.decode()).collect(),
+                    )
+                })
+        }
+    };
+    // the spinner would interleave with --verbose logs
+    let result = progress::spin(!cli.verbose, "generating", generate).await;
+    let (trace_id, images) = match result {
+        Ok(result) => result,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
 
     let outdir = cli.output.trim_end_matches('/').to_string();
+    let sheet = cli
+        .contact_sheet
+        .then(|| contact_sheet(&images, cli.columns));
+    let embed = |image: Vec<u8>| match &provenance {
+        Some((prompt, negative)) => embed_metadata(image, prompt, negative.as_deref(), &trace_id),
+        None => image,
+    };
     for (idx, image) in images.into_iter().enumerate() {
         if idx == 0 {
             println!("Writing:")
         }
         let path = format!("{}/{}-{}.png", outdir, trace_id, idx);
-        rusty_bedrock_lib::file::write_base64(path.as_str(), image);
+        rusty_bedrock_lib::file::write_bytes(path.as_str(), embed(image));
+        println!("{}", path);
+    }
+    if let Some(sheet) = sheet {
+        let path = format!("{}/{}-sheet.png", outdir, trace_id);
+        rusty_bedrock_lib::file::write_bytes(path.as_str(), embed(sheet));
         println!("{}", path);
     }
 }
+
+#[cfg(feature = "image")]
+fn contact_sheet(images: &[Vec<u8>], columns: Option<u32>) -> Vec<u8> {
+    canvas::contact_sheet::contact_sheet(images, columns)
+        .unwrap_or_else(|err| panic!("couldn't compose contact sheet: {}", err))
+}
+
+#[cfg(not(feature = "image"))]
+fn contact_sheet(_images: &[Vec<u8>], _columns: Option<u32>) -> Vec<u8> {
+    eprintln!("canvas was built without the image feature, rebuild with --features image");
+    std::process::exit(1);
+}
+
+#[cfg(feature = "image")]
+fn embed_metadata(
+    image: Vec<u8>,
+    prompt: &str,
+    negative: Option<&str>,
+    trace_id: &rusty_bedrock_lib::TraceId,
+) -> Vec<u8> {
+    let fields = canvas::metadata::provenance(prompt, negative, trace_id.as_ref());
+    canvas::metadata::embed(&image, &fields)
+        .unwrap_or_else(|err| panic!("couldn't embed metadata: {}", err.0))
+}
+
+#[cfg(not(feature = "image"))]
+fn embed_metadata(
+    _image: Vec<u8>,
+    _prompt: &str,
+    _negative: Option<&str>,
+    _trace_id: &rusty_bedrock_lib::TraceId,
+) -> Vec<u8> {
+    eprintln!("canvas was built without the image feature, rebuild with --features image");
+    std::process::exit(1);
+}
@@ -7,14 +7,61 @@
 //! https://docs.rs/aws-sdk-bedrockruntime/latest/aws_sdk_bedrockruntime/operation/converse/builders/struct.ConverseFluentBuilder.html
 
 use aws_sdk_bedrockruntime::types::{
-    ContentBlock, ConversationRole, ConverseOutput, Message, SystemContentBlock,
+    ContentBlock, ConversationRole, ConverseOutput, DocumentBlock, GuardrailConfiguration,
+    GuardrailStreamConfiguration, GuardrailTrace, GuardrailTraceAssessment, InferenceConfiguration,
+    Message, PerformanceConfiguration, ReasoningContentBlock, StopReason, SystemContentBlock,
+    TokenUsage, ToolConfiguration,
 };
 use aws_sdk_bedrockruntime::Client;
-use clap::Parser;
+use aws_smithy_types::Document;
+use clap::{CommandFactory, FromArgMatches, Parser};
+use dump::{dump_request, dump_response, DumpRequestArgs, DumpResponseArgs};
+use format::{format_clear, format_json, FormatClearArgs, FormatJsonArgs};
+use history::{
+    branches, cite_last, discard, export_openai, keep, regen, restore, summarize, Branch,
+    BranchesArgs, CiteLastArgs, DiscardArgs, ExportOpenAiArgs, KeepArgs, RegenArgs, RestoreArgs,
+    SummarizeArgs,
+};
 use log::{debug, warn};
-use rusty_bedrock_lib::converse::modalities::{AttachmentPath, InvalidPath};
+use paste::{paste, PasteArgs};
+use pins::{pin, unpin, PinArgs, Pinned, UnpinArgs};
+use prompts::{prompt_save, prompt_use, prompts, PromptSaveArgs, PromptUseArgs, PromptsArgs};
+use replay::{replay, ReplayArgs};
+use rusty_bedrock_lib::amazon_nova::text::json;
+use rusty_bedrock_lib::cli::{ClientArgs, RetryArgs};
+use rusty_bedrock_lib::converse::modalities::{
+    self, AttachmentLimitError, AttachmentPath, ChunkError, InvalidPath, UnsupportedModality,
+    DEFAULT_MAX_ATTACHMENTS, MAX_DOCUMENT_BYTES,
+};
+use rusty_bedrock_lib::converse::{
+    citations, converse_json, nova_json, openai, sse, stream, tool_use,
+};
+use rusty_bedrock_lib::file::{self, FileReference, NotText};
+use rusty_bedrock_lib::logging::{self, LogFormat};
+use rusty_bedrock_lib::tokens::Keep;
+use rusty_bedrock_lib::{
+    chat, guardrail, inference, markdown, pager, redact, request_file, retry, tags, tokens,
+    ClientOptions, Geo, Latency,
+};
 use shellfish::rustyline::DefaultEditor as DefaultEditorRusty;
-use shellfish::{clap_command, handler::DefaultAsyncHandler, Shell};
+use shellfish::{clap_command, Shell};
+use slash::SlashHandler;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::OnceCell;
+use trim::{trim_to, RequestUsage, TrimToArgs};
+
+mod dump;
+mod format;
+mod history;
+mod paste;
+mod pins;
+mod prompts;
+mod replay;
+mod slash;
+mod tools;
+mod trim;
 
 /// Hold a multi-turn interactive conversation with a model
 ///
@@ -30,8 +77,8 @@ struct CliArgs {
     /// AWS region and credentials are selected in the following sequence:
     ///
     /// 1/ Explicit Override:
-    /// When this --profile option is specified, the named profile will be read from
-    ///     ~/.aws/config and ~/.aws/credentials.
+    /// When this --profile option (or BEDROCK_PROFILE) is specified, the named profile
+    ///     will be read from ~/.aws/config and ~/.aws/credentials.
     ///
     /// 2/ Environment Variables, as described here:
     ///     https://docs.aws.amazon.com/cli/v1/userguide/cli-configure-envvars.html
@@ -43,13 +90,36 @@ struct CliArgs {
     ///   https://docs.aws.amazon.com/sdkref/latest/guide/file-format.html
     ///   https://docs.aws.amazon.com/sdk-for-rust/latest/dg/region.html
     ///   https://docs.aws.amazon.com/sdk-for-rust/latest/dg/credproviders.html
-    #[clap(long)]
+    #[clap(long, env = "BEDROCK_PROFILE")]
     aws_profile: Option<String>,
 
+    /// AWS region override, e.g. us-west-2
+    ///
+    /// Takes precedence over the region from --aws-profile or the environment.
+    #[clap(long, env = "BEDROCK_REGION")]
+    region: Option<String>,
+
+    #[clap(flatten)]
+    retry: RetryArgs,
+
+    #[clap(flatten)]
+    client: ClientArgs,
+
     /// Whether output should be verbose
     #[clap(short, long)]
     verbose: bool,
 
+    /// Log format on stderr: plain, or json (one object per line, for log collectors)
+    #[clap(long, arg_enum, default_value = "plain")]
+    log_format: LogFormat,
+
+    /// With --verbose, log messages in full
+    ///
+    /// By default attachment bytes are cut down to their length, and secret-like text
+    /// (AWS access key ids, api keys, private keys) is masked.
+    #[clap(long)]
+    no_redact: bool,
+
     /// Model or inference profile id to use
     ///
     /// Not all models support Converse.  Some models such as those in the Amazon
@@ -64,6 +134,12 @@ struct CliArgs {
     ///   model-id: anthropic.claude-3-5-sonnet-20241022-v2:0
     ///   inference-profile-id: us.anthropic.claude-3-5-sonnet-20241022-v2:0
     ///
+    /// Full ARNs are passed through unmodified, including application inference profiles
+    /// (e.g. for cost tracking):
+    ///   arn:aws:bedrock:us-east-1:123456789012:application-inference-profile/a1b2c3d4e5f6
+    /// and imported (Custom Model Import) models, whose request schema is up to the model.
+    /// An ARN must be in the --region used.
+    ///
     /// See:
     ///   https://docs.aws.amazon.com/bedrock/latest/userguide/models-supported.html
     ///   https://docs.aws.amazon.com/bedrock/latest/userguide/conversation-inference-supported-models-features.html
@@ -71,46 +147,525 @@ struct CliArgs {
     #[clap(
         short,
         long,
+        env = "BEDROCK_MODEL",
         default_value = "us.anthropic.claude-3-5-sonnet-20241022-v2:0",
         verbatim_doc_comment
     )]
     model: String,
 
+    /// Inference profile geography: us, eu, or apac
+    ///
+    /// Geo-prefixed model ids (like the us.amazon.nova-lite-v1:0 default) are switched to
+    /// this geography's profile, e.g. eu.amazon.nova-lite-v1:0.  Defaults to the
+    /// geography of the resolved region.  Plain model ids and ARNs aren't changed.
+    #[clap(long)]
+    geo: Option<Geo>,
+
     /// System prompt for the entire conversation
+    ///
+    /// May be repeated to layer instructions (e.g. a persona, then format rules), each
+    /// sent as its own system prompt block.
     #[clap(short, long)]
-    system: Option<String>,
+    system: Vec<String>,
+
+    /// Latency setting: optimized or standard
+    ///
+    /// Optimized latency is only available for some models (e.g. Claude 3.5 Haiku,
+    /// Nova Pro), and is ignored with a warning for others.
+    ///
+    /// See:
+    ///   https://docs.aws.amazon.com/bedrock/latest/userguide/latency-optimized-inference.html
+    #[clap(long, default_value = "standard", verbatim_doc_comment)]
+    latency: Latency,
+
+    /// Seed the conversation from a saved InvokeModel request body
+    ///
+    /// Reads Nova InvokeModel request json (system and messages, as logged by
+    /// `nova --verbose`) and continues that conversation interactively.  An explicit
+    /// --system takes precedence over the file's system prompt.
+    #[clap(long)]
+    load_invoke: Option<String>,
+
+    /// Seed the conversation from an OpenAI chat format json file
+    ///
+    /// Reads a `messages` array (or a chat completions request body with one), e.g. as
+    /// written by `export-openai`.  System messages become the system prompt, unless
+    /// --system is given.  Tool calls and tool results aren't supported.
+    #[clap(long, conflicts_with = "load-invoke")]
+    load_openai: Option<String>,
+
+    /// Read the model, system prompt, inference config, and first turn from a json file
+    ///
+    /// e.g. {"model": "us.amazon.nova-pro-v1:0", "prompt": "List the typos.",
+    ///       "attachments": ["draft.md"], "inference_config": {"temperature": 0.2}}
+    /// The prompt and attachments are sent as the first `say`.  Args given on the
+    /// command line (--model, --system, --max-tokens) take precedence.
+    #[clap(long, verbatim_doc_comment)]
+    request_file: Option<String>,
+
+    /// Read the inference config from a json file, e.g. a shared creative.json
+    ///
+    /// e.g. {"max_tokens": 2000, "temperature": 0.9, "top_p": 0.95}
+    /// Fields: max_tokens, temperature, top_p, top_k, and stop_sequences.  They take
+    /// precedence over --request-file's inference_config, and --max-tokens and --stop
+    /// over them.
+    #[clap(long, verbatim_doc_comment)]
+    inference_config: Option<String>,
+
+    /// Render the model's markdown output (headings, lists, highlighted code blocks)
+    ///
+    /// Falls back to raw text when stdout isn't a terminal or NO_COLOR is set.  Streamed
+    /// output is printed as it arrives, so isn't rendered.
+    #[clap(long)]
+    pretty: bool,
+
+    /// Page responses taller than the terminal through $PAGER (less -R when unset)
+    ///
+    /// Only when stdout is a terminal.  Streamed output is printed as it arrives, so isn't paged.
+    #[clap(long)]
+    pager: bool,
+
+    /// Hide the content of this XML-like tag in the printed answer, e.g. thinking for
+    /// <thinking>...</thinking> (repeat for more tags)
+    ///
+    /// For prompts that have the model reason before answering.  The tags are kept in the
+    /// conversation, only the output shown is stripped.
+    #[clap(long, value_name = "TAG")]
+    strip_tags: Vec<String>,
+
+    /// Print the model's reasoning (extended thinking) ahead of its answer
+    ///
+    /// Reasoning models return their thinking as separate content, which is otherwise
+    /// hidden.  Either way it's kept in the history, as Bedrock requires.
+    #[clap(long)]
+    show_thinking: bool,
+
+    /// Ask for citations from attached documents, and print what each answer cites
+    ///
+    /// Models that support citations (e.g. Claude) then point to the document and
+    /// character, page, or chunk range each part of their answer came from.
+    #[clap(long)]
+    show_citations: bool,
+
+    /// Stream the response, printing text as it's generated
+    #[clap(long)]
+    stream: bool,
+
+    /// Print streamed answers on stdout as Server-Sent Events, for a web service to forward
+    ///
+    /// Each text delta is a `data: {"type":"text","text":...}` event, and each response
+    /// ends with a stop event (its stopReason and usage) or an error event, then
+    /// `data: [DONE]`.  With prompts piped in, e.g.
+    ///   echo 'say "Hello"' | converse --sse
+    /// stdout has only events, and blank lines, which EventSource ignores: status lines
+    /// (stop reasons, guardrail traces, ...) go to stderr.  Implies --stream.
+    #[clap(long, verbatim_doc_comment)]
+    sse: bool,
+
+    /// Treat bare input as a prompt, and lines starting with / as commands
+    ///
+    /// e.g. `tell me a joke` instead of `say "tell me a joke"`, and `/summarize`,
+    /// `/help`, or `/quit` for commands.  Attachments still need `/say --attach`.
+    #[clap(long)]
+    slash: bool,
+
+    /// Also write streamed text to this file or named pipe (FIFO) as it arrives
+    ///
+    /// Text is appended, so a companion process can consume tokens live, e.g.
+    ///   mkfifo /tmp/tokens && cat /tmp/tokens &
+    ///   converse --stream --output-pipe /tmp/tokens
+    ///
+    /// Implies --stream.
+    #[clap(long, verbatim_doc_comment)]
+    output_pipe: Option<String>,
+
+    /// Offer the model the built-in read-only filesystem tools (read_file, list_directory)
+    ///
+    /// Each tool call is shown and must be confirmed before it runs, unless --auto-tools
+    /// is also passed.
+    #[clap(short, long)]
+    tools: bool,
+
+    /// Run tool calls without asking for confirmation, until the model gives a final answer
+    ///
+    /// Implies --tools.
+    #[clap(long)]
+    auto_tools: bool,
+
+    /// Split text documents (txt, md, csv) over Bedrock's 4.5MB document limit
+    ///
+    /// Oversized documents are split on line boundaries into several attachments
+    /// named <name>-part-<n>.  Binary documents (pdf, docx, ...) must be split by hand.
+    #[clap(long)]
+    auto_chunk: bool,
+
+    /// Maximum number of attachments per `say`, checked before anything is sent
+    ///
+    /// The attachments across the whole history are also checked against Bedrock's 20MB
+    /// request limit, since every turn resends the full conversation.
+    #[clap(long, default_value_t = DEFAULT_MAX_ATTACHMENTS)]
+    max_attachments: usize,
+
+    /// Abort the turn when an attachment is a type (image, video) the model doesn't take as input
+    ///
+    /// Otherwise it's a warning.  Input types come from ListFoundationModels, so models
+    /// it doesn't list (e.g. ARNs) aren't checked.
+    #[clap(long)]
+    strict_modalities: bool,
+
+    /// Apply this guardrail (id or ARN) to every turn
+    ///
+    /// A turn it blocks shows the guardrail's message instead of the model's answer.
+    #[clap(long)]
+    guardrail_id: Option<String>,
+
+    /// Version of --guardrail-id: a number, or DRAFT for the working draft
+    #[clap(long, default_value = "DRAFT", requires = "guardrail-id")]
+    guardrail_version: String,
+
+    /// Print what the guardrail assessed each turn (topics, content filters, PII, ...)
+    #[clap(long, requires = "guardrail-id")]
+    guardrail_trace: bool,
+
+    /// Cut each prompt down to about this many tokens before sending, with a warning
+    ///
+    /// For pasted text too long for the model.  Tokens are estimated at 4 characters
+    /// each, and --keep picks the part of each prompt kept.
+    #[clap(long)]
+    max_input_tokens: Option<usize>,
+
+    /// With --max-input-tokens, keep the head (beginning) or tail (end) of each prompt
+    #[clap(long, arg_enum, default_value = "head", requires = "max-input-tokens")]
+    keep: Keep,
+
+    /// Maximum number of tool-use round trips within a single turn
+    #[clap(long, default_value = "10")]
+    max_tool_iterations: usize,
+
+    /// Maximum number of tokens in each response (default: the model's own limit)
+    #[clap(long)]
+    max_tokens: Option<i32>,
+
+    /// Stop generating when the model outputs this text (may be repeated)
+    ///
+    /// The count is checked against the model's limit (e.g. 4 for Nova) before sending.
+    #[clap(long)]
+    stop: Vec<String>,
+
+    /// Model specific response field to return, e.g. /stop_sequence for Claude
+    ///
+    /// Sent as Converse's additionalModelResponseFieldPaths (JSON pointer paths), and
+    /// whatever the model returns for them is printed after each response.  May be
+    /// repeated.
+    ///
+    /// See:
+    ///     https://docs.aws.amazon.com/bedrock/latest/APIReference/API_runtime_Converse.html
+    #[clap(long, verbatim_doc_comment)]
+    response_field: Vec<String>,
+
+    /// Prompt used by the `summarize` command to compress older turns
+    #[clap(long, default_value = history::DEFAULT_SUMMARY_PROMPT)]
+    summary_prompt: String,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli: CliArgs = CliArgs::parse();
+    let (mut cli, matches): (CliArgs, _) = rusty_bedrock_lib::cli::parse_or_complete_with_matches();
+
+    logging::init(cli.verbose, cli.log_format);
+    redact::set_enabled(!cli.no_redact);
+    pager::set_enabled(cli.pager);
+    tags::set_stripped(cli.strip_tags.clone());
+
+    let client_options = ClientOptions {
+        aws_profile: cli.aws_profile.clone(),
+        region: cli.region.clone(),
+        retry: cli.retry.options(),
+        ..cli.client.options()
+    };
+    let request = cli.request_file.as_deref().map(|path| {
+        request_file::load(path).unwrap_or_else(|err| {
+            eprintln!("{}", err.0);
+            std::process::exit(1);
+        })
+    });
+    let mut file_config = json::InferenceConfig::default();
+    let mut first_turn = None;
+    if let Some(request) = request {
+        let model_given = rusty_bedrock_lib::cli::from_command_line(&matches, "model");
+        cli.model = request_file::merge(model_given, cli.model, request.model);
+        if cli.system.is_empty() {
+            cli.system.extend(request.system);
+        }
+        if request.prefill.is_some() {
+            warn!("converse doesn't support a prefill, ignoring the request file's");
+        }
+        file_config = request.inference_config.to_nova();
+        first_turn = Some(SayArgs {
+            parts: request
+                .attachments
+                .iter()
+                .map(|path| ("attach", path.clone()))
+                .collect(),
+            attach: request.attachments,
+            text: vec![],
+            include_file: vec![],
+            prompt: request.prompt,
+        });
+    }
 
-    let verbosity = if cli.verbose { 3 } else { 2 };
-    stderrlog::new().verbosity(verbosity).init().unwrap();
+    let client = rusty_bedrock_lib::new_runtime_client(&client_options).await;
+    let region = client.config().region().map(|region| region.to_string());
+    cli.model = rusty_bedrock_lib::profile_for_region(
+        &client_options,
+        region.as_deref(),
+        &cli.model,
+        cli.geo,
+    )
+    .await
+    .unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    });
+    logging::set_field("model", cli.model.clone());
 
-    let client = rusty_bedrock_lib::new_runtime_client(cli.aws_profile).await;
+    let mut system_prompt = Some(cli.system)
+        .filter(|prompts| !prompts.is_empty())
+        .map(|prompts| prompts.into_iter().map(SystemContentBlock::Text).collect());
 
-    let system_prompt = cli.system.map(|sys| vec![SystemContentBlock::Text(sys)]);
+    let mut messages = vec![];
+    let loaded = if let Some(path) = &cli.load_invoke {
+        let request = nova_json::load_request(path).unwrap_or_else(|err| panic!("{}", err.0));
+        let messages = request
+            .messages
+            .iter()
+            .map(|msg| nova_json::to_sdk_message(msg).unwrap_or_else(|err| panic!("{}", err.0)))
+            .collect();
+        Some((path, nova_json::to_sdk_system(&request.system), messages))
+    } else if let Some(path) = &cli.load_openai {
+        let (system, messages) = openai::load_openai(path).unwrap_or_else(|err| {
+            eprintln!("Couldn't load {}: {}", path, err.0);
+            std::process::exit(1);
+        });
+        Some((path, system, messages))
+    } else {
+        None
+    };
+    if let Some((path, system, loaded)) = loaded {
+        if system_prompt.is_none() && !system.is_empty() {
+            system_prompt = Some(system);
+        }
+        messages = loaded;
 
-    let state = ConversationState {
+        // the next `say` adds a user message, so the history has to end on the assistant
+        if messages.last().map(|msg| msg.role()) == Some(&ConversationRole::User) {
+            warn!(
+                "Dropping the trailing user message from {}, it has no response.",
+                path
+            );
+            messages.pop();
+        }
+        println!("-- loaded {} messages from {} --", messages.len(), path);
+    }
+
+    let tool_config = if cli.tools || cli.auto_tools {
+        Some(tools::tool_config())
+    } else {
+        None
+    };
+
+    let trace = match cli.guardrail_trace {
+        true => GuardrailTrace::Enabled,
+        false => GuardrailTrace::Disabled,
+    };
+    let guardrail_config = cli.guardrail_id.as_ref().map(|id| {
+        GuardrailConfiguration::builder()
+            .guardrail_identifier(id)
+            .guardrail_version(&cli.guardrail_version)
+            .trace(trace)
+            .build()
+    });
+
+    let performance_config = cli
+        .latency
+        .for_model(&cli.model)
+        .map(|latency| PerformanceConfiguration::builder().latency(latency).build());
+
+    if let Some(path) = &cli.inference_config {
+        let config = request_file::load_inference_config(path).unwrap_or_else(|err| {
+            eprintln!("{}", err.0);
+            std::process::exit(1);
+        });
+        config.apply_to(&mut file_config);
+    }
+    if !cli.stop.is_empty() {
+        file_config.stop_sequences = cli.stop.clone();
+    }
+    let max_tokens = cli.max_tokens.or(file_config.max_new_tokens.map(i32::from));
+    if let Err(inference::OutOfRange(msg)) =
+        inference::check_with_max_tokens(&cli.model, &file_config, max_tokens)
+    {
+        eprintln!("{}", msg);
+        std::process::exit(1);
+    }
+    let inference_config = match (chat::converse_inference_config(file_config), cli.max_tokens) {
+        (config, None) => config,
+        // --max-tokens isn't limited to the u16 Nova's config holds
+        (config, Some(max_tokens)) => {
+            let mut config = config.unwrap_or_else(|| InferenceConfiguration::builder().build());
+            config.max_tokens = Some(max_tokens);
+            Some(config)
+        }
+    };
+
+    let mut state = ConversationState {
         model: cli.model.clone(),
         client,
+        client_options,
         verbose: cli.verbose,
         system_prompt,
-        messages: vec![],
+        messages,
+        tool_config,
+        performance_config,
+        guardrail_config,
+        guardrail_trace: cli.guardrail_trace,
+        inference_config,
+        auto_tools: cli.auto_tools,
+        auto_chunk: cli.auto_chunk,
+        stream: cli.stream || cli.output_pipe.is_some() || cli.sse,
+        sse: cli.sse,
+        pretty: cli.pretty,
+        show_thinking: cli.show_thinking,
+        show_citations: cli.show_citations,
+        output_pipe: cli.output_pipe,
+        max_attachments: cli.max_attachments,
+        strict_modalities: cli.strict_modalities,
+        max_input_tokens: cli.max_input_tokens,
+        keep: cli.keep,
+        model_inputs: OnceCell::new(),
+        max_tool_iterations: cli.max_tool_iterations,
+        response_fields: cli.response_field,
+        summary_prompt: cli.summary_prompt,
+        summary: None,
+        branches: BTreeMap::new(),
+        staged: vec![],
+        alternative: None,
+        interrupted: false,
+        last_request: None,
+        last_response: None,
+        pinned: vec![],
+        json_format: None,
+        usage: vec![],
     };
 
     println!();
     // Define a shell
+    if let Some(first_turn) = first_turn {
+        say(&mut state, first_turn).await?;
+    }
     let mut shell = Shell::new_with_async_handler(
         state,
         format!("[{}]\n> ", cli.model),
-        DefaultAsyncHandler::default(),
+        SlashHandler { slash: cli.slash },
         DefaultEditorRusty::new()?,
     );
+    shell.commands.insert(
+        "say",
+        shellfish::Command::new_async(
+            SayArgs::command()
+                .get_about()
+                .unwrap_or_default()
+                .to_string(),
+            shellfish::async_fn!(ConversationState, say_command),
+        ),
+    );
+    shell
+        .commands
+        .insert("ask", clap_command!(ConversationState, AskArgs, async ask));
+    shell.commands.insert(
+        "summarize",
+        clap_command!(ConversationState, SummarizeArgs, async summarize),
+    );
+    shell.commands.insert(
+        "trim-to",
+        clap_command!(ConversationState, TrimToArgs, trim_to),
+    );
+    shell.commands.insert(
+        "branches",
+        clap_command!(ConversationState, BranchesArgs, branches),
+    );
+    shell.commands.insert(
+        "restore",
+        clap_command!(ConversationState, RestoreArgs, restore),
+    );
+    shell.commands.insert(
+        "cite-last",
+        clap_command!(ConversationState, CiteLastArgs, cite_last),
+    );
+    shell
+        .commands
+        .insert("paste", clap_command!(ConversationState, PasteArgs, paste));
+    shell.commands.insert(
+        "regen",
+        clap_command!(ConversationState, RegenArgs, async regen),
+    );
+    shell
+        .commands
+        .insert("keep", clap_command!(ConversationState, KeepArgs, keep));
+    shell.commands.insert(
+        "discard",
+        clap_command!(ConversationState, DiscardArgs, discard),
+    );
+    shell.commands.insert(
+        "prompt-save",
+        clap_command!(ConversationState, PromptSaveArgs, prompt_save),
+    );
+    shell.commands.insert(
+        "prompt-use",
+        clap_command!(ConversationState, PromptUseArgs, prompt_use),
+    );
+    shell.commands.insert(
+        "prompts",
+        clap_command!(ConversationState, PromptsArgs, prompts),
+    );
+    shell
+        .commands
+        .insert("pin", clap_command!(ConversationState, PinArgs, pin));
     shell
         .commands
-        .insert("say", clap_command!(ConversationState, SayArgs, async say));
+        .insert("unpin", clap_command!(ConversationState, UnpinArgs, unpin));
+    shell.commands.insert(
+        "format-json",
+        clap_command!(ConversationState, FormatJsonArgs, format_json),
+    );
+    shell.commands.insert(
+        "format-clear",
+        clap_command!(ConversationState, FormatClearArgs, format_clear),
+    );
+    shell.commands.insert(
+        "resume",
+        clap_command!(ConversationState, ResumeArgs, async resume),
+    );
+    shell.commands.insert(
+        "dump-request",
+        clap_command!(ConversationState, DumpRequestArgs, dump_request),
+    );
+    shell.commands.insert(
+        "dump-response",
+        clap_command!(ConversationState, DumpResponseArgs, dump_response),
+    );
+    shell.commands.insert(
+        "replay",
+        clap_command!(ConversationState, ReplayArgs, async replay),
+    );
+    shell.commands.insert(
+        "verbose",
+        clap_command!(ConversationState, VerboseArgs, verbose),
+    );
+    shell.commands.insert(
+        "export-openai",
+        clap_command!(ConversationState, ExportOpenAiArgs, export_openai),
+    );
     shell.run_async().await?;
 
     Ok(())
@@ -120,9 +675,101 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 pub struct ConversationState {
     pub model: String,
     pub client: Client, // bedrock client
+    pub client_options: ClientOptions,
     pub verbose: bool,
     pub system_prompt: Option<Vec<SystemContentBlock>>,
     pub messages: Vec<Message>,
+    pub tool_config: Option<ToolConfiguration>,
+    pub performance_config: Option<PerformanceConfiguration>,
+    /// --guardrail-id, and whether to print its trace
+    pub guardrail_config: Option<GuardrailConfiguration>,
+    pub guardrail_trace: bool,
+    /// --max-tokens
+    pub inference_config: Option<InferenceConfiguration>,
+    pub auto_tools: bool,
+    pub auto_chunk: bool,
+    pub stream: bool,
+    pub pretty: bool,
+    pub show_thinking: bool,
+    pub show_citations: bool,
+    pub output_pipe: Option<String>,
+    /// --sse: streamed answers are printed as Server-Sent Events
+    pub sse: bool,
+    pub max_attachments: usize,
+    pub strict_modalities: bool,
+    /// --max-input-tokens, and the part of a prompt it keeps
+    pub max_input_tokens: Option<usize>,
+    pub keep: Keep,
+    /// The model's input modalities, listed on the first turn with attachments
+    pub model_inputs: OnceCell<Option<Vec<String>>>,
+    pub max_tool_iterations: usize,
+    /// --response-field paths
+    pub response_fields: Vec<String>,
+    pub summary_prompt: String,
+    /// Summary of turns dropped by `summarize`, sent along with the system prompt
+    pub summary: Option<String>,
+    /// Histories set aside by `summarize`, restorable by name
+    pub branches: BTreeMap<String, Branch>,
+    /// Content added to the next `say`, e.g. by `cite-last`
+    pub staged: Vec<ContentBlock>,
+    /// Alternative to the last answer from `regen`, until it's kept or discarded
+    pub alternative: Option<Message>,
+    /// Whether the last answer is the partial text of a stream that failed, which
+    /// `resume` can continue
+    pub interrupted: bool,
+    /// The last request sent, and its response, as Converse json for `dump-request`
+    /// and `dump-response`
+    pub last_request: Option<serde_json::Value>,
+    pub last_response: Option<serde_json::Value>,
+    /// Files sent with every request, see `pin`
+    pub pinned: Vec<Pinned>,
+    /// The tool config for `format-json`, sent instead of `tool_config` while set
+    pub json_format: Option<ToolConfiguration>,
+    /// Token counts Bedrock reported for each request, for `trim-to`
+    pub usage: Vec<RequestUsage>,
+}
+impl ConversationState {
+    /// The system prompt, plus the summary of any condensed turns
+    pub fn system(&self) -> Option<Vec<SystemContentBlock>> {
+        let mut system = self.system_prompt.clone().unwrap_or_default();
+        if let Some(summary) = &self.summary {
+            system.push(SystemContentBlock::Text(format!(
+                "Summary of the earlier conversation:\n{}",
+                summary
+            )));
+        }
+        Some(system).filter(|system| !system.is_empty())
+    }
+
+    pub fn response_field_paths(&self) -> Option<Vec<String>> {
+        Some(self.response_fields.clone()).filter(|paths| !paths.is_empty())
+    }
+
+    /// The tool config as sent: the `format-json` tool while set, otherwise --tools'
+    pub fn request_tool_config(&self) -> Option<ToolConfiguration> {
+        self.json_format
+            .clone()
+            .or_else(|| self.tool_config.clone())
+    }
+
+    /// The messages as sent: the history, with any pinned files
+    pub fn request_messages(&self) -> Vec<Message> {
+        pins::with_pinned(&self.pinned, &self.messages)
+    }
+
+    /// Keeps the request about to be sent for `dump-request`, clearing the last response,
+    /// and warns if it's close to Bedrock's request size limit.
+    fn record_request(&mut self) {
+        let request = converse_json::request(
+            &self.system().unwrap_or_default(),
+            &self.request_messages(),
+            self.request_tool_config().as_ref(),
+            self.inference_config.as_ref(),
+        );
+        modalities::warn_near_request_limit(converse_json::body_size(&request));
+        self.last_request = Some(request);
+        self.last_response = None;
+    }
 }
 
 /// Send a message to the model
@@ -139,12 +786,53 @@ struct SayArgs {
     /// - Documents: csv, doc, docx, html, md, pdf, txt, xls, xlsx (local files only)
     ///
     /// Note: S3 locations (s3://) are only supported for video files.
+    /// Note: Append @format to override the extension, e.g. --attach ~/screenshot@png
+    /// Note: Append =name to name a document for the model (and citations), e.g. --attach "report.pdf=Q3 Financials"
+    /// Note: Append #bytes=<start>-<end> (text documents) or #pages=<first>-<last> (pdf, with the pdf feature) to attach part of a file, e.g. --attach big.txt#bytes=0-100000
     /// Note: Not all models support all modalities.
     #[clap(short, long)]
     attach: Vec<String>,
 
-    /// The prompt for your next turn in the conversation
-    prompt: String,
+    /// Additional text content, placed in order among the --attach files.
+    ///
+    /// e.g. say "compare these" --text "before:" --attach a.png --text "after:" --attach b.png
+    #[clap(long)]
+    text: Vec<String>,
+
+    /// A text file whose contents go into the prompt as text, placed in order like --text
+    ///
+    /// Unlike --attach, which sends a typed document, the text is spliced in as is, e.g.
+    /// say "why doesn't this compile?" --include-file src/main.rs
+    /// The file must be utf-8.
+    #[clap(long)]
+    include_file: Vec<String>,
+
+    /// The prompt for your next turn in the conversation, always the first content
+    #[clap(required_unless_present_any = &["text", "include-file"])]
+    prompt: Option<String>,
+
+    /// --text, --include-file, and --attach values in the order given (filled in by `say_command`)
+    #[clap(skip)]
+    parts: Vec<(&'static str, String)>,
+}
+
+/// Parses SayArgs like `clap_command!`, also keeping the order of --text and --attach,
+/// which the derived struct loses.
+async fn say_command(
+    state: &mut ConversationState,
+    args: Vec<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let matches = match SayArgs::command().try_get_matches_from(&args) {
+        Ok(matches) => matches,
+        Err(err) if err.kind() == clap::ErrorKind::DisplayHelp => {
+            err.print()?;
+            return Ok(());
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let mut parsed = SayArgs::from_arg_matches(&matches)?;
+    parsed.parts = rusty_bedrock_lib::cli::in_order(&matches, &["text", "include-file", "attach"]);
+    say(state, parsed).await
 }
 
 async fn say(
@@ -158,50 +846,158 @@ async fn say(
     let mut msg_builder = Message::builder().role(ConversationRole::User);
 
     // ---- prompt ----
-    msg_builder = msg_builder.content(ContentBlock::Text(args.prompt));
+    if let Some(prompt) = args.prompt {
+        let prompt = match state.max_input_tokens {
+            Some(max_tokens) => tokens::fit(prompt, max_tokens, state.keep),
+            None => prompt,
+        };
+        msg_builder = msg_builder.content(ContentBlock::Text(prompt));
+    }
 
     // --- add attachments ---
-    for path in args.attach {
+    if let Err(AttachmentLimitError(msg)) =
+        modalities::check_attachments(&args.attach, state.max_attachments)
+    {
+        notice(
+            state.sse,
+            format!("Too many or too large attachments, aborting turn. {}", msg),
+        );
+        return Ok(());
+    }
+    if !args.attach.is_empty() {
+        let inputs = state
+            .model_inputs
+            .get_or_init(|| rusty_bedrock_lib::model_inputs(&state.client_options, &state.model))
+            .await;
+        if let Some(Err(UnsupportedModality(msg))) = inputs
+            .as_ref()
+            .map(|inputs| modalities::check_modalities(&state.model, inputs, &args.attach))
+        {
+            if state.strict_modalities {
+                notice(
+                    state.sse,
+                    format!("Unsupported attachment, aborting turn. {}", msg),
+                );
+                return Ok(());
+            }
+            warn!("{}", msg);
+        }
+    }
+    for (id, value) in args.parts {
+        if id == "text" {
+            msg_builder = msg_builder.content(ContentBlock::Text(value));
+            continue;
+        }
+        if id == "include-file" {
+            match file::read_text(&value) {
+                Ok(text) => msg_builder = msg_builder.content(ContentBlock::Text(text)),
+                Err(NotText(msg)) => {
+                    notice(
+                        state.sse,
+                        format!("Couldn't include file, aborting turn. {}", msg),
+                    );
+                    return Ok(());
+                }
+            }
+            continue;
+        }
+        let path = value;
+        if state.auto_chunk {
+            match chunk_if_oversized(&path.clone().into()) {
+                Ok(Some(blocks)) => {
+                    notice(
+                        state.sse,
+                        format!("-- split {} into {} documents --", path, blocks.len()),
+                    );
+                    for block in blocks {
+                        msg_builder = msg_builder.content(ContentBlock::Document(block));
+                    }
+                    continue;
+                }
+                Ok(None) => {}
+                Err(ChunkError(msg)) => {
+                    notice(
+                        state.sse,
+                        format!("Couldn't split attachment, aborting turn. {}", msg),
+                    );
+                    return Ok(());
+                }
+            }
+        }
+
         let attachment_path = AttachmentPath(path);
         let content_block = match attachment_path.try_into() {
             Ok(content_block) => content_block,
             Err(InvalidPath(path)) => {
-                println!("Invalid attachment path, aborting turn. path: {}", path);
+                notice(
+                    state.sse,
+                    format!("Invalid attachment path, aborting turn. path: {}", path),
+                );
                 return Ok(());
             }
         };
         msg_builder = msg_builder.content(content_block);
     }
 
+    // --- add staged content (kept until the turn is sent) ---
+    for block in &state.staged {
+        msg_builder = msg_builder.content(block.clone());
+    }
+
     // ------- construct message --------
-    let new_msg = msg_builder.build().unwrap();
+    let mut new_msg = msg_builder.build().unwrap();
+    if state.show_citations {
+        new_msg.content.iter_mut().for_each(citations::enable);
+    }
+
+    // every turn resends the whole history, so earlier (and pinned) attachments count too
+    let total = state
+        .messages
+        .iter()
+        .chain([&new_msg])
+        .flat_map(|msg| msg.content())
+        .chain(state.pinned.iter().map(|pin| &pin.block))
+        .map(modalities::inline_bytes)
+        .sum();
+    if let Err(AttachmentLimitError(msg)) = modalities::check_request_bytes(total) {
+        notice(
+            state.sse,
+            format!("Conversation is too large to send, aborting turn. {}", msg),
+        );
+        return Ok(());
+    }
     if state.verbose {
         debug!("model: {}", state.model);
-        debug!("{:?}", new_msg);
+        debug!("{}", redact::debug(&new_msg));
     }
     state.messages.push(new_msg);
+    state.staged.clear();
+    state.alternative = None;
+    state.interrupted = false;
 
     // ===========================
-    // Send request to bedrock with entire conversation history
+    // Send requests to bedrock with entire conversation history, looping for as
+    // long as the model asks for tools to be run
     // ===========================
-    let conversation = state
-        .client
-        .converse()
-        .model_id(state.model.clone())
-        .set_system(state.system_prompt.clone())
-        .set_messages(Some(state.messages.clone()))
-        .send()
-        .await
-        .unwrap();
-
-    debug!("{:?}", conversation);
+    let turn_start = state.messages.len() - 1;
+    let mut iterations = 0;
+    loop {
+        let sse = state.sse;
+        let (msg, stop_reason) = match until_ctrl_c(sse, send(state)).await {
+            Some(response) => response,
+            None => {
+                // drop the unanswered turn, so the history still ends on the last response
+                state.messages.truncate(turn_start);
+                return Ok(());
+            }
+        };
 
-    // ===========================
-    // Process response, add assistant's response onto the message history state
-    // ===========================
-    if let Some(ConverseOutput::Message(msg)) = conversation.output() {
+        // ===========================
+        // Process response, add assistant's response onto the message history state
+        // ===========================
         assert_eq!(&ConversationRole::Assistant, msg.role());
-        debug!("{:?}", msg);
+        debug!("{}", redact::debug(&msg));
+        let mut tool_uses = vec![];
         for content in msg.content() {
             match content {
                 ContentBlock::Document(_document_block) => todo!(),
@@ -209,19 +1005,655 @@ async fn say(
                     warn!("-- guardrail --")
                 }
                 ContentBlock::Image(_image_block) => warn!("-- image --"),
-                ContentBlock::Text(s) => println!("{}", s),
+                // streamed text was already printed as it arrived
+                ContentBlock::Text(s) if !state.stream => markdown::print(s, state.pretty),
+                ContentBlock::Text(_) => {}
+                ContentBlock::CitationsContent(cited) => {
+                    if !state.stream {
+                        markdown::print(&citations::generated_text(cited), state.pretty);
+                    }
+                    if state.show_citations {
+                        let documents = citations::document_names(&state.request_messages());
+                        for (i, citation) in cited.citations().iter().enumerate() {
+                            println!("[{}] {}", i + 1, citations::describe(citation, &documents));
+                        }
+                    }
+                }
+                // streamed thinking was also printed as it arrived
+                ContentBlock::ReasoningContent(reasoning)
+                    if state.show_thinking && !state.stream =>
+                {
+                    match reasoning {
+                        ReasoningContentBlock::ReasoningText(reasoning) => {
+                            println!("-- thinking --\n{}\n-- end thinking --", reasoning.text())
+                        }
+                        _ => println!("-- thinking (redacted) --"),
+                    }
+                }
+                ContentBlock::ReasoningContent(_) => {}
                 ContentBlock::ToolResult(_tool_result_block) => warn!("-- tool result --"),
-                ContentBlock::ToolUse(_tool_use_block) => warn!("-- tool use --"),
+                ContentBlock::ToolUse(tool_use_block) => tool_uses.push(tool_use_block.clone()),
                 ContentBlock::Video(_video_block) => warn!("-- video --"),
                 _ => panic!("Unknown response ContentBlock: {:?}", content),
             }
         }
 
+        // A format-json answer is the format tool's input, kept in the history as text
+        if let Some(answer) = state
+            .json_format
+            .as_ref()
+            .and_then(|_| format::answer(&msg))
+        {
+            notice(state.sse, serde_json::to_string_pretty(&answer)?);
+            state.messages.push(format::as_text(msg, &answer));
+            break;
+        }
+
         // Add the response to the tail of the conversation for the next turn
-        state.messages.push(msg.clone())
-    } else {
-        panic!("No output??");
+        state.messages.push(msg);
+
+        match stop_reason {
+            StopReason::ToolUse if !tool_uses.is_empty() => {}
+            stop_reason => {
+                report_stop_reason(state.sse, &stop_reason);
+                break;
+            }
+        }
+
+        iterations += 1;
+        if iterations > state.max_tool_iterations {
+            // The history would end on an unanswered tool use, so drop the whole turn
+            warn!(
+                "Model still requesting tools after {} iterations, discarding turn.",
+                state.max_tool_iterations
+            );
+            state.messages.truncate(turn_start);
+            break;
+        }
+
+        // ===========================
+        // Run the requested tools and send their results back as the next user message
+        // ===========================
+        let mut results = Message::builder().role(ConversationRole::User);
+        for tool_use in tool_uses {
+            notice(
+                state.sse,
+                format!("-- tool use: {} {:?} --", tool_use.name(), tool_use.input()),
+            );
+            let output = if state.auto_tools || confirm("Run this tool?")? {
+                tools::run(tool_use.name(), tool_use.input())
+            } else {
+                Err("The user declined to run this tool.".to_string())
+            };
+            debug!("tool output: {}", redact::debug(&output));
+            let id = tool_use.tool_use_id();
+            results = results.content(match output {
+                Ok(output) => tool_use::tool_result(id, output),
+                Err(err) => tool_use::tool_result_error(id, err),
+            });
+        }
+        state.messages.push(results.build().unwrap());
+    }
+
+    Ok(())
+}
+
+/// Continue an answer cut off by an interrupted stream, from where it stopped
+///
+/// The partial answer is sent as the start of the model's reply (a prefill), which the
+/// model carries on from.  Not all models accept a prefill.
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+struct ResumeArgs {}
+
+async fn resume(
+    state: &mut ConversationState,
+    _args: ResumeArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !state.interrupted {
+        println!("Nothing to resume: the last answer wasn't cut off by an interrupted stream.");
+        return Ok(());
+    }
+    let partial = state.messages.pop().unwrap();
+    state.messages.push(stream::prefill(&partial));
+    state.interrupted = false;
+
+    let response = send(state).await;
+    let prefill = state.messages.pop().unwrap();
+    let Some((continuation, stop_reason)) = response else {
+        // keep the partial answer, to resume again
+        state.messages.push(partial);
+        state.interrupted = true;
+        return Ok(());
     };
+    if !state.stream {
+        for text in continuation
+            .content()
+            .iter()
+            .filter_map(|content| content.as_text().ok())
+        {
+            markdown::print(text, state.pretty);
+        }
+    }
+    state.messages.push(stream::join(prefill, &continuation));
+    match stop_reason {
+        StopReason::ToolUse => println!("-- the continuation asked for a tool, dropping it --"),
+        stop_reason => report_stop_reason(state.sse, &stop_reason),
+    }
+    Ok(())
+}
+
+/// Ask a side question outside the conversation
+///
+/// Only the system prompt and this prompt are sent: no history, summary, pinned files,
+/// or tools.  The answer is printed, and nothing is added to the history.
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+struct AskArgs {
+    prompt: String,
+}
+
+async fn ask(
+    state: &mut ConversationState,
+    args: AskArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let prompt = match state.max_input_tokens {
+        Some(max_tokens) => tokens::fit(args.prompt, max_tokens, state.keep),
+        None => args.prompt,
+    };
+    let question = Message::builder()
+        .role(ConversationRole::User)
+        .content(ContentBlock::Text(prompt))
+        .build()
+        .unwrap();
+
+    // sent with send_request rather than send, which keeps the request and its response
+    // (for dump-request, trim-to, and resume) as the conversation's
+    let request = Request {
+        system: state
+            .system_prompt
+            .clone()
+            .filter(|system| !system.is_empty()),
+        messages: vec![question],
+        tool_config: None,
+    };
+    let Some(Sent {
+        message: answer,
+        stop_reason,
+        ..
+    }) = until_ctrl_c(state.sse, send_request(state, request)).await
+    else {
+        return Ok(());
+    };
+    if !state.stream {
+        for text in answer
+            .content()
+            .iter()
+            .filter_map(|content| content.as_text().ok())
+        {
+            markdown::print(text, state.pretty);
+        }
+    }
+    report_stop_reason(state.sse, &stop_reason);
+    notice(state.sse, "-- not added to the conversation --");
+    Ok(())
+}
+
+/// Turn verbose output (debug logs of requests and responses) on or off for the rest of
+/// the session (or, with neither, show whether it's on)
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+struct VerboseArgs {
+    #[clap(arg_enum)]
+    setting: Option<Switch>,
+}
 
+#[derive(clap::ArgEnum, Debug, Clone, Copy)]
+enum Switch {
+    On,
+    Off,
+}
+
+fn verbose(
+    state: &mut ConversationState,
+    args: VerboseArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(setting) = args.setting {
+        state.verbose = matches!(setting, Switch::On);
+        logging::set_verbose(state.verbose);
+    }
+    notice(
+        state.sse,
+        format!(
+            "-- verbose is {} --",
+            if state.verbose { "on" } else { "off" }
+        ),
+    );
     Ok(())
 }
+
+/// Waits for a request, or for Ctrl-C, which cancels it (by dropping it) rather than
+/// exiting.
+async fn until_ctrl_c<T>(
+    sse: bool,
+    request: impl std::future::Future<Output = Option<T>>,
+) -> Option<T> {
+    tokio::select! {
+        response = request => response,
+        _ = tokio::signal::ctrl_c() => {
+            notice(sse, "\n-- interrupted, request cancelled --");
+            None
+        }
+    }
+}
+
+/// What a request sends besides the session's model and settings
+struct Request {
+    system: Option<Vec<SystemContentBlock>>,
+    messages: Vec<Message>,
+    tool_config: Option<ToolConfiguration>,
+}
+
+/// A response message, with what `send` keeps of it
+struct Sent {
+    message: Message,
+    stop_reason: StopReason,
+    usage: Option<TokenUsage>,
+    /// Whether the message is the partial text of a stream that failed
+    interrupted: bool,
+}
+
+/// Sends the conversation to the model, returning the response message.
+///
+/// Keeps the request and response for `dump-request` and `dump-response`, and the token
+/// counts for `trim-to`.  A partial answer from a failed stream is marked for `resume`.
+async fn send(state: &mut ConversationState) -> Option<(Message, StopReason)> {
+    state.record_request();
+    let request = Request {
+        system: state.system(),
+        messages: state.request_messages(),
+        tool_config: state.request_tool_config(),
+    };
+    let sent = send_request(state, request).await?;
+    if sent.interrupted {
+        notice(
+            state.sse,
+            "-- kept the partial answer: resume to have the model continue it --",
+        );
+        state.interrupted = true;
+        return Some((sent.message, sent.stop_reason));
+    }
+    trim::record(state, sent.usage.as_ref());
+    state.last_response = Some(converse_json::response(
+        &sent.message,
+        &sent.stop_reason,
+        sent.usage.as_ref(),
+    ));
+    Some((sent.message, sent.stop_reason))
+}
+
+/// Sends a request, streamed with --stream, leaving the session's state as it is.
+async fn send_request(state: &ConversationState, request: Request) -> Option<Sent> {
+    if state.stream {
+        send_streaming(state, request).await
+    } else {
+        send_unstreamed(state, request).await
+    }
+}
+
+/// Sends a request, returning the response message.
+///
+/// Retries model timeouts with `retry::Policy::MODEL_TIMEOUT`.  If it recurs, prints a
+/// hint and returns None.
+async fn send_unstreamed(state: &ConversationState, request: Request) -> Option<Sent> {
+    let converse = state
+        .client
+        .converse()
+        .model_id(state.model.clone())
+        .set_system(request.system)
+        .set_messages(Some(request.messages))
+        .set_tool_config(request.tool_config)
+        .set_performance_config(state.performance_config.clone())
+        .set_guardrail_config(state.guardrail_config.clone())
+        .set_inference_config(state.inference_config.clone())
+        .set_additional_model_response_field_paths(state.response_field_paths());
+
+    let result = retry::with_backoff(
+        || converse.clone().send(),
+        &retry::Policy::MODEL_TIMEOUT,
+        rusty_bedrock_lib::is_model_timeout,
+    )
+    .await;
+    if matches!(&result, Err(err) if rusty_bedrock_lib::is_model_timeout(err)) {
+        notice(state.sse, rusty_bedrock_lib::MODEL_TIMEOUT_HINT);
+        return None;
+    }
+    if matches!(&result, Err(err) if rusty_bedrock_lib::is_model_not_found(err)) {
+        report_model_not_found(state).await;
+        return None;
+    }
+    if matches!(&result, Err(err) if rusty_bedrock_lib::is_credentials_error(err)) {
+        notice(state.sse, rusty_bedrock_lib::credentials_hint());
+        return None;
+    }
+    let conversation = result.unwrap();
+
+    debug!("{}", redact::debug(&conversation));
+    print_response_fields(
+        state.sse,
+        conversation.additional_model_response_fields.as_ref(),
+    );
+    print_guardrail_trace(
+        state,
+        conversation.trace().and_then(|trace| trace.guardrail()),
+    );
+    print_routed_model(
+        state.sse,
+        conversation
+            .trace()
+            .and_then(|trace| trace.prompt_router())
+            .and_then(|router| router.invoked_model_id()),
+    );
+
+    match conversation.output {
+        Some(ConverseOutput::Message(message)) => Some(Sent {
+            message,
+            stop_reason: conversation.stop_reason,
+            usage: conversation.usage,
+            interrupted: false,
+        }),
+        _ => panic!("No output??"),
+    }
+}
+
+/// Like `send_unstreamed`, but streams the response, printing text (and copying it to
+/// the --output-pipe) as it arrives.
+///
+/// If the stream fails part way, the text received so far is returned as the answer,
+/// marked as interrupted.
+async fn send_streaming(state: &ConversationState, request: Request) -> Option<Sent> {
+    let converse = state
+        .client
+        .converse_stream()
+        .model_id(state.model.clone())
+        .set_system(request.system)
+        .set_messages(Some(request.messages))
+        .set_tool_config(request.tool_config)
+        .set_performance_config(state.performance_config.clone())
+        .set_guardrail_config(state.guardrail_config.as_ref().map(|config| {
+            GuardrailStreamConfiguration::builder()
+                .guardrail_identifier(config.guardrail_identifier())
+                .guardrail_version(config.guardrail_version())
+                .trace(config.trace().clone())
+                .build()
+        }))
+        .set_inference_config(state.inference_config.clone())
+        .set_additional_model_response_field_paths(state.response_field_paths());
+
+    let result = retry::with_backoff(
+        || converse.clone().send(),
+        &retry::Policy::MODEL_TIMEOUT,
+        rusty_bedrock_lib::is_model_timeout,
+    )
+    .await;
+    if state.sse {
+        if let Err(err) = &result {
+            print!(
+                "{}{}",
+                sse::error(&aws_sdk_bedrockruntime::error::DisplayErrorContext(err).to_string()),
+                sse::DONE
+            );
+            let _ = std::io::stdout().flush();
+            return None;
+        }
+    }
+    if matches!(&result, Err(err) if rusty_bedrock_lib::is_model_timeout(err)) {
+        notice(state.sse, rusty_bedrock_lib::MODEL_TIMEOUT_HINT);
+        return None;
+    }
+    if matches!(&result, Err(err) if rusty_bedrock_lib::is_model_not_found(err)) {
+        report_model_not_found(state).await;
+        return None;
+    }
+    if matches!(&result, Err(err) if rusty_bedrock_lib::is_credentials_error(err)) {
+        notice(state.sse, rusty_bedrock_lib::credentials_hint());
+        return None;
+    }
+    let output = result.unwrap();
+
+    let mut pipe = state.output_pipe.as_deref().map(file::open_append);
+    let mut stdout = std::io::stdout();
+    let thinking = AtomicBool::new(false); // whether the last thing printed was thinking
+    let mut stripper = tags::Stripper::new();
+    let sse = state.sse;
+    let response = stream::collect(
+        output,
+        |text| {
+            let text = stripper.push(text);
+            if sse {
+                if !text.is_empty() {
+                    print!("{}", sse::text(&text));
+                }
+            } else {
+                if thinking.swap(false, Ordering::Relaxed) {
+                    println!("\n-- end thinking --");
+                }
+                print!("{}", text);
+            }
+            let _ = stdout.flush();
+            if let Some(pipe) = pipe.as_mut() {
+                let _ = pipe.write_all(text.as_bytes()).and_then(|_| pipe.flush());
+            }
+        },
+        |text| {
+            if !state.show_thinking {
+                return;
+            }
+            if sse {
+                print!("{}", sse::thinking(text));
+            } else {
+                if !thinking.swap(true, Ordering::Relaxed) {
+                    println!("-- thinking --");
+                }
+                print!("{}", text);
+            }
+            let _ = std::io::stdout().flush();
+        },
+    )
+    .await;
+    let rest = stripper.finish();
+    match sse {
+        true if !rest.is_empty() => print!("{}", sse::text(&rest)),
+        true => {}
+        false => println!("{}", rest),
+    }
+    if let Some(pipe) = pipe.as_mut() {
+        let _ = pipe.write_all(rest.as_bytes()).and_then(|_| pipe.flush());
+    }
+    let response = match response {
+        Ok(response) => response,
+        Err(interrupted) if sse => {
+            let msg =
+                aws_sdk_bedrockruntime::error::DisplayErrorContext(&interrupted.error).to_string();
+            print!("{}{}", sse::error(&msg), sse::DONE);
+            let _ = std::io::stdout().flush();
+            return keep_partial(state, interrupted);
+        }
+        Err(interrupted) => return keep_partial(state, interrupted),
+    };
+
+    debug!("{}", redact::debug(&response));
+    print_response_fields(state.sse, response.additional_fields.as_ref());
+    print_routed_model(state.sse, response.invoked_model_id.as_deref());
+    print_guardrail_trace(state, response.guardrail_trace.as_ref());
+
+    let stop_reason = response.stop_reason.unwrap_or(StopReason::EndTurn);
+    if state.sse {
+        print!(
+            "{}{}",
+            sse::stop(&stop_reason, response.usage.as_ref()),
+            sse::DONE
+        );
+        let _ = std::io::stdout().flush();
+    }
+    Some(Sent {
+        message: response.message,
+        stop_reason,
+        usage: response.usage,
+        interrupted: false,
+    })
+}
+
+/// Prints a status line: on stdout, or on stderr with --sse, so stdout has only events.
+fn notice(sse: bool, line: impl std::fmt::Display) {
+    match sse {
+        true => eprintln!("{}", line),
+        false => println!("{}", line),
+    }
+}
+
+/// Reports a stream that failed part way, keeping the text received before it did.
+fn keep_partial(state: &ConversationState, interrupted: stream::Interrupted) -> Option<Sent> {
+    notice(
+        state.sse,
+        format!(
+            "-- the stream was interrupted (trace id {}): {} --",
+            interrupted.request_id.as_deref().unwrap_or("UNKNOWN"),
+            aws_sdk_bedrockruntime::error::DisplayErrorContext(&interrupted.error)
+        ),
+    );
+    if interrupted.partial.content().is_empty() {
+        return None;
+    }
+    // the partial answer ends the turn like a complete one
+    Some(Sent {
+        message: interrupted.partial,
+        stop_reason: StopReason::EndTurn,
+        usage: None,
+        interrupted: true,
+    })
+}
+
+/// Explains a response that stopped short of a normal end of turn.
+fn report_stop_reason(sse: bool, stop_reason: &StopReason) {
+    let explanation = match stop_reason {
+        StopReason::EndTurn | StopReason::StopSequence => return,
+        StopReason::MaxTokens => "-- the response was cut off at the token limit: say \"continue\", or restart with a higher --max-tokens --".to_string(),
+        StopReason::GuardrailIntervened => "-- a guardrail blocked this turn; the response above is the guardrail's message, not the model's --".to_string(),
+        StopReason::ContentFiltered => {
+            "-- the response was blocked by Bedrock's content filters --".to_string()
+        }
+        StopReason::ModelContextWindowExceeded => {
+            "-- the conversation is too long for the model's context window, try summarize --"
+                .to_string()
+        }
+        StopReason::ToolUse => {
+            "-- the model stopped for a tool use, but didn't say which tool --".to_string()
+        }
+        other => format!("-- the model stopped: {} --", other.as_str()),
+    };
+    notice(sse, explanation);
+}
+
+/// Prints which model a prompt router (given as --model) picked for the response.
+fn print_routed_model(sse: bool, invoked_model_id: Option<&str>) {
+    if let Some(model_id) = invoked_model_id {
+        notice(sse, format!("-- routed to {} --", model_id));
+    }
+}
+
+/// Prints the guardrail's assessment of the turn, with --guardrail-trace.
+fn print_guardrail_trace(state: &ConversationState, trace: Option<&GuardrailTraceAssessment>) {
+    if !state.guardrail_trace {
+        return;
+    }
+    let findings = trace.map(guardrail::trace_findings).unwrap_or_default();
+    match findings.is_empty() {
+        true => notice(state.sse, "-- guardrail: nothing matched --"),
+        false => {
+            notice(state.sse, "-- guardrail --");
+            findings
+                .iter()
+                .for_each(|finding| notice(state.sse, finding));
+        }
+    }
+}
+
+/// Prints the additional model response fields asked for with --response-field.
+fn print_response_fields(sse: bool, fields: Option<&Document>) {
+    if let Some(fields) = fields {
+        let json = tool_use::document_to_json(fields);
+        notice(
+            sse,
+            format!(
+                "-- response fields: {} --",
+                serde_json::to_string_pretty(&json).unwrap()
+            ),
+        );
+    }
+}
+
+/// Prints the closest model ids to the --model that wasn't found.
+///
+/// The model can't be changed mid-conversation, so this is a hint for restarting.
+async fn report_model_not_found(state: &ConversationState) {
+    notice(state.sse, format!("Model not found: {}", state.model));
+    let cpclient = rusty_bedrock_lib::new_controlplane_client(&state.client_options).await;
+    if let Some(hint) = rusty_bedrock_lib::did_you_mean(&cpclient, &state.model).await {
+        notice(
+            state.sse,
+            format!("{} (restart converse with --model)", hint),
+        );
+    }
+}
+
+/// Splits a local document over Bedrock's per-document size limit into several documents.
+///
+/// Returns None when the attachment isn't an oversized local document, or when it has a
+/// `#bytes=` or `#pages=` range, which is sent as is (see `file::Range`).
+fn chunk_if_oversized(file_ref: &FileReference) -> Result<Option<Vec<DocumentBlock>>, ChunkError> {
+    if file_ref.range.is_some() {
+        return Ok(None);
+    }
+    if !matches!(
+        (&file_ref.file_type, &file_ref.location),
+        (file::Type::Document, file::Location::Local)
+    ) {
+        return Ok(None);
+    }
+    let size = std::fs::metadata(file::expand(&file_ref.path))
+        .map(|metadata| metadata.len())
+        .unwrap_or_default();
+    if size <= MAX_DOCUMENT_BYTES as u64 {
+        return Ok(None);
+    }
+    let bytes = file::read(&file_ref.path);
+    modalities::chunk_document(
+        &modalities::attachment_name(file_ref),
+        &bytes,
+        &file_ref.extension.0,
+        MAX_DOCUMENT_BYTES,
+    )
+    .map(Some)
+}
+
+/// Asks a yes/no question on stdin, defaulting to no.
+fn confirm(question: &str) -> Result<bool, std::io::Error> {
+    print!("{} [y/N] ", question);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+#[test]
+fn ranged_attachments_arent_chunked() {
+    let path = std::env::temp_dir().join("bedrock-lib-oversized.txt");
+    std::fs::write(&path, "line\n".repeat(MAX_DOCUMENT_BYTES / 4)).unwrap();
+    let path = path.to_str().unwrap();
+
+    let chunks = chunk_if_oversized(&path.to_string().into()).unwrap();
+    assert!(chunks.is_some_and(|chunks| chunks.len() > 1));
+    let ranged = format!("{}#bytes=0-1000", path);
+    assert!(chunk_if_oversized(&ranged.into()).unwrap().is_none());
+}
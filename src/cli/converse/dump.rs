@@ -0,0 +1,51 @@
+//! Commands for debugging what was sent to and received from the model
+//!
+//! `dump-request` and `dump-response` pretty print the last Converse request (messages,
+//! system, tool config, and inference config) and its response as the API's json.
+//! Attachment bytes are shortened like `--verbose` logs, unless converse was started
+//! with --no-redact.
+
+use clap::Parser;
+use serde_json::Value;
+
+use rusty_bedrock_lib::redact;
+
+use crate::ConversationState;
+
+/// Print the json of the last request sent to the model
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+pub struct DumpRequestArgs {}
+
+pub fn dump_request(
+    state: &mut ConversationState,
+    _args: DumpRequestArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match &state.last_request {
+        Some(request) => print_json(request),
+        None => println!("No request sent yet."),
+    }
+    Ok(())
+}
+
+/// Print the json of the last response from the model
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+pub struct DumpResponseArgs {}
+
+pub fn dump_response(
+    state: &mut ConversationState,
+    _args: DumpResponseArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match (&state.last_response, &state.last_request) {
+        (Some(response), _) => print_json(response),
+        (None, Some(_)) => println!("The last request didn't get a response."),
+        (None, None) => println!("No request sent yet."),
+    }
+    Ok(())
+}
+
+fn print_json(value: &Value) {
+    let redacted = redact::value(value.clone());
+    println!("{}", serde_json::to_string_pretty(&redacted).unwrap());
+}
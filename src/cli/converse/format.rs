@@ -0,0 +1,121 @@
+//! Commands for structured output: `format-json` and `format-clear`
+//!
+//! `format-json` reads a json schema and, until `format-clear`, has the model answer
+//! every turn by calling a tool whose input is that schema, with the tool choice pinned
+//! to it.  The tool use's input is printed as the answer, and kept in the history as
+//! text, so the conversation doesn't carry tool uses without results.  The `--tools`
+//! tools aren't offered meanwhile.
+
+use aws_sdk_bedrockruntime::types::{ContentBlock, Message};
+use clap::Parser;
+
+use rusty_bedrock_lib::converse::tool_use::{self, InvalidSchema};
+use rusty_bedrock_lib::file::{self, NotText};
+
+use crate::ConversationState;
+
+/// Have the model answer with json matching a schema, from now on
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+pub struct FormatJsonArgs {
+    /// A json schema for an object, e.g.
+    /// {"type": "object", "properties": {"city": {"type": "string"}}}
+    schema_file: String,
+}
+
+pub fn format_json(
+    state: &mut ConversationState,
+    args: FormatJsonArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let text = match file::read_text(&args.schema_file) {
+        Ok(text) => text,
+        Err(NotText(msg)) => {
+            println!("{}", msg);
+            return Ok(());
+        }
+    };
+    let schema = match serde_json::from_str(&text) {
+        Ok(schema) => schema,
+        Err(err) => {
+            println!("{} isn't valid json: {}", args.schema_file, err);
+            return Ok(());
+        }
+    };
+    match tool_use::mk_json_format(&schema) {
+        Ok(config) => {
+            state.json_format = Some(config);
+            println!("-- answering in json from {} --", args.schema_file);
+        }
+        Err(InvalidSchema(msg)) => println!("Invalid schema {}: {}", args.schema_file, msg),
+    }
+    Ok(())
+}
+
+/// Stop answering with json, see format-json
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+pub struct FormatClearArgs {}
+
+pub fn format_clear(
+    state: &mut ConversationState,
+    _args: FormatClearArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match state.json_format.take() {
+        Some(_) => println!("-- answering in text again --"),
+        None => println!("No json format is set."),
+    }
+    Ok(())
+}
+
+/// The answer in a response to a json format request: the format tool use's input.
+pub fn answer(msg: &Message) -> Option<serde_json::Value> {
+    msg.content().iter().find_map(|block| match block {
+        ContentBlock::ToolUse(tool_use) if tool_use.name() == tool_use::JSON_FORMAT_TOOL => {
+            Some(tool_use::document_to_json(tool_use.input()))
+        }
+        _ => None,
+    })
+}
+
+/// The response with its format tool use replaced by the json answer as text.
+pub fn as_text(mut msg: Message, answer: &serde_json::Value) -> Message {
+    msg.content.retain(|block| !block.is_tool_use());
+    msg.content.push(ContentBlock::Text(answer.to_string()));
+    msg
+}
+
+#[test]
+fn json_answers() {
+    use aws_sdk_bedrockruntime::types::{ConversationRole, ToolUseBlock};
+
+    let answer_json = serde_json::json!({"city": "Paris"});
+    let tool_use = ToolUseBlock::builder()
+        .tool_use_id("t1")
+        .name(tool_use::JSON_FORMAT_TOOL)
+        .input(tool_use::json_to_document(&answer_json))
+        .build()
+        .unwrap();
+    let msg = Message::builder()
+        .role(ConversationRole::Assistant)
+        .content(ContentBlock::Text("Here you go.".to_string()))
+        .content(ContentBlock::ToolUse(tool_use))
+        .build()
+        .unwrap();
+    assert_eq!(Some(answer_json.clone()), answer(&msg));
+
+    let text = as_text(msg, &answer_json);
+    assert_eq!(
+        vec!["Here you go.", r#"{"city":"Paris"}"#],
+        text.content()
+            .iter()
+            .map(|block| block.as_text().unwrap().as_str())
+            .collect::<Vec<_>>()
+    );
+
+    let plain = Message::builder()
+        .role(ConversationRole::Assistant)
+        .content(ContentBlock::Text("no json".to_string()))
+        .build()
+        .unwrap();
+    assert_eq!(None, answer(&plain));
+}
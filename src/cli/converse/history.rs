@@ -0,0 +1,457 @@
+//! Commands for managing the conversation history
+//!
+//! `summarize` compresses older turns into a summary carried in the system prompt, so
+//! long conversations cost fewer tokens.  The full history is set aside as a branch
+//! first, so `restore` can always bring it back.  `cite-last` stages the last answer
+//! as a document for the next turn.  `regen` asks for an alternative to the last answer,
+//! which `keep` swaps in and `discard` drops.  `export-openai` writes the history in
+//! OpenAI's chat format.
+
+use aws_sdk_bedrockruntime::types::{
+    ContentBlock, ConversationRole, ConverseOutput, DocumentBlock, DocumentFormat, DocumentSource,
+    InferenceConfiguration, Message,
+};
+use clap::Parser;
+use log::debug;
+
+use rusty_bedrock_lib::amazon_nova::text::json;
+use rusty_bedrock_lib::converse::nova_json::ConversionError;
+use rusty_bedrock_lib::converse::openai;
+use rusty_bedrock_lib::inference::{self, OutOfRange};
+use rusty_bedrock_lib::{file, markdown, redact};
+
+use crate::{format, pins, ConversationState};
+
+pub const DEFAULT_SUMMARY_PROMPT: &str = "Summarize our conversation so far, including any \
+earlier summary you were given.  Keep every fact, decision, and open question needed to \
+continue the conversation, and leave out pleasantries.";
+
+/// A saved copy of the conversation history
+#[derive(Debug, Clone)]
+pub struct Branch {
+    pub messages: Vec<Message>,
+    pub summary: Option<String>,
+}
+
+/// Summarize older turns to shrink the history, keeping recent turns verbatim
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+pub struct SummarizeArgs {
+    /// Number of most recent turns (prompt + response) to keep verbatim
+    #[clap(short, long, default_value = "2")]
+    keep: usize,
+
+    /// Override the summary prompt for this summarization
+    #[clap(short, long)]
+    prompt: Option<String>,
+}
+
+pub async fn summarize(
+    state: &mut ConversationState,
+    args: SummarizeArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let split = match summary_split(&state.messages, args.keep) {
+        Some(split) => split,
+        None => {
+            println!("Nothing to summarize.");
+            return Ok(());
+        }
+    };
+
+    // ===========================
+    // Ask the model for a summary of the older turns
+    // ===========================
+    let prompt = args.prompt.unwrap_or_else(|| state.summary_prompt.clone());
+    let mut older = state.messages[..split].to_vec();
+    older.push(
+        Message::builder()
+            .role(ConversationRole::User)
+            .content(ContentBlock::Text(prompt))
+            .build()
+            .unwrap(),
+    );
+
+    let conversation = state
+        .client
+        .converse()
+        .model_id(state.model.clone())
+        .set_system(state.system())
+        .set_messages(Some(older))
+        .set_tool_config(state.tool_config.clone())
+        .send()
+        .await
+        .unwrap();
+
+    debug!("{}", redact::debug(&conversation));
+
+    let summary = match conversation.output() {
+        Some(ConverseOutput::Message(msg)) => msg
+            .content()
+            .iter()
+            .filter_map(|content| content.as_text().ok())
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => panic!("No output??"),
+    };
+
+    // ===========================
+    // Set aside the full history, then swap the older turns for the summary
+    // ===========================
+    let name = format!("pre-summary-{}", state.branches.len() + 1);
+    state.branches.insert(
+        name.clone(),
+        Branch {
+            messages: state.messages.clone(),
+            summary: state.summary.clone(),
+        },
+    );
+    state.messages.drain(..split);
+    state.summary = Some(summary.clone());
+
+    println!("{}", summary);
+    println!(
+        "-- summarized {} messages, full history saved as branch {} --",
+        split, name
+    );
+    Ok(())
+}
+
+/// Finds the index splitting the history into turns to summarize and turns to keep.
+///
+/// The kept part must start on a user prompt (not a tool result) so the history still
+/// alternates correctly once the older part is removed.
+fn summary_split(messages: &[Message], keep: usize) -> Option<usize> {
+    let is_prompt = |msg: &Message| {
+        msg.role() == &ConversationRole::User
+            && !msg
+                .content()
+                .iter()
+                .any(|content| matches!(content, ContentBlock::ToolResult(_)))
+    };
+
+    // walk back over the turns to keep, each of which starts with a prompt
+    let mut split = messages.len();
+    let mut kept = 0;
+    while kept < keep {
+        split = messages[..split].iter().rposition(is_prompt)?;
+        kept += 1;
+    }
+
+    // when keeping everything back to the start there's nothing left to summarize
+    Some(split).filter(|split| *split > 0)
+}
+
+/// List saved branches of the conversation history
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+pub struct BranchesArgs {}
+
+pub fn branches(
+    state: &mut ConversationState,
+    _args: BranchesArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if state.branches.is_empty() {
+        println!("No saved branches.");
+    }
+    for (name, branch) in &state.branches {
+        println!("{} ({} messages)", name, branch.messages.len());
+    }
+    Ok(())
+}
+
+/// Replace the current history with a saved branch
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+pub struct RestoreArgs {
+    /// Name of the branch, as shown by `branches`
+    name: String,
+}
+
+pub fn restore(
+    state: &mut ConversationState,
+    args: RestoreArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match state.branches.get(&args.name) {
+        Some(branch) => {
+            state.messages = branch.messages.clone();
+            state.summary = branch.summary.clone();
+            state.alternative = None;
+            println!(
+                "-- restored {} ({} messages) --",
+                args.name,
+                state.messages.len()
+            );
+        }
+        None => println!("No branch named {}", args.name),
+    }
+    Ok(())
+}
+
+/// Attach the last answer as a text document to the next `say`, e.g. to critique it
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+pub struct CiteLastArgs {}
+
+pub fn cite_last(
+    state: &mut ConversationState,
+    _args: CiteLastArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let last = state
+        .messages
+        .iter()
+        .enumerate()
+        .rev()
+        .find(|(_, msg)| msg.role() == &ConversationRole::Assistant);
+    let Some((idx, msg)) = last else {
+        println!("No answer to cite yet.");
+        return Ok(());
+    };
+    let text = msg
+        .content()
+        .iter()
+        .filter_map(|content| content.as_text().ok())
+        .cloned()
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    // document names must be unique within the conversation
+    let name = format!("previous-answer-{}", idx);
+    let document = DocumentBlock::builder()
+        .format(DocumentFormat::Txt)
+        .source(DocumentSource::Bytes(text.into_bytes().into()))
+        .name(&name)
+        .build()
+        .unwrap();
+    state.staged.push(ContentBlock::Document(document));
+    println!("-- {} will be attached to the next say --", name);
+    Ok(())
+}
+
+/// Ask for another take on the last answer, e.g. for creative writing
+///
+/// Re-sends the last prompt at a higher temperature (Converse has no seed parameter to
+/// vary instead).  The history isn't changed until the alternative is kept with `keep`;
+/// `discard` or the next `say` drops it.
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+pub struct RegenArgs {
+    /// Temperature for the alternative
+    #[clap(short, long, default_value = "1.0")]
+    temperature: f32,
+}
+
+pub async fn regen(
+    state: &mut ConversationState,
+    args: RegenArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(last) = regen_point(&state.messages) else {
+        println!("The last turn has to be a prompt and its answer (without tool use) to regen.");
+        return Ok(());
+    };
+    let config = json::InferenceConfig {
+        temperature: Some(args.temperature),
+        ..Default::default()
+    };
+    if let Err(OutOfRange(msg)) = inference::check(&state.model, &config) {
+        println!("{}", msg);
+        return Ok(());
+    }
+
+    let base = state.inference_config.as_ref();
+    let inference_config = InferenceConfiguration::builder()
+        .set_max_tokens(base.and_then(|config| config.max_tokens()))
+        .set_stop_sequences(base.and_then(|config| config.stop_sequences.clone()))
+        .temperature(args.temperature)
+        .build();
+    let result = state
+        .client
+        .converse()
+        .model_id(state.model.clone())
+        .set_system(state.system())
+        .set_messages(Some(pins::with_pinned(
+            &state.pinned,
+            &state.messages[..last],
+        )))
+        .set_tool_config(state.request_tool_config())
+        .set_performance_config(state.performance_config.clone())
+        .set_guardrail_config(state.guardrail_config.clone())
+        .inference_config(inference_config)
+        .send()
+        .await;
+    let conversation = match result {
+        Ok(conversation) => conversation,
+        Err(err) => {
+            println!(
+                "regen failed: {}",
+                aws_sdk_bedrockruntime::error::DisplayErrorContext(&err)
+            );
+            return Ok(());
+        }
+    };
+    debug!("{}", redact::debug(&conversation));
+
+    let Some(ConverseOutput::Message(mut msg)) = conversation.output else {
+        panic!("No output??");
+    };
+    if let Some(answer) = state.json_format.as_ref().and_then(|_| format::answer(&msg)) {
+        msg = format::as_text(msg, &answer);
+    }
+    if msg
+        .content()
+        .iter()
+        .any(|content| matches!(content, ContentBlock::ToolUse(_)))
+    {
+        println!("-- the alternative asked for a tool, discarding it --");
+        return Ok(());
+    }
+    for text in msg
+        .content()
+        .iter()
+        .filter_map(|content| content.as_text().ok())
+    {
+        markdown::print(text, state.pretty);
+    }
+    state.alternative = Some(msg);
+    println!(
+        "-- alternative at temperature {}: keep or discard --",
+        args.temperature
+    );
+    Ok(())
+}
+
+/// Replace the last answer with the alternative from `regen`
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+pub struct KeepArgs {}
+
+pub fn keep(
+    state: &mut ConversationState,
+    _args: KeepArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match (state.alternative.take(), regen_point(&state.messages)) {
+        (Some(alternative), Some(last)) => {
+            state.messages[last] = alternative;
+            println!("-- kept the alternative --");
+        }
+        _ => println!("No alternative to keep, see regen."),
+    }
+    Ok(())
+}
+
+/// Drop the alternative from `regen`, keeping the original answer
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+pub struct DiscardArgs {}
+
+pub fn discard(
+    state: &mut ConversationState,
+    _args: DiscardArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match state.alternative.take() {
+        Some(_) => println!("-- discarded the alternative --"),
+        None => println!("No alternative to discard."),
+    }
+    Ok(())
+}
+
+/// Index of the last answer, if the history ends with a prompt (not a tool result) and
+/// its answer (not a tool use), which is all `regen` can redo without rerunning tools.
+fn regen_point(messages: &[Message]) -> Option<usize> {
+    let has = |msg: &Message, tool: fn(&ContentBlock) -> bool| msg.content().iter().any(tool);
+    match messages {
+        [.., prompt, answer]
+            if prompt.role() == &ConversationRole::User
+                && answer.role() == &ConversationRole::Assistant
+                && !has(prompt, |content| {
+                    matches!(content, ContentBlock::ToolResult(_))
+                })
+                && !has(answer, |content| {
+                    matches!(content, ContentBlock::ToolUse(_))
+                }) =>
+        {
+            Some(messages.len() - 1)
+        }
+        _ => None,
+    }
+}
+
+/// Write the conversation as OpenAI chat format json, for tools that expect it
+///
+/// The system prompt (with any summary) becomes a leading system message, and images and
+/// pdfs become content parts.  Videos can't be exported.
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+pub struct ExportOpenAiArgs {
+    /// File to write (overwritten)
+    path: String,
+}
+
+pub fn export_openai(
+    state: &mut ConversationState,
+    args: ExportOpenAiArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let system = state.system().unwrap_or_default();
+    match openai::to_openai(&system, &state.messages) {
+        Ok(messages) => {
+            let json = serde_json::to_string_pretty(&messages).unwrap();
+            match file::write_string(&args.path, json) {
+                Ok(()) => println!("-- wrote {} messages to {} --", messages.len(), args.path),
+                Err(err) => println!("Couldn't write {}: {}", args.path, err),
+            }
+        }
+        Err(ConversionError(msg)) => println!("Couldn't export the conversation: {}", msg),
+    }
+    Ok(())
+}
+
+#[test]
+fn split_keeps_recent_turns() {
+    let msg = |role: ConversationRole| {
+        Message::builder()
+            .role(role)
+            .content(ContentBlock::Text("hi".to_string()))
+            .build()
+            .unwrap()
+    };
+    let turn = || vec![msg(ConversationRole::User), msg(ConversationRole::Assistant)];
+    let messages = [turn(), turn(), turn()].concat();
+
+    assert_eq!(Some(4), summary_split(&messages, 1));
+    assert_eq!(Some(2), summary_split(&messages, 2));
+    assert_eq!(None, summary_split(&messages, 3));
+    assert_eq!(None, summary_split(&messages, 4));
+    assert_eq!(Some(6), summary_split(&messages, 0));
+}
+
+#[test]
+fn regen_needs_a_plain_last_turn() {
+    let msg = |role: ConversationRole, content: ContentBlock| {
+        Message::builder()
+            .role(role)
+            .content(content)
+            .build()
+            .unwrap()
+    };
+    let text = || ContentBlock::Text("hi".to_string());
+    let prompt = msg(ConversationRole::User, text());
+    let answer = msg(ConversationRole::Assistant, text());
+    let tool_result = msg(
+        ConversationRole::User,
+        rusty_bedrock_lib::converse::tool_use::tool_result("id", "ok"),
+    );
+
+    assert_eq!(None, regen_point(&[]));
+    assert_eq!(None, regen_point(std::slice::from_ref(&prompt)));
+    assert_eq!(Some(1), regen_point(&[prompt.clone(), answer.clone()]));
+    assert_eq!(
+        Some(3),
+        regen_point(&[
+            prompt.clone(),
+            answer.clone(),
+            prompt.clone(),
+            answer.clone()
+        ])
+    );
+    assert_eq!(None, regen_point(&[tool_result, answer]));
+}
@@ -0,0 +1,91 @@
+//! `paste`: stages an image from the system clipboard for the next turn
+//!
+//! Quicker than saving a screenshot to attach it.  The clipboard is read with arboard,
+//! so the command needs the `arboard` feature, and the image is sent as a PNG.
+
+use aws_sdk_bedrockruntime::types::{ContentBlock, ImageBlock, ImageFormat, ImageSource};
+use aws_smithy_types::Blob;
+use clap::Parser;
+
+use crate::ConversationState;
+
+/// Attach the image on the clipboard (e.g. a screenshot) to the next `say`
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+pub struct PasteArgs {}
+
+#[derive(Debug)]
+pub struct PasteError(pub String);
+
+pub fn paste(
+    state: &mut ConversationState,
+    _args: PasteArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let png = match clipboard_png() {
+        Ok(png) => png,
+        Err(PasteError(msg)) => {
+            println!("Couldn't paste: {}", msg);
+            return Ok(());
+        }
+    };
+    let kb = png.len() / 1000;
+    let image = ImageBlock::builder()
+        .format(ImageFormat::Png)
+        .source(ImageSource::Bytes(Blob::new(png)))
+        .build()
+        .unwrap();
+    state.staged.push(ContentBlock::Image(image));
+    println!(
+        "-- the clipboard image ({} KB) will be attached to the next say --",
+        kb
+    );
+    Ok(())
+}
+
+#[cfg(feature = "arboard")]
+fn clipboard_png() -> Result<Vec<u8>, PasteError> {
+    let mut clipboard = arboard::Clipboard::new()
+        .map_err(|err| PasteError(format!("couldn't open the clipboard: {}", err)))?;
+    let image = match clipboard.get_image() {
+        Ok(image) => image,
+        Err(arboard::Error::ContentNotAvailable) => {
+            return Err(PasteError(
+                "the clipboard doesn't hold an image".to_string(),
+            ))
+        }
+        Err(err) => return Err(PasteError(format!("couldn't read the clipboard: {}", err))),
+    };
+    png(image.width, image.height, image.bytes.into_owned())
+}
+
+#[cfg(not(feature = "arboard"))]
+fn clipboard_png() -> Result<Vec<u8>, PasteError> {
+    Err(PasteError(
+        "converse was built without the arboard feature, rebuild with --features arboard"
+            .to_string(),
+    ))
+}
+
+/// Encodes the clipboard's RGBA pixels as a PNG.
+#[cfg(feature = "arboard")]
+fn png(width: usize, height: usize, rgba: Vec<u8>) -> Result<Vec<u8>, PasteError> {
+    let invalid = || PasteError("the clipboard image's size doesn't match its pixels".to_string());
+    let width = u32::try_from(width).map_err(|_| invalid())?;
+    let height = u32::try_from(height).map_err(|_| invalid())?;
+    let image = image::RgbaImage::from_raw(width, height, rgba).ok_or_else(invalid)?;
+    let mut png = std::io::Cursor::new(vec![]);
+    image
+        .write_to(&mut png, image::ImageFormat::Png)
+        .map_err(|err| PasteError(format!("couldn't encode the image: {}", err)))?;
+    Ok(png.into_inner())
+}
+
+#[cfg(feature = "arboard")]
+#[test]
+fn clipboard_pixels_to_png() {
+    let encoded = png(2, 1, vec![255, 0, 0, 255, 0, 0, 255, 255]).unwrap();
+    let decoded = image::load_from_memory(&encoded).unwrap().to_rgba8();
+    assert_eq!((2, 1), decoded.dimensions());
+    assert_eq!([0, 0, 255, 255], decoded.get_pixel(1, 0).0);
+    assert!(png(2, 2, vec![0; 8]).is_err());
+}
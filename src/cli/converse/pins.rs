@@ -0,0 +1,149 @@
+//! Commands for pinned attachments, sent with every request until unpinned
+//!
+//! `pin` reads a file (e.g. a reference document) once, and from then on it's sent at
+//! the start of the conversation in each request, so the model has it for the whole
+//! session.  Pinned files aren't kept in the history: each request carries one copy,
+//! however many turns there have been, but that copy is paid for in input tokens on
+//! every turn.  `unpin` stops sending one, or all of them.
+
+use aws_sdk_bedrockruntime::types::{ContentBlock, Message};
+use clap::Parser;
+
+use rusty_bedrock_lib::converse::citations;
+use rusty_bedrock_lib::converse::modalities::{
+    self, AttachmentLimitError, AttachmentPath, InvalidPath,
+};
+
+use crate::ConversationState;
+
+/// Pinned files at least this large get a warning about their per-turn cost
+const LARGE_PIN_BYTES: usize = 100_000;
+
+/// A file sent with every request
+#[derive(Debug, Clone)]
+pub struct Pinned {
+    pub path: String,
+    pub block: ContentBlock,
+}
+
+/// The messages with the pinned files added to the start of the first one, as sent.
+pub fn with_pinned(pinned: &[Pinned], messages: &[Message]) -> Vec<Message> {
+    let mut messages = messages.to_vec();
+    if let Some(first) = messages.first_mut().filter(|_| !pinned.is_empty()) {
+        first
+            .content
+            .splice(0..0, pinned.iter().map(|pin| pin.block.clone()));
+    }
+    messages
+}
+
+/// Attach a file to every request from now on, e.g. a reference document (or, with no
+/// path, list the pinned files)
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+pub struct PinArgs {
+    /// The file, as for say --attach
+    path: Option<String>,
+}
+
+pub fn pin(state: &mut ConversationState, args: PinArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(path) = args.path else {
+        match state.pinned.is_empty() {
+            true => println!("Nothing pinned."),
+            false => state.pinned.iter().for_each(|pin| println!("{}", pin.path)),
+        }
+        return Ok(());
+    };
+    if state.pinned.iter().any(|pin| pin.path == path) {
+        println!("{} is already pinned.", path);
+        return Ok(());
+    }
+    let paths = [path.clone()];
+    if let Err(AttachmentLimitError(msg)) = modalities::check_attachments(&paths, 1) {
+        println!("Couldn't pin {}: {}", path, msg);
+        return Ok(());
+    }
+    let mut block = match ContentBlock::try_from(AttachmentPath(path.clone())) {
+        Ok(block) => block,
+        Err(InvalidPath(path)) => {
+            println!("Invalid attachment path: {}", path);
+            return Ok(());
+        }
+    };
+    if state.show_citations {
+        citations::enable(&mut block);
+    }
+    let bytes = modalities::inline_bytes(&block);
+    if bytes >= LARGE_PIN_BYTES {
+        println!(
+            "-- {} is {} KB, and is resent (and billed as input) with every request --",
+            path,
+            bytes / 1000
+        );
+    }
+    state.pinned.push(Pinned { path, block });
+    println!("-- pinned {} --", paths[0]);
+    Ok(())
+}
+
+/// Stop sending a pinned file (or, with no path, all of them)
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+pub struct UnpinArgs {
+    path: Option<String>,
+}
+
+pub fn unpin(
+    state: &mut ConversationState,
+    args: UnpinArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match args.path {
+        None => {
+            println!("-- unpinned {} files --", state.pinned.len());
+            state.pinned.clear();
+        }
+        Some(path) => {
+            let before = state.pinned.len();
+            state.pinned.retain(|pin| pin.path != path);
+            match state.pinned.len() < before {
+                true => println!("-- unpinned {} --", path),
+                false => println!("{} isn't pinned, see pin.", path),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn pinned_files_start_the_conversation() {
+    use aws_sdk_bedrockruntime::types::ConversationRole;
+
+    let msg = |role: ConversationRole, text: &str| {
+        Message::builder()
+            .role(role)
+            .content(ContentBlock::Text(text.to_string()))
+            .build()
+            .unwrap()
+    };
+    let messages = vec![
+        msg(ConversationRole::User, "first"),
+        msg(ConversationRole::Assistant, "answer"),
+        msg(ConversationRole::User, "second"),
+    ];
+    assert_eq!(messages, with_pinned(&[], &messages));
+
+    let pinned = [Pinned {
+        path: "notes.md".to_string(),
+        block: ContentBlock::Text("pinned".to_string()),
+    }];
+    let sent = with_pinned(&pinned, &messages);
+    assert_eq!(
+        vec!["pinned", "first"],
+        sent[0]
+            .content()
+            .iter()
+            .map(|block| block.as_text().unwrap().as_str())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(messages[1..], sent[1..]);
+}
@@ -0,0 +1,87 @@
+//! Commands for the named prompt snippet library (see `snippets`)
+//!
+//! `prompt-save` saves a snippet, `prompt-use` adds one to the next `say` (as text after
+//! its prompt), and `prompts` lists them.
+
+use aws_sdk_bedrockruntime::types::ContentBlock;
+use clap::Parser;
+
+use rusty_bedrock_lib::snippets::{self, SnippetError};
+
+use crate::ConversationState;
+
+/// Save a named prompt snippet, e.g. prompt-save json Respond only in JSON.
+///
+/// Snippets are kept across sessions, and a snippet of the same name is replaced.
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+pub struct PromptSaveArgs {
+    /// Name to use it by, without spaces
+    name: String,
+
+    /// The snippet's text (the rest of the line)
+    #[clap(required = true)]
+    text: Vec<String>,
+}
+
+pub fn prompt_save(
+    _state: &mut ConversationState,
+    args: PromptSaveArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match snippets::save(&args.name, &args.text.join(" ")) {
+        Ok(()) => println!("-- saved prompt {} --", args.name),
+        Err(SnippetError(msg)) => println!("Couldn't save the prompt: {}", msg),
+    }
+    Ok(())
+}
+
+/// Add a saved prompt snippet to the next say
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+pub struct PromptUseArgs {
+    name: String,
+}
+
+pub fn prompt_use(
+    state: &mut ConversationState,
+    args: PromptUseArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let snippets = match snippets::load() {
+        Ok(snippets) => snippets,
+        Err(SnippetError(msg)) => {
+            println!("{}", msg);
+            return Ok(());
+        }
+    };
+    match snippets.get(&args.name) {
+        Some(text) => {
+            state.staged.push(ContentBlock::Text(text.clone()));
+            println!("-- prompt {} will be added to the next say --", args.name);
+        }
+        None => println!("No prompt named {}, see prompts.", args.name),
+    }
+    Ok(())
+}
+
+/// List the saved prompt snippets
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+pub struct PromptsArgs {}
+
+pub fn prompts(
+    _state: &mut ConversationState,
+    _args: PromptsArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match snippets::load() {
+        Ok(snippets) if snippets.is_empty() => {
+            println!("No saved prompts yet, see prompt-save.")
+        }
+        Ok(snippets) => {
+            for (name, text) in snippets {
+                println!("{}: {}", name, text);
+            }
+        }
+        Err(SnippetError(msg)) => println!("{}", msg),
+    }
+    Ok(())
+}
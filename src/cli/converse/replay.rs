@@ -0,0 +1,217 @@
+//! `replay`: re-sends a saved conversation's prompts to the live model, turn by turn
+//!
+//! For regression testing prompts against model updates: each saved prompt is sent with
+//! the saved history before it, and the model's answer is compared line by line with
+//! the saved one.  Divergences print as a diff, and the session's own conversation is
+//! left as it was.
+//!
+//! The file is Nova request json (as for --load-invoke) or OpenAI chat json (as for
+//! --load-openai, e.g. from `export-openai`).  Turns with tool use aren't replayed, as
+//! their tool results came from the original run, but they're sent as history for later
+//! turns, with the session's tool config (or converse's own tools, as Bedrock requires
+//! one for history with tool use).
+
+use aws_sdk_bedrockruntime::types::{
+    ContentBlock, ConversationRole, ConverseOutput, Message, SystemContentBlock, ToolConfiguration,
+};
+use clap::Parser;
+use log::debug;
+use std::io::Write;
+
+use rusty_bedrock_lib::converse::nova_json::{self, ConversionError};
+use rusty_bedrock_lib::converse::openai;
+use rusty_bedrock_lib::{diff, markdown, redact};
+
+use crate::{tools, ConversationState};
+
+/// Step through a saved conversation, re-sending each prompt to the model and flagging
+/// answers that differ from the saved ones
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+pub struct ReplayArgs {
+    /// Nova request json or OpenAI chat json, e.g. from export-openai
+    path: String,
+
+    /// Send every turn without waiting for Enter
+    #[clap(long)]
+    no_wait: bool,
+}
+
+pub async fn replay(
+    state: &mut ConversationState,
+    args: ReplayArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (system, messages) = match load(&args.path) {
+        Ok(loaded) => loaded,
+        Err(ConversionError(msg)) => {
+            println!("Couldn't load {}: {}", args.path, msg);
+            return Ok(());
+        }
+    };
+    let system = Some(system)
+        .filter(|system| !system.is_empty())
+        .or_else(|| state.system());
+
+    let turns = replayable_turns(&messages);
+    if turns.is_empty() {
+        println!("{} has no prompts with saved answers to replay.", args.path);
+        return Ok(());
+    }
+    let (mut matched, mut diverged) = (0, 0);
+    for (number, &turn) in turns.iter().enumerate() {
+        println!("-- turn {}/{} --", number + 1, turns.len());
+        println!("{}", text(&messages[turn]));
+        if !args.no_wait && !next_turn()? {
+            break;
+        }
+
+        let history = &messages[..=turn];
+        let tool_config = tool_config(state.tool_config.as_ref(), history);
+        let result = state
+            .client
+            .converse()
+            .model_id(state.model.clone())
+            .set_system(system.clone())
+            .set_messages(Some(history.to_vec()))
+            .set_tool_config(tool_config)
+            .set_performance_config(state.performance_config.clone())
+            .set_inference_config(state.inference_config.clone())
+            .send()
+            .await;
+        let output = match result {
+            Ok(output) => output,
+            Err(err) => {
+                println!(
+                    "replay failed: {}",
+                    aws_sdk_bedrockruntime::error::DisplayErrorContext(&err)
+                );
+                break;
+            }
+        };
+        debug!("{}", redact::debug(&output));
+        let Some(ConverseOutput::Message(live)) = output.output else {
+            return Err(format!("turn {}: the response has no message", number + 1).into());
+        };
+
+        let (saved, live) = (text(&messages[turn + 1]), text(&live));
+        markdown::print(&live, state.pretty);
+        if saved == live {
+            matched += 1;
+            println!("-- matches the saved answer --");
+        } else {
+            diverged += 1;
+            println!("-- differs from the saved answer (- saved, + live) --");
+            for line in diff::lines(&saved, &live) {
+                println!("{}", line);
+            }
+        }
+    }
+    println!(
+        "-- replayed {} of {} turns: {} matched, {} diverged --",
+        matched + diverged,
+        turns.len(),
+        matched,
+        diverged
+    );
+    Ok(())
+}
+
+/// Reads a saved conversation, as Nova request json or else OpenAI chat json.
+fn load(path: &str) -> Result<(Vec<SystemContentBlock>, Vec<Message>), ConversionError> {
+    let Ok(request) = nova_json::load_request(path) else {
+        return openai::load_openai(path);
+    };
+    let messages = request
+        .messages
+        .iter()
+        .map(nova_json::to_sdk_message)
+        .collect::<Result<_, _>>()?;
+    Ok((nova_json::to_sdk_system(&request.system), messages))
+}
+
+/// The session's tool config, or else converse's tools if the history has tool use, as
+/// Bedrock rejects tool blocks sent without a tool config.
+fn tool_config(
+    session: Option<&ToolConfiguration>,
+    history: &[Message],
+) -> Option<ToolConfiguration> {
+    session
+        .cloned()
+        .or_else(|| history.iter().any(uses_tools).then(tools::tool_config))
+}
+
+/// Whether the message has tool use or tool result blocks
+fn uses_tools(msg: &Message) -> bool {
+    msg.content().iter().any(|block| {
+        matches!(
+            block,
+            ContentBlock::ToolUse(_) | ContentBlock::ToolResult(_)
+        )
+    })
+}
+
+/// Indexes of the user messages followed by a saved answer, leaving out tool use turns.
+fn replayable_turns(messages: &[Message]) -> Vec<usize> {
+    messages
+        .windows(2)
+        .enumerate()
+        .filter(|(_, pair)| {
+            pair[0].role() == &ConversationRole::User
+                && pair[1].role() == &ConversationRole::Assistant
+                && !uses_tools(&pair[0])
+                && !uses_tools(&pair[1])
+        })
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+/// The message's text blocks, one after another
+fn text(msg: &Message) -> String {
+    msg.content()
+        .iter()
+        .filter_map(|block| block.as_text().ok())
+        .cloned()
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Waits for Enter, returning false on q (or the end of stdin).
+fn next_turn() -> Result<bool, std::io::Error> {
+    print!("[Enter to send, q to stop] ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    let read = std::io::stdin().read_line(&mut answer)?;
+    Ok(read > 0 && !answer.trim().eq_ignore_ascii_case("q"))
+}
+
+#[test]
+fn replay_turns() {
+    use aws_sdk_bedrockruntime::types::ToolUseBlock;
+
+    let msg = |role: ConversationRole, block: ContentBlock| {
+        Message::builder()
+            .role(role)
+            .content(block)
+            .build()
+            .unwrap()
+    };
+    let text_msg = |role, text: &str| msg(role, ContentBlock::Text(text.to_string()));
+    let tool_use = ToolUseBlock::builder()
+        .tool_use_id("t1")
+        .name("lookup")
+        .input(aws_smithy_types::Document::Null)
+        .build()
+        .unwrap();
+    let messages = vec![
+        text_msg(ConversationRole::User, "hi"),
+        text_msg(ConversationRole::Assistant, "hello"),
+        text_msg(ConversationRole::User, "look it up"),
+        msg(ConversationRole::Assistant, ContentBlock::ToolUse(tool_use)),
+        text_msg(ConversationRole::User, "thanks"),
+    ];
+    assert_eq!(vec![0], replayable_turns(&messages));
+
+    // a tool config for the history with tool use, even without --tools
+    assert!(tool_config(None, &messages[..2]).is_none());
+    assert!(tool_config(None, &messages).is_some());
+}
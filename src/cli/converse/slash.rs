@@ -0,0 +1,71 @@
+//! `converse --slash`: bare input is a prompt, and commands start with /
+//!
+//! Saves typing `say` every turn, e.g. `tell me a joke` is `say -- tell me a joke` and
+//! `/summarize --keep 1` is `summarize --keep 1`.  Built-ins are slashed too: `/help`,
+//! `/quit`, `/exit`.
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use shellfish::handler::DefaultAsyncHandler;
+use shellfish::{AsyncHandler, Command};
+
+/// Routes each line to a command, as `DefaultAsyncHandler` does, after rewriting it for
+/// `--slash` mode when enabled.
+#[derive(Default, Clone, Copy)]
+pub struct SlashHandler {
+    pub slash: bool,
+}
+
+#[async_trait]
+impl<T: Send> AsyncHandler<T> for SlashHandler {
+    async fn handle_async(
+        &self,
+        line: Vec<String>,
+        commands: &HashMap<&str, Command<T>>,
+        state: &mut T,
+        description: &str,
+    ) -> bool {
+        let line = if self.slash { route(line) } else { line };
+        DefaultAsyncHandler::default()
+            .handle_async(line, commands, state, description)
+            .await
+    }
+}
+
+/// Rewrites a line for `--slash` mode: `/cmd args` runs `cmd args`, and anything else
+/// is the prompt of a `say`.
+///
+/// The shell has already split the line into words (removing "quotes"), so a prompt is
+/// rejoined with single spaces.
+fn route(line: Vec<String>) -> Vec<String> {
+    match line.first() {
+        None => line,
+        Some(first) if first.starts_with('/') => {
+            let mut line = line;
+            line[0] = line[0][1..].to_string();
+            line
+        }
+        // -- so a prompt starting with - isn't parsed as a flag
+        Some(_) => vec!["say".to_string(), "--".to_string(), line.join(" ")],
+    }
+}
+
+#[test]
+fn slash_routes() {
+    let words = |line: &str| line.split(' ').map(str::to_string).collect::<Vec<_>>();
+    assert_eq!(
+        vec!["say", "--", "tell me a joke"],
+        route(words("tell me a joke"))
+    );
+    assert_eq!(
+        vec!["say", "--", "-1 is negative"],
+        route(words("-1 is negative"))
+    );
+    assert_eq!(
+        words("summarize --keep 1"),
+        route(words("/summarize --keep 1"))
+    );
+    assert_eq!(words("quit"), route(words("/quit")));
+    assert!(route(vec![]).is_empty());
+}
@@ -0,0 +1,56 @@
+//! Built-in tools offered to the model when converse runs with `--tools`
+//!
+//! The tools are read-only views of the local filesystem, so a model can look at
+//! files the user points it to.
+
+use aws_sdk_bedrockruntime::types::ToolConfiguration;
+use aws_smithy_types::Document;
+use rusty_bedrock_lib::converse::tool_use::{self, ToolArg, ToolArgType};
+
+pub fn tool_config() -> ToolConfiguration {
+    tool_use::mk_tool_config(vec![
+        tool_use::mk_tool_spec(
+            "read_file",
+            "reads the contents of a local text file",
+            vec![ToolArg::new(
+                "path",
+                "path to the file, ~ and env variables are expanded",
+                ToolArgType::String,
+                true,
+            )],
+        ),
+        tool_use::mk_tool_spec(
+            "list_directory",
+            "lists the entries of a local directory",
+            vec![ToolArg::new(
+                "path",
+                "path to the directory, ~ and env variables are expanded",
+                ToolArgType::String,
+                true,
+            )],
+        ),
+    ])
+}
+
+/// Runs the named tool, returning its output or a message describing the failure.
+pub fn run(name: &str, input: &Document) -> Result<String, String> {
+    let path = tool_use::tool_input_str(input, "path")
+        .map(rusty_bedrock_lib::file::expand)
+        .ok_or_else(|| "missing required argument: path".to_string())?;
+    match name {
+        "read_file" => std::fs::read(&path)
+            .map(|bytes| String::from_utf8_lossy(&bytes).to_string())
+            .map_err(|err| format!("couldn't read {}: {}", path, err)),
+        "list_directory" => {
+            let entries = std::fs::read_dir(&path)
+                .map_err(|err| format!("couldn't list {}: {}", path, err))?;
+            let mut names = entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .collect::<Vec<_>>();
+            names.sort();
+            Ok(names.join("\n"))
+        }
+        _ => Err(format!("unknown tool: {}", name)),
+    }
+}
@@ -0,0 +1,277 @@
+//! `trim-to`: drops the oldest turns to bring the history under a token target, going by
+//! the token counts Bedrock reported rather than an estimate from the text's length
+//!
+//! Each request's usage is recorded against the history it sent, so the tokens in a
+//! leading stretch of the history ending on an answer are known: that request's input
+//! tokens, plus its answer's output tokens, less what every request carries anyway (the
+//! system prompt, tools, and pinned files).  That last share is estimated once, from
+//! the first recorded request.  Like `summarize`, the full history is set aside as a
+//! branch first, so `restore` can bring it back.
+
+use aws_sdk_bedrockruntime::types::{ContentBlock, ConversationRole, Message, TokenUsage};
+use clap::Parser;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use rusty_bedrock_lib::tokens;
+
+use crate::history::Branch;
+use crate::ConversationState;
+
+/// The token usage Bedrock reported for one request
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestUsage {
+    /// The number of history messages sent
+    messages: usize,
+    /// Fingerprint of the last of them, to tell whether the history has changed since
+    last: u64,
+    /// Input tokens, including any read from or written to the prompt cache
+    input_tokens: usize,
+    output_tokens: usize,
+}
+
+/// Records the usage of a request for the current history, once its response arrives.
+pub fn record(state: &mut ConversationState, usage: Option<&TokenUsage>) {
+    if let Some(usage) = usage.and_then(|usage| request_usage(&state.messages, usage)) {
+        state.usage.push(usage);
+    }
+}
+
+fn request_usage(messages: &[Message], usage: &TokenUsage) -> Option<RequestUsage> {
+    let count = |tokens: i32| usize::try_from(tokens).unwrap_or(0);
+    let cached =
+        usage.cache_read_input_tokens.unwrap_or(0) + usage.cache_write_input_tokens.unwrap_or(0);
+    Some(RequestUsage {
+        messages: messages.len(),
+        last: fingerprint(messages.last()?),
+        input_tokens: count(usage.input_tokens) + count(cached),
+        output_tokens: count(usage.output_tokens),
+    })
+}
+
+/// Drop the oldest turns until the history is about this many tokens, by Bedrock's
+/// token counts for the turns so far
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+pub struct TrimToArgs {
+    /// Token target for the whole request, system prompt and pinned files included
+    tokens: usize,
+}
+
+pub fn trim_to(
+    state: &mut ConversationState,
+    args: TrimToArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(trim) = plan(&state.messages, &state.usage, args.tokens) else {
+        println!("No token counts from Bedrock for this history yet, send a prompt first.");
+        return Ok(());
+    };
+    if trim.before <= args.tokens {
+        println!(
+            "-- the history is ~{} tokens, already within {} --",
+            trim.before, args.tokens
+        );
+        return Ok(());
+    }
+    if trim.drop == 0 {
+        println!(
+            "-- the history is ~{} tokens, and no turns can be dropped without the last one --",
+            trim.before
+        );
+        return Ok(());
+    }
+
+    let name = format!("pre-trim-{}", state.branches.len() + 1);
+    state.branches.insert(
+        name.clone(),
+        Branch {
+            messages: state.messages.clone(),
+            summary: state.summary.clone(),
+        },
+    );
+    let turns = state.messages.drain(..trim.drop).filter(is_prompt).count();
+    let dropped = trim.before - trim.after;
+    state.usage.retain(|usage| usage.messages > trim.drop);
+    for usage in &mut state.usage {
+        usage.messages -= trim.drop;
+        usage.input_tokens = usage.input_tokens.saturating_sub(dropped);
+    }
+
+    println!(
+        "-- dropped {} turns ({} messages), ~{} to ~{} tokens{}, full history saved as branch {} --",
+        turns,
+        trim.drop,
+        trim.before,
+        trim.after,
+        match trim.after > args.tokens {
+            true => " (the fewest turns left are still over)",
+            false => "",
+        },
+        name
+    );
+    Ok(())
+}
+
+/// Where to cut the history to get it to about `target` tokens
+#[derive(Debug, Clone, PartialEq)]
+struct Trim {
+    /// The number of leading messages to drop, 0 for none
+    drop: usize,
+    /// The request's tokens before and after dropping them
+    before: usize,
+    after: usize,
+}
+
+/// Plans the smallest cut that gets the history within `target`, or else the largest
+/// one that keeps the last turn.  None without usage recorded for this history.
+fn plan(messages: &[Message], usage: &[RequestUsage], target: usize) -> Option<Trim> {
+    let usage = usage
+        .iter()
+        .filter(|usage| {
+            (1..=messages.len()).contains(&usage.messages)
+                && fingerprint(&messages[usage.messages - 1]) == usage.last
+        })
+        .collect::<Vec<_>>();
+    let first = usage.iter().min_by_key(|usage| usage.messages)?;
+    let last = usage.iter().max_by_key(|usage| usage.messages)?;
+
+    // what every request carries, whatever the history
+    let fixed = first
+        .input_tokens
+        .saturating_sub(estimate(&messages[..first.messages]));
+    let before = match messages.get(last.messages..) {
+        Some([_answer, rest @ ..]) => last.input_tokens + last.output_tokens + estimate(rest),
+        _ => last.input_tokens,
+    };
+
+    // cut after an answer, before the next turn's prompt
+    let cuts = usage
+        .iter()
+        .filter(|usage| messages.get(usage.messages + 1).is_some_and(is_prompt))
+        .map(|usage| {
+            let dropped = (usage.input_tokens + usage.output_tokens).saturating_sub(fixed);
+            Trim {
+                drop: usage.messages + 1,
+                before,
+                after: before.saturating_sub(dropped),
+            }
+        })
+        .collect::<Vec<_>>();
+    let within = cuts
+        .iter()
+        .filter(|cut| cut.after <= target)
+        .min_by_key(|cut| cut.drop);
+    let fewest = cuts.iter().max_by_key(|cut| cut.drop);
+    let keep_all = Trim {
+        drop: 0,
+        before,
+        after: before,
+    };
+    match before <= target {
+        true => Some(keep_all),
+        false => Some(within.or(fewest).cloned().unwrap_or(keep_all)),
+    }
+}
+
+/// Whether the message is a user's prompt, starting a turn, rather than tool results
+fn is_prompt(msg: &Message) -> bool {
+    msg.role() == &ConversationRole::User
+        && !msg
+            .content()
+            .iter()
+            .any(|block| matches!(block, ContentBlock::ToolResult(_)))
+}
+
+/// Estimated tokens in the messages' text
+fn estimate(messages: &[Message]) -> usize {
+    messages
+        .iter()
+        .flat_map(|msg| msg.content())
+        .filter_map(|block| block.as_text().ok())
+        .map(|text| tokens::estimate(text))
+        .sum()
+}
+
+/// A hash of the message's role and text, cheap enough to take for every request
+fn fingerprint(msg: &Message) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    msg.role().as_str().hash(&mut hasher);
+    msg.content().len().hash(&mut hasher);
+    for block in msg.content() {
+        if let Ok(text) = block.as_text() {
+            text.hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+#[test]
+fn trim_by_usage() {
+    let msg = |role: ConversationRole, text: &str| {
+        Message::builder()
+            .role(role)
+            .content(ContentBlock::Text(text.to_string()))
+            .build()
+            .unwrap()
+    };
+    let messages = vec![
+        msg(ConversationRole::User, "aaaa"),
+        msg(ConversationRole::Assistant, "first answer"),
+        msg(ConversationRole::User, "bbbb"),
+        msg(ConversationRole::Assistant, "second answer"),
+        msg(ConversationRole::User, "cccc"),
+        msg(ConversationRole::Assistant, "third answer"),
+    ];
+    let usage = |sent: usize, input: i32, output: i32| {
+        let usage = TokenUsage::builder()
+            .input_tokens(input)
+            .output_tokens(output)
+            .total_tokens(input + output)
+            .build()
+            .unwrap();
+        request_usage(&messages[..sent], &usage).unwrap()
+    };
+    // 100 tokens of system prompt, and the first prompt's 1
+    let usage = vec![usage(1, 101, 400), usage(3, 1001, 300), usage(5, 1801, 200)];
+    assert_eq!(None, plan(&messages, &[], 1000));
+
+    let trim = |target| plan(&messages, &usage, target).unwrap();
+    assert_eq!(
+        Trim {
+            drop: 0,
+            before: 2001,
+            after: 2001
+        },
+        trim(5000)
+    );
+    // the first turn is 401 tokens, the first two 1201
+    assert_eq!(
+        Trim {
+            drop: 2,
+            before: 2001,
+            after: 1600
+        },
+        trim(1800)
+    );
+    assert_eq!(
+        Trim {
+            drop: 4,
+            before: 2001,
+            after: 800
+        },
+        trim(1000)
+    );
+    // the last turn is kept, even when over
+    assert_eq!(4, trim(10).drop);
+
+    // counts for a history that's since changed are ignored
+    let mut edited = messages.clone();
+    edited[4] = msg(ConversationRole::User, "edited");
+    assert_eq!(
+        Trim {
+            drop: 2,
+            before: 1306,
+            after: 905
+        },
+        plan(&edited, &usage, 1000).unwrap()
+    );
+}
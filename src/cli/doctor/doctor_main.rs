@@ -0,0 +1,110 @@
+use clap::{Parser, Subcommand};
+use rusty_bedrock_lib::cli::ClientArgs;
+use rusty_bedrock_lib::{doctor, ClientOptions};
+
+/// Diagnoses AWS connectivity and Bedrock permissions
+///
+/// Runs a checklist of region, credentials, bedrock:ListFoundationModels, and
+/// model access, printing pass/fail with remediation hints for failures.
+///
+/// The model access check sends a single-token request to the model, so callers
+/// need permission for `bedrock:InvokeModel`.
+///
+/// Example:
+///     doctor --aws-profile bedrock -m us.amazon.nova-lite-v1:0
+///     doctor --aws-profile dev warmup
+#[derive(Parser, Debug, Clone)]
+#[clap(author, version, about, verbatim_doc_comment)]
+struct CliArgs {
+    /// AWS profile override
+    ///
+    /// AWS region and credentials are selected in the following sequence:
+    ///
+    /// 1/ Explicit Override:
+    ///     When this --profile option (or BEDROCK_PROFILE) is specified, the named profile
+    ///     will be read from ~/.aws/config and ~/.aws/credentials.
+    ///
+    /// 2/ Environment Variables, as described here:
+    ///
+    ///     https://docs.aws.amazon.com/cli/v1/userguide/cli-configure-envvars.html
+    ///
+    /// 3/ Default profile:
+    ///     Uses the default profile from ~/.aws/config and ~/.aws/credentials.
+    ///
+    /// See the AWS docs for more information:
+    ///
+    ///     https://docs.aws.amazon.com/sdkref/latest/guide/file-format.html
+    ///     https://docs.aws.amazon.com/sdk-for-rust/latest/dg/region.html
+    ///     https://docs.aws.amazon.com/sdk-for-rust/latest/dg/credproviders.html
+    #[clap(long, env = "BEDROCK_PROFILE", verbatim_doc_comment)]
+    aws_profile: Option<String>,
+
+    /// AWS region override, e.g. us-west-2
+    ///
+    /// Takes precedence over the region from --aws-profile or the environment.
+    #[clap(long, env = "BEDROCK_REGION")]
+    region: Option<String>,
+
+    #[clap(flatten)]
+    client: ClientArgs,
+
+    /// Model or inference profile id to check access for
+    #[clap(
+        short,
+        long,
+        env = "BEDROCK_MODEL",
+        default_value = "us.amazon.nova-lite-v1:0"
+    )]
+    model: String,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Resolves the credentials once and prints when the copy it fetched expires, e.g.
+    /// ahead of a script that runs the CLIs many times
+    ///
+    /// For an SSO profile, this refreshes the session's token if it's due, and the SDK
+    /// saves it to ~/.aws/sso/cache, so the runs that follow don't each refresh it.
+    /// They still fetch their own role credentials, which are only cached in memory, so
+    /// the expiry printed is this process's, not the SSO session's.
+    /// Exits with status 1 when no credentials resolve.
+    Warmup,
+}
+
+#[tokio::main]
+async fn main() {
+    let cli: CliArgs = rusty_bedrock_lib::cli::parse_or_complete();
+
+    let options = ClientOptions {
+        aws_profile: cli.aws_profile,
+        region: cli.region,
+        ..cli.client.options()
+    };
+    if let Some(Command::Warmup) = cli.command {
+        match doctor::warm_up(&options).await {
+            Ok(warmed) => println!(
+                "credentials from {} (this process's copy {})",
+                warmed.source,
+                doctor::describe_expiry(warmed.expiry, std::time::SystemTime::now())
+            ),
+            Err(doctor::WarmupError(msg)) => {
+                eprintln!("couldn't resolve credentials: {}", msg);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    let checks = doctor::run_checks(&options, cli.model).await;
+    let mut failed = false;
+    for check in checks {
+        failed |= matches!(check.status, doctor::Status::Fail);
+        println!("{}", check);
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
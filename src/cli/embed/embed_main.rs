@@ -0,0 +1,373 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use clap::{Parser, Subcommand};
+use log::{debug, warn};
+use rusty_bedrock_lib::cli::{ClientArgs, RetryArgs};
+use rusty_bedrock_lib::embeddings::{self, Embedding, EmbeddingError};
+use rusty_bedrock_lib::logging::{self, LogFormat};
+use rusty_bedrock_lib::{file, recording, ClientOptions};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+
+/// Generates text embeddings with Amazon Titan Text Embeddings V2 on Bedrock
+///
+/// model-id: amazon.titan-embed-text-v2:0
+///
+/// You must be opted into the model specified in you AWS account have have
+/// `bedrock:InvokeModel` permissions:
+///     https://docs.aws.amazon.com/bedrock/latest/userguide/model-access.html
+///
+/// === Example usage ===
+///
+///     embed text "a sentence to embed"
+///     embed embed-dir --output notes.jsonl ~/notes
+///     embed search notes.jsonl "where did I put the tax forms"
+#[derive(Parser, Debug, Clone)]
+#[clap(author, version, about, verbatim_doc_comment)]
+struct EmbedCliArgs {
+    /// AWS profile override
+    ///
+    /// AWS region and credentials are selected in the following sequence:
+    ///
+    /// 1/ Explicit Override:
+    ///     When this --profile option (or BEDROCK_PROFILE) is specified, the named profile
+    ///     will be read from ~/.aws/config and ~/.aws/credentials.
+    ///
+    /// 2/ Environment Variables, as described here:
+    ///
+    ///     https://docs.aws.amazon.com/cli/v1/userguide/cli-configure-envvars.html
+    ///
+    /// 3/ Default profile:
+    ///     Uses the default profile from ~/.aws/config and ~/.aws/credentials.
+    ///
+    /// See the AWS docs for more information:
+    ///
+    ///     https://docs.aws.amazon.com/sdkref/latest/guide/file-format.html
+    ///     https://docs.aws.amazon.com/sdk-for-rust/latest/dg/region.html
+    ///     https://docs.aws.amazon.com/sdk-for-rust/latest/dg/credproviders.html
+    #[clap(long, env = "BEDROCK_PROFILE", verbatim_doc_comment)]
+    aws_profile: Option<String>,
+
+    /// AWS region override, e.g. us-west-2
+    ///
+    /// Takes precedence over the region from --aws-profile or the environment.
+    #[clap(long, env = "BEDROCK_REGION")]
+    region: Option<String>,
+
+    #[clap(flatten)]
+    retry: RetryArgs,
+
+    #[clap(flatten)]
+    client: ClientArgs,
+
+    /// prints request/response detail
+    #[clap(short, long)]
+    verbose: bool,
+
+    /// Log format on stderr: plain, or json (one object per line, for log collectors)
+    #[clap(long, arg_enum, default_value = "plain")]
+    log_format: LogFormat,
+
+    /// Save each response into this directory, keyed by a hash of the request
+    ///
+    /// Replay them later with --replay, e.g. for demos or tests without AWS access.
+    #[clap(long, conflicts_with = "replay")]
+    record: Option<String>,
+
+    /// Answer from responses saved by --record instead of calling Bedrock
+    #[clap(long)]
+    replay: Option<String>,
+
+    /// Embedding model id
+    #[clap(short, long, default_value = embeddings::DEFAULT_MODEL_ID)]
+    model: String,
+
+    /// Size of each embedding: 256, 512, or 1024
+    #[clap(short, long, default_value = "1024", possible_values = ["256", "512", "1024"])]
+    dimensions: u32,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Prints the embedding of some text as a json array
+    Text {
+        /// Text to embed
+        text: String,
+    },
+
+    /// Embeds every text and markdown file under a directory into a JSONL index
+    ///
+    /// Each line of the index is `{"path", "embedding", "token_count"}`.  Files other
+    /// than .txt and .md are skipped, as are files over the model's input limit.
+    EmbedDir {
+        /// Directory to walk (recursively)
+        dir: String,
+
+        /// Where to write the index (overwritten)
+        #[clap(short, long, default_value = "index.jsonl")]
+        output: String,
+
+        /// Maximum number of requests in flight at once
+        #[clap(long, default_value = "4")]
+        concurrency: usize,
+    },
+
+    /// Prints the paths in an embed-dir index most similar to a query, best first
+    ///
+    /// Embed the query with the same --model and --dimensions as the index.
+    Search {
+        /// Index written by embed-dir
+        index: String,
+
+        /// Text to search for
+        query: String,
+
+        /// Number of results to print
+        #[clap(short = 'k', long, default_value = "5")]
+        top_k: usize,
+    },
+}
+
+/// One line of the embed-dir index
+#[derive(Serialize, Deserialize, Debug)]
+struct IndexEntry {
+    path: String,
+    embedding: Vec<f32>,
+    token_count: u32,
+}
+
+#[tokio::main]
+async fn main() {
+    let cli: EmbedCliArgs = rusty_bedrock_lib::cli::parse_or_complete();
+
+    logging::init(cli.verbose, cli.log_format);
+
+    recording::set_mode(recording::Mode::from_args(
+        cli.record.clone(),
+        cli.replay.clone(),
+    ));
+
+    let options = ClientOptions {
+        aws_profile: cli.aws_profile,
+        region: cli.region,
+        retry: cli.retry.options(),
+        ..cli.client.options()
+    };
+    let client = rusty_bedrock_lib::new_runtime_client(&options).await;
+    logging::set_field("model", cli.model.clone());
+
+    match cli.command {
+        Command::Text { text } => {
+            let embedding = embed(&client, &cli.model, &text, cli.dimensions)
+                .await
+                .unwrap_or_else(|err| exit(err));
+            println!("{}", serde_json::to_string(&embedding.vector).unwrap());
+        }
+        Command::EmbedDir {
+            dir,
+            output,
+            concurrency,
+        } => {
+            let mut files = vec![];
+            walk(Path::new(&file::expand(&dir)), &mut files);
+            files.sort();
+            embed_dir(
+                &client,
+                &cli.model,
+                cli.dimensions,
+                files,
+                &output,
+                concurrency,
+            )
+            .await;
+        }
+        Command::Search {
+            index,
+            query,
+            top_k,
+        } => {
+            let entries = read_index(&index);
+            let query = embed(&client, &cli.model, &query, cli.dimensions)
+                .await
+                .unwrap_or_else(|err| exit(err));
+            for (score, path) in search(&entries, &query.vector, top_k) {
+                println!("{:.4}\t{}", score, path);
+            }
+        }
+    }
+}
+
+async fn embed(
+    client: &aws_sdk_bedrockruntime::Client,
+    model: &str,
+    text: &str,
+    dimensions: u32,
+) -> Result<Embedding, EmbeddingError> {
+    embeddings::embed_text(client, model, text, dimensions)
+        .await
+        .map(|(_, embedding)| embedding)
+}
+
+/// Collects the .txt and .md files under `dir`
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = std::fs::read_dir(dir)
+        .unwrap_or_else(|err| panic!("couldn't read {}: {}", dir.display(), err));
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            walk(&path, files);
+        } else if is_text(&path) {
+            files.push(path);
+        } else {
+            debug!("skipping {}", path.display());
+        }
+    }
+}
+
+fn is_text(path: &Path) -> bool {
+    let ext = path.extension().and_then(|ext| ext.to_str());
+    matches!(ext.map(str::to_lowercase).as_deref(), Some("txt" | "md"))
+}
+
+/// Embeds the files, at most `concurrency` at a time, writing each to the index as it
+/// finishes.  A file that can't be embedded is reported and left out of the index.
+async fn embed_dir(
+    client: &aws_sdk_bedrockruntime::Client,
+    model: &str,
+    dimensions: u32,
+    files: Vec<PathBuf>,
+    output: &str,
+    concurrency: usize,
+) {
+    if let Err(err) = file::write_string(output, String::new()) {
+        eprintln!("couldn't write {}: {}", output, err);
+        std::process::exit(1);
+    }
+    let mut index = file::open_append(output);
+    let permits = Arc::new(Semaphore::new(concurrency.max(1)));
+
+    let mut tasks = tokio::task::JoinSet::new();
+    for path in files {
+        let permits = permits.clone();
+        let client = client.clone();
+        let model = model.to_string();
+        tasks.spawn(async move {
+            let _permit = permits.acquire().await.unwrap();
+            let name = path.display().to_string();
+            let text = match std::fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(err) => return (name, Err(EmbeddingError(err.to_string()))),
+            };
+            let result = embed(&client, &model, &text, dimensions).await;
+            (name, result)
+        });
+    }
+
+    let (mut written, mut skipped) = (0, 0);
+    while let Some(task) = tasks.join_next().await {
+        let (path, result) = task.unwrap();
+        match result {
+            Ok(embedding) => {
+                let entry = IndexEntry {
+                    path,
+                    embedding: embedding.vector,
+                    token_count: embedding.token_count,
+                };
+                let line = serde_json::to_string(&entry).unwrap();
+                writeln!(index, "{}", line)
+                    .and_then(|_| index.flush())
+                    .unwrap_or_else(|err| panic!("couldn't write {}: {}", output, err));
+                written += 1;
+            }
+            Err(err) => {
+                warn!("skipping {}: {}", path, err.0);
+                skipped += 1;
+            }
+        }
+    }
+    println!(
+        "wrote {} embeddings to {} ({} skipped)",
+        written, output, skipped
+    );
+}
+
+fn read_index(filename: &str) -> Vec<IndexEntry> {
+    let contents = String::from_utf8(file::read(filename))
+        .unwrap_or_else(|err| panic!("{} isn't utf-8: {}", filename, err));
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(idx, line)| {
+            serde_json::from_str(line)
+                .unwrap_or_else(|err| panic!("{} line {}: {}", filename, idx + 1, err))
+        })
+        .collect()
+}
+
+/// The `top_k` entries most similar to `query`, as (score, path), best first
+fn search<'a>(entries: &'a [IndexEntry], query: &[f32], top_k: usize) -> Vec<(f32, &'a str)> {
+    let mut scored = entries
+        .iter()
+        .filter(|entry| {
+            let matches = entry.embedding.len() == query.len();
+            if !matches {
+                warn!(
+                    "skipping {}: {} dimensions, the query has {}",
+                    entry.path,
+                    entry.embedding.len(),
+                    query.len()
+                );
+            }
+            matches
+        })
+        .map(|entry| {
+            let score = embeddings::cosine_similarity(&entry.embedding, query);
+            (score, entry.path.as_str())
+        })
+        .collect::<Vec<_>>();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.truncate(top_k);
+    scored
+}
+
+fn exit(err: EmbeddingError) -> ! {
+    eprintln!("{}", err.0);
+    std::process::exit(1);
+}
+
+#[test]
+fn text_files() {
+    assert!(is_text(Path::new("notes/a.md")));
+    assert!(is_text(Path::new("A.TXT")));
+    assert!(!is_text(Path::new("image.png")));
+    assert!(!is_text(Path::new("README")));
+}
+
+#[test]
+fn nearest() {
+    let entry = |path: &str, embedding: Vec<f32>| IndexEntry {
+        path: path.to_string(),
+        embedding,
+        token_count: 1,
+    };
+    let entries = vec![
+        entry("far.md", vec![0.0, 1.0]),
+        entry("near.md", vec![1.0, 0.1]),
+        entry("other-model.md", vec![1.0, 0.0, 0.0]),
+        entry("nearer.md", vec![1.0, 0.0]),
+    ];
+    let paths = |k| {
+        search(&entries, &[1.0, 0.0], k)
+            .into_iter()
+            .map(|(_, path)| path)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(vec!["nearer.md", "near.md", "far.md"], paths(5));
+    assert_eq!(vec!["nearer.md"], paths(1));
+}
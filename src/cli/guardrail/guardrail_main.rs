@@ -0,0 +1,153 @@
+use clap::{Parser, Subcommand};
+use rusty_bedrock_lib::cli::{ClientArgs, RetryArgs};
+use rusty_bedrock_lib::guardrail::{self, GuardrailError};
+use rusty_bedrock_lib::logging::{self, LogFormat};
+use rusty_bedrock_lib::ClientOptions;
+
+/// Works with Amazon Bedrock Guardrails
+///
+/// You must have `bedrock:ApplyGuardrail` permission on the guardrail:
+///     https://docs.aws.amazon.com/bedrock/latest/userguide/guardrails-use-independent-api.html
+/// and `bedrock:ListGuardrails` to list them.
+///
+/// === Example usage ===
+///
+///     guardrail list-guardrails
+///     guardrail guardrail-check --guardrail-id gr-abc123 --version 1 "my candidate prompt"
+#[derive(Parser, Debug, Clone)]
+#[clap(author, version, about, verbatim_doc_comment)]
+struct GuardrailCliArgs {
+    /// AWS profile override
+    ///
+    /// AWS region and credentials are selected in the following sequence:
+    ///
+    /// 1/ Explicit Override:
+    ///     When this --profile option (or BEDROCK_PROFILE) is specified, the named profile
+    ///     will be read from ~/.aws/config and ~/.aws/credentials.
+    ///
+    /// 2/ Environment Variables, as described here:
+    ///
+    ///     https://docs.aws.amazon.com/cli/v1/userguide/cli-configure-envvars.html
+    ///
+    /// 3/ Default profile:
+    ///     Uses the default profile from ~/.aws/config and ~/.aws/credentials.
+    ///
+    /// See the AWS docs for more information:
+    ///
+    ///     https://docs.aws.amazon.com/sdkref/latest/guide/file-format.html
+    ///     https://docs.aws.amazon.com/sdk-for-rust/latest/dg/region.html
+    ///     https://docs.aws.amazon.com/sdk-for-rust/latest/dg/credproviders.html
+    #[clap(long, env = "BEDROCK_PROFILE", verbatim_doc_comment)]
+    aws_profile: Option<String>,
+
+    /// AWS region override, e.g. us-west-2
+    ///
+    /// Takes precedence over the region from --aws-profile or the environment.
+    #[clap(long, env = "BEDROCK_REGION")]
+    region: Option<String>,
+
+    #[clap(flatten)]
+    retry: RetryArgs,
+
+    #[clap(flatten)]
+    client: ClientArgs,
+
+    /// prints request/response detail
+    #[clap(short, long)]
+    verbose: bool,
+
+    /// Log format on stderr: plain, or json (one object per line, for log collectors)
+    #[clap(long, arg_enum, default_value = "plain")]
+    log_format: LogFormat,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Runs a guardrail over some text without invoking a model, printing what it found
+    ///
+    /// Exits with status 2 when the guardrail intervenes (blocks or masks the text), so
+    /// scripts can screen prompts before sending them.
+    GuardrailCheck {
+        /// Guardrail id or ARN
+        #[clap(long)]
+        guardrail_id: String,
+
+        /// Guardrail version number, or DRAFT
+        #[clap(long, default_value = "DRAFT")]
+        version: String,
+
+        /// Check the text as a model response rather than as a prompt
+        #[clap(long)]
+        as_output: bool,
+
+        /// Text to check
+        text: String,
+    },
+    /// Lists the account's guardrails, printing each one's name, id, version, and status
+    ///
+    /// Without --guardrail-id, each guardrail is listed once, as its DRAFT version.
+    ListGuardrails {
+        /// List every version of this guardrail (id or ARN)
+        #[clap(long)]
+        guardrail_id: Option<String>,
+    },
+}
+
+#[tokio::main]
+async fn main() {
+    let cli: GuardrailCliArgs = rusty_bedrock_lib::cli::parse_or_complete();
+
+    logging::init(cli.verbose, cli.log_format);
+
+    let options = ClientOptions {
+        aws_profile: cli.aws_profile,
+        region: cli.region,
+        retry: cli.retry.options(),
+        ..cli.client.options()
+    };
+    match cli.command {
+        Command::GuardrailCheck {
+            guardrail_id,
+            version,
+            as_output,
+            text,
+        } => {
+            let client = rusty_bedrock_lib::new_runtime_client(&options).await;
+            let check = guardrail::check_text(&client, &guardrail_id, &version, &text, as_output)
+                .await
+                .unwrap_or_else(|err| exit(err));
+            for finding in &check.findings {
+                println!("{}", finding);
+            }
+            if !check.intervened {
+                println!("-- passed --");
+                return;
+            }
+            for output in &check.outputs {
+                println!("-- guardrail output --\n{}", output);
+            }
+            println!("-- intervened --");
+            std::process::exit(2);
+        }
+        Command::ListGuardrails { guardrail_id } => {
+            let cpclient = rusty_bedrock_lib::new_controlplane_client(&options).await;
+            let guardrails = guardrail::list(&cpclient, guardrail_id.as_deref())
+                .await
+                .unwrap_or_else(|err| exit(err));
+            if guardrails.is_empty() {
+                println!("No guardrails found.");
+            }
+            for summary in &guardrails {
+                println!("{}", guardrail::summary_line(summary));
+            }
+        }
+    }
+}
+
+fn exit(err: GuardrailError) -> ! {
+    eprintln!("{}", err.0);
+    std::process::exit(1);
+}
@@ -1,4 +1,7 @@
 use clap::Parser;
+use rusty_bedrock_lib::cli::ClientArgs;
+use rusty_bedrock_lib::logging::{self, LogFormat};
+use rusty_bedrock_lib::{ClientOptions, SortBy};
 
 /// Lists Bedrock models
 #[derive(Parser, Debug, Clone)]
@@ -9,8 +12,8 @@ struct CliArgs {
     /// AWS region and credentials are selected in the following sequence:
     ///
     /// 1/ Explicit Override:
-    ///     When this --profile option is specified, the named profile will be read from
-    ///     ~/.aws/config and ~/.aws/credentials.
+    ///     When this --profile option (or BEDROCK_PROFILE) is specified, the named profile
+    ///     will be read from ~/.aws/config and ~/.aws/credentials.
     ///
     /// 2/ Environment Variables, as described here:
     ///
@@ -24,23 +27,69 @@ struct CliArgs {
     ///     https://docs.aws.amazon.com/sdkref/latest/guide/file-format.html
     ///     https://docs.aws.amazon.com/sdk-for-rust/latest/dg/region.html
     ///     https://docs.aws.amazon.com/sdk-for-rust/latest/dg/credproviders.html
-    #[clap(long, verbatim_doc_comment)]
+    #[clap(long, env = "BEDROCK_PROFILE", verbatim_doc_comment)]
     aws_profile: Option<String>,
 
+    /// AWS region override, e.g. us-west-2
+    ///
+    /// Takes precedence over the region from --aws-profile or the environment.
+    #[clap(long, env = "BEDROCK_REGION")]
+    region: Option<String>,
+
+    #[clap(flatten)]
+    client: ClientArgs,
+
     /// Optional case-insensitive provider filter, e.g. Amazon, amazon, Anthropic.
     ///
     /// https://docs.aws.amazon.com/bedrock/latest/userguide/models-supported.html
     provider: Option<String>,
+
+    /// Only list models this account has been granted access to (see Model access in the
+    /// Bedrock console).
+    ///
+    /// Makes an extra GetFoundationModelAvailability call per model, so listing is slower.
+    #[clap(long)]
+    accessible_only: bool,
+
+    /// Leave out models Bedrock marks LEGACY, which are slated for retirement
+    #[clap(long)]
+    exclude_legacy: bool,
+
+    /// Order of the list: by provider then name, by name, or grouped by output modality
+    #[clap(long, arg_enum, default_value = "provider")]
+    sort_by: SortBy,
 }
 
 // #[async_std::main]
 #[tokio::main]
 async fn main() {
-    let cli = CliArgs::parse();
+    let cli: CliArgs = rusty_bedrock_lib::cli::parse_or_complete();
+    // the library warns when it lists models without their profiles or availability
+    logging::init(false, LogFormat::Plain);
 
-    let cpclient = rusty_bedrock_lib::new_controlplane_client(cli.aws_profile.clone()).await;
-    let list = rusty_bedrock_lib::list_models(&cpclient, cli.provider).await;
+    let options = ClientOptions {
+        aws_profile: cli.aws_profile,
+        region: cli.region,
+        ..cli.client.options()
+    };
+    let cpclient = rusty_bedrock_lib::new_controlplane_client(&options).await;
+    let mut list = rusty_bedrock_lib::list_models(&cpclient, cli.provider).await;
+    if cli.exclude_legacy {
+        list.retain(|model| !model.is_legacy());
+    }
+    if cli.accessible_only {
+        list = rusty_bedrock_lib::accessible_models(&cpclient, list).await;
+    }
+    rusty_bedrock_lib::sort_models(&mut list, cli.sort_by);
+    let mut group = None;
     for item in list {
+        if cli.sort_by == SortBy::Modality {
+            let modality = rusty_bedrock_lib::output_modality(&item);
+            if group.as_ref() != Some(&modality) {
+                println!("-- {} --", modality);
+                group = Some(modality);
+            }
+        }
         println!("{}", item);
     }
 }
@@ -0,0 +1,255 @@
+//! `nova --batch`: runs each line of a file as its own prompt
+//!
+//! Prompts are sent one at a time, with the rest of the command line (--system,
+//! --prefill, --text, --attach, ...) applied to every one.  A prompt that fails is
+//! reported (and recorded in --output-jsonl) without stopping the batch.
+//!
+//! Ctrl-C stops the batch cleanly: no more prompts are sent, the one in flight gets up
+//! to `DRAIN_TIMEOUT` to finish (and be recorded), and the summary says which line to
+//! start from to run the rest.  --output-jsonl then holds exactly the prompts that
+//! completed.  A second Ctrl-C exits at once.
+
+use std::io::Write;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use aws_sdk_bedrockruntime::Client;
+use rusty_bedrock_lib::amazon_nova::text::json::InferenceConfig;
+use rusty_bedrock_lib::chat::{self, ChatConfig, ChatError, ChatMessage, ChatPart, ChatResponse};
+use rusty_bedrock_lib::rate_limit::RateLimiter;
+use rusty_bedrock_lib::tokens::{self, Keep};
+use rusty_bedrock_lib::{file, metrics, Latency, ModelNotFound};
+use serde::Serialize;
+use tokio::sync::watch;
+
+use crate::out;
+
+/// How long the prompt in flight has to finish after Ctrl-C
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The exit code for a batch stopped with Ctrl-C, as for SIGINT
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Settings shared by every prompt in the batch
+pub struct Batch {
+    pub client: Client,
+    pub model: String,
+    /// --system prompts
+    pub system: Vec<String>,
+    pub prefill: Option<String>,
+    pub latency: Latency,
+    /// --inference-config and --stop, checked against the model's ranges
+    pub inference_config: InferenceConfig,
+    /// --text and --attach parts, sent after each prompt
+    pub parts: Vec<ChatPart>,
+    /// --max-input-tokens, cutting each prompt down as --keep says
+    pub max_input_tokens: Option<usize>,
+    pub keep: Keep,
+    /// Appends a `Record` per prompt to this file
+    pub output_jsonl: Option<String>,
+    /// --rpm pacing
+    pub limiter: Option<Arc<RateLimiter>>,
+    /// --out template for each response, see `out::path`
+    pub out: Option<String>,
+    /// Don't print responses to stdout
+    pub quiet: bool,
+}
+
+/// One line of --output-jsonl
+#[derive(Serialize, Debug)]
+struct Record {
+    prompt: String,
+    text: Option<String>,
+    trace_id: Option<String>,
+    input_tokens: Option<u32>,
+    output_tokens: Option<u32>,
+    latency_ms: u128,
+    stop_reason: Option<String>,
+    /// The model a prompt router picked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    invoked_model_id: Option<String>,
+    error: Option<String>,
+}
+
+/// How a batch ended
+pub struct Finished {
+    /// For --metrics-file
+    pub usage: metrics::Run,
+    /// Whether Ctrl-C stopped it before the last prompt
+    pub interrupted: bool,
+}
+
+/// Runs the prompts (one per non-empty line of `filename`), printing each response.
+///
+/// A mistyped model id fails every prompt the same way, so it ends the batch as
+/// `ModelNotFound` instead.
+pub async fn run(batch: Batch, filename: &str) -> Result<Finished, ModelNotFound> {
+    let prompts = String::from_utf8(file::read(filename))
+        .unwrap_or_else(|err| panic!("{} isn't utf-8: {}", filename, err));
+    let prompts = prompts
+        .lines()
+        .enumerate()
+        .map(|(line, prompt)| (line + 1, prompt.trim()))
+        .filter(|(_, prompt)| !prompt.is_empty())
+        .collect::<Vec<_>>();
+
+    // opened for appending and flushed per line, so a crash leaves a valid partial file
+    let mut jsonl = batch.output_jsonl.as_deref().map(file::open_append);
+    let mut usage = metrics::Run::default();
+    let mut failed = 0;
+    let interrupt = on_interrupt();
+
+    for (idx, (line, prompt)) in prompts.iter().enumerate() {
+        if *interrupt.borrow() {
+            return Ok(interrupted(
+                usage,
+                idx,
+                prompts.len(),
+                failed,
+                filename,
+                *line,
+            ));
+        }
+        println!("-- {}/{}: {} --", idx + 1, prompts.len(), prompt);
+        if let Some(limiter) = &batch.limiter {
+            tokio::select! {
+                _ = limiter.acquire() => {}
+                _ = interrupted_for(interrupt.clone(), Duration::ZERO) => {
+                    return Ok(interrupted(usage, idx, prompts.len(), failed, filename, *line));
+                }
+            }
+        }
+        let start = Instant::now();
+        let result = tokio::select! {
+            result = send(&batch, prompt) => result,
+            _ = interrupted_for(interrupt.clone(), DRAIN_TIMEOUT) => {
+                eprintln!("-- no response {}s after Ctrl-C, not recorded --", DRAIN_TIMEOUT.as_secs());
+                return Ok(interrupted(usage, idx, prompts.len(), failed, filename, *line));
+            }
+        };
+        let latency = start.elapsed();
+        let latency_ms = latency.as_millis();
+        usage.record(latency, result.as_ref().ok());
+
+        let record = match result {
+            Ok(response) => {
+                if let Some(template) = &batch.out {
+                    let path = out::path(template, response.trace_id.as_ref(), Some(idx + 1));
+                    if let Err(err) = file::write_string(&path, response.text.clone()) {
+                        eprintln!("couldn't write {}: {}", path, err);
+                        std::process::exit(1);
+                    }
+                }
+                if !batch.quiet {
+                    println!("{}", response.text);
+                }
+                Record {
+                    prompt: prompt.to_string(),
+                    text: Some(response.text),
+                    trace_id: Some(response.trace_id.to_string()),
+                    input_tokens: Some(response.input_tokens),
+                    output_tokens: Some(response.output_tokens),
+                    latency_ms,
+                    stop_reason: Some(response.stop_reason),
+                    invoked_model_id: response.invoked_model_id,
+                    error: None,
+                }
+            }
+            Err(ChatError::ModelNotFound(not_found)) => return Err(not_found),
+            Err(error) => {
+                let error = error.to_string();
+                failed += 1;
+                eprintln!("failed: {}", error);
+                Record {
+                    prompt: prompt.to_string(),
+                    text: None,
+                    trace_id: None,
+                    input_tokens: None,
+                    output_tokens: None,
+                    latency_ms,
+                    stop_reason: None,
+                    invoked_model_id: None,
+                    error: Some(error),
+                }
+            }
+        };
+        println!();
+
+        if let Some(jsonl) = jsonl.as_mut() {
+            let line = serde_json::to_string(&record).unwrap();
+            writeln!(jsonl, "{}", line)
+                .and_then(|_| jsonl.flush())
+                .unwrap_or_else(|err| panic!("couldn't write --output-jsonl: {}", err));
+        }
+    }
+    Ok(Finished {
+        usage,
+        interrupted: false,
+    })
+}
+
+/// Watches for Ctrl-C: the first sets the flag, and a second exits.
+fn on_interrupt() -> watch::Receiver<bool> {
+    let (interrupt, interrupted) = watch::channel(false);
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        eprintln!("-- Ctrl-C: finishing the prompt in flight, Ctrl-C again to exit now --");
+        let _ = interrupt.send(true);
+        let _ = tokio::signal::ctrl_c().await;
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    });
+    interrupted
+}
+
+/// Completes `grace` after Ctrl-C (and never without one).
+async fn interrupted_for(mut interrupt: watch::Receiver<bool>, grace: Duration) {
+    if interrupt
+        .wait_for(|interrupted| *interrupted)
+        .await
+        .is_err()
+    {
+        std::future::pending::<()>().await;
+    }
+    tokio::time::sleep(grace).await;
+}
+
+/// Prints the summary of a batch stopped before prompt `next` (from 0), on `line`.
+fn interrupted(
+    usage: metrics::Run,
+    next: usize,
+    total: usize,
+    failed: usize,
+    filename: &str,
+    line: usize,
+) -> Finished {
+    println!(
+        "-- interrupted: {} of {} prompts run ({} failed), the rest start at line {} of {} --",
+        next, total, failed, line, filename
+    );
+    Finished {
+        usage,
+        interrupted: true,
+    }
+}
+
+/// Sends one prompt.
+async fn send(batch: &Batch, prompt: &str) -> Result<ChatResponse, ChatError> {
+    let prompt = match batch.max_input_tokens {
+        Some(max_tokens) => tokens::fit(prompt.to_string(), max_tokens, batch.keep),
+        None => prompt.to_string(),
+    };
+    let mut parts = vec![ChatPart::Text(prompt)];
+    parts.extend(batch.parts.iter().cloned());
+    let mut messages = vec![ChatMessage::user_parts(parts)];
+    if let Some(prefill) = &batch.prefill {
+        messages.push(ChatMessage::assistant(prefill));
+    }
+    let config = ChatConfig {
+        system_prompts: batch.system.clone(),
+        latency: batch.latency,
+        inference_config: batch.inference_config.clone(),
+    };
+    chat::chat(&batch.client, batch.model.clone(), messages, config).await
+}
@@ -0,0 +1,185 @@
+//! `nova --bench`: sends the same small request many times and reports its latency
+//!
+//! For capacity planning: the requests run in parallel, at most --concurrency at a time
+//! (and paced by --rpm), and the report has the p50/p90/p99 latency of the requests
+//! that succeeded, the throughput, and how many were throttled.
+//!
+//! Every request goes through the one client, so they share the SDK's retry quota
+//! (see `rate_limit`): once throttling drains it, requests fail instead of retrying,
+//! and the bench stops sending the rest rather than keep pressing the account's quota.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use aws_sdk_bedrockruntime::Client;
+use rusty_bedrock_lib::chat::{self, ChatConfig, ChatError, ChatMessage};
+use rusty_bedrock_lib::rate_limit::RateLimiter;
+use rusty_bedrock_lib::{metrics, ModelNotFound};
+use tokio::sync::Semaphore;
+
+/// Sent when --bench is given without a prompt
+pub const DEFAULT_PROMPT: &str = "Reply with just the word OK.";
+
+/// The error code of a throttled request, as it appears in its failure
+const THROTTLED: &str = "ThrottlingException";
+
+/// Output token limit for the requests, unless the request file sets one
+pub const MAX_TOKENS: u16 = 16;
+
+pub struct Bench {
+    pub client: Client,
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    pub config: ChatConfig,
+    /// Number of requests to send
+    pub requests: usize,
+    /// Maximum number of requests in flight at once
+    pub concurrency: usize,
+    /// --rpm pacing
+    pub limiter: Option<Arc<RateLimiter>>,
+}
+
+/// How a request ended
+enum Outcome {
+    Ok(Duration),
+    Throttled,
+    Failed(String),
+    /// Not sent, after throttling stopped the bench
+    Skipped,
+}
+
+/// Runs the bench and prints its report, returning its usage for --metrics-file.
+pub async fn run(bench: Bench) -> Result<metrics::Run, ModelNotFound> {
+    let permits = Arc::new(Semaphore::new(bench.concurrency.max(1)));
+    let stop = Arc::new(AtomicBool::new(false));
+    let retries_before = metrics::retries();
+    let start = Instant::now();
+
+    let tasks = (0..bench.requests)
+        .map(|_| {
+            let permits = permits.clone();
+            let stop = stop.clone();
+            let limiter = bench.limiter.clone();
+            let client = bench.client.clone();
+            let model = bench.model.clone();
+            let messages = bench.messages.clone();
+            let config = bench.config.clone();
+            tokio::spawn(async move {
+                let _permit = permits.acquire().await.unwrap();
+                if let Some(limiter) = limiter {
+                    limiter.acquire().await;
+                }
+                if stop.load(Ordering::Relaxed) {
+                    return None;
+                }
+                let start = Instant::now();
+                let result = chat::chat(&client, model, messages, config).await;
+                if matches!(&result, Err(error) if error.to_string().contains(THROTTLED)) {
+                    stop.store(true, Ordering::Relaxed);
+                }
+                Some((start.elapsed(), result))
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut usage = metrics::Run::default();
+    let mut outcomes = vec![];
+    for task in tasks {
+        let outcome = match task.await.unwrap() {
+            None => Outcome::Skipped,
+            Some((latency, Ok(response))) => {
+                usage.record(latency, Some(&response));
+                Outcome::Ok(latency)
+            }
+            Some((_, Err(ChatError::ModelNotFound(not_found)))) => return Err(not_found),
+            Some((latency, Err(error))) => {
+                usage.record(latency, None);
+                let error = error.to_string();
+                match error.contains(THROTTLED) {
+                    true => Outcome::Throttled,
+                    false => Outcome::Failed(error),
+                }
+            }
+        };
+        outcomes.push(outcome);
+    }
+    let elapsed = start.elapsed();
+
+    let mut latencies = outcomes
+        .iter()
+        .filter_map(|outcome| match outcome {
+            Outcome::Ok(latency) => Some(*latency),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    latencies.sort();
+    let count = |matches: fn(&Outcome) -> bool| outcomes.iter().filter(|o| matches(o)).count();
+    let throttled = count(|o| matches!(o, Outcome::Throttled));
+    let failed = count(|o| matches!(o, Outcome::Failed(_)));
+    let skipped = count(|o| matches!(o, Outcome::Skipped));
+    let sent = outcomes.len() - skipped;
+
+    println!(
+        "-- {} requests to {}, {} at a time, in {:.1}s --",
+        sent,
+        bench.model,
+        bench.concurrency.max(1),
+        elapsed.as_secs_f64()
+    );
+    match latencies.is_empty() {
+        true => println!("latency: no requests succeeded"),
+        false => println!(
+            "latency: p50 {}  p90 {}  p99 {}",
+            millis(percentile(&latencies, 50)),
+            millis(percentile(&latencies, 90)),
+            millis(percentile(&latencies, 99)),
+        ),
+    }
+    println!(
+        "throughput: {:.2} requests/s",
+        latencies.len() as f64 / elapsed.as_secs_f64()
+    );
+    println!(
+        "throttled: {} ({:.1}%), other errors: {}, SDK retries: {}",
+        throttled,
+        100.0 * throttled as f64 / sent.max(1) as f64,
+        failed,
+        metrics::retries() - retries_before
+    );
+    if skipped > 0 {
+        println!(
+            "-- stopped after the SDK gave up retrying, {} requests not sent --",
+            skipped
+        );
+    }
+    for error in outcomes.iter().filter_map(|outcome| match outcome {
+        Outcome::Failed(error) => Some(error),
+        _ => None,
+    }) {
+        eprintln!("failed: {}", error);
+    }
+    Ok(usage)
+}
+
+/// The nearest-rank percentile of sorted latencies
+fn percentile(sorted: &[Duration], p: usize) -> Duration {
+    let rank = (sorted.len() * p).div_ceil(100).max(1);
+    sorted[rank.min(sorted.len()) - 1]
+}
+
+fn millis(latency: Duration) -> String {
+    format!("{}ms", latency.as_millis())
+}
+
+#[test]
+fn latency_percentiles() {
+    let latencies = (1..=200).map(Duration::from_millis).collect::<Vec<_>>();
+    assert_eq!(Duration::from_millis(100), percentile(&latencies, 50));
+    assert_eq!(Duration::from_millis(180), percentile(&latencies, 90));
+    assert_eq!(Duration::from_millis(198), percentile(&latencies, 99));
+
+    let one = [Duration::from_millis(7)];
+    assert_eq!(one[0], percentile(&one, 50));
+    assert_eq!(one[0], percentile(&one, 99));
+}
@@ -0,0 +1,87 @@
+//! `nova --compare`: sends the same prompt to two models and diffs their answers
+//!
+//! Both requests run at once, and each answer prints labeled with its model, its token
+//! usage, and its latency, followed by a line diff from the first model's answer to
+//! the second's.
+
+use std::time::{Duration, Instant};
+
+use aws_sdk_bedrockruntime::Client;
+use rusty_bedrock_lib::chat::{self, ChatConfig, ChatError, ChatMessage, ChatResponse};
+use rusty_bedrock_lib::{diff, metrics, ModelNotFound};
+
+pub struct Compare {
+    pub client: Client,
+    /// --model
+    pub model_a: String,
+    /// --compare
+    pub model_b: String,
+    pub messages: Vec<ChatMessage>,
+    pub config: ChatConfig,
+}
+
+/// Runs the comparison, returning each model's usage for --metrics-file.
+pub async fn run(compare: Compare) -> Result<[(String, metrics::Run); 2], ModelNotFound> {
+    let tasks = [compare.model_a, compare.model_b].map(|model| {
+        let client = compare.client.clone();
+        let messages = compare.messages.clone();
+        let config = compare.config.clone();
+        let task = tokio::spawn({
+            let model = model.clone();
+            async move {
+                let start = Instant::now();
+                let result = chat::chat(&client, model, messages, config).await;
+                (start.elapsed(), result)
+            }
+        });
+        (model, task)
+    });
+
+    let mut answers = vec![];
+    let mut usage = vec![];
+    for (model, task) in tasks {
+        let mut run = metrics::Run::default();
+        println!("-- {} --", model);
+        let (latency, result) = task.await.unwrap();
+        match result {
+            Ok(response) => {
+                run.record(latency, Some(&response));
+                println!("{}", response.text);
+                println!("-- {} --", summary(&response, latency));
+                answers.push(Some(response.text));
+            }
+            Err(ChatError::ModelNotFound(not_found)) => return Err(not_found),
+            Err(error) => {
+                run.record(latency, None);
+                eprintln!("failed: {}", error);
+                answers.push(None);
+            }
+        }
+        println!();
+        usage.push((model, run));
+    }
+
+    if let [Some(a), Some(b)] = &answers[..] {
+        match a == b {
+            true => println!("-- the answers match --"),
+            false => {
+                println!("-- diff (- {}, + {}) --", usage[0].0, usage[1].0);
+                for line in diff::lines(a, b) {
+                    println!("{}", line);
+                }
+            }
+        }
+    }
+    let [a, b]: [_; 2] = usage.try_into().unwrap();
+    Ok([a, b])
+}
+
+/// e.g. "120 input, 45 output tokens in 1.52s"
+fn summary(response: &ChatResponse, latency: Duration) -> String {
+    format!(
+        "{} input, {} output tokens in {:.2}s",
+        response.input_tokens,
+        response.output_tokens,
+        latency.as_secs_f64()
+    )
+}
@@ -1,9 +1,33 @@
+mod batch;
+mod bench;
+mod compare;
+mod out;
+mod sweep;
+
+use std::io::IsTerminal;
+use std::sync::Arc;
+use std::time::Instant;
+
 use clap::Parser;
-use rusty_bedrock_lib::{file::FileReference, nova};
+use rusty_bedrock_lib::amazon_nova::text::json::InferenceConfig;
+use rusty_bedrock_lib::chat::{self, ChatBackend, ChatConfig, ChatError, ChatMessage, ChatPart};
+use rusty_bedrock_lib::cli::{ClientArgs, RetryArgs};
+use rusty_bedrock_lib::converse::modalities::{
+    self, AttachmentLimitError, UnsupportedModality, DEFAULT_MAX_ATTACHMENTS,
+};
+use rusty_bedrock_lib::logging::{self, LogFormat};
+use rusty_bedrock_lib::metrics::{self, MetricsError};
+use rusty_bedrock_lib::rate_limit::RateLimiter;
+use rusty_bedrock_lib::tokens::Keep;
+use rusty_bedrock_lib::{
+    file, inference, markdown, pager, progress, recording, redact, request_file, tags, tokens,
+    ClientOptions, Geo, Latency, ModelNotFound,
+};
 
 /// Invokes Amazon's Nova family of text models on Bedrock
 ///
 /// Creative content models (Canvas and Reel) are not supported by this tool.
+/// Non-Nova models passed via --model are sent through bedrock:Converse instead.
 ///
 /// For more information on Amazon Nova, read the user guide:
 ///     https://docs.aws.amazon.com/nova/latest/userguide/
@@ -24,8 +48,8 @@ struct CliArgs {
     /// AWS region and credentials are selected in the following sequence:
     ///
     /// 1/ Explicit Override:
-    ///     When this --profile option is specified, the named profile will be read from
-    ///     ~/.aws/config and ~/.aws/credentials.
+    ///     When this --profile option (or BEDROCK_PROFILE) is specified, the named profile
+    ///     will be read from ~/.aws/config and ~/.aws/credentials.
     ///
     /// 2/ Environment Variables, as described here:
     ///
@@ -39,16 +63,52 @@ struct CliArgs {
     ///     https://docs.aws.amazon.com/sdkref/latest/guide/file-format.html
     ///     https://docs.aws.amazon.com/sdk-for-rust/latest/dg/region.html
     ///     https://docs.aws.amazon.com/sdk-for-rust/latest/dg/credproviders.html
-    #[clap(long, verbatim_doc_comment)]
+    #[clap(long, env = "BEDROCK_PROFILE", verbatim_doc_comment)]
     aws_profile: Option<String>,
 
+    /// AWS region override, e.g. us-west-2
+    ///
+    /// Takes precedence over the region from --aws-profile or the environment.
+    #[clap(long, env = "BEDROCK_REGION")]
+    region: Option<String>,
+
+    #[clap(flatten)]
+    retry: RetryArgs,
+
+    #[clap(flatten)]
+    client: ClientArgs,
+
     /// prints request/response detail
     #[clap(short, long)]
     verbose: bool,
 
+    /// Log format on stderr: plain, or json (one object per line, for log collectors)
+    #[clap(long, arg_enum, default_value = "plain")]
+    log_format: LogFormat,
+
+    /// With --verbose, log request/response bodies in full
+    ///
+    /// By default base64 data is cut down to its length, and secret-like text (AWS
+    /// access key ids, api keys, private keys) is masked.
+    #[clap(long)]
+    no_redact: bool,
+
+    /// Save each response into this directory, keyed by a hash of the request
+    ///
+    /// Replay them later with --replay, e.g. for demos or tests without AWS access.
+    #[clap(long, conflicts_with = "replay")]
+    record: Option<String>,
+
+    /// Answer from responses saved by --record instead of calling Bedrock
+    ///
+    /// Only Nova models (which use InvokeModel) are recorded and replayed.
+    #[clap(long)]
+    replay: Option<String>,
+
     /// System prompt.
     ///
-    /// Provides a system prompt for the model.
+    /// Provides a system prompt for the model.  May be repeated to layer instructions
+    /// (e.g. a persona, then format rules), each sent as its own system prompt block.
     ///
     /// See:
     ///     https://docs.aws.amazon.com/bedrock/latest/userguide/prompt-management-create.
@@ -56,7 +116,7 @@ struct CliArgs {
     ///     https://www.regie.ai/blog/user-prompts-vs-system-prompts
     ///     https://docs.aws.amazon.com/bedrock/latest/userguide/prompt-management-create
     #[clap(short, long, verbatim_doc_comment)]
-    system: Option<String>,
+    system: Vec<String>,
 
     /// The model to use.  Default: us.amazon.nova-lite-v1:0
     ///
@@ -76,6 +136,12 @@ struct CliArgs {
     ///
     /// Not all models support all modalities (e.g. micro doesn't accept image/video input).
     ///
+    /// Full ARNs are passed through unmodified, including application inference profiles
+    /// (e.g. for cost tracking), which are sent through bedrock:Converse:
+    ///     arn:aws:bedrock:us-east-1:123456789012:application-inference-profile/a1b2c3d4e5f6
+    /// Imported (Custom Model Import) models are too; whether they accept Converse
+    /// requests depends on how they were built.  An ARN must be in the --region used.
+    ///
     /// For more information, visit:
     ///
     ///     https://docs.aws.amazon.com/nova/latest/userguide/
@@ -83,21 +149,67 @@ struct CliArgs {
     #[clap(
         short,
         long,
+        env = "BEDROCK_MODEL",
         default_value = "us.amazon.nova-lite-v1:0",
         verbatim_doc_comment
     )]
     model: String,
 
+    /// Inference profile geography: us, eu, or apac
+    ///
+    /// Geo-prefixed model ids (like the us.amazon.nova-lite-v1:0 default) are switched to
+    /// this geography's profile, e.g. eu.amazon.nova-lite-v1:0.  Defaults to the
+    /// geography of the resolved region.  Plain model ids and ARNs aren't changed.
+    #[clap(long)]
+    geo: Option<Geo>,
+
+    /// Latency setting: optimized or standard
+    ///
+    /// Optimized latency is only available for some models (e.g. Nova Pro), and is
+    /// ignored with a warning for others.
+    ///
+    /// See:
+    ///     https://docs.aws.amazon.com/bedrock/latest/userguide/latency-optimized-inference.html
+    #[clap(long, default_value = "standard", verbatim_doc_comment)]
+    latency: Latency,
+
+    /// Render the model's markdown output (headings, lists, highlighted code blocks)
+    ///
+    /// Falls back to raw text when stdout isn't a terminal or NO_COLOR is set.
+    #[clap(long)]
+    pretty: bool,
+
+    /// Page responses taller than the terminal through $PAGER (less -R when unset)
+    ///
+    /// Only when stdout is a terminal.
+    #[clap(long)]
+    pager: bool,
+
+    /// Hide the content of this XML-like tag in the printed answer, e.g. thinking for
+    /// <thinking>...</thinking> (repeat for more tags)
+    ///
+    /// For prompts that have the model reason before answering.  The tags are kept in the
+    /// conversation, only the output shown is stripped.
+    #[clap(long, value_name = "TAG")]
+    strip_tags: Vec<String>,
+
     /// Lists Amazon-provided models
     ///
     /// Useful if you want to try another model and need it's model-id or inference-profile-id
     #[clap(short, long)]
     list: bool,
 
+    /// Prints the JSON Schema of the Nova InvokeModel request body
+    ///
+    /// Requires building with the `schema` feature.
+    #[clap(long)]
+    schema: bool,
+
     /// Prefilled assistant response.
     ///
     /// If provided, then when this model is invoked this prompt will be sent to the model for it to use to start off its answer.
-    #[clap(short, long)]
+    /// It can't end in whitespace (Claude rejects the request), so trailing whitespace is trimmed with a warning.
+    #[clap(short, long, alias = "assistant")]
     prefill: Option<String>,
 
     /// Additional media files (images, videos) to attach as context for the model.
@@ -110,25 +222,182 @@ struct CliArgs {
     /// - Videos: mp4, mov, mkv, webm, flv, mpeg, mpg, wmv, 3gp (supports both local files and S3 locations via s3://)
     ///
     /// Note: S3 locations (s3://) are only supported for video files.
+    /// Note: Append @format to override the extension, e.g. --attach ~/screenshot@png
     #[clap(short, long)]
     attach: Vec<String>,
 
+    /// Additional text content, placed in order among the --attach files.
+    ///
+    /// Lets a prompt refer to attachments one by one, e.g.
+    ///     nova "compare these" --text "before:" --attach a.png --text "after:" --attach b.png
+    /// The positional prompt, if any, always comes first.
+    #[clap(long)]
+    text: Vec<String>,
+
+    /// Maximum number of --attach files, checked before anything is sent
+    ///
+    /// The total size of attachments is also checked against Bedrock's 20MB request limit.
+    #[clap(long, default_value_t = DEFAULT_MAX_ATTACHMENTS)]
+    max_attachments: usize,
+
+    /// Exit when an attachment is a type (image, video) the model doesn't take as input
+    ///
+    /// Otherwise it's a warning.  Input types come from ListFoundationModels, so models
+    /// it doesn't list (e.g. ARNs) aren't checked.
+    #[clap(long)]
+    strict_modalities: bool,
+
+    /// Cut the prompt down to about this many tokens before sending, with a warning
+    ///
+    /// For pasted text too long for the model, and applied to each --batch prompt.  Tokens
+    /// are estimated at 4 characters each, and --keep picks the part of the prompt kept.
+    #[clap(long)]
+    max_input_tokens: Option<usize>,
+
+    /// With --max-input-tokens, keep the head (beginning) or tail (end) of the prompt
+    #[clap(long, arg_enum, default_value = "head", requires = "max-input-tokens")]
+    keep: Keep,
+
+    /// Run each line of this file as its own prompt, one after another
+    ///
+    /// The other args (--system, --prefill, --text, --attach, ...) apply to every prompt.
+    /// Failed prompts are reported without stopping the batch.  Ctrl-C stops it after
+    /// the prompt in flight (waiting up to a minute for it), and prints the line the rest
+    /// start at.
+    #[clap(long, conflicts_with = "prompt")]
+    batch: Option<String>,
+
+    /// With --batch, append a json line per prompt to this file
+    ///
+    /// Each line has the prompt, text, trace_id, input_tokens, output_tokens, latency_ms,
+    /// stop_reason, and error (for failed prompts).  Lines are flushed as they're written,
+    /// so an interrupted batch still leaves a valid file.
+    #[clap(long, requires = "batch")]
+    output_jsonl: Option<String>,
+
+    /// Also write the response text to this file
+    ///
+    /// The path may contain {trace} (the trace id), {date} (today, UTC, as YYYY-MM-DD),
+    /// and with --batch {index} (the prompt's number, from 1), which --batch requires.
+    ///     e.g. --out "answers/{date}-{index}.md"
+    #[clap(long, conflicts_with = "temperature-sweep", verbatim_doc_comment)]
+    out: Option<String>,
+
+    /// Don't print the response to stdout (with --out)
+    #[clap(short, long, requires = "out")]
+    quiet: bool,
+
+    /// Stop generating when the model outputs this text (may be repeated)
+    ///
+    /// The count is checked against the model's limit (e.g. 4 for Nova) before sending.
+    #[clap(long)]
+    stop: Vec<String>,
+
+    /// Run the prompt once per temperature and print the responses labeled by temperature
+    ///
+    /// e.g. --temperature-sweep 0.0,0.5,1.0
+    #[clap(long, use_value_delimiter = true, conflicts_with = "batch")]
+    temperature_sweep: Vec<f32>,
+
+    /// Send the prompt this many times and report latency, throughput, and throttling
+    ///
+    /// For capacity planning: requests run --concurrency at a time (and at most --rpm),
+    /// with a small output limit, and the report has the p50/p90/p99 latency.  Without
+    /// a prompt, a short one is sent.  Requests share the SDK's retry quota, and the
+    /// bench stops once a request fails throttled after retries.
+    #[clap(long, value_name = "REQUESTS", conflicts_with_all = &["batch", "temperature-sweep", "out"])]
+    bench: Option<usize>,
+
+    /// Also send the prompt to this model, and diff its answer with --model's
+    ///
+    /// Both requests are sent at once, with the same system prompt and inference
+    /// config.  Each answer is printed with its token usage and latency, then a line
+    /// diff (- for --model, + for this model).  The model is resolved like --model.
+    #[clap(long, value_name = "MODEL", conflicts_with_all = &["batch", "temperature-sweep", "bench", "out"])]
+    compare: Option<String>,
+
+    /// Maximum number of --temperature-sweep or --bench requests sent at once
+    #[clap(long, default_value = "4")]
+    concurrency: usize,
+
+    /// Target requests per minute for --batch, --temperature-sweep, and --bench
+    ///
+    /// Requests are spaced evenly to stay under the account's quota, rather than each
+    /// being throttled and retried on its own.
+    #[clap(long)]
+    rpm: Option<u32>,
+
+    /// Add the run's usage to this file, in Prometheus text format
+    ///
+    /// Requests, errors, input and output tokens, SDK retries, and latency are added to
+    /// the totals already in the file, labeled by model, e.g. for node_exporter's
+    /// textfile collector (--metrics-file /var/lib/node_exporter/bedrock.prom).
+    #[clap(long)]
+    metrics_file: Option<String>,
+
+    /// Read the model, prompts, attachments, and inference config from a json file
+    ///
+    /// e.g. {"model": "us.amazon.nova-pro-v1:0", "prompt": "List the typos.",
+    ///       "attachments": ["draft.md"], "inference_config": {"temperature": 0.2}}
+    /// Fields: model, system, prompt, prefill, attachments, and inference_config
+    /// (max_tokens, temperature, top_p, top_k, stop_sequences).  Args given on the
+    /// command line take precedence, and --attach files are added after the file's.
+    #[clap(long, conflicts_with_all = &["batch", "temperature-sweep"], verbatim_doc_comment)]
+    request_file: Option<String>,
+
+    /// Read the inference config from a json file, e.g. a shared creative.json
+    ///
+    /// e.g. {"max_tokens": 2000, "temperature": 0.9, "top_p": 0.95}
+    /// Fields: max_tokens, temperature, top_p, top_k, and stop_sequences.  They take
+    /// precedence over --request-file's inference_config, and --stop and
+    /// --temperature-sweep over them.
+    #[clap(long, verbatim_doc_comment)]
+    inference_config: Option<String>,
+
+    /// Prompt text, or - to read it from stdin (to the end of input)
+    ///
+    /// With a prompt argument too, this text follows it in the same prompt, e.g.
+    ///     generate_data | nova --prompt - "Summarize:"
+    /// sends "Summarize:", a blank line, and the data.  An empty stdin is an error.
+    #[clap(
+        long = "prompt",
+        value_name = "PROMPT",
+        conflicts_with = "batch",
+        verbatim_doc_comment
+    )]
+    prompt_input: Option<String>,
+
     /// User prompt.
     ///
     /// The actual user prompt.
-    prompt: String,
+    #[clap(required_unless_present_any = &["list", "schema", "text", "batch", "request-file", "bench", "prompt-input"])]
+    prompt: Option<String>,
 }
 
 // #[async_std::main]
 #[tokio::main]
 async fn main() {
-    let cli = CliArgs::parse();
+    let (mut cli, matches): (CliArgs, _) = rusty_bedrock_lib::cli::parse_or_complete_with_matches();
 
-    let verbosity = if cli.verbose { 3 } else { 2 };
-    stderrlog::new().verbosity(verbosity).init().unwrap();
+    logging::init(cli.verbose, cli.log_format);
+    redact::set_enabled(!cli.no_redact);
+    pager::set_enabled(cli.pager);
+    tags::set_stripped(cli.strip_tags.clone());
+
+    recording::set_mode(recording::Mode::from_args(
+        cli.record.clone(),
+        cli.replay.clone(),
+    ));
+
+    let options = ClientOptions {
+        aws_profile: cli.aws_profile.clone(),
+        region: cli.region.clone(),
+        retry: cli.retry.options(),
+        ..cli.client.options()
+    };
 
     if cli.list {
-        let cpclient = rusty_bedrock_lib::new_controlplane_client(cli.aws_profile.clone()).await;
+        let cpclient = rusty_bedrock_lib::new_controlplane_client(&options).await;
         let list = rusty_bedrock_lib::list_models(&cpclient, Some("Amazon".to_string())).await;
         for item in list {
             println!("{}", item);
@@ -136,19 +405,297 @@ async fn main() {
         return;
     }
 
-    let client = rusty_bedrock_lib::new_runtime_client(cli.aws_profile).await;
+    if cli.schema {
+        print_schema();
+        return;
+    }
+
+    let mut inference_config = InferenceConfig::default();
+    let mut file_attachments = vec![];
+    if let Some(path) = &cli.request_file {
+        let request = request_file::load(path).unwrap_or_else(|err| {
+            eprintln!("{}", err.0);
+            std::process::exit(1);
+        });
+        let model_given = rusty_bedrock_lib::cli::from_command_line(&matches, "model");
+        cli.model = request_file::merge(model_given, cli.model, request.model);
+        if cli.system.is_empty() {
+            cli.system.extend(request.system);
+        }
+        cli.prefill = cli.prefill.or(request.prefill);
+        cli.prompt = cli.prompt.or(request.prompt);
+        cli.attach.splice(0..0, request.attachments.iter().cloned());
+        file_attachments = request.attachments;
+        inference_config = request.inference_config.to_nova();
+    }
+    if let Some(input) = cli.prompt_input.take() {
+        let input = match input.as_str() {
+            "-" => {
+                if std::io::stdin().is_terminal() {
+                    eprintln!("reading the prompt from stdin, end it with Ctrl-D");
+                }
+                rusty_bedrock_lib::cli::read_prompt(std::io::stdin().lock()).unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                })
+            }
+            _ => input,
+        };
+        cli.prompt = Some(match cli.prompt.take() {
+            Some(prompt) => format!("{}\n\n{}", prompt, input),
+            None => input,
+        });
+    }
+    cli.prefill = cli.prefill.and_then(chat::trim_prefill);
+    if let Some(path) = &cli.inference_config {
+        let file_config = request_file::load_inference_config(path).unwrap_or_else(|err| {
+            eprintln!("{}", err.0);
+            std::process::exit(1);
+        });
+        file_config.apply_to(&mut inference_config);
+    }
+
+    if let Err(AttachmentLimitError(msg)) =
+        modalities::check_attachments(&cli.attach, cli.max_attachments)
+    {
+        eprintln!("Too many or too large attachments: {}", msg);
+        std::process::exit(1);
+    }
+
+    let client = rusty_bedrock_lib::new_runtime_client(&options).await;
+    let region = client.config().region().map(|region| region.to_string());
+    cli.model =
+        rusty_bedrock_lib::profile_for_region(&options, region.as_deref(), &cli.model, cli.geo)
+            .await
+            .unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            });
+
+    logging::set_field("model", cli.model.clone());
+    if let Some(model_b) = cli.compare {
+        let resolved =
+            rusty_bedrock_lib::profile_for_region(&options, region.as_deref(), &model_b, cli.geo)
+                .await
+                .unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                });
+        cli.compare = Some(resolved);
+    }
+
+    if !cli.stop.is_empty() {
+        inference_config.stop_sequences = cli.stop.clone();
+    }
+    if cli.bench.is_some() {
+        inference_config.max_new_tokens =
+            inference_config.max_new_tokens.or(Some(bench::MAX_TOKENS));
+        if cli.prompt.is_none() && !matches.is_present("text") {
+            cli.prompt = Some(bench::DEFAULT_PROMPT.to_string());
+        }
+    }
+    inference::check_or_exit(&cli.model, &inference_config);
+    if let Some(model_b) = &cli.compare {
+        inference::check_or_exit(model_b, &inference_config);
+    }
+
+    if !cli.attach.is_empty() {
+        let inputs = rusty_bedrock_lib::model_inputs(&options, &cli.model).await;
+        if let Some(Err(UnsupportedModality(msg))) =
+            inputs.map(|inputs| modalities::check_modalities(&cli.model, &inputs, &cli.attach))
+        {
+            if cli.strict_modalities {
+                eprintln!("{}", msg);
+                std::process::exit(1);
+            }
+            log::warn!("{}", msg);
+        }
+    }
+
+    if let Some(max_tokens) = cli.max_input_tokens {
+        cli.prompt = cli
+            .prompt
+            .map(|prompt| tokens::fit(prompt, max_tokens, cli.keep));
+    }
+    let mut parts: Vec<ChatPart> = cli.prompt.into_iter().map(ChatPart::Text).collect();
+    parts.extend(file_attachments.into_iter().map(ChatPart::Attachment));
+    for (id, value) in rusty_bedrock_lib::cli::in_order(&matches, &["text", "attach"]) {
+        parts.push(match id {
+            "text" => ChatPart::Text(value),
+            _ => ChatPart::Attachment(value),
+        });
+    }
+
+    if let Some(filename) = &cli.batch {
+        if cli.out.as_ref().is_some_and(|out| !out.contains("{index}")) {
+            eprintln!("--out needs an {{index}} placeholder with --batch, so results don't overwrite each other");
+            std::process::exit(1);
+        }
+        let batch = batch::Batch {
+            client,
+            model: cli.model.clone(),
+            system: cli.system,
+            prefill: cli.prefill,
+            latency: cli.latency,
+            inference_config,
+            parts,
+            max_input_tokens: cli.max_input_tokens,
+            keep: cli.keep,
+            output_jsonl: cli.output_jsonl,
+            limiter: cli.rpm.map(|rpm| Arc::new(RateLimiter::per_minute(rpm))),
+            out: cli.out,
+            quiet: cli.quiet,
+        };
+        match batch::run(batch, filename).await {
+            Ok(finished) => {
+                write_metrics(cli.metrics_file.as_deref(), &cli.model, &finished.usage);
+                if finished.interrupted {
+                    std::process::exit(batch::INTERRUPTED_EXIT_CODE);
+                }
+            }
+            Err(ModelNotFound(model_id)) => exit_model_not_found(&options, &model_id).await,
+        }
+        return;
+    }
+
+    let mut messages = vec![ChatMessage::user_parts(parts)];
+    if let Some(prefill) = cli.prefill {
+        messages.push(ChatMessage::assistant(prefill));
+    }
+
+    if !cli.temperature_sweep.is_empty() {
+        for temperature in &cli.temperature_sweep {
+            let config = InferenceConfig {
+                temperature: Some(*temperature),
+                ..inference_config.clone()
+            };
+            inference::check_or_exit(&cli.model, &config);
+        }
+        let sweep = sweep::Sweep {
+            client,
+            model: cli.model.clone(),
+            system: cli.system,
+            latency: cli.latency,
+            messages,
+            temperatures: cli.temperature_sweep,
+            inference_config,
+            concurrency: cli.concurrency,
+            limiter: cli.rpm.map(|rpm| Arc::new(RateLimiter::per_minute(rpm))),
+        };
+        match sweep::run(sweep).await {
+            Ok(usage) => write_metrics(cli.metrics_file.as_deref(), &cli.model, &usage),
+            Err(ModelNotFound(model_id)) => exit_model_not_found(&options, &model_id).await,
+        }
+        return;
+    }
+    let config = ChatConfig {
+        system_prompts: cli.system,
+        latency: cli.latency,
+        inference_config,
+    };
+    if let Some(model_b) = cli.compare {
+        let compare = compare::Compare {
+            client,
+            model_a: cli.model,
+            model_b,
+            messages,
+            config,
+        };
+        match compare::run(compare).await {
+            Ok(usage) => {
+                for (model, usage) in &usage {
+                    write_metrics(cli.metrics_file.as_deref(), model, usage);
+                }
+            }
+            Err(ModelNotFound(model_id)) => exit_model_not_found(&options, &model_id).await,
+        }
+        return;
+    }
+    if let Some(requests) = cli.bench {
+        let bench = bench::Bench {
+            client,
+            model: cli.model.clone(),
+            messages,
+            config,
+            requests,
+            concurrency: cli.concurrency,
+            limiter: cli.rpm.map(|rpm| Arc::new(RateLimiter::per_minute(rpm))),
+        };
+        match bench::run(bench).await {
+            Ok(usage) => write_metrics(cli.metrics_file.as_deref(), &cli.model, &usage),
+            Err(ModelNotFound(model_id)) => exit_model_not_found(&options, &model_id).await,
+        }
+        return;
+    }
+    if cli.replay.is_some() && ChatBackend::for_model(&cli.model) == ChatBackend::Converse {
+        log::warn!(
+            "{} uses Converse, which isn't replayed; calling Bedrock",
+            cli.model
+        );
+    }
+    // the spinner would interleave with --verbose logs
+    let spinner = !cli.quiet && !cli.verbose;
+    let label = format!("waiting for {}", cli.model);
+    let model = cli.model.clone();
+    let start = Instant::now();
+    let response = chat::chat(&client, cli.model, messages, config);
+    let result = match progress::spin(spinner, &label, response).await {
+        Ok(result) => result,
+        Err(ChatError::ModelNotFound(ModelNotFound(model_id))) => {
+            exit_model_not_found(&options, &model_id).await
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    let mut usage = metrics::Run::default();
+    usage.record(start.elapsed(), Some(&result));
+    write_metrics(cli.metrics_file.as_deref(), &model, &usage);
+
+    if let Some(template) = &cli.out {
+        let path = out::path(template, result.trace_id.as_ref(), None);
+        if let Err(err) = file::write_string(&path, result.text.clone()) {
+            eprintln!("couldn't write {}: {}", path, err);
+            std::process::exit(1);
+        }
+    }
+    if let Some(model_id) = &result.invoked_model_id {
+        log::info!("routed to {}", model_id);
+    }
+    if !cli.quiet {
+        markdown::print(&result.text, cli.pretty);
+    }
+}
+
+/// Adds the run's usage to --metrics-file, if given.  A failure to write it is logged,
+/// since the run itself succeeded.
+fn write_metrics(path: Option<&str>, model: &str, usage: &metrics::Run) {
+    if let Some(path) = path {
+        if let Err(MetricsError(msg)) = metrics::write(path, model, usage) {
+            log::error!("{}", msg);
+        }
+    }
+}
+
+/// Reports the mistyped model id, with the closest matches, and exits.
+async fn exit_model_not_found(options: &ClientOptions, model_id: &str) -> ! {
+    eprintln!("Model not found: {}", model_id);
+    let cpclient = rusty_bedrock_lib::new_controlplane_client(options).await;
+    if let Some(hint) = rusty_bedrock_lib::did_you_mean(&cpclient, model_id).await {
+        eprintln!("{}", hint);
+    }
+    std::process::exit(1);
+}
 
-    let attachments: Vec<FileReference> = cli.attach.into_iter().map(|s| s.into()).collect();
-    let result = nova::text::invoke_model(
-        &client,
-        cli.model,
-        None,
-        attachments,
-        cli.system,
-        cli.prefill,
-        cli.prompt,
-    )
-    .await;
+#[cfg(feature = "schema")]
+fn print_schema() {
+    let schema = rusty_bedrock_lib::amazon_nova::text::json::request_schema();
+    println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+}
 
-    println!("{}", result.1);
+#[cfg(not(feature = "schema"))]
+fn print_schema() {
+    eprintln!("nova was built without the schema feature, rebuild with --features schema");
+    std::process::exit(1);
 }
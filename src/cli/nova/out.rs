@@ -0,0 +1,39 @@
+//! `nova --out <template>`: where to write the response text
+//!
+//! Templates may contain `{trace}` (the request's trace id), `{date}` (today, UTC, as
+//! YYYY-MM-DD), and with --batch `{index}` (the prompt's line number among the
+//! prompts, counting from 1), e.g. `answers/{date}-{index}.md`.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusty_bedrock_lib::logging::civil_from_days;
+
+/// Fills in the template's placeholders.
+pub fn path(template: &str, trace: &str, index: Option<usize>) -> String {
+    let mut path = template
+        .replace("{trace}", trace)
+        .replace("{date}", &today());
+    if let Some(index) = index {
+        path = path.replace("{index}", &index.to_string());
+    }
+    path
+}
+
+fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[test]
+fn templates() {
+    assert_eq!(
+        "out/abc-3.md",
+        path("out/{trace}-{index}.md", "abc", Some(3))
+    );
+    assert_eq!("{index}.md", path("{index}.md", "abc", None));
+    assert_eq!(10, path("{date}", "abc", None).len());
+}
@@ -0,0 +1,84 @@
+//! `nova --temperature-sweep`: runs the same prompt at several temperatures
+//!
+//! The requests run in parallel, at most --concurrency at a time (and paced by --rpm),
+//! and the responses are printed in the order the temperatures were given, each
+//! labeled with its temperature.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use aws_sdk_bedrockruntime::Client;
+use rusty_bedrock_lib::amazon_nova::text::json::InferenceConfig;
+use rusty_bedrock_lib::chat::{self, ChatConfig, ChatError, ChatMessage};
+use rusty_bedrock_lib::rate_limit::RateLimiter;
+use rusty_bedrock_lib::{metrics, Latency, ModelNotFound};
+use tokio::sync::Semaphore;
+
+pub struct Sweep {
+    pub client: Client,
+    pub model: String,
+    /// --system prompts
+    pub system: Vec<String>,
+    pub latency: Latency,
+    pub messages: Vec<ChatMessage>,
+    pub temperatures: Vec<f32>,
+    /// --inference-config and --stop, with each temperature set in turn
+    pub inference_config: InferenceConfig,
+    /// Maximum number of requests in flight at once
+    pub concurrency: usize,
+    /// --rpm pacing
+    pub limiter: Option<Arc<RateLimiter>>,
+}
+
+/// Runs the sweep, returning its usage for --metrics-file.
+pub async fn run(sweep: Sweep) -> Result<metrics::Run, ModelNotFound> {
+    let permits = Arc::new(Semaphore::new(sweep.concurrency.max(1)));
+
+    let tasks = sweep
+        .temperatures
+        .iter()
+        .map(|temperature| {
+            let permits = permits.clone();
+            let limiter = sweep.limiter.clone();
+            let client = sweep.client.clone();
+            let model = sweep.model.clone();
+            let messages = sweep.messages.clone();
+            let config = ChatConfig {
+                system_prompts: sweep.system.clone(),
+                latency: sweep.latency,
+                inference_config: InferenceConfig {
+                    temperature: Some(*temperature),
+                    ..sweep.inference_config.clone()
+                },
+            };
+            tokio::spawn(async move {
+                let _permit = permits.acquire().await.unwrap();
+                if let Some(limiter) = limiter {
+                    limiter.acquire().await;
+                }
+                let start = Instant::now();
+                let result = chat::chat(&client, model, messages, config).await;
+                (start.elapsed(), result)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut usage = metrics::Run::default();
+    for (temperature, task) in sweep.temperatures.iter().zip(tasks) {
+        println!("-- temperature {} --", temperature);
+        let (latency, result) = task.await.unwrap();
+        match result {
+            Ok(response) => {
+                usage.record(latency, Some(&response));
+                println!("{}", response.text)
+            }
+            Err(ChatError::ModelNotFound(not_found)) => return Err(not_found),
+            Err(error) => {
+                usage.record(latency, None);
+                eprintln!("failed: {}", error)
+            }
+        }
+        println!();
+    }
+    Ok(usage)
+}
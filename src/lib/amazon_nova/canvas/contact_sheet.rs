@@ -0,0 +1,74 @@
+//! Composes several generated images into one grid image (a contact sheet)
+//!
+//! Handy for eyeballing a batch of generations without opening each file.  Requires the
+//! `image` feature.
+
+use std::io::Cursor;
+
+use image::{imageops, ImageError, ImageFormat, Rgba, RgbaImage};
+
+/// Gap between (and around) cells, in pixels
+const GUTTER: u32 = 8;
+
+/// Lays the images out left to right, top to bottom, `columns` to a row, returning a PNG.
+///
+/// Each cell is as large as the largest image, with smaller images centered in their
+/// cell.  With `columns` of None, the grid is as close to square as possible.
+pub fn contact_sheet(images: &[Vec<u8>], columns: Option<u32>) -> Result<Vec<u8>, ImageError> {
+    let decoded = images
+        .iter()
+        .map(|bytes| image::load_from_memory(bytes).map(|image| image.to_rgba8()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let count = decoded.len() as u32;
+    let columns = columns
+        .unwrap_or_else(|| (count as f64).sqrt().ceil() as u32)
+        .clamp(1, count.max(1));
+    let rows = count.div_ceil(columns);
+
+    let cell_width = decoded.iter().map(|image| image.width()).max().unwrap_or(0);
+    let cell_height = decoded
+        .iter()
+        .map(|image| image.height())
+        .max()
+        .unwrap_or(0);
+
+    let mut sheet = RgbaImage::from_pixel(
+        columns * (cell_width + GUTTER) + GUTTER,
+        rows * (cell_height + GUTTER) + GUTTER,
+        Rgba([255, 255, 255, 255]),
+    );
+    for (idx, image) in decoded.iter().enumerate() {
+        let (column, row) = (idx as u32 % columns, idx as u32 / columns);
+        let x = GUTTER + column * (cell_width + GUTTER) + (cell_width - image.width()) / 2;
+        let y = GUTTER + row * (cell_height + GUTTER) + (cell_height - image.height()) / 2;
+        imageops::overlay(&mut sheet, image, x.into(), y.into());
+    }
+
+    let mut png = Cursor::new(vec![]);
+    sheet.write_to(&mut png, ImageFormat::Png)?;
+    Ok(png.into_inner())
+}
+
+#[test]
+fn grid_layout() {
+    let png = |width, height| {
+        let mut bytes = Cursor::new(vec![]);
+        RgbaImage::new(width, height)
+            .write_to(&mut bytes, ImageFormat::Png)
+            .unwrap();
+        bytes.into_inner()
+    };
+    let images = vec![png(10, 10), png(20, 5), png(5, 5)];
+
+    let sheet = image::load_from_memory(&contact_sheet(&images, Some(2)).unwrap()).unwrap();
+    assert_eq!(2 * (20 + GUTTER) + GUTTER, sheet.width());
+    assert_eq!(2 * (10 + GUTTER) + GUTTER, sheet.height());
+
+    // defaults to as square as possible, 2x2 for 3 images
+    let sheet = image::load_from_memory(&contact_sheet(&images, None).unwrap()).unwrap();
+    assert_eq!(2 * (20 + GUTTER) + GUTTER, sheet.width());
+
+    let sheet = image::load_from_memory(&contact_sheet(&images, Some(5)).unwrap()).unwrap();
+    assert_eq!(3 * (20 + GUTTER) + GUTTER, sheet.width());
+}
@@ -41,11 +41,17 @@ pub struct TextToImageParams {
     pub negative_text: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-pub struct ImageGenerationConfig;
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageGenerationConfig {
+    /// 1 to 5 (default: 1)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub number_of_images: Option<u8>,
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CanvasResponse {
+    #[serde(default)]
     pub images: Vec<String>,
     pub error: Option<String>,
 }
@@ -0,0 +1,135 @@
+//! Embeds how an image was generated in the PNG itself (`--embed-metadata`)
+//!
+//! The prompt, negative prompt, model id, and trace id are added as PNG text chunks,
+//! which image viewers and tools like `exiftool` show, so a generated asset's
+//! parameters can be recovered wherever it ends up.  Text that fits Latin-1 goes in a
+//! tEXt chunk, and anything else in an iTXt chunk (utf-8), as tEXt can't hold it.
+//! Canvas only returns PNGs, so there's no EXIF (JPEG) path.  Requires the `image`
+//! feature.
+//!
+//! See: https://www.w3.org/TR/png/#11textinfo
+
+const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+#[derive(Debug)]
+pub struct NotPng(pub String);
+
+/// The generation parameters, as (keyword, text) pairs for `embed`
+pub fn provenance(
+    prompt: &str,
+    negative_prompt: Option<&str>,
+    trace_id: &str,
+) -> Vec<(&'static str, String)> {
+    let mut fields = vec![("Prompt", prompt.to_string())];
+    if let Some(negative) = negative_prompt {
+        fields.push(("Negative prompt", negative.to_string()));
+    }
+    fields.push(("Model", super::MODEL_ID.to_string()));
+    fields.push(("Trace id", trace_id.to_string()));
+    fields
+}
+
+/// The PNG with a text chunk for each (keyword, text) pair, after its header.
+pub fn embed(png: &[u8], fields: &[(&str, String)]) -> Result<Vec<u8>, NotPng> {
+    // the IHDR chunk comes first: 4 bytes of length, then its type, data, and crc
+    let ihdr_end = match png.strip_prefix(SIGNATURE) {
+        Some(rest) if rest.get(4..8) == Some(b"IHDR") => {
+            let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            SIGNATURE.len() + 12 + length
+        }
+        _ => return Err(NotPng("no PNG signature and header".to_string())),
+    };
+    if png.len() < ihdr_end {
+        return Err(NotPng("the PNG header is cut short".to_string()));
+    }
+
+    let mut out = png[..ihdr_end].to_vec();
+    for (keyword, text) in fields {
+        out.extend(text_chunk(keyword, text));
+    }
+    out.extend_from_slice(&png[ihdr_end..]);
+    Ok(out)
+}
+
+/// A tEXt chunk when the text is Latin-1, else an uncompressed iTXt chunk.
+fn text_chunk(keyword: &str, text: &str) -> Vec<u8> {
+    let latin1 = text
+        .chars()
+        .map(|c| u8::try_from(u32::from(c)).ok())
+        .collect::<Option<Vec<u8>>>();
+    let (kind, data) = match latin1 {
+        Some(text) => (b"tEXt", [keyword.as_bytes(), &[0], &text].concat()),
+        // keyword, then no compression, no language tag, and no translated keyword
+        None => (
+            b"iTXt",
+            [keyword.as_bytes(), &[0, 0, 0, 0, 0], text.as_bytes()].concat(),
+        ),
+    };
+    chunk(kind, &data)
+}
+
+fn chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    [
+        &(data.len() as u32).to_be_bytes()[..],
+        kind,
+        data,
+        &crc.finalize().to_be_bytes(),
+    ]
+    .concat()
+}
+
+#[test]
+fn embedded_metadata() {
+    use image::{ImageFormat, RgbaImage};
+    use std::io::Cursor;
+
+    let mut png = Cursor::new(vec![]);
+    RgbaImage::new(2, 2)
+        .write_to(&mut png, ImageFormat::Png)
+        .unwrap();
+    let png = png.into_inner();
+
+    let fields = provenance("a café at dusk, 夕方", Some("blur"), "abc-123");
+    let tagged = embed(&png, &fields).unwrap();
+
+    // still a valid image (the decoder checks each chunk's crc), the same one
+    let decoded = image::load_from_memory(&tagged).unwrap();
+    assert_eq!((2, 2), (decoded.width(), decoded.height()));
+
+    // and its text chunks read back
+    let mut text = vec![];
+    let mut rest = &tagged[SIGNATURE.len()..];
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let (kind, data) = (&rest[4..8], &rest[8..8 + length]);
+        let keyword = |data: &[u8]| {
+            let end = data.iter().position(|b| *b == 0).unwrap();
+            (String::from_utf8(data[..end].to_vec()).unwrap(), end + 1)
+        };
+        match kind {
+            b"tEXt" => {
+                let (keyword, start) = keyword(data);
+                text.push((keyword, data[start..].iter().map(|b| *b as char).collect()));
+            }
+            b"iTXt" => {
+                let (keyword, start) = keyword(data);
+                text.push((
+                    keyword,
+                    String::from_utf8(data[start + 4..].to_vec()).unwrap(),
+                ));
+            }
+            _ => {}
+        }
+        rest = &rest[12 + length..];
+    }
+    let has = |keyword: &str, value: &str| text.contains(&(keyword.to_string(), value.to_string()));
+    assert!(has("Prompt", "a café at dusk, 夕方"));
+    assert!(has("Negative prompt", "blur"));
+    assert!(has("Model", "amazon.nova-canvas-v1:0"));
+    assert!(has("Trace id", "abc-123"));
+
+    assert!(embed(b"GIF89a", &fields).is_err());
+}
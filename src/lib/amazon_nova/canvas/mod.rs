@@ -1,68 +1,326 @@
+use std::fmt::Display;
+
+use aws_sdk_bedrockruntime::config::http::HttpResponse;
+use aws_sdk_bedrockruntime::error::SdkError;
+use aws_sdk_bedrockruntime::operation::invoke_model::InvokeModelError;
 use aws_sdk_bedrockruntime::operation::RequestId;
-use json::{CanvasRequest, CanvasResponse, TextToImageParams};
-use log::debug;
+use json::{CanvasRequest, CanvasResponse, ImageGenerationConfig, TextToImageParams};
+use log::{debug, warn};
 
-use crate::{file::Base64Encoding, TraceId};
+use crate::{file::Base64Encoding, recording, redact, retry, TraceId};
 
+#[cfg(feature = "image")]
+pub mod contact_sheet;
+#[cfg(feature = "image")]
+pub mod metadata;
 pub mod json;
 
 static MODEL_ID: &str = "amazon.nova-canvas-v1:0";
 
-pub async fn text_to_image(
-    client: &aws_sdk_bedrockruntime::Client,
+#[derive(Debug)]
+pub enum CanvasError {
+    /// The InvokeModel call failed
+    Service(Box<SdkError<InvokeModelError, HttpResponse>>),
+    /// The response body wasn't the expected json, or an image wasn't valid base64
+    Deserialization(String),
+    /// The model reported an error and returned no images
+    Model(String),
+}
+impl Display for CanvasError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CanvasError::Service(err) if crate::is_credentials_error(err) => {
+                f.write_str(&crate::credentials_hint())
+            }
+            CanvasError::Service(err) => write!(f, "InvokeModelError:\n{:#?}", err),
+            CanvasError::Deserialization(msg) => write!(f, "malformed response: {}", msg),
+            CanvasError::Model(error) => write!(f, "InvokeModelOutput.error:\n{}", error),
+        }
+    }
+}
+impl std::error::Error for CanvasError {}
+
+/// Weights outside this range are rejected
+pub const MIN_WEIGHT: f32 = 0.1;
+pub const MAX_WEIGHT: f32 = 2.0;
+
+/// One part of a prompt, with how much it matters relative to the others
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeightedPrompt {
+    pub text: String,
+    pub weight: f32,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct InvalidWeight(pub String);
+impl Display for InvalidWeight {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+impl std::error::Error for InvalidWeight {}
+
+/// Parses `text:weight`, e.g. "cat:1.2".  Without a numeric suffix the weight is 1, and
+/// a whole number right after a digit (e.g. "aspect 16:9", "at 10:30") is part of the
+/// text, so a ratio or time needs an explicit weight: "aspect 16:9:1".
+impl std::str::FromStr for WeightedPrompt {
+    type Err = InvalidWeight;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let weighted = spec.rsplit_once(':').and_then(|(text, weight)| {
+            let ratio = text.ends_with(|c: char| c.is_ascii_digit())
+                && weight.chars().all(|c| c.is_ascii_digit());
+            match ratio {
+                true => None,
+                false => Some((text, weight.trim().parse::<f32>().ok()?)),
+            }
+        });
+        let (text, weight) = weighted.unwrap_or((spec, 1.0));
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(InvalidWeight(format!("{:?} has no prompt text", spec)));
+        }
+        if !(MIN_WEIGHT..=MAX_WEIGHT).contains(&weight) {
+            return Err(InvalidWeight(format!(
+                "{:?} has weight {}, outside {} to {}",
+                spec, weight, MIN_WEIGHT, MAX_WEIGHT
+            )));
+        }
+        Ok(WeightedPrompt {
+            text: text.to_string(),
+            weight,
+        })
+    }
+}
+
+/// Composes weighted parts into one Canvas prompt.
+///
+/// Parts weighted other than 1 are written in the common `(text:weight)` emphasis
+/// syntax, e.g. "(cat:1.2)", and plain parts as is.  Canvas gives the start of a prompt
+/// the most attention, so the parts are also joined heaviest first (keeping the given
+/// order among equal weights), as a comma separated caption.
+pub fn compose(prompts: &[WeightedPrompt]) -> String {
+    let mut prompts = prompts.iter().collect::<Vec<_>>();
+    prompts.sort_by(|a, b| b.weight.total_cmp(&a.weight));
+    prompts
+        .iter()
+        .map(|prompt| match prompt.weight == 1.0 {
+            true => prompt.text.clone(),
+            false => format!("({}:{})", prompt.text, prompt.weight),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn text_to_image_request(
     prompt: String,
     negative_prompt: Option<String>,
-) -> (TraceId, Vec<Base64Encoding>) {
+    config: Option<ImageGenerationConfig>,
+) -> CanvasRequest {
     let params = TextToImageParams {
         text: prompt,
         negative_text: negative_prompt.unwrap_or_default(),
     };
 
-    let request = CanvasRequest {
+    CanvasRequest {
         task_type: "TEXT_IMAGE".to_owned(),
         text_to_image_params: params,
-        image_generation_config: None,
+        image_generation_config: config,
+    }
+}
+
+pub async fn text_to_image(
+    client: &aws_sdk_bedrockruntime::Client,
+    prompt: String,
+    negative_prompt: Option<String>,
+    config: Option<ImageGenerationConfig>,
+) -> Result<(TraceId, Vec<Base64Encoding>), CanvasError> {
+    let request = text_to_image_request(prompt, negative_prompt, config);
+
+    debug!("model-id: {}", MODEL_ID);
+    debug!("{}", redact::json(&request.to_string()));
+
+    let request_body = request.to_string();
+    let (trace_id, body) = match recording::replay(MODEL_ID, request_body.as_bytes()) {
+        Some(body) => (TraceId("REPLAY".to_string()), body),
+        None => {
+            // https://docs.rs/aws-sdk-bedrockruntime/latest/aws_sdk_bedrockruntime/struct.Client.html#method.invoke_model
+            let invoke = client
+                .invoke_model()
+                .content_type("application/json")
+                .accept("application/json")
+                .model_id(MODEL_ID)
+                .body(request_body.clone().into_bytes().into());
+            let result = retry::with_backoff(
+                || invoke.clone().send(),
+                &retry::Policy::MODEL_TIMEOUT,
+                crate::is_model_timeout,
+            )
+            .await;
+
+            let result = result.map_err(|err| CanvasError::Service(Box::new(err)))?;
+            debug!("{}", redact::debug(&result));
+
+            let trace_id = TraceId(result.request_id().unwrap_or("UNKNOWN").to_string());
+            let body = result.body.into_inner();
+            recording::record(MODEL_ID, request_body.as_bytes(), &body);
+            (trace_id, body)
+        }
     };
 
+    let body = String::from_utf8_lossy(&body).to_string();
+    debug!("{}", redact::json(&body));
+
+    let rsp: CanvasResponse = serde_json::from_str(&body)
+        .map_err(|err| CanvasError::Deserialization(format!("\nbody: {:?} \nerr:{}", body, err)))?;
+
+    let images = images_or_error(rsp)?;
+
+    Ok((
+        trace_id,
+        images.into_iter().map(Base64Encoding::new).collect(),
+    ))
+}
+
+/// Keeps any images returned alongside an error, failing only when there are none.
+fn images_or_error(rsp: CanvasResponse) -> Result<Vec<String>, CanvasError> {
+    match rsp.error {
+        Some(error) if rsp.images.is_empty() => Err(CanvasError::Model(error)),
+        Some(error) => {
+            warn!(
+                "Canvas returned {} image(s) along with an error: {}",
+                rsp.images.len(),
+                error
+            );
+            Ok(rsp.images)
+        }
+        None => Ok(rsp.images),
+    }
+}
+
+/// Like `text_to_image`, but asks Bedrock for the raw image bytes (`accept: image/png`)
+/// instead of a base64 json body, which avoids the base64 bloat.
+///
+/// If the model rejects the binary accept type, or answers with json anyway, this falls
+/// back to the json/base64 path and decodes the images, so callers always get bytes.
+/// A binary response holds a single image, so requests for several also use json.
+pub async fn text_to_image_binary(
+    client: &aws_sdk_bedrockruntime::Client,
+    prompt: String,
+    negative_prompt: Option<String>,
+    config: Option<ImageGenerationConfig>,
+) -> Result<(TraceId, Vec<Vec<u8>>), CanvasError> {
+    // recordings are of json bodies, so record and replay go through the json path
+    // as do requests for several images
+    let several = config
+        .as_ref()
+        .and_then(|config| config.number_of_images)
+        .is_some_and(|count| count > 1);
+    if several || !matches!(recording::mode(), recording::Mode::Live) {
+        return decode_all(text_to_image(client, prompt, negative_prompt, config).await?);
+    }
+
+    let request = text_to_image_request(prompt.clone(), negative_prompt.clone(), config.clone());
+
     debug!("model-id: {}", MODEL_ID);
-    debug!("{}", request.to_string());
+    debug!("{}", redact::json(&request.to_string()));
 
-    // https://docs.rs/aws-sdk-bedrockruntime/latest/aws_sdk_bedrockruntime/struct.Client.html#method.invoke_model
-    let result = client
+    let invoke = client
         .invoke_model()
         .content_type("application/json")
-        .accept("application/json")
+        .accept("image/png")
         .model_id(MODEL_ID)
-        .body(request.to_string().into_bytes().into())
-        .send()
-        .await;
+        .body(request.to_string().into_bytes().into());
+    let result = retry::with_backoff(
+        || invoke.clone().send(),
+        &retry::Policy::MODEL_TIMEOUT,
+        crate::is_model_timeout,
+    )
+    .await;
 
-    // Process the results, pretty printing the output
     match result {
+        Ok(result) if result.content_type() == "image/png" => {
+            debug!("{}", redact::debug(&result));
+            let trace_id: TraceId = TraceId(result.request_id().unwrap_or("UNKNOWN").to_string());
+            Ok((trace_id, vec![result.body.into_inner()]))
+        }
         Ok(result) => {
-            let body_vec = result.body.as_ref().to_owned();
-            let body = String::from_utf8(body_vec).unwrap();
+            warn!(
+                "Binary output not returned (content-type: {}), falling back to json",
+                result.content_type()
+            );
+            decode_all(text_to_image(client, prompt, negative_prompt, config).await?)
+        }
+        Err(SdkError::ServiceError(err))
+            if matches!(err.err(), InvokeModelError::ValidationException(_)) =>
+        {
+            warn!(
+                "Binary output not supported, falling back to json: {:?}",
+                err.err()
+            );
+            decode_all(text_to_image(client, prompt, negative_prompt, config).await?)
+        }
+        Err(err) => Err(CanvasError::Service(Box::new(err))),
+    }
+}
 
-            debug!("{:?}", result);
-            debug!("{} ... {}", &body[0..50], &body[body.len() - 50..]);
+fn decode_all(
+    (trace_id, images): (TraceId, Vec<Base64Encoding>),
+) -> Result<(TraceId, Vec<Vec<u8>>), CanvasError> {
+    let images = images
+        .into_iter()
+        .map(|image| {
+            image
+                .try_decode()
+                .map_err(|err| CanvasError::Deserialization(format!("bad image base64: {}", err)))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((trace_id, images))
+}
 
-            let rsp: CanvasResponse = serde_json::from_str(&body)
-                .unwrap_or_else(|err| panic!("malformed json: \nbody: {:?} \nerr:{}", body, err));
+#[test]
+fn partial_recovery() {
+    let rsp: CanvasResponse =
+        serde_json::from_str(r#"{"images": ["aGk="], "error": "1 of 2 images blocked"}"#).unwrap();
+    assert_eq!(vec!["aGk=".to_string()], images_or_error(rsp).unwrap());
 
-            if let Some(error) = rsp.error {
-                panic!("InvokeModelOutput.error:\n{}", error);
-            }
+    let rsp: CanvasResponse = serde_json::from_str(r#"{"error": "blocked"}"#).unwrap();
+    assert!(matches!(images_or_error(rsp), Err(CanvasError::Model(msg)) if msg == "blocked"));
 
-            let trace_id: TraceId = TraceId(result.request_id().unwrap_or("UNKNOWN").to_string());
+    let rsp: CanvasResponse = serde_json::from_str(r#"{"images": [], "error": null}"#).unwrap();
+    assert!(images_or_error(rsp).unwrap().is_empty());
+}
 
-            (
-                trace_id,
-                rsp.images
-                    .into_iter()
-                    .map(Base64Encoding::new)
-                    .collect(),
-            )
-        }
-        Err(result) => panic!("InvokeModelError:\n{:#?}", result),
-    }
+#[test]
+fn weighted_prompts() {
+    let parse = |spec: &str| spec.parse::<WeightedPrompt>();
+    assert_eq!(
+        Ok(WeightedPrompt {
+            text: "cat".to_string(),
+            weight: 1.2
+        }),
+        parse("cat:1.2")
+    );
+    assert_eq!(1.0, parse("a lake at dawn").unwrap().weight);
+    assert_eq!("ratio: wide", parse("ratio: wide").unwrap().text);
+    assert_eq!("aspect 16:9", parse("aspect 16:9").unwrap().text);
+    assert_eq!(
+        Ok(WeightedPrompt {
+            text: "aspect 16:9".to_string(),
+            weight: 0.5
+        }),
+        parse("aspect 16:9:0.5")
+    );
+    assert!(parse("cat:3").is_err());
+    assert!(parse("cat:0").is_err());
+    assert!(parse(":1.5").is_err());
+
+    let prompts = ["sunset:0.8", "cat:1.2", "watercolor", "beach:0.8"]
+        .map(|spec| parse(spec).unwrap());
+    assert_eq!(
+        "(cat:1.2), watercolor, (sunset:0.8), (beach:0.8)",
+        compose(&prompts)
+    );
+    let weighted = |spec: &str| compose(&[parse(spec).unwrap()]);
+    assert_ne!(weighted("cat:1.2"), weighted("cat:1.9"));
 }
@@ -21,16 +21,17 @@ use std::fmt::Display;
 
 use serde::{Deserialize, Serialize};
 
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TextRequest {
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub system: Vec<SystemPrompt>,
 
     /// First message in the list MUST have a user role, and then they alternate from
     /// there (if calling Converse).  
     pub messages: Vec<Message>,
 
-    #[serde(rename = "inferenceConfig")]
+    #[serde(rename = "inferenceConfig", default)]
     #[serde(skip_serializing_if = "InferenceConfig::is_empty")]
     pub inference_config: InferenceConfig,
     // toolConfig: ToolConfig, // TODO
@@ -42,17 +43,20 @@ impl Display for TextRequest {
     }
 }
 
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SystemPrompt {
     pub text: String,
 }
 
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Message {
     pub role: Role,
     pub content: Vec<Content>,
 }
 
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum Role {
@@ -60,31 +64,46 @@ pub enum Role {
     Assistant,
 }
 
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "lowercase")]
 pub enum Content {
     Text(String),
     Image(Image),
     Video(Video),
+    #[serde(rename = "cachePoint")]
+    CachePoint(CachePoint),
 }
 
+/// Marks the end of a prompt prefix to cache, `{"cachePoint": {"type": "default"}}`
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CachePoint {
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Image {
     pub format: String,
     pub source: ImageSource,
 }
 
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ImageSource {
     pub bytes: String,
 }
 
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Video {
     pub format: String,
     pub source: VideoSource,
 }
 
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Serialize, Deserialize, Debug)]
 pub enum VideoSource {
     #[serde(rename = "s3Location")]
@@ -93,6 +112,7 @@ pub enum VideoSource {
     Bytes(String),
 }
 
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[derive(Serialize, Deserialize, Debug)]
 pub struct S3Location {
     pub uri: String,
@@ -101,20 +121,21 @@ pub struct S3Location {
     // pub bucket_owner: String,
 }
 
-// TODO make this configurable via CLI args
-#[derive(Serialize, Deserialize, Debug, Default)]
+// Valid ranges differ by model, see `inference::params_for`
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct InferenceConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub max_new_tokens: Option<u16>, // greater than 0, equal or less than 5k (default: dynamic*)
+    pub max_new_tokens: Option<u16>, // (default: dynamic*)
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub temperature: Option<f32>, // greater then 0 and less than 1.0 (default: 0.7)
+    pub temperature: Option<f32>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub top_p: Option<f32>, // greater than 0, equal or less than 1.0 (default: 0.9)
+    pub top_p: Option<f32>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub top_k: Option<u32>, // 0 or greater (default: 50)
+    pub top_k: Option<u32>,
 
     #[serde(rename = "stopSequences")]
     #[serde(skip_serializing_if = "Vec::is_empty")]
@@ -131,6 +152,13 @@ impl InferenceConfig {
     }
 }
 
+/// JSON Schema for the `TextRequest` body, so downstream tools can validate request
+/// bodies or generate forms from it.
+#[cfg(feature = "schema")]
+pub fn request_schema() -> serde_json::Value {
+    serde_json::to_value(schemars::schema_for!(TextRequest)).unwrap()
+}
+
 /// Can't find any documented response schema, so this is reverse engieered from a sample:
 ///
 /// ```text
@@ -199,3 +227,13 @@ fn video_encoding() {
 
     println!("s3\n{}", serde_json::to_string_pretty(&video2).unwrap());
 }
+
+#[cfg(feature = "schema")]
+#[test]
+fn schema() {
+    let schema = request_schema();
+    assert_eq!("TextRequest", schema["title"]);
+    let required = schema["required"].as_array().unwrap();
+    assert!(required.contains(&"messages".into()));
+    assert!(schema["properties"]["inferenceConfig"].is_object());
+}
@@ -1,9 +1,11 @@
 use aws_sdk_bedrockruntime::operation::RequestId;
 use json::InferenceConfig;
-use log::debug;
+use log::{debug, warn};
 
+use crate::converse::modalities::{self, InvalidPath};
 use crate::file::{self, FileReference};
-use crate::TraceId;
+use crate::{recording, redact, retry};
+use crate::{Latency, ModelNotFound, TraceId};
 
 pub mod json;
 
@@ -12,7 +14,7 @@ pub async fn invoke_model(
     model_id: String,
     inference_config: Option<InferenceConfig>,
     attachments: Vec<FileReference>,
-    system_prompt: Option<String>,
+    system_prompts: Vec<String>,
     assistant_prefill: Option<String>,
     user_prompt: String,
 ) -> (TraceId, String) {
@@ -29,33 +31,7 @@ pub async fn invoke_model(
 
     // add media attachments
     for attachment in attachments {
-        match (attachment.file_type, attachment.location) {
-            (file::Type::Image, file::Location::Local) => {
-                let base64 = file::read_base64(&attachment.path);
-                user_content.push(json::Content::Image(json::Image {
-                    format: attachment.extension.0,
-                    source: json::ImageSource {
-                        bytes: base64.unwrap(),
-                    },
-                }));
-            }
-            (file::Type::Video, file::Location::Local) => {
-                let base64 = file::read_base64(&attachment.path);
-                user_content.push(json::Content::Video(json::Video {
-                    format: attachment.extension.0,
-                    source: json::VideoSource::Bytes(base64.unwrap()),
-                }));
-            }
-            (file::Type::Video, file::Location::S3) => {
-                user_content.push(json::Content::Video(json::Video {
-                    format: attachment.extension.0,
-                    source: json::VideoSource::S3Location(json::S3Location {
-                        uri: attachment.path,
-                    }),
-                }));
-            }
-            _ => panic!("Unsupported file type: {}", attachment.path),
-        }
+        user_content.push(attachment_content(attachment).unwrap_or_else(|err| panic!("{}", err.0)));
     }
 
     // add now-complete user_content to messages
@@ -70,7 +46,7 @@ pub async fn invoke_model(
     //
     // https://www.walturn.com/insights/mastering-prompt-engineering-for-claude
     // --------------
-    if let Some(prefill) = assistant_prefill {
+    if let Some(prefill) = assistant_prefill.and_then(crate::chat::trim_prefill) {
         messages.push(json::Message {
             role: json::Role::Assistant,
             content: vec![json::Content::Text(prefill)],
@@ -78,15 +54,165 @@ pub async fn invoke_model(
     }
 
     // ===============
-    // The system prompt.  Optional.
+    // The system prompts.  Optional, and each is sent as its own block (e.g. a persona,
+    // then format rules).
     //
     // https://www.walturn.com/insights/mastering-prompt-engineering-for-claude
     // ===============
-    let mut system = vec![];
-    if let Some(text) = system_prompt {
-        system.push(json::SystemPrompt { text });
+    let system = system_prompts
+        .into_iter()
+        .map(|text| json::SystemPrompt { text })
+        .collect();
+
+    // built above starting with the user's message, so only the model id can be wrong
+    invoke_messages(
+        client,
+        model_id,
+        inference_config,
+        Latency::Standard,
+        system,
+        messages,
+        false,
+    )
+    .await
+    .unwrap_or_else(|err| panic!("{}", err))
+}
+
+#[derive(Debug)]
+pub struct InvalidMessages(pub String);
+
+/// Why `invoke_messages` returned without a response
+#[derive(Debug)]
+pub enum InvokeMessagesError {
+    /// The model id doesn't exist
+    ModelNotFound(ModelNotFound),
+    /// The messages were rejected before sending, see `check_messages`
+    InvalidMessages(InvalidMessages),
+    /// The model timed out on both tries, with `MODEL_TIMEOUT_HINT`
+    Timeout(String),
+    /// No usable AWS credentials, with `credentials_hint`
+    Credentials(String),
+    /// Any other error from Bedrock, or a response that couldn't be parsed
+    Service(String),
+}
+impl std::fmt::Display for InvokeMessagesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvokeMessagesError::ModelNotFound(ModelNotFound(model_id)) => {
+                write!(f, "model not found: {}", model_id)
+            }
+            InvokeMessagesError::InvalidMessages(InvalidMessages(msg)) => {
+                write!(f, "invalid messages: {}", msg)
+            }
+            InvokeMessagesError::Timeout(msg)
+            | InvokeMessagesError::Credentials(msg)
+            | InvokeMessagesError::Service(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+impl std::error::Error for InvokeMessagesError {}
+impl From<ModelNotFound> for InvokeMessagesError {
+    fn from(err: ModelNotFound) -> Self {
+        InvokeMessagesError::ModelNotFound(err)
+    }
+}
+impl From<InvalidMessages> for InvokeMessagesError {
+    fn from(err: InvalidMessages) -> Self {
+        InvokeMessagesError::InvalidMessages(err)
     }
+}
 
+/// Checks that the messages start with a user turn, as Bedrock requires.
+///
+/// With `drop_leading_assistant`, assistant messages before the first user message are
+/// dropped (with a warning) rather than rejected, e.g. a greeting a chat UI shows
+/// before the user has said anything.  An empty list is always an error.
+pub fn check_messages(
+    mut messages: Vec<json::Message>,
+    drop_leading_assistant: bool,
+) -> Result<Vec<json::Message>, InvalidMessages> {
+    let leading = messages
+        .iter()
+        .take_while(|msg| msg.role == json::Role::Assistant)
+        .count();
+    if messages.is_empty() {
+        return Err(InvalidMessages(
+            "no messages: there must be at least one, starting with a user message".to_string(),
+        ));
+    }
+    if leading == messages.len() {
+        return Err(InvalidMessages(format!(
+            "all {} messages are the assistant's: they must start with a user message",
+            leading
+        )));
+    }
+    if leading > 0 && !drop_leading_assistant {
+        return Err(InvalidMessages(format!(
+            "the first message is the assistant's: Bedrock requires the user's first \
+            (remove the {} leading assistant message(s), or have them dropped)",
+            leading
+        )));
+    }
+    if leading > 0 {
+        warn!(
+            "dropping {} assistant message(s) before the first user message",
+            leading
+        );
+        messages.drain(..leading);
+    }
+    Ok(messages)
+}
+
+/// Invokes the model with a complete, caller-assembled list of messages.
+///
+/// This is the multi-turn counterpart to `invoke_model`.  The first message must have
+/// a user role, and an optional trailing assistant message acts as a prefill.  That's
+/// checked before sending, see `check_messages`: messages that don't start with the
+/// user's are returned as `InvalidMessages`, or with `drop_leading_assistant` the
+/// assistant messages before the first user message are dropped.
+///
+/// A mistyped model id is returned as `ModelNotFound`, so callers can suggest
+/// alternatives, and the other failures as the remaining `InvokeMessagesError`s.
+pub async fn invoke_messages(
+    client: &aws_sdk_bedrockruntime::Client,
+    model_id: String,
+    inference_config: Option<InferenceConfig>,
+    latency: Latency,
+    system: Vec<json::SystemPrompt>,
+    messages: Vec<json::Message>,
+    drop_leading_assistant: bool,
+) -> Result<(TraceId, String), InvokeMessagesError> {
+    let (trace_id, rsp) = invoke_messages_response(
+        client,
+        model_id.clone(),
+        inference_config,
+        latency,
+        system,
+        messages,
+        drop_leading_assistant,
+    )
+    .await?;
+    Ok((trace_id, response_text(&model_id, &rsp)))
+}
+
+/// The size of the request's body as sent, base64 attachments and all, to check against
+/// `modalities::MAX_REQUEST_BYTES` before sending.
+pub fn request_size_bytes(request: &json::TextRequest) -> usize {
+    request.to_string().len()
+}
+
+/// Like `invoke_messages`, but returns the whole response, including the stop reason
+/// and token usage.
+pub async fn invoke_messages_response(
+    client: &aws_sdk_bedrockruntime::Client,
+    model_id: String,
+    inference_config: Option<InferenceConfig>,
+    latency: Latency,
+    system: Vec<json::SystemPrompt>,
+    messages: Vec<json::Message>,
+    drop_leading_assistant: bool,
+) -> Result<(TraceId, json::Response), InvokeMessagesError> {
+    let messages = check_messages(messages, drop_leading_assistant)?;
     let request = json::TextRequest {
         system,
         messages,
@@ -94,54 +220,229 @@ pub async fn invoke_model(
     };
 
     debug!("model-id: {}", model_id);
-    debug!("{}", request.to_string());
+
+    let request_body = request.to_string();
+    debug!("{}", redact::json(&request_body));
+    modalities::warn_near_request_limit(request_body.len());
+    if let Some(body) = recording::replay(&model_id, request_body.as_bytes()) {
+        let body = String::from_utf8(body).unwrap();
+        return Ok((TraceId("REPLAY".to_string()), parse_response(&body)?));
+    }
 
     // ===============
     // Send request to Amazon Bedrock
     // https://docs.rs/aws-sdk-bedrockruntime/latest/aws_sdk_bedrockruntime/struct.Client.html#method.invoke_model
     // ===============
-    let result = client
+    let invoke = client
         .invoke_model()
         .content_type("application/json")
         .accept("application/json")
         .model_id(model_id.clone())
-        .body(request.to_string().into_bytes().into())
-        .send()
-        .await;
+        .set_performance_config_latency(latency.for_model(&model_id))
+        .body(request_body.clone().into_bytes().into());
 
+    // Retry once on a model timeout, then surface a hint about the request size
+    let result = retry::with_backoff(
+        || invoke.clone().send(),
+        &retry::Policy::MODEL_TIMEOUT,
+        crate::is_model_timeout,
+    )
+    .await;
     // Process the results, pretty printing the output
     if let Ok(value) = result {
         let body_ref = value.body.as_ref();
         let body = String::from_utf8(body_ref.to_owned()).unwrap();
 
         // printing the result will redact the contents of the body, so we print explicitly
-        debug!("{:?}", value);
-        debug!("{}", body);
+        debug!("{}", redact::debug(&value));
+        debug!("{}", redact::json(&body));
 
-        let rsp: json::Response = serde_json::from_str(body.as_str())
-            .unwrap_or_else(|err| panic!("malformed json: err: {:?}, body:{}", err, body));
-        let msg = rsp.output.message;
+        recording::record(&model_id, request_body.as_bytes(), body.as_bytes());
 
-        assert_eq!(json::Role::Assistant, msg.role);
-
-        if msg.content.len() != 1 {
-            panic!("response content didn't have single element?\n{}", body);
+        let trace_id: TraceId = TraceId(value.request_id().unwrap_or("UNKNOWN").to_string());
+        return Ok((trace_id, parse_response(&body)?));
+    }
+    match result {
+        Err(err) if crate::is_model_timeout(&err) => {
+            debug!("{:#?}", err);
+            Err(InvokeMessagesError::Timeout(
+                crate::MODEL_TIMEOUT_HINT.to_string(),
+            ))
+        }
+        Err(err) if crate::is_model_not_found(&err) => Err(ModelNotFound(model_id).into()),
+        Err(err) if crate::is_credentials_error(&err) => {
+            Err(InvokeMessagesError::Credentials(crate::credentials_hint()))
         }
+        result => Err(InvokeMessagesError::Service(format!(
+            "bad response from bedrock:\n{:#?}",
+            result
+        ))),
+    }
+}
 
-        let content = &msg.content[0];
-        match content {
-            json::Content::Text(val) => {
-                let trace_id: TraceId =
-                    TraceId(value.request_id().unwrap_or("UNKNOWN").to_string());
-                return (trace_id, val.clone());
-            }
-            json::Content::Image(_) => {
-                unimplemented!("{} doesn't support image output modality", model_id.clone())
-            }
-            json::Content::Video(_) => {
-                unimplemented!("{} doesn't support video output modality", model_id)
-            }
+fn parse_response(body: &str) -> Result<json::Response, InvokeMessagesError> {
+    serde_json::from_str(body).map_err(|err| {
+        InvokeMessagesError::Service(format!("malformed json: err: {:?}, body:{}", err, body))
+    })
+}
+
+/// Extracts the text of the assistant message from an InvokeModel response.
+pub fn response_text(model_id: &str, rsp: &json::Response) -> String {
+    let msg = &rsp.output.message;
+
+    assert_eq!(json::Role::Assistant, msg.role);
+
+    if msg.content.len() != 1 {
+        panic!(
+            "response content didn't have single element?\n{}",
+            serde_json::to_string(rsp).unwrap()
+        );
+    }
+
+    match &msg.content[0] {
+        json::Content::Text(val) => val.clone(),
+        json::Content::Image(_) => {
+            unimplemented!("{} doesn't support image output modality", model_id)
+        }
+        json::Content::Video(_) => {
+            unimplemented!("{} doesn't support video output modality", model_id)
         }
+        json::Content::CachePoint(_) => String::new(),
     }
-    panic!("bad response from bedrock:\n{:#?}", result);
+}
+
+/// Maps a file attachment to the corresponding Nova content element.
+pub fn attachment_content(attachment: FileReference) -> Result<json::Content, InvalidPath> {
+    if let Some(range) = &attachment.range {
+        return Err(InvalidPath(format!(
+            "#{} isn't supported for {}, ranges only apply to documents sent through Converse",
+            range, attachment.path
+        )));
+    }
+    let content = match (attachment.file_type, attachment.location) {
+        (file::Type::Image, file::Location::Local) => {
+            let base64 = file::read_base64(&attachment.path);
+            json::Content::Image(json::Image {
+                format: attachment.extension.0,
+                source: json::ImageSource {
+                    bytes: base64.unwrap(),
+                },
+            })
+        }
+        (file::Type::Video, file::Location::Local) => {
+            let base64 = file::read_base64(&attachment.path);
+            json::Content::Video(json::Video {
+                format: attachment.extension.0,
+                source: json::VideoSource::Bytes(base64.unwrap()),
+            })
+        }
+        (file::Type::Video, file::Location::S3) => {
+            let uri = file::s3_uri(&attachment.path).map_err(|err| InvalidPath(err.0))?;
+            json::Content::Video(json::Video {
+                format: attachment.extension.0,
+                source: json::VideoSource::S3Location(json::S3Location {
+                    uri: uri.to_string(),
+                }),
+            })
+        }
+        _ => {
+            return Err(InvalidPath(format!(
+                "Unsupported file type: {}",
+                attachment.path
+            )))
+        }
+    };
+    Ok(content)
+}
+
+#[test]
+fn request_sizes() {
+    let request = |content: Vec<json::Content>| json::TextRequest {
+        system: vec![],
+        messages: vec![json::Message {
+            role: json::Role::User,
+            content,
+        }],
+        inference_config: InferenceConfig::default(),
+    };
+    let text = request(vec![json::Content::Text("hi".to_string())]);
+    assert_eq!(
+        r#"{"messages":[{"role":"user","content":[{"text":"hi"}]}]}"#.len(),
+        request_size_bytes(&text)
+    );
+
+    let image = |bytes: usize| {
+        request(vec![json::Content::Image(json::Image {
+            format: "png".to_string(),
+            source: json::ImageSource {
+                bytes: file::Base64Encoding::encode(vec![0; bytes]).unwrap(),
+            },
+        })])
+    };
+    // 3 bytes are 4 base64 characters
+    assert_eq!(
+        400,
+        request_size_bytes(&image(600)) - request_size_bytes(&image(300))
+    );
+}
+
+#[test]
+fn message_order() {
+    let msg = |role: json::Role, text: &str| json::Message {
+        role,
+        content: vec![json::Content::Text(text.to_string())],
+    };
+    // true for the user's messages
+    let roles = |messages: &[json::Message]| {
+        messages
+            .iter()
+            .map(|msg| msg.role == json::Role::User)
+            .collect::<Vec<_>>()
+    };
+
+    assert!(check_messages(vec![], false).is_err());
+    assert!(check_messages(vec![], true).is_err());
+
+    let ordered = vec![
+        msg(json::Role::User, "hi"),
+        msg(json::Role::Assistant, "hello"),
+        msg(json::Role::User, "how are you?"),
+    ];
+    let checked = check_messages(ordered, false).unwrap();
+    assert_eq!(vec![true, false, true], roles(&checked));
+
+    let leading = || {
+        vec![
+            msg(json::Role::Assistant, "How can I help?"),
+            msg(json::Role::User, "hi"),
+        ]
+    };
+    let InvalidMessages(err) = check_messages(leading(), false).unwrap_err();
+    assert!(err.contains("assistant"), "{}", err);
+    let fixed = check_messages(leading(), true).unwrap();
+    assert_eq!(vec![true], roles(&fixed));
+
+    let only_assistant = vec![msg(json::Role::Assistant, "How can I help?")];
+    assert!(check_messages(only_assistant, true).is_err());
+
+    // which invoke_messages returns rather than sending
+    let client = aws_sdk_bedrockruntime::Client::from_conf(
+        aws_sdk_bedrockruntime::Config::builder()
+            .behavior_version(aws_sdk_bedrockruntime::config::BehaviorVersion::latest())
+            .build(),
+    );
+    let invoke = invoke_messages(
+        &client,
+        "amazon.nova-micro-v1:0".to_string(),
+        None,
+        Latency::Standard,
+        vec![],
+        leading(),
+        false,
+    );
+    let result = tokio::runtime::Runtime::new().unwrap().block_on(invoke);
+    assert!(matches!(
+        result,
+        Err(InvokeMessagesError::InvalidMessages(_))
+    ));
 }
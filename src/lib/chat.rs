@@ -0,0 +1,520 @@
+//! A single chat entry point over both InvokeModel and Converse
+//!
+//! Some models are driven through model-specific InvokeModel bodies (see `amazon_nova`),
+//! others through the normalized Converse API.  `chat` takes one message type and picks
+//! the backend for the model, so callers don't have to know which API a model wants.
+
+use aws_sdk_bedrockruntime::operation::converse::builders::ConverseFluentBuilder;
+use aws_sdk_bedrockruntime::operation::RequestId;
+use aws_sdk_bedrockruntime::types::{
+    ContentBlock, ConversationRole, ConverseOutput, InferenceConfiguration, Message,
+    PerformanceConfiguration, SystemContentBlock,
+};
+use log::{debug, warn};
+
+use crate::amazon_nova::text::{self, json};
+use crate::converse::converse_json;
+use crate::converse::modalities::{self, AttachmentPath};
+use crate::inference::{self, OutOfRange};
+use crate::{logging, redact, retry, Latency, ModelNotFound, TraceId};
+
+/// Which Bedrock API a model is driven through
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ChatBackend {
+    /// Model-specific InvokeModel body, via the serde types in `amazon_nova::text::json`
+    InvokeModel,
+    /// Bedrock's normalized Converse API
+    Converse,
+}
+impl ChatBackend {
+    /// Looks up the backend for a model or inference profile id.
+    ///
+    /// Amazon Nova text models use the InvokeModel serde path.  Everything else
+    /// (Claude, Titan, Llama, ...) goes through Converse, as do prompt routers, whose
+    /// response says which model was picked, and custom (imported) model ARNs.
+    pub fn for_model(model_id: &str) -> Self {
+        if is_prompt_router(model_id) || crate::is_custom_model(model_id) {
+            ChatBackend::Converse
+        } else if model_id.contains("amazon.nova-") && !model_id.contains("amazon.nova-canvas") {
+            ChatBackend::InvokeModel
+        } else {
+            ChatBackend::Converse
+        }
+    }
+}
+
+/// Whether the id is a prompt router ARN, which picks a model per request, e.g.
+/// arn:aws:bedrock:us-east-1:123456789012:default-prompt-router/anthropic.claude:1
+///
+/// See: https://docs.aws.amazon.com/bedrock/latest/userguide/prompt-routing.html
+pub fn is_prompt_router(model_id: &str) -> bool {
+    model_id.starts_with("arn:")
+        && (model_id.contains(":prompt-router/") || model_id.contains(":default-prompt-router/"))
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ChatRole {
+    User,
+    Assistant,
+}
+
+/// One piece of message content, kept in the order given
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ChatPart {
+    Text(String),
+    /// Attachment path (see `file::FileReference`)
+    Attachment(String),
+}
+
+/// Backend-neutral message: text and attachment parts, in order
+#[derive(Debug, Clone)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    pub parts: Vec<ChatPart>,
+}
+impl ChatMessage {
+    /// The text, followed by the attachments
+    pub fn user(text: impl ToString, attachments: Vec<String>) -> Self {
+        let mut parts = vec![ChatPart::Text(text.to_string())];
+        parts.extend(attachments.into_iter().map(ChatPart::Attachment));
+        Self::user_parts(parts)
+    }
+
+    /// Text and attachments interleaved as given, e.g. a question about each image
+    pub fn user_parts(parts: Vec<ChatPart>) -> Self {
+        Self {
+            role: ChatRole::User,
+            parts,
+        }
+    }
+
+    pub fn assistant(text: impl ToString) -> Self {
+        Self {
+            role: ChatRole::Assistant,
+            parts: vec![ChatPart::Text(text.to_string())],
+        }
+    }
+}
+
+/// The prefill without trailing whitespace, which models (e.g. Claude) reject a prefill
+/// ending in, warning when there was some.  None when only whitespace is left, since an
+/// empty assistant message is rejected too.
+pub fn trim_prefill(prefill: String) -> Option<String> {
+    let trimmed = prefill.trim_end();
+    if trimmed.is_empty() {
+        warn!("the prefill is only whitespace, leaving it out");
+        return None;
+    }
+    if trimmed.len() == prefill.len() {
+        return Some(prefill);
+    }
+    warn!(
+        "the prefill can't end in whitespace, trimmed it to {:?}",
+        trimmed
+    );
+    Some(trimmed.to_string())
+}
+
+/// The model's answer, with the metadata both backends report
+#[derive(Debug, Clone)]
+pub struct ChatResponse {
+    pub trace_id: TraceId,
+    pub text: String,
+    /// e.g. end_turn, max_tokens (the spelling varies by backend)
+    pub stop_reason: String,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    /// The model a prompt router sent the request to
+    pub invoked_model_id: Option<String>,
+}
+
+/// Why `chat` returned without a response
+#[derive(Debug)]
+pub enum ChatError {
+    /// The model id doesn't exist, see `did_you_mean`
+    ModelNotFound(ModelNotFound),
+    /// The model timed out on both tries, with `MODEL_TIMEOUT_HINT`
+    Timeout(String),
+    /// No usable AWS credentials, with `credentials_hint`
+    Credentials(String),
+    /// An attachment that can't be sent to the model
+    Attachment(String),
+    /// Any other error from Bedrock
+    Service(String),
+}
+impl std::fmt::Display for ChatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChatError::ModelNotFound(ModelNotFound(model_id)) => {
+                write!(f, "model not found: {}", model_id)
+            }
+            ChatError::Attachment(msg) => write!(f, "Unsupported attachment: {}", msg),
+            ChatError::Timeout(msg) | ChatError::Credentials(msg) | ChatError::Service(msg) => {
+                write!(f, "{}", msg)
+            }
+        }
+    }
+}
+impl std::error::Error for ChatError {}
+impl From<ModelNotFound> for ChatError {
+    fn from(err: ModelNotFound) -> Self {
+        ChatError::ModelNotFound(err)
+    }
+}
+
+/// Settings shared by both backends
+#[derive(Debug, Default, Clone)]
+pub struct ChatConfig {
+    /// Each sent as its own system prompt block
+    pub system_prompts: Vec<String>,
+    pub inference_config: json::InferenceConfig,
+    pub latency: Latency,
+}
+
+/// Sends the messages to the model over whichever backend it supports.
+///
+/// The first message must have a user role.  A trailing assistant message acts as
+/// a prefill for the response.  A mistyped model id is returned as `ModelNotFound`, so
+/// callers can suggest alternatives (see `did_you_mean`), and the other failures as
+/// the remaining `ChatError`s.
+///
+/// Inference parameters outside the model's known ranges (see `inference::check`) are
+/// warned about but still sent, in case the model has since widened them.
+pub async fn chat(
+    client: &aws_sdk_bedrockruntime::Client,
+    model_id: String,
+    messages: Vec<ChatMessage>,
+    config: ChatConfig,
+) -> Result<ChatResponse, ChatError> {
+    if let Err(OutOfRange(msg)) = inference::check(&model_id, &config.inference_config) {
+        warn!("{}", msg);
+    }
+    let backend = ChatBackend::for_model(&model_id);
+    debug!("backend: {:?}", backend);
+    let response = match backend {
+        ChatBackend::InvokeModel => chat_invoke(client, model_id, messages, config).await,
+        ChatBackend::Converse => chat_converse(client, model_id, messages, config).await,
+    }?;
+    logging::set_field("trace_id", response.trace_id.as_ref());
+    Ok(response)
+}
+
+async fn chat_invoke(
+    client: &aws_sdk_bedrockruntime::Client,
+    model_id: String,
+    messages: Vec<ChatMessage>,
+    config: ChatConfig,
+) -> Result<ChatResponse, ChatError> {
+    let messages = messages
+        .into_iter()
+        .map(|msg| {
+            let role = match msg.role {
+                ChatRole::User => json::Role::User,
+                ChatRole::Assistant => json::Role::Assistant,
+            };
+            let content = msg
+                .parts
+                .into_iter()
+                .map(|part| match part {
+                    ChatPart::Text(text) => Ok(json::Content::Text(text)),
+                    ChatPart::Attachment(path) => text::attachment_content(path.into())
+                        .map_err(|err| ChatError::Attachment(err.0)),
+                })
+                .collect::<Result<_, _>>()?;
+            Ok(json::Message { role, content })
+        })
+        .collect::<Result<_, ChatError>>()?;
+
+    let system = config
+        .system_prompts
+        .into_iter()
+        .map(|text| json::SystemPrompt { text })
+        .collect();
+
+    let (trace_id, rsp) = text::invoke_messages_response(
+        client,
+        model_id.clone(),
+        Some(config.inference_config),
+        config.latency,
+        system,
+        messages,
+        false,
+    )
+    .await
+    .map_err(|err| match err {
+        text::InvokeMessagesError::ModelNotFound(err) => ChatError::ModelNotFound(err),
+        text::InvokeMessagesError::Timeout(msg) => ChatError::Timeout(msg),
+        text::InvokeMessagesError::Credentials(msg) => ChatError::Credentials(msg),
+        // built above from the chat's messages, which start with the user's
+        text::InvokeMessagesError::InvalidMessages(_) => unreachable!("{}", err),
+        text::InvokeMessagesError::Service(msg) => ChatError::Service(msg),
+    })?;
+    Ok(ChatResponse {
+        trace_id,
+        text: text::response_text(&model_id, &rsp),
+        stop_reason: rsp.stop_reason,
+        input_tokens: rsp.usage.input_tokens,
+        output_tokens: rsp.usage.output_tokens,
+        invoked_model_id: None,
+    })
+}
+
+async fn chat_converse(
+    client: &aws_sdk_bedrockruntime::Client,
+    model_id: String,
+    messages: Vec<ChatMessage>,
+    config: ChatConfig,
+) -> Result<ChatResponse, ChatError> {
+    debug!("model-id: {}", model_id);
+    let converse = converse_request(client, model_id.clone(), messages, config)?;
+
+    // Retry once on a model timeout, then surface a hint about the request size
+    let result = retry::with_backoff(
+        || converse.clone().send(),
+        &retry::Policy::MODEL_TIMEOUT,
+        crate::is_model_timeout,
+    )
+    .await;
+
+    match result {
+        Ok(output) => {
+            debug!("{}", redact::debug(&output));
+            let trace_id = TraceId(output.request_id().unwrap_or("UNKNOWN").to_string());
+            let text = match output.output() {
+                Some(ConverseOutput::Message(msg)) => msg
+                    .content()
+                    .iter()
+                    .filter_map(|block| block.as_text().ok())
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(""),
+                _ => return Err(ChatError::Service("No output??".to_string())),
+            };
+            let usage = output.usage();
+            Ok(ChatResponse {
+                trace_id,
+                text,
+                stop_reason: output.stop_reason().as_str().to_string(),
+                input_tokens: usage.map_or(0, |usage| usage.input_tokens() as u32),
+                output_tokens: usage.map_or(0, |usage| usage.output_tokens() as u32),
+                invoked_model_id: output
+                    .trace()
+                    .and_then(|trace| trace.prompt_router())
+                    .and_then(|router| router.invoked_model_id())
+                    .map(str::to_string),
+            })
+        }
+        Err(err) if crate::is_model_timeout(&err) => {
+            debug!("{:#?}", err);
+            Err(ChatError::Timeout(crate::MODEL_TIMEOUT_HINT.to_string()))
+        }
+        Err(err) if crate::is_model_not_found(&err) => Err(ModelNotFound(model_id).into()),
+        Err(err) if crate::is_credentials_error(&err) => {
+            Err(ChatError::Credentials(crate::credentials_hint()))
+        }
+        Err(err) => Err(ChatError::Service(format!(
+            "bad response from bedrock:\n{:#?}",
+            err
+        ))),
+    }
+}
+
+/// Builds the Converse request.  The model id is passed through as given, so it can be
+/// a model id, an inference profile id, or a full (application inference profile) ARN.
+fn converse_request(
+    client: &aws_sdk_bedrockruntime::Client,
+    model_id: String,
+    messages: Vec<ChatMessage>,
+    config: ChatConfig,
+) -> Result<ConverseFluentBuilder, ChatError> {
+    let messages = messages
+        .into_iter()
+        .map(|msg| {
+            let role = match msg.role {
+                ChatRole::User => ConversationRole::User,
+                ChatRole::Assistant => ConversationRole::Assistant,
+            };
+            let mut builder = Message::builder().role(role);
+            for part in msg.parts {
+                let block = match part {
+                    ChatPart::Text(text) => ContentBlock::Text(text),
+                    ChatPart::Attachment(path) => ContentBlock::try_from(AttachmentPath(path))
+                        .map_err(|err| ChatError::Attachment(err.0))?,
+                };
+                builder = builder.content(block);
+            }
+            Ok(builder.build().unwrap())
+        })
+        .collect::<Result<Vec<_>, ChatError>>()?;
+
+    let system = Some(config.system_prompts)
+        .filter(|prompts| !prompts.is_empty())
+        .map(|prompts| prompts.into_iter().map(SystemContentBlock::Text).collect());
+
+    let inference_config = converse_inference_config(config.inference_config);
+    let performance_config = config
+        .latency
+        .for_model(&model_id)
+        .map(|latency| PerformanceConfiguration::builder().latency(latency).build());
+    modalities::warn_near_request_limit(converse_json::request_size_bytes(
+        system.as_deref().unwrap_or_default(),
+        &messages,
+        None,
+        inference_config.as_ref(),
+    ));
+
+    Ok(client
+        .converse()
+        .model_id(model_id)
+        .set_system(system)
+        .set_messages(Some(messages))
+        .set_inference_config(inference_config)
+        .set_performance_config(performance_config))
+}
+
+/// Maps the Nova-style inference config onto Converse's, which has no top_k.
+pub fn converse_inference_config(config: json::InferenceConfig) -> Option<InferenceConfiguration> {
+    if config.is_empty() {
+        return None;
+    }
+    if config.top_k.is_some() {
+        warn!("top_k isn't supported by Converse, ignoring");
+    }
+    Some(
+        InferenceConfiguration::builder()
+            .set_max_tokens(config.max_new_tokens.map(i32::from))
+            .set_temperature(config.temperature)
+            .set_top_p(config.top_p)
+            .set_stop_sequences(Some(config.stop_sequences).filter(|s| !s.is_empty()))
+            .build(),
+    )
+}
+
+#[test]
+fn backend_lookup() {
+    assert_eq!(
+        ChatBackend::InvokeModel,
+        ChatBackend::for_model("us.amazon.nova-lite-v1:0")
+    );
+    assert_eq!(
+        ChatBackend::InvokeModel,
+        ChatBackend::for_model("amazon.nova-pro-v1:0")
+    );
+    assert_eq!(
+        ChatBackend::Converse,
+        ChatBackend::for_model("us.anthropic.claude-3-5-sonnet-20241022-v2:0")
+    );
+    assert_eq!(
+        ChatBackend::Converse,
+        ChatBackend::for_model("amazon.titan-text-express-v1")
+    );
+}
+
+#[test]
+fn profile_arns() {
+    let arn = "arn:aws:bedrock:us-east-1:123456789012:application-inference-profile/a1b2c3d4e5f6";
+    assert_eq!(ChatBackend::Converse, ChatBackend::for_model(arn));
+    assert!(!is_prompt_router(arn));
+
+    // routers serve through Converse, even when they route between Nova models
+    let router = "arn:aws:bedrock:us-east-1:123456789012:default-prompt-router/amazon.nova-lite:1";
+    assert!(is_prompt_router(router));
+    assert_eq!(ChatBackend::Converse, ChatBackend::for_model(router));
+    let router = "arn:aws:bedrock:us-east-1:123456789012:prompt-router/abcd1234efgh";
+    assert_eq!(ChatBackend::Converse, ChatBackend::for_model(router));
+    assert_eq!(
+        ChatBackend::InvokeModel,
+        ChatBackend::for_model("arn:aws:bedrock:us-east-1::foundation-model/amazon.nova-lite-v1:0")
+    );
+
+    let client = test_client();
+    let messages = vec![ChatMessage::user("hi", vec![])];
+    let request =
+        converse_request(&client, arn.to_string(), messages, ChatConfig::default()).unwrap();
+    assert_eq!(Some(&arn.to_string()), request.get_model_id().as_ref());
+
+    // imported models keep their ARN as the model id
+    let imported = "arn:aws:bedrock:us-east-1:123456789012:imported-model/abc123def456";
+    assert_eq!(ChatBackend::Converse, ChatBackend::for_model(imported));
+    let messages = vec![ChatMessage::user("hi", vec![])];
+    let request = converse_request(
+        &client,
+        imported.to_string(),
+        messages,
+        ChatConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(Some(&imported.to_string()), request.get_model_id().as_ref());
+}
+
+#[test]
+fn interleaved_parts() {
+    let client = test_client();
+    let messages = vec![ChatMessage::user_parts(vec![
+        ChatPart::Text("first".to_string()),
+        ChatPart::Attachment("s3://bucket/a.mp4".to_string()),
+        ChatPart::Text("second".to_string()),
+    ])];
+    let request = converse_request(
+        &client,
+        "model".to_string(),
+        messages,
+        ChatConfig::default(),
+    )
+    .unwrap();
+    let content = request.get_messages().as_ref().unwrap()[0].content();
+    assert_eq!(3, content.len());
+    assert_eq!(Ok(&"first".to_string()), content[0].as_text());
+    assert!(content[1].is_video());
+    assert_eq!(Ok(&"second".to_string()), content[2].as_text());
+}
+
+#[test]
+fn layered_system_prompts() {
+    let client = test_client();
+    let messages = vec![ChatMessage::user("hi", vec![])];
+    let config = ChatConfig {
+        system_prompts: vec![
+            "You are a pirate.".to_string(),
+            "Answer in JSON.".to_string(),
+        ],
+        ..Default::default()
+    };
+    let request = converse_request(&client, "model".to_string(), messages.clone(), config).unwrap();
+    assert_eq!(
+        &Some(vec![
+            SystemContentBlock::Text("You are a pirate.".to_string()),
+            SystemContentBlock::Text("Answer in JSON.".to_string())
+        ]),
+        request.get_system()
+    );
+
+    let request = converse_request(
+        &client,
+        "model".to_string(),
+        messages,
+        ChatConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(&None, request.get_system());
+}
+
+#[test]
+fn prefill_whitespace() {
+    assert_eq!(
+        Some("Here is a rhyming answer:".to_string()),
+        trim_prefill("Here is a rhyming answer: \n\t".to_string())
+    );
+    assert_eq!(Some("  {".to_string()), trim_prefill("  {".to_string()));
+    assert_eq!(None, trim_prefill("   ".to_string()));
+}
+
+/// A client for building requests in tests, which never sends them
+#[cfg(test)]
+fn test_client() -> aws_sdk_bedrockruntime::Client {
+    let config = aws_sdk_bedrockruntime::Config::builder()
+        .behavior_version(aws_sdk_bedrockruntime::config::BehaviorVersion::latest())
+        .region(aws_sdk_bedrockruntime::config::Region::new("us-east-1"))
+        .build();
+    aws_sdk_bedrockruntime::Client::from_conf(config)
+}
@@ -0,0 +1,155 @@
+//! Document citations: which parts of the attached documents an answer drew from
+//!
+//! With citations enabled on a document block, models that support them (e.g. Claude)
+//! answer with `CitationsContent` blocks instead of plain text: the generated text, plus
+//! the spans of the source documents it cites.  Spans refer to documents by their index
+//! among the documents sent in the request, which `document_names` maps back to names.
+//!
+//! See: https://docs.aws.amazon.com/bedrock/latest/APIReference/API_runtime_CitationsContentBlock.html
+
+use aws_sdk_bedrockruntime::types::{
+    Citation, CitationGeneratedContent, CitationLocation, CitationSourceContent, CitationsConfig,
+    CitationsContentBlock, ContentBlock, Message,
+};
+
+/// Asks for citations from a document block.  Other blocks are left alone.
+pub fn enable(block: &mut ContentBlock) {
+    if let ContentBlock::Document(doc) = block {
+        doc.citations = Some(CitationsConfig::builder().enabled(true).build().unwrap());
+    }
+}
+
+/// The names of the documents in a conversation, in the order citations index them.
+pub fn document_names(messages: &[Message]) -> Vec<String> {
+    messages
+        .iter()
+        .flat_map(|msg| msg.content())
+        .filter_map(|content| content.as_document().ok())
+        .map(|doc| doc.name().to_string())
+        .collect()
+}
+
+/// The text the model generated in a citations block
+pub fn generated_text(block: &CitationsContentBlock) -> String {
+    block
+        .content()
+        .iter()
+        .filter_map(|content| match content {
+            CitationGeneratedContent::Text(text) => Some(text.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Describes where a citation points, e.g. `notes.md chars 120-245: "quoted text"`.
+pub fn describe(citation: &Citation, documents: &[String]) -> String {
+    let name = |index: Option<i32>| {
+        index
+            .and_then(|index| documents.get(usize::try_from(index).ok()?))
+            .cloned()
+            .unwrap_or_else(|| format!("document {}", index.unwrap_or_default()))
+    };
+    let span = |start: Option<i32>, end: Option<i32>| match (start, end) {
+        (Some(start), Some(end)) => format!("{}-{}", start, end),
+        (Some(start), None) => format!("{}-", start),
+        _ => "?".to_string(),
+    };
+    let location = match citation.location() {
+        Some(CitationLocation::DocumentChar(loc)) => format!(
+            "{} chars {}",
+            name(loc.document_index()),
+            span(loc.start(), loc.end())
+        ),
+        Some(CitationLocation::DocumentPage(loc)) => format!(
+            "{} pages {}",
+            name(loc.document_index()),
+            span(loc.start(), loc.end())
+        ),
+        Some(CitationLocation::DocumentChunk(loc)) => format!(
+            "{} chunks {}",
+            name(loc.document_index()),
+            span(loc.start(), loc.end())
+        ),
+        Some(CitationLocation::Web(loc)) => loc.url().unwrap_or("(web)").to_string(),
+        Some(CitationLocation::SearchResultLocation(loc)) => format!(
+            "search result {}",
+            loc.search_result_index().unwrap_or_default()
+        ),
+        _ => citation.title().unwrap_or("(unknown location)").to_string(),
+    };
+    let quoted = citation
+        .source_content()
+        .iter()
+        .filter_map(|content| match content {
+            CitationSourceContent::Text(text) => Some(text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    match quoted.is_empty() {
+        true => location,
+        false => format!("{}: {:?}", location, quoted),
+    }
+}
+
+#[test]
+fn citation_locations() {
+    use aws_sdk_bedrockruntime::types::{
+        ConversationRole, DocumentBlock, DocumentCharLocation, DocumentFormat,
+        DocumentPageLocation, DocumentSource,
+    };
+
+    let doc = |name: &str| {
+        let block = DocumentBlock::builder()
+            .format(DocumentFormat::Txt)
+            .name(name)
+            .source(DocumentSource::Bytes(b"text".to_vec().into()))
+            .build()
+            .unwrap();
+        ContentBlock::Document(block)
+    };
+    let mut cited = doc("notes");
+    enable(&mut cited);
+    assert_eq!(
+        Some(true),
+        cited
+            .as_document()
+            .unwrap()
+            .citations()
+            .map(|config| config.enabled())
+    );
+    let messages = vec![Message::builder()
+        .role(ConversationRole::User)
+        .content(ContentBlock::Text("compare".to_string()))
+        .content(cited)
+        .content(doc("draft"))
+        .build()
+        .unwrap()];
+    let documents = document_names(&messages);
+    assert_eq!(vec!["notes", "draft"], documents);
+
+    let chars = Citation::builder()
+        .location(CitationLocation::DocumentChar(
+            DocumentCharLocation::builder()
+                .document_index(1)
+                .start(10)
+                .end(42)
+                .build(),
+        ))
+        .source_content(CitationSourceContent::Text("the quote".to_string()))
+        .build();
+    assert_eq!(
+        "draft chars 10-42: \"the quote\"",
+        describe(&chars, &documents)
+    );
+    let pages = Citation::builder()
+        .location(CitationLocation::DocumentPage(
+            DocumentPageLocation::builder()
+                .document_index(5)
+                .start(2)
+                .end(3)
+                .build(),
+        ))
+        .build();
+    assert_eq!("document 5 pages 2-3", describe(&pages, &documents));
+}
@@ -0,0 +1,324 @@
+//! Converse requests and responses as the API's json
+//!
+//! The SDK types don't serialize, so for showing what was sent and received (the
+//! converse `dump-request` and `dump-response` commands) this maps them to the json
+//! of the Converse API: camelCase fields, base64 bytes, and tool inputs and schemas as
+//! json (with `tool_use::document_to_json`).
+//!
+//! The mapping is for reading, not for sending: content without a json form here
+//! (audio, search results, guard content, ...) is shown by its type alone.
+//!
+//! See: https://docs.aws.amazon.com/bedrock/latest/APIReference/API_runtime_Converse.html
+
+use aws_sdk_bedrockruntime::types::{
+    ContentBlock, DocumentBlock, DocumentSource, ImageBlock, ImageSource, InferenceConfiguration,
+    Message, ReasoningContentBlock, StopReason, SystemContentBlock, TokenUsage, Tool, ToolChoice,
+    ToolConfiguration, ToolInputSchema, ToolResultContentBlock, VideoBlock, VideoSource,
+};
+use aws_smithy_types::Blob;
+use serde_json::{json, Map, Value};
+
+use crate::converse::{citations, tool_use};
+use crate::file::Base64Encoding;
+
+/// The body of a Converse request
+pub fn request(
+    system: &[SystemContentBlock],
+    messages: &[Message],
+    tool_config: Option<&ToolConfiguration>,
+    inference_config: Option<&InferenceConfiguration>,
+) -> Value {
+    let mut body = Map::new();
+    body.insert(
+        "messages".to_string(),
+        messages.iter().map(message).collect(),
+    );
+    if !system.is_empty() {
+        body.insert(
+            "system".to_string(),
+            system.iter().map(system_block).collect(),
+        );
+    }
+    if let Some(config) = tool_config {
+        body.insert("toolConfig".to_string(), tools(config));
+    }
+    if let Some(config) = inference_config {
+        body.insert("inferenceConfig".to_string(), inference(config));
+    }
+    Value::Object(body)
+}
+
+/// The size of a Converse request's body as sent, base64 attachments and all, to check
+/// against `modalities::MAX_REQUEST_BYTES` before sending.  Content shown by its type
+/// alone (audio, ...) isn't counted.
+pub fn request_size_bytes(
+    system: &[SystemContentBlock],
+    messages: &[Message],
+    tool_config: Option<&ToolConfiguration>,
+    inference_config: Option<&InferenceConfiguration>,
+) -> usize {
+    body_size(&request(system, messages, tool_config, inference_config))
+}
+
+/// The size of a json body, e.g. from `request`
+pub fn body_size(body: &Value) -> usize {
+    serde_json::to_vec(body).map_or(0, |bytes| bytes.len())
+}
+
+/// The body of a Converse response, from its message, stop reason, and usage
+pub fn response(msg: &Message, stop_reason: &StopReason, usage: Option<&TokenUsage>) -> Value {
+    let mut body = json!({
+        "output": {"message": message(msg)},
+        "stopReason": stop_reason.as_str(),
+    });
+    if let Some(usage) = usage {
+        body["usage"] = json!({
+            "inputTokens": usage.input_tokens,
+            "outputTokens": usage.output_tokens,
+            "totalTokens": usage.total_tokens,
+        });
+    }
+    body
+}
+
+pub fn message(msg: &Message) -> Value {
+    json!({
+        "role": msg.role().as_str(),
+        "content": msg.content().iter().map(content).collect::<Vec<_>>(),
+    })
+}
+
+fn system_block(block: &SystemContentBlock) -> Value {
+    match block {
+        SystemContentBlock::Text(text) => json!({"text": text}),
+        SystemContentBlock::CachePoint(point) => {
+            json!({"cachePoint": {"type": point.r#type.as_str()}})
+        }
+        SystemContentBlock::GuardContent(_) => json!({"guardContent": {}}),
+        _ => json!({"unknown": {}}),
+    }
+}
+
+fn content(block: &ContentBlock) -> Value {
+    match block {
+        ContentBlock::Text(text) => json!({"text": text}),
+        ContentBlock::Image(image) => json!({"image": image_json(image)}),
+        ContentBlock::Video(video) => json!({"video": video_json(video)}),
+        ContentBlock::Document(doc) => json!({"document": document_json(doc)}),
+        ContentBlock::ToolUse(tool) => json!({"toolUse": {
+            "toolUseId": tool.tool_use_id,
+            "name": tool.name,
+            "input": tool_use::document_to_json(&tool.input),
+        }}),
+        ContentBlock::ToolResult(result) => {
+            let mut tool_result = json!({
+                "toolUseId": result.tool_use_id,
+                "content": result.content.iter().map(tool_result_content).collect::<Vec<_>>(),
+            });
+            if let Some(status) = &result.status {
+                tool_result["status"] = json!(status.as_str());
+            }
+            json!({ "toolResult": tool_result })
+        }
+        ContentBlock::ReasoningContent(ReasoningContentBlock::ReasoningText(reasoning)) => {
+            json!({"reasoningContent": {"reasoningText": {
+                "text": reasoning.text,
+                "signature": reasoning.signature,
+            }}})
+        }
+        ContentBlock::ReasoningContent(ReasoningContentBlock::RedactedContent(blob)) => {
+            json!({"reasoningContent": {"redactedContent": base64(blob)}})
+        }
+        ContentBlock::CitationsContent(cited) => json!({"citationsContent": {
+            "content": [{"text": citations::generated_text(cited)}],
+            "citations": cited.citations().len(),
+        }}),
+        ContentBlock::CachePoint(point) => json!({"cachePoint": {"type": point.r#type.as_str()}}),
+        other => json!({ variant(other): {} }),
+    }
+}
+
+fn tool_result_content(block: &ToolResultContentBlock) -> Value {
+    match block {
+        ToolResultContentBlock::Text(text) => json!({"text": text}),
+        ToolResultContentBlock::Json(doc) => json!({"json": tool_use::document_to_json(doc)}),
+        ToolResultContentBlock::Image(image) => json!({"image": image_json(image)}),
+        ToolResultContentBlock::Video(video) => json!({"video": video_json(video)}),
+        ToolResultContentBlock::Document(doc) => json!({"document": document_json(doc)}),
+        _ => json!({"unknown": {}}),
+    }
+}
+
+fn image_json(image: &ImageBlock) -> Value {
+    let source = match &image.source {
+        Some(ImageSource::Bytes(blob)) => json!({"bytes": base64(blob)}),
+        Some(ImageSource::S3Location(s3)) => json!({"s3Location": {"uri": s3.uri}}),
+        _ => Value::Null,
+    };
+    json!({"format": image.format.as_str(), "source": source})
+}
+
+fn video_json(video: &VideoBlock) -> Value {
+    let source = match &video.source {
+        Some(VideoSource::Bytes(blob)) => json!({"bytes": base64(blob)}),
+        Some(VideoSource::S3Location(s3)) => json!({"s3Location": {"uri": s3.uri}}),
+        _ => Value::Null,
+    };
+    json!({"format": video.format.as_str(), "source": source})
+}
+
+fn document_json(doc: &DocumentBlock) -> Value {
+    let source = match &doc.source {
+        Some(DocumentSource::Bytes(blob)) => json!({"bytes": base64(blob)}),
+        Some(DocumentSource::S3Location(s3)) => json!({"s3Location": {"uri": s3.uri}}),
+        Some(DocumentSource::Text(text)) => json!({ "text": text }),
+        _ => Value::Null,
+    };
+    let mut document = json!({
+        "format": doc.format.as_str(),
+        "name": doc.name,
+        "source": source,
+    });
+    if let Some(config) = &doc.citations {
+        document["citations"] = json!({"enabled": config.enabled()});
+    }
+    document
+}
+
+fn tools(config: &ToolConfiguration) -> Value {
+    let tools = config
+        .tools
+        .iter()
+        .map(|tool| match tool {
+            Tool::ToolSpec(spec) => {
+                let schema = match &spec.input_schema {
+                    Some(ToolInputSchema::Json(doc)) => tool_use::document_to_json(doc),
+                    _ => Value::Null,
+                };
+                json!({"toolSpec": {
+                    "name": spec.name,
+                    "description": spec.description,
+                    "inputSchema": {"json": schema},
+                }})
+            }
+            Tool::CachePoint(point) => json!({"cachePoint": {"type": point.r#type.as_str()}}),
+            _ => json!({"unknown": {}}),
+        })
+        .collect::<Vec<_>>();
+    let mut config_json = json!({ "tools": tools });
+    let choice = match &config.tool_choice {
+        Some(ToolChoice::Any(_)) => Some(json!({"any": {}})),
+        Some(ToolChoice::Auto(_)) => Some(json!({"auto": {}})),
+        Some(ToolChoice::Tool(tool)) => Some(json!({"tool": {"name": tool.name}})),
+        _ => None,
+    };
+    if let Some(choice) = choice {
+        config_json["toolChoice"] = choice;
+    }
+    config_json
+}
+
+fn inference(config: &InferenceConfiguration) -> Value {
+    let mut inference = Map::new();
+    if let Some(max_tokens) = config.max_tokens {
+        inference.insert("maxTokens".to_string(), json!(max_tokens));
+    }
+    if let Some(temperature) = config.temperature {
+        inference.insert("temperature".to_string(), json!(temperature));
+    }
+    if let Some(top_p) = config.top_p {
+        inference.insert("topP".to_string(), json!(top_p));
+    }
+    if let Some(stop_sequences) = &config.stop_sequences {
+        inference.insert("stopSequences".to_string(), json!(stop_sequences));
+    }
+    Value::Object(inference)
+}
+
+fn base64(blob: &Blob) -> String {
+    Base64Encoding::encode(blob.as_ref().to_vec()).unwrap()
+}
+
+/// The json name of content without a mapping, e.g. "audio"
+fn variant(block: &ContentBlock) -> &'static str {
+    match block {
+        ContentBlock::Audio(_) => "audio",
+        ContentBlock::GuardContent(_) => "guardContent",
+        ContentBlock::SearchResult(_) => "searchResult",
+        _ => "unknown",
+    }
+}
+
+#[test]
+fn converse_request_json() {
+    use aws_sdk_bedrockruntime::types::{ConversationRole, ImageFormat, ToolUseBlock};
+    use aws_smithy_types::Document;
+
+    let messages = vec![
+        Message::builder()
+            .role(ConversationRole::User)
+            .content(ContentBlock::Text("what's this?".to_string()))
+            .content(ContentBlock::Image(
+                ImageBlock::builder()
+                    .format(ImageFormat::Png)
+                    .source(ImageSource::Bytes(Blob::new(b"png".to_vec())))
+                    .build()
+                    .unwrap(),
+            ))
+            .build()
+            .unwrap(),
+        Message::builder()
+            .role(ConversationRole::Assistant)
+            .content(ContentBlock::ToolUse(
+                ToolUseBlock::builder()
+                    .tool_use_id("t1")
+                    .name("lookup")
+                    .input(Document::Object(
+                        [("q".to_string(), Document::String("png".to_string()))].into(),
+                    ))
+                    .build()
+                    .unwrap(),
+            ))
+            .build()
+            .unwrap(),
+    ];
+    let system = [SystemContentBlock::Text("be brief".to_string())];
+    let inference = InferenceConfiguration::builder().max_tokens(100).build();
+    assert_eq!(
+        json!({
+            "messages": [
+                {"role": "user", "content": [
+                    {"text": "what's this?"},
+                    {"image": {"format": "png", "source": {"bytes": "cG5n"}}},
+                ]},
+                {"role": "assistant", "content": [
+                    {"toolUse": {"toolUseId": "t1", "name": "lookup", "input": {"q": "png"}}},
+                ]},
+            ],
+            "system": [{"text": "be brief"}],
+            "inferenceConfig": {"maxTokens": 100},
+        }),
+        request(&system, &messages, None, Some(&inference))
+    );
+    assert_eq!(
+        request(&system, &messages, None, Some(&inference))
+            .to_string()
+            .len(),
+        request_size_bytes(&system, &messages, None, Some(&inference))
+    );
+
+    let usage = TokenUsage::builder()
+        .input_tokens(10)
+        .output_tokens(2)
+        .total_tokens(12)
+        .build()
+        .unwrap();
+    assert_eq!(
+        json!({
+            "output": {"message": message(&messages[1])},
+            "stopReason": "tool_use",
+            "usage": {"inputTokens": 10, "outputTokens": 2, "totalTokens": 12},
+        }),
+        response(&messages[1], &StopReason::ToolUse, Some(&usage))
+    );
+}
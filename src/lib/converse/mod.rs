@@ -1,2 +1,8 @@
+pub mod citations;
+pub mod converse_json;
 pub mod modalities;
+pub mod nova_json;
+pub mod openai;
+pub mod sse;
+pub mod stream;
 pub mod tool_use;
@@ -9,7 +9,17 @@ use aws_sdk_bedrockruntime::types::{
     ImageSource, S3Location, VideoBlock, VideoFormat, VideoSource,
 };
 
-use crate::file::FileReference;
+use crate::file::{FileReference, Range};
+
+/// Bedrock's per-document size limit for Converse
+pub const MAX_DOCUMENT_BYTES: usize = 4_500_000;
+
+/// Bedrock's limit on attachments (images, videos, documents) in a single message
+pub const DEFAULT_MAX_ATTACHMENTS: usize = 20;
+
+/// Bedrock's ceiling on the size of a whole request.  Inline attachments count against
+/// it base64 encoded, so they're a third larger than on disk.
+pub const MAX_REQUEST_BYTES: usize = 20 * 1024 * 1024;
 
 pub struct AttachmentPath(pub String);
 #[derive(Debug)]
@@ -19,7 +29,12 @@ impl TryFrom<AttachmentPath> for ContentBlock {
 
     fn try_from(value: AttachmentPath) -> Result<Self, Self::Error> {
         let path = value.0;
-        let file_ref: FileReference = path.into();
+        let file_ref: FileReference = path.clone().into();
+        let range = attachment_range(&path, &file_ref)?;
+        if file_ref.name.is_some() && !matches!(file_ref.file_type, crate::file::Type::Document) {
+            log::warn!("{}: only documents are named, the name is ignored", path);
+        }
+        let name = attachment_name(&file_ref);
         match (file_ref.file_type, file_ref.location) {
             (crate::file::Type::Image, crate::file::Location::Local) => {
                 let format = match image_fmt(&file_ref.extension.0) {
@@ -62,8 +77,10 @@ impl TryFrom<AttachmentPath> for ContentBlock {
                         return Err(InvalidPath(file_ref.path));
                     }
                 };
+                let uri = crate::file::s3_uri(&file_ref.path)
+                    .map_err(|crate::file::InvalidS3Uri(msg)| InvalidPath(msg))?;
                 let s3loc = S3Location::builder()
-                    .uri(file_ref.path.clone())
+                    .uri(uri.to_string())
                     .build()
                     .unwrap();
                 let vid_src = VideoSource::S3Location(s3loc);
@@ -82,12 +99,19 @@ impl TryFrom<AttachmentPath> for ContentBlock {
                         return Err(InvalidPath(file_ref.path));
                     }
                 };
-                let blob = crate::file::read(&file_ref.path).into();
-                let doc_src = DocumentSource::Bytes(blob);
+                let (blob, name) = match range {
+                    Some(range) => {
+                        let bytes = crate::file::read_range(&file_ref.path, &range)
+                            .map_err(|err| InvalidPath(format!("{}: {}", path, err)))?;
+                        (bytes, format!("{} {}", name, range.name_suffix()))
+                    }
+                    None => (crate::file::read(&file_ref.path), name),
+                };
+                let doc_src = DocumentSource::Bytes(blob.into());
                 let doc_block = DocumentBlock::builder()
                     .format(format)
                     .source(doc_src)
-                    .name(file_ref.stem.0)
+                    .name(name)
                     .build()
                     .unwrap();
                 Ok(ContentBlock::Document(doc_block))
@@ -99,9 +123,236 @@ impl TryFrom<AttachmentPath> for ContentBlock {
     }
 }
 
+/// The name as Bedrock accepts it for a document: letters, digits, hyphens, parentheses,
+/// square brackets, and single spaces.  Other characters become spaces, e.g.
+/// "Q3: Financials" is sent as "Q3 Financials".
+pub fn document_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c.is_alphanumeric() || "-()[]".contains(c) {
+            true => c,
+            false => ' ',
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The document name for an attachment: its `=name` if given, otherwise its file stem.
+pub fn attachment_name(file_ref: &FileReference) -> String {
+    file_ref
+        .name
+        .as_deref()
+        .map(document_name)
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| file_ref.stem.0.clone())
+}
+
+#[derive(Debug)]
+pub struct UnsupportedFormat(pub String);
+
+/// Builds an image, video, or document content block from in-memory bytes, for media
+/// that doesn't live on disk (e.g. received over a network).
+///
+/// `format` is a file extension as `TryFrom<AttachmentPath>` would see it, e.g. "png",
+/// "mp4", or "pdf".  Documents are named "document"; Bedrock requires names to be unique
+/// within a message, so rebuild the block with another name when sending several.
+pub fn attachment_from_bytes(
+    bytes: Vec<u8>,
+    format: &str,
+) -> Result<ContentBlock, UnsupportedFormat> {
+    let format = format.trim_start_matches('.').to_lowercase();
+    if let Some(format) = image_fmt(&format) {
+        let img_block = ImageBlock::builder()
+            .format(format)
+            .source(ImageSource::Bytes(bytes.into()))
+            .build()
+            .unwrap();
+        Ok(ContentBlock::Image(img_block))
+    } else if let Some(format) = video_fmt(&format) {
+        let vid_block = VideoBlock::builder()
+            .format(format)
+            .source(VideoSource::Bytes(bytes.into()))
+            .build()
+            .unwrap();
+        Ok(ContentBlock::Video(vid_block))
+    } else if let Some(format) = doc_fmt(&format) {
+        let doc_block = DocumentBlock::builder()
+            .format(format)
+            .source(DocumentSource::Bytes(bytes.into()))
+            .name("document")
+            .build()
+            .unwrap();
+        Ok(ContentBlock::Document(doc_block))
+    } else {
+        Err(UnsupportedFormat(format))
+    }
+}
+
+/// Parses the attachment's `#bytes=`/`#pages=` range, if any, checking the file's
+/// format supports it: byte ranges for local text documents, page ranges for local pdfs.
+fn attachment_range(path: &str, file_ref: &FileReference) -> Result<Option<Range>, InvalidPath> {
+    let Some(spec) = &file_ref.range else {
+        return Ok(None);
+    };
+    let range = Range::parse(spec).map_err(|err| InvalidPath(format!("{}: {}", path, err)))?;
+    let extension = file_ref.extension.0.to_lowercase();
+    let supported = matches!(file_ref.location, crate::file::Location::Local)
+        && match range {
+            Range::Bytes { .. } => matches!(extension.as_str(), "txt" | "md" | "csv" | "html"),
+            Range::Pages { .. } => extension == "pdf",
+        };
+    if !supported {
+        return Err(InvalidPath(format!(
+            "{}: {} isn't supported for {} files (byte ranges are for local txt, md, csv, and html, page ranges for local pdf)",
+            path, spec, extension
+        )));
+    }
+    Ok(Some(range))
+}
+
+#[derive(Debug)]
+pub struct AttachmentLimitError(pub String);
+
+/// Checks attachment paths against the attachment count and request size limits.
+///
+/// Catches oversized requests before any file is read, instead of with a confusing error
+/// from Bedrock.  Only local files count towards the size, S3 videos aren't sent inline.
+pub fn check_attachments(
+    paths: &[String],
+    max_attachments: usize,
+) -> Result<(), AttachmentLimitError> {
+    if paths.len() > max_attachments {
+        return Err(AttachmentLimitError(format!(
+            "{} attachments is over the limit of {} per message",
+            paths.len(),
+            max_attachments
+        )));
+    }
+
+    let mut total = 0;
+    for path in paths {
+        let file_ref: FileReference = path.clone().into();
+        if let crate::file::Location::Local = file_ref.location {
+            let range = file_ref.range.as_deref().and_then(|spec| Range::parse(spec).ok());
+            if let Some(Range::Bytes { start, end }) = range {
+                total += base64_len((end - start) as usize);
+                continue;
+            }
+            // page ranges are counted as the whole file, as an upper bound
+            let expanded = crate::file::expand(&file_ref.path);
+            if let Ok(metadata) = std::fs::metadata(expanded) {
+                total += base64_len(metadata.len() as usize);
+            }
+        }
+    }
+    check_request_bytes(total)
+}
+
+/// Requests at least this large get a warning that they're close to `MAX_REQUEST_BYTES`
+pub const NEAR_REQUEST_LIMIT_BYTES: usize = MAX_REQUEST_BYTES / 10 * 9;
+
+/// Logs a warning when a request body of this size is close to (or over) the limit.
+pub fn warn_near_request_limit(bytes: usize) {
+    if bytes >= NEAR_REQUEST_LIMIT_BYTES {
+        log::warn!(
+            "request is {:.1}MB, close to Bedrock's {}MB limit (attachments count base64 encoded)",
+            bytes as f64 / (1024.0 * 1024.0),
+            MAX_REQUEST_BYTES / (1024 * 1024)
+        );
+    }
+}
+
+/// Checks a total of (base64 encoded) inline attachment bytes against `MAX_REQUEST_BYTES`
+pub fn check_request_bytes(total: usize) -> Result<(), AttachmentLimitError> {
+    if total > MAX_REQUEST_BYTES {
+        return Err(AttachmentLimitError(format!(
+            "attachments total {:.1}MB base64 encoded, over Bedrock's {}MB request limit",
+            total as f64 / (1024.0 * 1024.0),
+            MAX_REQUEST_BYTES / (1024 * 1024)
+        )));
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+pub struct UnsupportedModality(pub String);
+
+/// Checks image and video attachments against the input modalities a model accepts
+/// (`ModelDetails::input`, e.g. TEXT, IMAGE, VIDEO), so e.g. a video sent to Nova Micro
+/// is caught before it's uploaded.  Documents are sent as text, so aren't checked.
+pub fn check_modalities(
+    model_id: &str,
+    inputs: &[String],
+    paths: &[String],
+) -> Result<(), UnsupportedModality> {
+    for path in paths {
+        let file_ref: FileReference = path.clone().into();
+        let needed = match file_ref.file_type {
+            crate::file::Type::Image => "IMAGE",
+            crate::file::Type::Video => "VIDEO",
+            crate::file::Type::Document => continue,
+        };
+        if !inputs.iter().any(|input| input.eq_ignore_ascii_case(needed)) {
+            return Err(UnsupportedModality(format!(
+                "{} doesn't take {} input ({}), only {}",
+                model_id,
+                needed.to_lowercase(),
+                path,
+                inputs.join(", ").to_lowercase()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Size of the content block's inline bytes once base64 encoded, 0 for text, S3, etc.
+pub fn inline_bytes(block: &ContentBlock) -> usize {
+    let len = match block {
+        ContentBlock::Image(image) => match image.source() {
+            Some(ImageSource::Bytes(blob)) => blob.as_ref().len(),
+            _ => 0,
+        },
+        ContentBlock::Video(video) => match video.source() {
+            Some(VideoSource::Bytes(blob)) => blob.as_ref().len(),
+            _ => 0,
+        },
+        ContentBlock::Document(doc) => match doc.source() {
+            Some(DocumentSource::Bytes(blob)) => blob.as_ref().len(),
+            _ => 0,
+        },
+        _ => 0,
+    };
+    base64_len(len)
+}
+
+fn base64_len(bytes: usize) -> usize {
+    bytes.div_ceil(3) * 4
+}
+
+const IMAGE_FORMATS: &[&str] = &["gif", "jpeg", "jpg", "png", "webp"];
+const VIDEO_FORMATS: &[&str] = &["3gp", "flv", "mkv", "mov", "mp4", "mpeg", "mpg", "webm", "wmv"];
+const DOCUMENT_FORMATS: &[&str] = &["csv", "doc", "docx", "html", "md", "pdf", "txt", "xls", "xlsx"];
+
+/// The file extensions sent as images, e.g. for a file picker's filter.  File types
+/// (`file::Type`) come from these lists, so other extensions aren't attachments.
+pub fn supported_image_formats() -> &'static [&'static str] {
+    IMAGE_FORMATS
+}
+
+/// The file extensions sent as videos, from local files or S3.
+pub fn supported_video_formats() -> &'static [&'static str] {
+    VIDEO_FORMATS
+}
+
+/// The file extensions sent as documents.
+pub fn supported_document_formats() -> &'static [&'static str] {
+    DOCUMENT_FORMATS
+}
+
 // https://docs.rs/aws-sdk-bedrockruntime/latest/aws_sdk_bedrockruntime/types/enum.VideoFormat.html
-fn video_fmt(format: &str) -> Option<VideoFormat> {
-    match format {
+pub(crate) fn video_fmt(format: &str) -> Option<VideoFormat> {
+    match format.to_lowercase().as_str() {
         "flv" => Some(VideoFormat::Flv),
         "mkv" => Some(VideoFormat::Mkv),
         "mov" => Some(VideoFormat::Mov),
@@ -116,7 +367,7 @@ fn video_fmt(format: &str) -> Option<VideoFormat> {
 }
 
 // https://docs.rs/aws-sdk-bedrockruntime/latest/aws_sdk_bedrockruntime/types/enum.ImageFormat.html
-fn image_fmt(format: &str) -> Option<ImageFormat> {
+pub(crate) fn image_fmt(format: &str) -> Option<ImageFormat> {
     match format.to_lowercase().as_str() {
         "gif" => Some(ImageFormat::Gif),
         "jpeg" | "jpg" => Some(ImageFormat::Jpeg),
@@ -127,7 +378,7 @@ fn image_fmt(format: &str) -> Option<ImageFormat> {
 }
 
 // https://docs.rs/aws-sdk-bedrockruntime/latest/aws_sdk_bedrockruntime/types/enum.DocumentFormat.html
-fn doc_fmt(format: &str) -> Option<DocumentFormat> {
+pub(crate) fn doc_fmt(format: &str) -> Option<DocumentFormat> {
     match format.to_lowercase().as_str() {
         "csv" => Some(DocumentFormat::Csv),
         "doc" => Some(DocumentFormat::Doc),
@@ -141,3 +392,255 @@ fn doc_fmt(format: &str) -> Option<DocumentFormat> {
         _ => None,
     }
 }
+
+#[derive(Debug)]
+pub struct ChunkError(pub String);
+
+/// Splits a document into several document blocks, each at most `max_bytes`.
+///
+/// Only text-like formats (txt, md, csv) can be split, on line boundaries.  CSV chunks
+/// each repeat the header row.  Binary formats (pdf, docx, ...) are rejected, and must be
+/// split before attaching.  Splits are named `<name>-part-<n>`.
+pub fn chunk_document(
+    name: &str,
+    bytes: &[u8],
+    format: &str,
+    max_bytes: usize,
+) -> Result<Vec<DocumentBlock>, ChunkError> {
+    let doc_format = doc_fmt(format)
+        .ok_or_else(|| ChunkError(format!("unsupported document format: {}", format)))?;
+    if !matches!(
+        doc_format,
+        DocumentFormat::Txt | DocumentFormat::Md | DocumentFormat::Csv
+    ) {
+        return Err(ChunkError(format!(
+            "{} documents can't be split automatically, split {} into files under {} bytes before attaching",
+            format, name, max_bytes
+        )));
+    }
+
+    let text = std::str::from_utf8(bytes)
+        .map_err(|_| ChunkError(format!("{} isn't valid utf-8, can't split it", name)))?;
+
+    let block = |name: String, text: String| {
+        DocumentBlock::builder()
+            .format(doc_format.clone())
+            .source(DocumentSource::Bytes(text.into_bytes().into()))
+            .name(name)
+            .build()
+            .unwrap()
+    };
+
+    if text.len() <= max_bytes {
+        return Ok(vec![block(name.to_string(), text.to_string())]);
+    }
+
+    // csv splits get the header row, so each one can be read on its own
+    let (header, body) = match doc_format {
+        DocumentFormat::Csv => match text.split_once('\n') {
+            Some((header, body)) => (format!("{}\n", header), body),
+            None => (String::new(), text),
+        },
+        _ => (String::new(), text),
+    };
+
+    // leave room for at least one (4 byte) utf-8 char per chunk
+    if max_bytes < header.len() + 4 {
+        return Err(ChunkError(format!(
+            "{} bytes is too small to split {} into",
+            max_bytes, name
+        )));
+    }
+
+    Ok(split_lines(body, max_bytes - header.len())
+        .into_iter()
+        .enumerate()
+        .map(|(idx, chunk)| {
+            block(
+                format!("{}-part-{}", name, idx + 1),
+                format!("{}{}", header, chunk),
+            )
+        })
+        .collect())
+}
+
+/// Splits text into chunks of at most `max_bytes`, preferring line boundaries.
+///
+/// A single line longer than `max_bytes` is split at the last char boundary that fits.
+fn split_lines(text: &str, max_bytes: usize) -> Vec<&str> {
+    let mut chunks = vec![];
+    let mut start = 0;
+    let mut end = 0;
+    for line in text.split_inclusive('\n') {
+        if end > start && end - start + line.len() > max_bytes {
+            chunks.push(&text[start..end]);
+            start = end;
+        }
+        end += line.len();
+
+        while end - start > max_bytes {
+            let mut cut = start + max_bytes;
+            while !text.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            chunks.push(&text[start..cut]);
+            start = cut;
+        }
+    }
+    if end > start {
+        chunks.push(&text[start..end]);
+    }
+    chunks
+}
+
+#[test]
+fn chunking() {
+    assert_eq!(vec!["ab\n", "cd\n", "ef"], split_lines("ab\ncd\nef", 4));
+    assert_eq!(vec!["ab\ncd\n", "ef"], split_lines("ab\ncd\nef", 6));
+    assert_eq!(vec!["abc", "def", "g\nh"], split_lines("abcdefg\nh", 3));
+    assert_eq!(vec!["é", "é"], split_lines("éé", 3));
+
+    let csv = "a,b\n1,2\n3,4\n";
+    let blocks = chunk_document("data", csv.as_bytes(), "csv", 8).unwrap();
+    assert_eq!(2, blocks.len());
+    assert_eq!("data-part-1", blocks[0].name());
+    let text = |block: &DocumentBlock| match block.source() {
+        Some(DocumentSource::Bytes(blob)) => String::from_utf8(blob.clone().into_inner()).unwrap(),
+        _ => panic!("expected bytes"),
+    };
+    assert_eq!("a,b\n1,2\n", text(&blocks[0]));
+    assert_eq!("a,b\n3,4\n", text(&blocks[1]));
+
+    let blocks = chunk_document("notes", b"short", "txt", 100).unwrap();
+    assert_eq!(1, blocks.len());
+    assert_eq!("notes", blocks[0].name());
+
+    assert!(chunk_document("report", b"%PDF", "pdf", 100).is_err());
+}
+
+#[test]
+fn attachment_limits() {
+    assert_eq!(0, base64_len(0));
+    assert_eq!(4, base64_len(1));
+    assert_eq!(8, base64_len(6));
+
+    let videos = vec!["s3://bucket/a.mp4".to_string(); 3];
+    assert!(check_attachments(&videos, 3).is_ok());
+    assert!(check_attachments(&videos, 2).is_err());
+
+    assert!(check_request_bytes(MAX_REQUEST_BYTES).is_ok());
+    assert!(check_request_bytes(MAX_REQUEST_BYTES + 1).is_err());
+}
+
+#[test]
+fn ranged_attachments() {
+    let path = std::env::temp_dir().join("bedrock-lib-ranged.txt");
+    std::fs::write(&path, "0123456789").unwrap();
+    let path = path.to_str().unwrap();
+
+    let block = ContentBlock::try_from(AttachmentPath(format!("{}#bytes=2-5", path))).unwrap();
+    let document = block.as_document().unwrap();
+    assert_eq!("bedrock-lib-ranged bytes 2-5", document.name());
+    assert!(matches!(document.source(), Some(DocumentSource::Bytes(b)) if b.as_ref() == b"234"));
+
+    // ranges past the end, or on formats that can't be ranged, are rejected
+    assert!(ContentBlock::try_from(AttachmentPath(format!("{}#bytes=2-50", path))).is_err());
+    assert!(ContentBlock::try_from(AttachmentPath(format!("{}#pages=1-2", path))).is_err());
+    assert!(ContentBlock::try_from(AttachmentPath("/tmp/a.png#bytes=0-10".to_string())).is_err());
+}
+
+#[test]
+fn attachments_from_bytes() {
+    let block = attachment_from_bytes(vec![1, 2, 3], "PNG").unwrap();
+    assert_eq!(&ImageFormat::Png, block.as_image().unwrap().format());
+
+    let block = attachment_from_bytes(vec![1, 2, 3], ".mp4").unwrap();
+    assert_eq!(&VideoFormat::Mp4, block.as_video().unwrap().format());
+
+    let block = attachment_from_bytes(b"hello".to_vec(), "md").unwrap();
+    let document = block.as_document().unwrap();
+    assert_eq!(&DocumentFormat::Md, document.format());
+    assert!(matches!(document.source(), Some(DocumentSource::Bytes(b)) if b.as_ref() == b"hello"));
+
+    assert!(attachment_from_bytes(vec![], "exe").is_err());
+}
+
+#[test]
+fn model_modalities() {
+    let text_only = vec!["TEXT".to_string()];
+    let multimodal = vec!["TEXT".to_string(), "IMAGE".to_string(), "VIDEO".to_string()];
+    let attachments = vec!["notes.md".to_string(), "clip.mp4".to_string()];
+
+    assert_eq!(
+        Err(UnsupportedModality(
+            "amazon.nova-micro-v1:0 doesn't take video input (clip.mp4), only text".to_string()
+        )),
+        check_modalities("amazon.nova-micro-v1:0", &text_only, &attachments)
+    );
+    assert_eq!(
+        Ok(()),
+        check_modalities("amazon.nova-lite-v1:0", &multimodal, &attachments)
+    );
+    assert_eq!(
+        Ok(()),
+        check_modalities("amazon.nova-micro-v1:0", &text_only, &attachments[..1])
+    );
+}
+
+#[test]
+fn named_attachments() {
+    assert_eq!("Q3 Financials", document_name("Q3:  Financials"));
+    assert_eq!("notes (v2) [draft]", document_name("notes_(v2) [draft]."));
+    assert_eq!("", document_name("..."));
+
+    let path = std::env::temp_dir().join("bedrock-lib-named.txt");
+    std::fs::write(&path, "numbers").unwrap();
+    let path = path.to_str().unwrap();
+    let name = |attachment: String| {
+        let block = ContentBlock::try_from(AttachmentPath(attachment)).unwrap();
+        block.as_document().unwrap().name().to_string()
+    };
+    assert_eq!("Q3 Financials", name(format!("{}=Q3 Financials", path)));
+    assert_eq!("Q3 bytes 0-3", name(format!("{}#bytes=0-3=Q3", path)));
+    assert_eq!("bedrock-lib-named", name(format!("{}=...", path)));
+}
+
+#[test]
+fn supported_formats() {
+    use crate::file::Type;
+    use std::collections::HashSet;
+
+    let file_type = |ext: &str| FileReference::from(format!("file.{}", ext)).file_type;
+    for ext in supported_image_formats() {
+        assert!(image_fmt(ext).is_some() && matches!(file_type(ext), Type::Image), "{}", ext);
+    }
+    for ext in supported_video_formats() {
+        assert!(video_fmt(ext).is_some() && matches!(file_type(ext), Type::Video), "{}", ext);
+    }
+    for ext in supported_document_formats() {
+        assert!(doc_fmt(ext).is_some() && matches!(file_type(ext), Type::Document), "{}", ext);
+    }
+
+    // every format Bedrock takes has an extension
+    let reached = |formats: Vec<&str>| {
+        formats.into_iter().map(String::from).collect::<HashSet<_>>()
+    };
+    let all = |values: &[&str]| reached(values.to_vec());
+    let images = supported_image_formats().iter().filter_map(|ext| image_fmt(ext));
+    let images = images.collect::<Vec<_>>();
+    assert_eq!(all(ImageFormat::values()), reached(images.iter().map(|f| f.as_str()).collect()));
+    let videos = supported_video_formats().iter().filter_map(|ext| video_fmt(ext));
+    let videos = videos.collect::<Vec<_>>();
+    assert_eq!(all(VideoFormat::values()), reached(videos.iter().map(|f| f.as_str()).collect()));
+    let docs = supported_document_formats().iter().filter_map(|ext| doc_fmt(ext));
+    let docs = docs.collect::<Vec<_>>();
+    assert_eq!(all(DocumentFormat::values()), reached(docs.iter().map(|f| f.as_str()).collect()));
+
+    // and nothing else is supported
+    for ext in ["avi", "m4v", "bmp", "rtf", "zip"] {
+        assert!(image_fmt(ext).is_none() && video_fmt(ext).is_none() && doc_fmt(ext).is_none());
+    }
+    // though avi and m4v are still videos, which fail to attach instead of panicking
+    assert!(matches!(file_type("avi"), Type::Video));
+    assert!(ContentBlock::try_from(AttachmentPath("clip.m4v".to_string())).is_err());
+}
@@ -0,0 +1,224 @@
+//! Conversions between the Nova InvokeModel json types and the Converse SDK types
+//!
+//! The invoke path (`amazon_nova::text::json`) and bedrock:Converse model the same
+//! conversation with two parallel sets of types.  The functions here map between them,
+//! so a conversation captured on one path can be continued on the other.
+//!
+//! Only content both sides can express maps across: text, images, videos, and cache
+//! points (kept in place, so a reloaded conversation caches the same prefix).  Anything
+//! else (documents, tool use, ...) is a `ConversionError`.
+
+use aws_sdk_bedrockruntime::types::{
+    CachePointBlock, CachePointType, ContentBlock, ConversationRole, ImageBlock, ImageSource,
+    Message, S3Location, SystemContentBlock, VideoBlock, VideoSource,
+};
+
+use crate::amazon_nova::text::json;
+use crate::converse::citations;
+use crate::converse::modalities::{image_fmt, video_fmt};
+use crate::file::{self, Base64Encoding};
+
+#[derive(Debug)]
+pub struct ConversionError(pub String);
+
+/// Reads an InvokeModel request body (see `json::TextRequest`) from a file.
+///
+/// Filenames support ~ and env variables
+pub fn load_request(filename: &str) -> Result<json::TextRequest, ConversionError> {
+    let expanded = file::expand(filename);
+    let body = std::fs::read(&expanded)
+        .map_err(|err| ConversionError(format!("couldn't read {}: {}", filename, err)))?;
+    serde_json::from_slice(&body)
+        .map_err(|err| ConversionError(format!("malformed request json {}: {}", filename, err)))
+}
+
+pub fn to_sdk_system(system: &[json::SystemPrompt]) -> Vec<SystemContentBlock> {
+    system
+        .iter()
+        .map(|prompt| SystemContentBlock::Text(prompt.text.clone()))
+        .collect()
+}
+
+pub fn to_sdk_message(msg: &json::Message) -> Result<Message, ConversionError> {
+    let role = match msg.role {
+        json::Role::User => ConversationRole::User,
+        json::Role::Assistant => ConversationRole::Assistant,
+    };
+    let content = msg
+        .content
+        .iter()
+        .map(to_sdk_content)
+        .collect::<Result<Vec<_>, _>>()?;
+    Message::builder()
+        .role(role)
+        .set_content(Some(content))
+        .build()
+        .map_err(|err| ConversionError(err.to_string()))
+}
+
+fn to_sdk_content(content: &json::Content) -> Result<ContentBlock, ConversionError> {
+    match content {
+        json::Content::Text(text) => Ok(ContentBlock::Text(text.clone())),
+        json::Content::Image(image) => {
+            let format = image_fmt(&image.format).ok_or_else(|| {
+                ConversionError(format!("unsupported image format: {}", image.format))
+            })?;
+            let bytes = decode(&image.source.bytes)?;
+            let block = ImageBlock::builder()
+                .format(format)
+                .source(ImageSource::Bytes(bytes.into()))
+                .build()
+                .unwrap();
+            Ok(ContentBlock::Image(block))
+        }
+        json::Content::Video(video) => {
+            let format = video_fmt(&video.format).ok_or_else(|| {
+                ConversionError(format!("unsupported video format: {}", video.format))
+            })?;
+            let source = match &video.source {
+                json::VideoSource::Bytes(bytes) => VideoSource::Bytes(decode(bytes)?.into()),
+                json::VideoSource::S3Location(location) => VideoSource::S3Location(
+                    S3Location::builder()
+                        .uri(location.uri.clone())
+                        .build()
+                        .unwrap(),
+                ),
+            };
+            let block = VideoBlock::builder()
+                .format(format)
+                .source(source)
+                .build()
+                .unwrap();
+            Ok(ContentBlock::Video(block))
+        }
+        json::Content::CachePoint(point) => {
+            let block = CachePointBlock::builder()
+                .r#type(CachePointType::from(point.kind.as_str()))
+                .build()
+                .map_err(|err| ConversionError(err.to_string()))?;
+            Ok(ContentBlock::CachePoint(block))
+        }
+    }
+}
+
+pub fn from_sdk_message(msg: &Message) -> Result<json::Message, ConversionError> {
+    let role = match msg.role() {
+        ConversationRole::User => json::Role::User,
+        ConversationRole::Assistant => json::Role::Assistant,
+        role => return Err(ConversionError(format!("unsupported role: {}", role))),
+    };
+    let content = msg
+        .content()
+        .iter()
+        .map(from_sdk_content)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(json::Message { role, content })
+}
+
+fn from_sdk_content(content: &ContentBlock) -> Result<json::Content, ConversionError> {
+    match content {
+        ContentBlock::Text(text) => Ok(json::Content::Text(text.clone())),
+        ContentBlock::CitationsContent(cited) => {
+            Ok(json::Content::Text(citations::generated_text(cited)))
+        }
+        ContentBlock::Image(image) => match image.source() {
+            Some(ImageSource::Bytes(blob)) => Ok(json::Content::Image(json::Image {
+                format: image.format().as_str().to_string(),
+                source: json::ImageSource {
+                    bytes: Base64Encoding::encode(blob.clone().into_inner()).unwrap(),
+                },
+            })),
+            _ => Err(ConversionError(
+                "only inline image bytes are supported".to_string(),
+            )),
+        },
+        ContentBlock::Video(video) => {
+            let source = match video.source() {
+                Some(VideoSource::Bytes(blob)) => json::VideoSource::Bytes(
+                    Base64Encoding::encode(blob.clone().into_inner()).unwrap(),
+                ),
+                Some(VideoSource::S3Location(location)) => {
+                    json::VideoSource::S3Location(json::S3Location {
+                        uri: location.uri().to_string(),
+                    })
+                }
+                _ => return Err(ConversionError("unsupported video source".to_string())),
+            };
+            Ok(json::Content::Video(json::Video {
+                format: video.format().as_str().to_string(),
+                source,
+            }))
+        }
+        ContentBlock::CachePoint(point) => Ok(json::Content::CachePoint(json::CachePoint {
+            kind: point.r#type().as_str().to_string(),
+        })),
+        _ => Err(ConversionError(format!(
+            "content has no Nova json equivalent: {:?}",
+            content
+        ))),
+    }
+}
+
+fn decode(base64: &str) -> Result<Vec<u8>, ConversionError> {
+    Base64Encoding::new(base64.to_string())
+        .try_decode()
+        .map_err(|err| ConversionError(format!("malformed base64: {}", err)))
+}
+
+#[test]
+fn round_trip() {
+    let body = r#"{
+        "system": [{"text": "be brief"}],
+        "messages": [
+            {"role": "user", "content": [
+                {"text": "what's this?"},
+                {"image": {"format": "png", "source": {"bytes": "aGVsbG8="}}},
+                {"video": {"format": "mp4", "source": {"s3Location": {"uri": "s3://b/k.mp4"}}}}
+            ]},
+            {"role": "assistant", "content": [{"text": "a picture"}]}
+        ]
+    }"#;
+    let request: json::TextRequest = serde_json::from_str(body).unwrap();
+    assert_eq!(1, to_sdk_system(&request.system).len());
+
+    let messages = request
+        .messages
+        .iter()
+        .map(to_sdk_message)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(&ConversationRole::User, messages[0].role());
+    assert_eq!(3, messages[0].content().len());
+    assert_eq!(&ConversationRole::Assistant, messages[1].role());
+
+    let back = from_sdk_message(&messages[0]).unwrap();
+    assert_eq!(
+        serde_json::to_value(&request.messages[0]).unwrap(),
+        serde_json::to_value(&back).unwrap()
+    );
+}
+
+#[test]
+fn cache_point_round_trip() {
+    let body = r#"{
+        "role": "user",
+        "content": [
+            {"text": "a long reference document"},
+            {"cachePoint": {"type": "default"}},
+            {"text": "the question"}
+        ]
+    }"#;
+    let msg: json::Message = serde_json::from_str(body).unwrap();
+    let sdk = to_sdk_message(&msg).unwrap();
+    assert!(sdk.content()[1].is_cache_point());
+    assert_eq!(
+        &CachePointType::Default,
+        sdk.content()[1].as_cache_point().unwrap().r#type()
+    );
+
+    let back = from_sdk_message(&sdk).unwrap();
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(body).unwrap(),
+        serde_json::to_value(&back).unwrap()
+    );
+}
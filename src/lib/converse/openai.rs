@@ -0,0 +1,458 @@
+//! Conversions between Converse messages and OpenAI's chat completions format
+//!
+//! OpenAI's format is a flat list of `{role, content}` messages, with the system prompt
+//! as a leading "system" message, tool calls on the assistant message, and each tool
+//! result as its own "tool" message.  Text content is a string; other content is a list
+//! of parts (`text`, `image_url` as a data url, and `file` for pdfs).
+//!
+//! Reasoning content has no OpenAI equivalent and is left out.  Videos, s3 sources,
+//! and binary office documents are a `ConversionError`.
+//!
+//! Going the other way, `from_openai` reads system, user, and assistant messages with
+//! text, inline image, and pdf content.  Tool calls and results don't map back, since
+//! Converse needs the tool definitions they were made with.  The messages can be mapped
+//! on to the invoke path with `nova_json::from_sdk_message`.
+//!
+//! See: https://platform.openai.com/docs/api-reference/chat/create
+
+use aws_sdk_bedrockruntime::types::{
+    ContentBlock, ConversationRole, DocumentBlock, DocumentFormat, DocumentSource, ImageBlock,
+    ImageSource, Message, SystemContentBlock, ToolResultBlock, ToolResultContentBlock,
+};
+use aws_smithy_types::Blob;
+use serde_json::{json, Value};
+
+use crate::converse::modalities::image_fmt;
+use crate::converse::nova_json::ConversionError;
+use crate::converse::{citations, tool_use};
+use crate::file::{self, Base64Encoding};
+
+/// Converts the system prompt and messages to an OpenAI `messages` array.
+pub fn to_openai(
+    system: &[SystemContentBlock],
+    messages: &[Message],
+) -> Result<Vec<Value>, ConversionError> {
+    let mut openai = vec![];
+    let system = system
+        .iter()
+        .filter_map(|block| block.as_text().ok())
+        .cloned()
+        .collect::<Vec<_>>();
+    if !system.is_empty() {
+        openai.push(json!({"role": "system", "content": system.join("\n\n")}));
+    }
+    for msg in messages {
+        match msg.role() {
+            ConversationRole::User => user_messages(msg, &mut openai)?,
+            ConversationRole::Assistant => openai.push(assistant_message(msg)?),
+            role => return Err(ConversionError(format!("unsupported role: {}", role))),
+        }
+    }
+    Ok(openai)
+}
+
+/// A user message, preceded by a "tool" message for each tool result it carries
+fn user_messages(msg: &Message, openai: &mut Vec<Value>) -> Result<(), ConversionError> {
+    let mut parts = vec![];
+    for content in msg.content() {
+        match content {
+            ContentBlock::ToolResult(result) => openai.push(tool_message(result)?),
+            ContentBlock::Text(text) => parts.push(json!({"type": "text", "text": text})),
+            ContentBlock::Image(image) => parts.push(image_part(image)?),
+            ContentBlock::Document(doc) => parts.push(document_part(doc)?),
+            // caching is Bedrock's, and leaving the marker out doesn't change the content
+            ContentBlock::ReasoningContent(_) | ContentBlock::CachePoint(_) => {}
+            _ => {
+                return Err(ConversionError(format!(
+                    "content has no OpenAI equivalent: {:?}",
+                    content
+                )))
+            }
+        }
+    }
+    if !parts.is_empty() {
+        openai.push(json!({"role": "user", "content": content_value(parts)}));
+    }
+    Ok(())
+}
+
+fn assistant_message(msg: &Message) -> Result<Value, ConversionError> {
+    let mut text = vec![];
+    let mut tool_calls = vec![];
+    for content in msg.content() {
+        match content {
+            ContentBlock::Text(t) => text.push(t.clone()),
+            // the citations themselves have no equivalent
+            ContentBlock::CitationsContent(cited) => text.push(citations::generated_text(cited)),
+            ContentBlock::ToolUse(tool_use) => tool_calls.push(json!({
+                "id": tool_use.tool_use_id(),
+                "type": "function",
+                "function": {
+                    "name": tool_use.name(),
+                    "arguments": tool_use::document_to_json(tool_use.input()).to_string(),
+                },
+            })),
+            ContentBlock::ReasoningContent(_) => {}
+            _ => {
+                return Err(ConversionError(format!(
+                    "assistant content has no OpenAI equivalent: {:?}",
+                    content
+                )))
+            }
+        }
+    }
+    let mut message = json!({"role": "assistant", "content": text.join("")});
+    if !tool_calls.is_empty() {
+        message["tool_calls"] = Value::Array(tool_calls);
+    }
+    Ok(message)
+}
+
+fn tool_message(result: &ToolResultBlock) -> Result<Value, ConversionError> {
+    let content = result
+        .content()
+        .iter()
+        .map(|content| match content {
+            ToolResultContentBlock::Text(text) => Ok(text.clone()),
+            ToolResultContentBlock::Json(doc) => Ok(tool_use::document_to_json(doc).to_string()),
+            _ => Err(ConversionError(format!(
+                "tool result has no OpenAI equivalent: {:?}",
+                content
+            ))),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(json!({
+        "role": "tool",
+        "tool_call_id": result.tool_use_id(),
+        "content": content.join("\n"),
+    }))
+}
+
+/// Text-only content is a plain string, as most OpenAI tooling expects
+fn content_value(parts: Vec<Value>) -> Value {
+    match parts.as_slice() {
+        [part] if part["type"] == "text" => part["text"].clone(),
+        _ => Value::Array(parts),
+    }
+}
+
+fn image_part(image: &ImageBlock) -> Result<Value, ConversionError> {
+    let Some(ImageSource::Bytes(blob)) = image.source() else {
+        return Err(ConversionError(
+            "only inline image bytes are supported".to_string(),
+        ));
+    };
+    let base64 = Base64Encoding::encode(blob.clone().into_inner()).unwrap();
+    let url = format!("data:image/{};base64,{}", image.format().as_str(), base64);
+    Ok(json!({"type": "image_url", "image_url": {"url": url}}))
+}
+
+/// PDFs become file parts, and plain text documents text parts.
+fn document_part(doc: &DocumentBlock) -> Result<Value, ConversionError> {
+    let Some(DocumentSource::Bytes(blob)) = doc.source() else {
+        return Err(ConversionError(
+            "only inline document bytes are supported".to_string(),
+        ));
+    };
+    let bytes = blob.clone().into_inner();
+    match doc.format() {
+        DocumentFormat::Pdf => {
+            let base64 = Base64Encoding::encode(bytes).unwrap();
+            Ok(json!({"type": "file", "file": {
+                "filename": format!("{}.pdf", doc.name()),
+                "file_data": format!("data:application/pdf;base64,{}", base64),
+            }}))
+        }
+        DocumentFormat::Txt | DocumentFormat::Md | DocumentFormat::Csv | DocumentFormat::Html => {
+            let text = String::from_utf8(bytes).map_err(|err| {
+                ConversionError(format!("document {} isn't utf-8: {}", doc.name(), err))
+            })?;
+            Ok(json!({"type": "text", "text": text}))
+        }
+        format => Err(ConversionError(format!(
+            "{} documents have no OpenAI equivalent",
+            format
+        ))),
+    }
+}
+
+/// Reads an OpenAI chat json file: either a `messages` array, or a request body with
+/// one.
+///
+/// Filenames support ~ and env variables
+pub fn load_openai(
+    filename: &str,
+) -> Result<(Vec<SystemContentBlock>, Vec<Message>), ConversionError> {
+    let expanded = file::expand(filename);
+    let body = std::fs::read(&expanded)
+        .map_err(|err| ConversionError(format!("couldn't read {}: {}", filename, err)))?;
+    let json: Value = serde_json::from_slice(&body)
+        .map_err(|err| ConversionError(format!("malformed json {}: {}", filename, err)))?;
+    let messages = match &json {
+        Value::Array(messages) => messages,
+        _ => match json.get("messages") {
+            Some(Value::Array(messages)) => messages,
+            _ => {
+                return Err(ConversionError(format!(
+                    "{} has no messages array",
+                    filename
+                )))
+            }
+        },
+    };
+    from_openai(messages)
+}
+
+/// Converts an OpenAI `messages` array to a system prompt and Converse messages.
+///
+/// Consecutive messages with the same role are merged, since Converse requires the
+/// roles to alternate.
+pub fn from_openai(
+    messages: &[Value],
+) -> Result<(Vec<SystemContentBlock>, Vec<Message>), ConversionError> {
+    let mut system = vec![];
+    let mut turns: Vec<(ConversationRole, Vec<ContentBlock>)> = vec![];
+    for (idx, msg) in messages.iter().enumerate() {
+        let role = msg["role"].as_str().unwrap_or_default();
+        let role = match role {
+            "system" | "developer" => {
+                system.extend(
+                    from_openai_content(&msg["content"])?
+                        .into_iter()
+                        .filter_map(|block| block.as_text().ok().cloned())
+                        .map(SystemContentBlock::Text),
+                );
+                continue;
+            }
+            "user" => ConversationRole::User,
+            "assistant" if msg.get("tool_calls").is_some_and(|calls| !calls.is_null()) => {
+                return Err(ConversionError(format!(
+                    "message {} has tool calls, which aren't supported",
+                    idx + 1
+                )))
+            }
+            "assistant" => ConversationRole::Assistant,
+            "tool" | "function" => {
+                return Err(ConversionError(format!(
+                    "message {} has role {}: tool results aren't supported",
+                    idx + 1,
+                    role
+                )))
+            }
+            role => {
+                return Err(ConversionError(format!(
+                    "message {} has unsupported role: {:?}",
+                    idx + 1,
+                    role
+                )))
+            }
+        };
+        let content = from_openai_content(&msg["content"])?;
+        match turns.last_mut() {
+            Some((last, blocks)) if *last == role => blocks.extend(content),
+            _ => turns.push((role, content)),
+        }
+    }
+
+    let messages = turns
+        .into_iter()
+        .map(|(role, content)| {
+            Message::builder()
+                .role(role)
+                .set_content(Some(content))
+                .build()
+                .map_err(|err| ConversionError(err.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((system, messages))
+}
+
+fn from_openai_content(content: &Value) -> Result<Vec<ContentBlock>, ConversionError> {
+    match content {
+        Value::Null => Ok(vec![]),
+        Value::String(text) => Ok(vec![ContentBlock::Text(text.clone())]),
+        Value::Array(parts) => parts.iter().map(from_openai_part).collect(),
+        _ => Err(ConversionError(format!("unsupported content: {}", content))),
+    }
+}
+
+fn from_openai_part(part: &Value) -> Result<ContentBlock, ConversionError> {
+    match part["type"].as_str().unwrap_or_default() {
+        "text" => Ok(ContentBlock::Text(
+            part["text"].as_str().unwrap_or_default().to_string(),
+        )),
+        "image_url" => {
+            let url = part["image_url"]["url"].as_str().unwrap_or_default();
+            let (media_type, bytes) = from_data_url(url)?;
+            let format = media_type
+                .strip_prefix("image/")
+                .and_then(image_fmt)
+                .ok_or_else(|| {
+                    ConversionError(format!("unsupported image type: {}", media_type))
+                })?;
+            let block = ImageBlock::builder()
+                .format(format)
+                .source(ImageSource::Bytes(Blob::new(bytes)))
+                .build()
+                .unwrap();
+            Ok(ContentBlock::Image(block))
+        }
+        "file" => {
+            let data = part["file"]["file_data"].as_str().unwrap_or_default();
+            let (media_type, bytes) = from_data_url(data)?;
+            if media_type != "application/pdf" {
+                return Err(ConversionError(format!(
+                    "unsupported file type: {}",
+                    media_type
+                )));
+            }
+            let filename = part["file"]["filename"].as_str().unwrap_or("document");
+            let block = DocumentBlock::builder()
+                .format(DocumentFormat::Pdf)
+                .name(file::sanitize(file::get_file_stem(filename)))
+                .source(DocumentSource::Bytes(Blob::new(bytes)))
+                .build()
+                .unwrap();
+            Ok(ContentBlock::Document(block))
+        }
+        kind => Err(ConversionError(format!(
+            "unsupported content part type: {:?}",
+            kind
+        ))),
+    }
+}
+
+/// Splits a base64 data url into its media type and bytes.  Remote urls aren't fetched.
+fn from_data_url(url: &str) -> Result<(&str, Vec<u8>), ConversionError> {
+    let (media_type, data) = url
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(";base64,"))
+        .ok_or_else(|| {
+            ConversionError(format!(
+                "only base64 data urls are supported, not {:.40}",
+                url
+            ))
+        })?;
+    let bytes = Base64Encoding::new(data.to_string())
+        .try_decode()
+        .map_err(|err| ConversionError(format!("malformed base64: {}", err)))?;
+    Ok((media_type, bytes))
+}
+
+#[test]
+fn openai_export() {
+    use aws_sdk_bedrockruntime::types::{ImageFormat, ToolUseBlock};
+    use aws_smithy_types::Document;
+
+    let message = |role: ConversationRole, content: Vec<ContentBlock>| {
+        Message::builder()
+            .role(role)
+            .set_content(Some(content))
+            .build()
+            .unwrap()
+    };
+    let image = ImageBlock::builder()
+        .format(ImageFormat::Png)
+        .source(ImageSource::Bytes(b"hello".to_vec().into()))
+        .build()
+        .unwrap();
+    let tool_use = ToolUseBlock::builder()
+        .tool_use_id("t1")
+        .name("list_files")
+        .input(Document::Object(Default::default()))
+        .build()
+        .unwrap();
+    let messages = vec![
+        message(
+            ConversationRole::User,
+            vec![
+                ContentBlock::Text("what's this?".to_string()),
+                ContentBlock::Image(image),
+            ],
+        ),
+        message(
+            ConversationRole::Assistant,
+            vec![
+                ContentBlock::Text("let me look".to_string()),
+                ContentBlock::ToolUse(tool_use),
+            ],
+        ),
+        message(
+            ConversationRole::User,
+            vec![tool_use::tool_result("t1", "a.png")],
+        ),
+    ];
+    let system = vec![SystemContentBlock::Text("be brief".to_string())];
+
+    assert_eq!(
+        json!([
+            {"role": "system", "content": "be brief"},
+            {"role": "user", "content": [
+                {"type": "text", "text": "what's this?"},
+                {"type": "image_url", "image_url": {"url": "data:image/png;base64,aGVsbG8="}}
+            ]},
+            {"role": "assistant", "content": "let me look", "tool_calls": [
+                {"id": "t1", "type": "function", "function": {"name": "list_files", "arguments": "{}"}}
+            ]},
+            {"role": "tool", "tool_call_id": "t1", "content": "a.png"}
+        ]),
+        Value::Array(to_openai(&system, &messages).unwrap())
+    );
+
+    let text_only = message(
+        ConversationRole::User,
+        vec![ContentBlock::Text("hi".into())],
+    );
+    assert_eq!(
+        vec![json!({"role": "user", "content": "hi"})],
+        to_openai(&[], &[text_only]).unwrap()
+    );
+}
+
+#[test]
+fn openai_import() {
+    let messages = json!([
+        {"role": "system", "content": "be brief"},
+        {"role": "user", "content": [
+            {"type": "text", "text": "what's this?"},
+            {"type": "image_url", "image_url": {"url": "data:image/png;base64,aGVsbG8="}}
+        ]},
+        {"role": "user", "content": "well?"},
+        {"role": "assistant", "content": "a picture"}
+    ]);
+    let (system, imported) = from_openai(messages.as_array().unwrap()).unwrap();
+    assert_eq!(
+        vec![SystemContentBlock::Text("be brief".to_string())],
+        system
+    );
+    assert_eq!(2, imported.len());
+    assert_eq!(&ConversationRole::User, imported[0].role());
+    assert_eq!(3, imported[0].content().len());
+    assert_eq!(
+        b"hello".as_slice(),
+        imported[0].content()[1]
+            .as_image()
+            .unwrap()
+            .source()
+            .unwrap()
+            .as_bytes()
+            .unwrap()
+            .as_ref()
+    );
+
+    // and back again
+    let system = vec![SystemContentBlock::Text("be brief".to_string())];
+    let exported = to_openai(&system, &imported).unwrap();
+    assert_eq!("a picture", exported[2]["content"]);
+
+    let tool = json!([{"role": "tool", "tool_call_id": "t1", "content": "a.png"}]);
+    let ConversionError(msg) = from_openai(tool.as_array().unwrap()).unwrap_err();
+    assert_eq!(
+        "message 1 has role tool: tool results aren't supported",
+        msg
+    );
+    let remote = json!([{"role": "user", "content": [
+        {"type": "image_url", "image_url": {"url": "https://example.com/a.png"}}
+    ]}]);
+    assert!(from_openai(remote.as_array().unwrap()).is_err());
+}
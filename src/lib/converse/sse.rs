@@ -0,0 +1,79 @@
+//! Formats a streamed response as Server-Sent Events (`--sse`)
+//!
+//! For a web service wrapping the CLI: each event is a `data: <json>` line followed by a
+//! blank line, which an HTTP handler can forward to a browser's EventSource unchanged.
+//! Text deltas are `{"type":"text","text":...}`, and reasoning deltas the same with
+//! type "thinking".  A response ends with `{"type":"stop",...}`, carrying its
+//! stopReason and usage as in Converse json, or `{"type":"error","message":...}` if it
+//! failed, and then `data: [DONE]`, as OpenAI's streams do.
+//!
+//! json strings escape newlines, so each event's data is always a single line.
+
+use aws_sdk_bedrockruntime::types::{StopReason, TokenUsage};
+use serde_json::{json, Value};
+
+/// The last event of each response
+pub const DONE: &str = "data: [DONE]\n\n";
+
+/// An event with the json as its data
+pub fn event(data: &Value) -> String {
+    format!("data: {}\n\n", data)
+}
+
+/// A text delta
+pub fn text(text: &str) -> String {
+    event(&json!({"type": "text", "text": text}))
+}
+
+/// A reasoning (extended thinking) text delta
+pub fn thinking(text: &str) -> String {
+    event(&json!({"type": "thinking", "text": text}))
+}
+
+/// The end of a response
+pub fn stop(stop_reason: &StopReason, usage: Option<&TokenUsage>) -> String {
+    let mut data = json!({"type": "stop", "stopReason": stop_reason.as_str()});
+    if let Some(usage) = usage {
+        data["usage"] = json!({
+            "inputTokens": usage.input_tokens,
+            "outputTokens": usage.output_tokens,
+            "totalTokens": usage.total_tokens,
+        });
+    }
+    event(&data)
+}
+
+/// A response that failed, before or part way through its stream
+pub fn error(message: &str) -> String {
+    event(&json!({"type": "error", "message": message}))
+}
+
+#[test]
+fn sse_events() {
+    assert_eq!(
+        "data: {\"text\":\"two\\nlines\",\"type\":\"text\"}\n\n",
+        text("two\nlines")
+    );
+    let usage = TokenUsage::builder()
+        .input_tokens(10)
+        .output_tokens(2)
+        .total_tokens(12)
+        .build()
+        .unwrap();
+    let stop = stop(&StopReason::EndTurn, Some(&usage));
+    let data: Value = serde_json::from_str(
+        stop.strip_prefix("data: ")
+            .and_then(|rest| rest.strip_suffix("\n\n"))
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        json!({
+            "type": "stop",
+            "stopReason": "end_turn",
+            "usage": {"inputTokens": 10, "outputTokens": 2, "totalTokens": 12}
+        }),
+        data
+    );
+    assert!(error("throttled").starts_with("data: {"));
+}
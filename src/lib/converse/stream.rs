@@ -0,0 +1,568 @@
+//! Reassembles a bedrock:ConverseStream response
+//!
+//! ConverseStream delivers the assistant's message as a sequence of events, with text
+//! arriving in deltas per content block.  `collect` hands each text delta to a callback
+//! as it arrives, and rebuilds the complete `Message` for the conversation history,
+//! including any reasoning (extended thinking) blocks, tool uses, whose json input
+//! is streamed in fragments, and document citations, which arrive alongside the text
+//! they cite.
+//!
+//! A stream can fail part way, e.g. on a network blip.  Bedrock can't resume it, but the
+//! text received so far is returned with the error, rather than lost.
+//!
+//! See:
+//! https://docs.aws.amazon.com/bedrock/latest/userguide/conversation-inference-call.html
+//! https://docs.rs/aws-sdk-bedrockruntime/latest/aws_sdk_bedrockruntime/operation/converse_stream/builders/struct.ConverseStreamFluentBuilder.html
+
+use std::collections::BTreeMap;
+
+use aws_sdk_bedrockruntime::error::SdkError;
+use aws_sdk_bedrockruntime::operation::converse_stream::ConverseStreamOutput as StreamOutput;
+use aws_sdk_bedrockruntime::operation::RequestId;
+use aws_sdk_bedrockruntime::types::error::ConverseStreamOutputError;
+use aws_sdk_bedrockruntime::types::{
+    Citation, CitationGeneratedContent, CitationSourceContent, CitationsContentBlock,
+    CitationsDelta, ContentBlock, ContentBlockDelta, ContentBlockStart, ConversationRole,
+    ConverseStreamOutput, GuardrailTraceAssessment, Message, ReasoningContentBlock,
+    ReasoningContentBlockDelta, ReasoningTextBlock, StopReason, TokenUsage, ToolUseBlock,
+};
+use aws_smithy_types::event_stream::RawMessage;
+use aws_smithy_types::{Blob, Document};
+use log::{debug, warn};
+
+use crate::converse::tool_use;
+use crate::redact;
+
+pub type StreamError = SdkError<ConverseStreamOutputError, RawMessage>;
+
+/// The assistant message rebuilt from a stream, along with the stream's metadata
+#[derive(Debug)]
+pub struct StreamedResponse {
+    pub message: Message,
+    pub stop_reason: Option<StopReason>,
+    pub usage: Option<TokenUsage>,
+    /// Model specific fields asked for with `additional_model_response_field_paths`
+    pub additional_fields: Option<Document>,
+    /// The model a prompt router sent the request to
+    pub invoked_model_id: Option<String>,
+    /// What a guardrail assessed, when its trace is enabled
+    pub guardrail_trace: Option<GuardrailTraceAssessment>,
+}
+
+/// A stream that failed before it finished
+#[derive(Debug)]
+pub struct Interrupted {
+    pub error: StreamError,
+    /// The request's id, which AWS support calls the trace id
+    pub request_id: Option<String>,
+    /// The text received before the failure.  Unfinished reasoning and tool uses are
+    /// dropped, since Bedrock would reject them in a later turn.
+    pub partial: Message,
+}
+
+/// Consumes the stream, calling `on_text` with each text delta as it arrives, and
+/// `on_thinking` with each reasoning (extended thinking) text delta.
+pub async fn collect(
+    mut output: StreamOutput,
+    mut on_text: impl FnMut(&str),
+    mut on_thinking: impl FnMut(&str),
+) -> Result<StreamedResponse, Interrupted> {
+    let request_id = output.request_id().map(str::to_string);
+    let mut collector = Collector::default();
+    loop {
+        match output.stream.recv().await {
+            Ok(Some(event)) => {
+                debug!("{}", redact::debug(&event));
+                collector.event(event, &mut on_text, &mut on_thinking);
+            }
+            Ok(None) => return Ok(collector.finish()),
+            Err(error) => {
+                return Err(Interrupted {
+                    error,
+                    request_id,
+                    partial: collector.partial(),
+                })
+            }
+        }
+    }
+}
+
+/// The partial answer of an interrupted stream, as a prefill for the model to continue:
+/// the start of the assistant's message, sent as the last message.  Trailing whitespace
+/// is trimmed, since models reject a prefill ending in it.
+pub fn prefill(partial: &Message) -> Message {
+    let mut prefill = partial.clone();
+    if let Some(ContentBlock::Text(text)) = prefill.content.last_mut() {
+        text.truncate(text.trim_end().len());
+    }
+    prefill
+}
+
+/// Joins a prefill and the model's continuation of it into one answer.  Unfinished
+/// answers are text, so only the continuation's text is kept.
+pub fn join(prefill: Message, continuation: &Message) -> Message {
+    let mut answer = prefill;
+    for content in continuation.content() {
+        match (answer.content.last_mut(), content) {
+            (Some(ContentBlock::Text(text)), ContentBlock::Text(more)) => text.push_str(more),
+            (_, ContentBlock::Text(_) | ContentBlock::CitationsContent(_)) => {
+                answer.content.push(content.clone())
+            }
+            _ => {}
+        }
+    }
+    answer
+}
+
+/// The state of a stream being collected, updated an event at a time
+#[derive(Default)]
+struct Collector {
+    /// content blocks, keyed by their index in the final message
+    blocks: BTreeMap<i32, Block>,
+    stop_reason: Option<StopReason>,
+    usage: Option<TokenUsage>,
+    additional_fields: Option<Document>,
+    invoked_model_id: Option<String>,
+    guardrail_trace: Option<GuardrailTraceAssessment>,
+}
+impl Collector {
+    fn event(
+        &mut self,
+        event: ConverseStreamOutput,
+        on_text: &mut impl FnMut(&str),
+        on_thinking: &mut impl FnMut(&str),
+    ) {
+        match event {
+            ConverseStreamOutput::ContentBlockStart(start) => {
+                if let Some(ContentBlockStart::ToolUse(tool_use)) = start.start() {
+                    self.blocks.insert(
+                        start.content_block_index(),
+                        Block::ToolUse {
+                            id: tool_use.tool_use_id().to_string(),
+                            name: tool_use.name().to_string(),
+                            input: String::new(),
+                        },
+                    );
+                }
+            }
+            ConverseStreamOutput::ContentBlockDelta(event) => {
+                let index = event.content_block_index();
+                match event.delta() {
+                    Some(ContentBlockDelta::Text(text)) => {
+                        on_text(text);
+                        match self
+                            .blocks
+                            .entry(index)
+                            .or_insert(Block::Text(String::new()))
+                        {
+                            Block::Text(block) | Block::Cited { text: block, .. } => {
+                                block.push_str(text)
+                            }
+                            block => warn!("text delta for a non-text block: {:?}", block),
+                        }
+                    }
+                    Some(ContentBlockDelta::Citation(delta)) => {
+                        let block = self
+                            .blocks
+                            .entry(index)
+                            .or_insert(Block::Text(String::new()));
+                        if let Block::Text(text) = block {
+                            *block = Block::Cited {
+                                text: std::mem::take(text),
+                                citations: vec![],
+                            };
+                        }
+                        match block {
+                            Block::Cited { citations, .. } => citations.push(citation(delta)),
+                            block => warn!("citation delta for a non-text block: {:?}", block),
+                        }
+                    }
+                    // tool input arrives as fragments of a json object
+                    Some(ContentBlockDelta::ToolUse(delta)) => match self.blocks.get_mut(&index) {
+                        Some(Block::ToolUse { input, .. }) => input.push_str(delta.input()),
+                        block => warn!("tool use delta for a non-tool use block: {:?}", block),
+                    },
+                    Some(ContentBlockDelta::ReasoningContent(delta)) => {
+                        let block = self.blocks.entry(index).or_insert(Block::Reasoning {
+                            text: String::new(),
+                            signature: None,
+                            redacted: vec![],
+                        });
+                        let Block::Reasoning {
+                            text,
+                            signature,
+                            redacted,
+                        } = block
+                        else {
+                            warn!("reasoning delta for a non-reasoning block: {:?}", block);
+                            return;
+                        };
+                        match delta {
+                            ReasoningContentBlockDelta::Text(delta) => {
+                                on_thinking(delta);
+                                text.push_str(delta);
+                            }
+                            ReasoningContentBlockDelta::Signature(delta) => {
+                                signature.get_or_insert_with(String::new).push_str(delta)
+                            }
+                            ReasoningContentBlockDelta::RedactedContent(delta) => {
+                                redacted.extend_from_slice(delta.as_ref())
+                            }
+                            _ => warn!("unknown reasoning delta: {:?}", delta),
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            ConverseStreamOutput::MessageStop(stop) => {
+                self.stop_reason = Some(stop.stop_reason);
+                self.additional_fields = stop.additional_model_response_fields;
+            }
+            ConverseStreamOutput::Metadata(metadata) => {
+                self.invoked_model_id = metadata
+                    .trace()
+                    .and_then(|trace| trace.prompt_router())
+                    .and_then(|router| router.invoked_model_id())
+                    .map(str::to_string);
+                self.guardrail_trace = metadata.trace.and_then(|trace| trace.guardrail);
+                self.usage = metadata.usage;
+            }
+            _ => {}
+        }
+    }
+
+    /// The complete text blocks received so far, as an assistant message
+    fn partial(self) -> Message {
+        let content = self
+            .blocks
+            .into_values()
+            .filter(|block| matches!(block, Block::Text(_) | Block::Cited { .. }))
+            .map(Block::into_content)
+            .collect();
+        Message::builder()
+            .role(ConversationRole::Assistant)
+            .set_content(Some(content))
+            .build()
+            .unwrap()
+    }
+
+    fn finish(self) -> StreamedResponse {
+        let content = self.blocks.into_values().map(Block::into_content).collect();
+        let message = Message::builder()
+            .role(ConversationRole::Assistant)
+            .set_content(Some(content))
+            .build()
+            .unwrap();
+
+        StreamedResponse {
+            message,
+            stop_reason: self.stop_reason,
+            usage: self.usage,
+            additional_fields: self.additional_fields,
+            invoked_model_id: self.invoked_model_id,
+            guardrail_trace: self.guardrail_trace,
+        }
+    }
+}
+
+/// A content block being reassembled from its deltas
+#[derive(Debug)]
+enum Block {
+    Text(String),
+    /// Text that cites the request's documents
+    Cited {
+        text: String,
+        citations: Vec<Citation>,
+    },
+    /// Reasoning is sent back in later turns exactly as received, signature included,
+    /// so Bedrock can verify it wasn't altered.
+    Reasoning {
+        text: String,
+        signature: Option<String>,
+        redacted: Vec<u8>,
+    },
+    /// `input` is the json text so far, only parsed once the block is complete
+    ToolUse {
+        id: String,
+        name: String,
+        input: String,
+    },
+}
+impl Block {
+    fn into_content(self) -> ContentBlock {
+        match self {
+            Block::Text(text) => ContentBlock::Text(text),
+            Block::Cited { text, citations } => {
+                let block = CitationsContentBlock::builder()
+                    .content(CitationGeneratedContent::Text(text))
+                    .set_citations(Some(citations))
+                    .build();
+                ContentBlock::CitationsContent(block)
+            }
+            Block::Reasoning { redacted, .. } if !redacted.is_empty() => {
+                ContentBlock::ReasoningContent(ReasoningContentBlock::RedactedContent(Blob::new(
+                    redacted,
+                )))
+            }
+            Block::Reasoning {
+                text, signature, ..
+            } => {
+                let block = ReasoningTextBlock::builder()
+                    .text(text)
+                    .set_signature(signature)
+                    .build()
+                    .unwrap();
+                ContentBlock::ReasoningContent(ReasoningContentBlock::ReasoningText(block))
+            }
+            Block::ToolUse { id, name, input } => {
+                // a tool without arguments may get no input deltas at all
+                let input = match input.trim() {
+                    "" => serde_json::json!({}),
+                    input => serde_json::from_str(input).unwrap_or_else(|err| {
+                        warn!("malformed input for tool {}: {} ({})", name, input, err);
+                        serde_json::json!({})
+                    }),
+                };
+                let block = ToolUseBlock::builder()
+                    .tool_use_id(id)
+                    .name(name)
+                    .input(tool_use::json_to_document(&input))
+                    .build()
+                    .unwrap();
+                ContentBlock::ToolUse(block)
+            }
+        }
+    }
+}
+
+/// A citation as it arrives in a stream, which is whole rather than in fragments
+fn citation(delta: &CitationsDelta) -> Citation {
+    let source_content = delta
+        .source_content()
+        .iter()
+        .filter_map(|content| content.text())
+        .map(|text| CitationSourceContent::Text(text.to_string()))
+        .collect();
+    Citation::builder()
+        .set_title(delta.title().map(str::to_string))
+        .set_source(delta.source().map(str::to_string))
+        .set_source_content(Some(source_content))
+        .set_location(delta.location().cloned())
+        .build()
+}
+
+#[test]
+fn reasoning_blocks() {
+    let block = Block::Reasoning {
+        text: "hmm".to_string(),
+        signature: Some("sig".to_string()),
+        redacted: vec![],
+    };
+    let ContentBlock::ReasoningContent(ReasoningContentBlock::ReasoningText(reasoning)) =
+        block.into_content()
+    else {
+        panic!("expected reasoning text");
+    };
+    assert_eq!("hmm", reasoning.text());
+    assert_eq!(Some("sig"), reasoning.signature());
+
+    let block = Block::Reasoning {
+        text: String::new(),
+        signature: None,
+        redacted: vec![1, 2, 3],
+    };
+    assert!(matches!(
+        block.into_content(),
+        ContentBlock::ReasoningContent(ReasoningContentBlock::RedactedContent(_))
+    ));
+}
+
+#[test]
+fn streamed_tool_use() {
+    let (collector, texts) = collect_events([
+        delta_event(0, ContentBlockDelta::Text("Checking".to_string())),
+        tool_use_start(1, "tooluse-1", "weather"),
+        delta_event(1, tool_input(r#"{"city": "Sea"#)),
+        delta_event(1, tool_input(r#"ttle", "days""#)),
+        delta_event(1, tool_input(": 3}")),
+    ]);
+    let response = collector.finish();
+
+    assert_eq!(vec!["Checking"], texts);
+    let content = response.message.content();
+    assert_eq!("Checking", content[0].as_text().unwrap());
+    let tool_use = content[1].as_tool_use().unwrap();
+    assert_eq!("tooluse-1", tool_use.tool_use_id());
+    assert_eq!("weather", tool_use.name());
+    assert_eq!(
+        serde_json::json!({"city": "Seattle", "days": 3}),
+        tool_use::document_to_json(tool_use.input())
+    );
+}
+
+#[test]
+fn interrupted_stream_keeps_text() {
+    let (collector, _) = collect_events([
+        delta_event(0, ContentBlockDelta::Text("Let me ".to_string())),
+        delta_event(0, ContentBlockDelta::Text("check".to_string())),
+        tool_use_start(1, "tooluse-1", "weather"),
+        delta_event(1, tool_input(r#"{"city": "Sea"#)),
+    ]);
+    let partial = collector.partial();
+
+    // the half-streamed tool use is dropped
+    assert_eq!(1, partial.content().len());
+    assert_eq!("Let me check", partial.content()[0].as_text().unwrap());
+}
+
+#[test]
+fn resumed_answers() {
+    let message = |texts: &[&str]| {
+        Message::builder()
+            .role(ConversationRole::Assistant)
+            .set_content(Some(
+                texts
+                    .iter()
+                    .map(|text| ContentBlock::Text(text.to_string()))
+                    .collect(),
+            ))
+            .build()
+            .unwrap()
+    };
+    let prefill = prefill(&message(&["The three causes were \n"]));
+    assert_eq!(
+        "The three causes were",
+        prefill.content()[0].as_text().unwrap()
+    );
+
+    let answer = join(prefill, &message(&[" drought, war, and debt."]));
+    assert_eq!(1, answer.content().len());
+    assert_eq!(
+        "The three causes were drought, war, and debt.",
+        answer.content()[0].as_text().unwrap()
+    );
+}
+
+#[test]
+fn streamed_citations() {
+    use aws_sdk_bedrockruntime::types::{
+        CitationLocation, CitationSourceContentDelta, DocumentCharLocation,
+    };
+
+    let location = DocumentCharLocation::builder()
+        .document_index(0)
+        .start(4)
+        .end(20)
+        .build();
+    let cite = CitationsDelta::builder()
+        .title("notes")
+        .source_content(
+            CitationSourceContentDelta::builder()
+                .text("due Friday")
+                .build(),
+        )
+        .location(CitationLocation::DocumentChar(location))
+        .build();
+
+    let (collector, texts) = collect_events([
+        delta_event(0, ContentBlockDelta::Text("It's due ".to_string())),
+        delta_event(0, ContentBlockDelta::Citation(cite)),
+        delta_event(0, ContentBlockDelta::Text("Friday.".to_string())),
+    ]);
+    let response = collector.finish();
+
+    assert_eq!(vec!["It's due ", "Friday."], texts);
+    let cited = response.message.content()[0]
+        .as_citations_content()
+        .unwrap();
+    assert_eq!(
+        vec![CitationGeneratedContent::Text(
+            "It's due Friday.".to_string()
+        )],
+        cited.content()
+    );
+    assert_eq!(1, cited.citations().len());
+    assert_eq!(Some("notes"), cited.citations()[0].title());
+    assert_eq!(
+        vec![CitationSourceContent::Text("due Friday".to_string())],
+        cited.citations()[0].source_content()
+    );
+}
+
+#[test]
+fn routed_model() {
+    use aws_sdk_bedrockruntime::types::{
+        ConverseStreamMetadataEvent, ConverseStreamTrace, PromptRouterTrace,
+    };
+
+    let trace = ConverseStreamTrace::builder()
+        .prompt_router(
+            PromptRouterTrace::builder()
+                .invoked_model_id("anthropic.claude-3-haiku-20240307-v1:0")
+                .build(),
+        )
+        .build();
+    let metadata = ConverseStreamMetadataEvent::builder().trace(trace).build();
+
+    let mut collector = Collector::default();
+    collector.event(
+        ConverseStreamOutput::Metadata(metadata),
+        &mut |_| {},
+        &mut |_| {},
+    );
+    assert_eq!(
+        Some("anthropic.claude-3-haiku-20240307-v1:0"),
+        collector.finish().invoked_model_id.as_deref()
+    );
+}
+
+/// Runs the events through a collector, returning it and the text deltas it passed on.
+#[cfg(test)]
+fn collect_events<const N: usize>(events: [ConverseStreamOutput; N]) -> (Collector, Vec<String>) {
+    let mut texts = vec![];
+    let mut collector = Collector::default();
+    for event in events {
+        collector.event(event, &mut |text| texts.push(text.to_string()), &mut |_| {});
+    }
+    (collector, texts)
+}
+
+#[cfg(test)]
+fn delta_event(index: i32, delta: ContentBlockDelta) -> ConverseStreamOutput {
+    ConverseStreamOutput::ContentBlockDelta(
+        aws_sdk_bedrockruntime::types::ContentBlockDeltaEvent::builder()
+            .content_block_index(index)
+            .delta(delta)
+            .build()
+            .unwrap(),
+    )
+}
+
+#[cfg(test)]
+fn tool_use_start(index: i32, tool_use_id: &str, name: &str) -> ConverseStreamOutput {
+    use aws_sdk_bedrockruntime::types::{ContentBlockStartEvent, ToolUseBlockStart};
+
+    let start = ToolUseBlockStart::builder()
+        .tool_use_id(tool_use_id)
+        .name(name)
+        .build()
+        .unwrap();
+    ConverseStreamOutput::ContentBlockStart(
+        ContentBlockStartEvent::builder()
+            .content_block_index(index)
+            .start(ContentBlockStart::ToolUse(start))
+            .build()
+            .unwrap(),
+    )
+}
+
+/// A fragment of a tool use's json input
+#[cfg(test)]
+fn tool_input(fragment: &str) -> ContentBlockDelta {
+    ContentBlockDelta::ToolUse(
+        aws_sdk_bedrockruntime::types::ToolUseBlockDelta::builder()
+            .input(fragment)
+            .build()
+            .unwrap(),
+    )
+}
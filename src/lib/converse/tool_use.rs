@@ -21,10 +21,19 @@
 //!
 //! let tool_config = tool_use::mk_tool(name, description, inputs)
 //! ```
+//!
+//! To offer several tools at once, build each with `mk_tool_spec` and combine them
+//! with `mk_tool_config`.  Results are sent back to the model with `tool_result`.
+//!
+//! For structured output, `mk_json_format` builds a tool whose input is the user's json
+//! schema, and has the model call it: the tool use's input is the answer.
 
 use std::{collections::HashMap, fmt::Display};
 
-use aws_sdk_bedrockruntime::types::{Tool, ToolConfiguration, ToolInputSchema, ToolSpecification};
+use aws_sdk_bedrockruntime::types::{
+    ContentBlock, SpecificToolChoice, Tool, ToolChoice, ToolConfiguration, ToolInputSchema,
+    ToolResultBlock, ToolResultContentBlock, ToolResultStatus, ToolSpecification,
+};
 use aws_smithy_types::Document;
 
 /// Rust struct representation of a tool's argument.
@@ -91,6 +100,11 @@ pub fn mk_tool(
     description: impl ToString,
     inputs: Vec<ToolArg>,
 ) -> ToolConfiguration {
+    mk_tool_config(vec![mk_tool_spec(name, description, inputs)])
+}
+
+/// Builds a single tool, for combining several tools with `mk_tool_config`.
+pub fn mk_tool_spec(name: impl ToString, description: impl ToString, inputs: Vec<ToolArg>) -> Tool {
     let mut arg_map = HashMap::new();
     let mut required: Vec<Document> = vec![];
 
@@ -119,6 +133,203 @@ pub fn mk_tool(
         .input_schema(input_schema)
         .build()
         .unwrap();
-    let tool = Tool::ToolSpec(spec);
-    ToolConfiguration::builder().tools(tool).build().unwrap()
+    Tool::ToolSpec(spec)
+}
+
+/// Combines tools into the config sent with each Converse request.
+pub fn mk_tool_config(tools: Vec<Tool>) -> ToolConfiguration {
+    ToolConfiguration::builder()
+        .set_tools(Some(tools))
+        .build()
+        .unwrap()
+}
+
+/// The tool `mk_json_format` has the model call with its answer
+pub const JSON_FORMAT_TOOL: &str = "respond_in_json";
+
+#[derive(Debug, PartialEq)]
+pub struct InvalidSchema(pub String);
+
+/// A tool config that makes the model answer with json matching `schema`, by offering a
+/// single tool taking it as its input and pinning the tool choice to that tool.
+///
+/// Tool inputs are json objects, so the schema must be for an object, e.g.
+/// {"type": "object", "properties": {"city": {"type": "string"}}, "required": ["city"]}
+pub fn mk_json_format(schema: &serde_json::Value) -> Result<ToolConfiguration, InvalidSchema> {
+    let Some(fields) = schema.as_object() else {
+        return Err(InvalidSchema(
+            "the schema must be a json object".to_string(),
+        ));
+    };
+    if fields.get("type").and_then(|kind| kind.as_str()) != Some("object") {
+        return Err(InvalidSchema(
+            "the schema must have \"type\": \"object\", as tool input is an object".to_string(),
+        ));
+    }
+    for keyword in ["properties", "$defs", "definitions"] {
+        if fields.get(keyword).is_some_and(|value| !value.is_object()) {
+            return Err(InvalidSchema(format!("{} must be an object", keyword)));
+        }
+    }
+    if fields.get("required").is_some_and(|value| {
+        !value
+            .as_array()
+            .is_some_and(|names| names.iter().all(|name| name.is_string()))
+    }) {
+        return Err(InvalidSchema(
+            "required must be an array of property names".to_string(),
+        ));
+    }
+
+    let spec = ToolSpecification::builder()
+        .name(JSON_FORMAT_TOOL)
+        .description("Responds to the user with json matching the input schema")
+        .input_schema(ToolInputSchema::Json(json_to_document(schema)))
+        .build()
+        .unwrap();
+    let choice = SpecificToolChoice::builder()
+        .name(JSON_FORMAT_TOOL)
+        .build()
+        .unwrap();
+    Ok(ToolConfiguration::builder()
+        .tools(Tool::ToolSpec(spec))
+        .tool_choice(ToolChoice::Tool(choice))
+        .build()
+        .unwrap())
+}
+
+/// Wraps a tool's text output as the result block for the matching tool use.
+pub fn tool_result(tool_use_id: impl ToString, output: impl ToString) -> ContentBlock {
+    let result = ToolResultBlock::builder()
+        .tool_use_id(tool_use_id.to_string())
+        .content(ToolResultContentBlock::Text(output.to_string()))
+        .build()
+        .unwrap();
+    ContentBlock::ToolResult(result)
+}
+
+/// Reports a tool failure as the result block for the matching tool use.
+///
+/// The `error` status tells the model the tool didn't run successfully, so it can retry
+/// or work around it rather than treating `message` as the tool's output.  Not every
+/// model supports the status field (Claude and Nova do).
+pub fn tool_result_error(tool_use_id: impl ToString, message: impl ToString) -> ContentBlock {
+    let result = ToolResultBlock::builder()
+        .tool_use_id(tool_use_id.to_string())
+        .content(ToolResultContentBlock::Text(message.to_string()))
+        .status(ToolResultStatus::Error)
+        .build()
+        .unwrap();
+    ContentBlock::ToolResult(result)
+}
+
+/// Reads a string argument out of a tool use's input document.
+pub fn tool_input_str<'a>(input: &'a Document, name: &str) -> Option<&'a str> {
+    input.as_object()?.get(name)?.as_string()
+}
+
+/// Converts a Converse document (tool input, additional model response fields) to json.
+pub fn document_to_json(document: &Document) -> serde_json::Value {
+    use aws_smithy_types::Number;
+    use serde_json::Value;
+
+    match document {
+        Document::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| (key.clone(), document_to_json(value)))
+                .collect(),
+        ),
+        Document::Array(values) => Value::Array(values.iter().map(document_to_json).collect()),
+        Document::Number(Number::PosInt(n)) => Value::from(*n),
+        Document::Number(Number::NegInt(n)) => Value::from(*n),
+        Document::Number(Number::Float(n)) => Value::from(*n),
+        Document::String(s) => Value::String(s.clone()),
+        Document::Bool(b) => Value::Bool(*b),
+        Document::Null => Value::Null,
+    }
+}
+
+/// Converts json to a Converse document, e.g. for tool input reassembled from a stream.
+pub fn json_to_document(json: &serde_json::Value) -> Document {
+    use aws_smithy_types::Number;
+    use serde_json::Value;
+
+    match json {
+        Value::Object(map) => Document::Object(
+            map.iter()
+                .map(|(key, value)| (key.clone(), json_to_document(value)))
+                .collect(),
+        ),
+        Value::Array(values) => Document::Array(values.iter().map(json_to_document).collect()),
+        Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(n), _) => Document::Number(Number::PosInt(n)),
+            (None, Some(n)) => Document::Number(Number::NegInt(n)),
+            _ => Document::Number(Number::Float(n.as_f64().unwrap_or_default())),
+        },
+        Value::String(s) => Document::String(s.clone()),
+        Value::Bool(b) => Document::Bool(*b),
+        Value::Null => Document::Null,
+    }
+}
+
+#[test]
+fn result_status() {
+    let status = |block: ContentBlock| block.as_tool_result().unwrap().status().cloned();
+    assert_eq!(None, status(tool_result("id", "ok")));
+    assert_eq!(
+        Some(ToolResultStatus::Error),
+        status(tool_result_error("id", "no such file"))
+    );
+}
+
+#[test]
+fn document_json() {
+    let document = Document::Object(HashMap::from([
+        ("city".to_string(), Document::String("Seattle".to_string())),
+        (
+            "days".to_string(),
+            Document::Array(vec![
+                Document::Number(aws_smithy_types::Number::PosInt(1)),
+                Document::Null,
+            ]),
+        ),
+    ]));
+    assert_eq!(
+        serde_json::json!({"city": "Seattle", "days": [1, null]}),
+        document_to_json(&document)
+    );
+
+    let json = serde_json::json!({"n": -2, "x": 0.5, "ok": true, "list": ["a"]});
+    assert_eq!(json, document_to_json(&json_to_document(&json)));
+}
+
+#[test]
+fn json_formats() {
+    let schema = serde_json::json!({
+        "type": "object",
+        "properties": {"city": {"type": "string"}},
+        "required": ["city"]
+    });
+    let config = mk_json_format(&schema).unwrap();
+    let Some(ToolChoice::Tool(choice)) = config.tool_choice() else {
+        panic!("tool choice isn't pinned");
+    };
+    assert_eq!(JSON_FORMAT_TOOL, choice.name());
+    let Tool::ToolSpec(spec) = &config.tools()[0] else {
+        panic!("not a tool spec");
+    };
+    let Some(ToolInputSchema::Json(input)) = spec.input_schema() else {
+        panic!("no input schema");
+    };
+    assert_eq!(schema, document_to_json(input));
+
+    let invalid = |schema| mk_json_format(&schema).is_err();
+    assert!(invalid(serde_json::json!(["type", "object"])));
+    assert!(invalid(serde_json::json!({"type": "array"})));
+    assert!(invalid(
+        serde_json::json!({"type": "object", "properties": []})
+    ));
+    assert!(invalid(
+        serde_json::json!({"type": "object", "required": "city"})
+    ));
 }
@@ -0,0 +1,50 @@
+//! Line diffs between two texts, e.g. a saved answer and a live one
+//!
+//! The diff is unified style, without hunk headers: every line of both texts is kept,
+//! prefixed with "  " when it's in both, "- " when it's only in the first, and "+ "
+//! when it's only in the second.  Lines are matched by their longest common
+//! subsequence, which is fine for model answers but quadratic in the line count.
+
+/// The lines from `old` to `new`, each prefixed with "  ", "- ", or "+ ".
+pub fn lines(old: &str, new: &str) -> Vec<String> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+
+    // common[i][j] is the longest common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = match old[i] == new[j] {
+                true => common[i + 1][j + 1] + 1,
+                false => common[i + 1][j].max(common[i][j + 1]),
+            };
+        }
+    }
+
+    let mut diff = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            diff.push(format!("  {}", old[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if j == new.len() || (i < old.len() && common[i + 1][j] >= common[i][j + 1]) {
+            diff.push(format!("- {}", old[i]));
+            i += 1;
+        } else {
+            diff.push(format!("+ {}", new[j]));
+            j += 1;
+        }
+    }
+    diff
+}
+
+#[test]
+fn line_diffs() {
+    assert_eq!(
+        vec!["  one", "- two", "+ 2", "  three", "+ four"],
+        lines("one\ntwo\nthree", "one\n2\nthree\nfour")
+    );
+    assert_eq!(vec!["  same"], lines("same", "same"));
+    assert_eq!(vec!["- gone"], lines("gone", ""));
+    assert!(lines("", "").is_empty());
+}
@@ -0,0 +1,275 @@
+//! Setup diagnostics for AWS connectivity and Bedrock permissions
+//!
+//! New users often can't tell whether a failure comes from credentials, region, or
+//! model access.  The checks here run in order and report pass/fail with a hint on
+//! how to remediate each failure.
+//!
+//! `warm_up` resolves the credentials ahead of a batch of runs, and says when the ones
+//! it fetched expire.
+
+use std::fmt::Display;
+use std::time::{Duration, SystemTime};
+
+use aws_credential_types::provider::ProvideCredentials;
+use aws_sdk_bedrockruntime::error::{DisplayErrorContext, SdkError};
+use aws_sdk_bedrockruntime::operation::converse::ConverseError;
+use aws_sdk_bedrockruntime::types::{
+    ContentBlock, ConversationRole, InferenceConfiguration, Message,
+};
+
+use crate::ClientOptions;
+
+pub enum Status {
+    Pass,
+    Fail,
+    Skip,
+}
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Status::Pass => write!(f, "PASS"),
+            Status::Fail => write!(f, "FAIL"),
+            Status::Skip => write!(f, "SKIP"),
+        }
+    }
+}
+
+/// Outcome of a single diagnostic check
+pub struct Check {
+    pub name: String,
+    pub status: Status,
+    pub detail: String,
+    pub hint: Option<String>,
+}
+impl Check {
+    fn pass(name: &str, detail: impl ToString) -> Self {
+        Self {
+            name: name.to_string(),
+            status: Status::Pass,
+            detail: detail.to_string(),
+            hint: None,
+        }
+    }
+
+    fn fail(name: &str, detail: impl ToString, hint: impl ToString) -> Self {
+        Self {
+            name: name.to_string(),
+            status: Status::Fail,
+            detail: detail.to_string(),
+            hint: Some(hint.to_string()),
+        }
+    }
+
+    fn skip(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            status: Status::Skip,
+            detail: "skipped due to earlier failure".to_string(),
+            hint: None,
+        }
+    }
+
+    fn is_pass(&self) -> bool {
+        matches!(self.status, Status::Pass)
+    }
+}
+impl Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}: {}", self.status, self.name, self.detail)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n       hint: {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs each check in sequence, skipping the remaining ones once a check fails.
+///
+/// The model check sends a single-token Converse request, so it incurs a (tiny) cost.
+pub async fn run_checks(options: &ClientOptions, model_id: String) -> Vec<Check> {
+    let config = crate::load_sdk_config(options).await;
+    let profile_hint = match &options.aws_profile {
+        Some(profile) => format!("check the [profile {}] section of ~/.aws/config", profile),
+        None => "set AWS_REGION or a region in the [default] section of ~/.aws/config".to_string(),
+    };
+
+    let mut checks = vec![];
+
+    // ---- region ----
+    match config.region() {
+        Some(region) => checks.push(Check::pass("region", region)),
+        None => checks.push(Check::fail("region", "no region resolved", profile_hint)),
+    }
+
+    // ---- credentials ----
+    let creds = match config.credentials_provider() {
+        Some(provider) => provider
+            .provide_credentials()
+            .await
+            .map_err(|e| e.to_string()),
+        None => Err("no credentials provider configured".to_string()),
+    };
+    match creds {
+        Ok(creds) => {
+            let expiry = match creds.expiry() {
+                Some(_) => "temporary",
+                None => "long-lived",
+            };
+            checks.push(Check::pass(
+                "credentials",
+                format!(
+                    "resolved {} credentials for {}",
+                    expiry,
+                    creds.access_key_id()
+                ),
+            ))
+        }
+        Err(err) => checks.push(Check::fail(
+            "credentials",
+            err,
+            "run `aws sso login` or check ~/.aws/credentials (and --aws-profile)",
+        )),
+    }
+
+    // ---- bedrock:ListFoundationModels ----
+    if checks.iter().all(Check::is_pass) {
+        let cpclient = aws_sdk_bedrock::Client::new(&config);
+        match cpclient.list_foundation_models().send().await {
+            Ok(output) => checks.push(Check::pass(
+                "bedrock:ListFoundationModels",
+                format!("{} models visible", output.model_summaries().len()),
+            )),
+            Err(err) => checks.push(Check::fail(
+                "bedrock:ListFoundationModels",
+                DisplayErrorContext(err),
+                "grant the caller bedrock:ListFoundationModels, and check Bedrock is offered in this region",
+            )),
+        }
+    } else {
+        checks.push(Check::skip("bedrock:ListFoundationModels"));
+    }
+
+    // ---- model access ----
+    let name = format!("model access ({})", model_id);
+    if checks.iter().all(Check::is_pass) {
+        let client = aws_sdk_bedrockruntime::Client::new(&config);
+        let msg = Message::builder()
+            .role(ConversationRole::User)
+            .content(ContentBlock::Text("ping".to_string()))
+            .build()
+            .unwrap();
+        let result = client
+            .converse()
+            .model_id(model_id)
+            .messages(msg)
+            .inference_config(InferenceConfiguration::builder().max_tokens(1).build())
+            .send()
+            .await;
+        match result {
+            Ok(_) => checks.push(Check::pass(&name, "model responded")),
+            Err(err) => {
+                let hint = model_access_hint(&err);
+                checks.push(Check::fail(&name, DisplayErrorContext(err), hint))
+            }
+        }
+    } else {
+        checks.push(Check::skip(&name));
+    }
+
+    checks
+}
+
+#[derive(Debug)]
+pub struct WarmupError(pub String);
+
+/// Credentials resolved by `warm_up`
+#[derive(Debug)]
+pub struct Warmed {
+    /// Where they came from, e.g. "SSO (profile dev)"
+    pub source: String,
+    /// When the credentials this process fetched expire, None for long-lived ones.  For
+    /// SSO these are the role credentials, not the session's token.
+    pub expiry: Option<SystemTime>,
+}
+
+/// Resolves the credentials, as the first request of a run would.
+///
+/// For SSO profiles this refreshes the session's access token if it's due, and the SDK
+/// saves the refreshed token to ~/.aws/sso/cache, so the runs after it don't each stop
+/// to refresh it.  The role credentials it gets in exchange are only cached in memory,
+/// for this process, so each run still fetches its own: warming up can't make them
+/// outlive the SSO session, whose expiry is what the runs depend on.  `Warmed::expiry`
+/// is the role credentials' expiry, so it only says how long this process's copy lasts.
+pub async fn warm_up(options: &ClientOptions) -> Result<Warmed, WarmupError> {
+    let config = crate::load_sdk_config(options).await;
+    let provider = config
+        .credentials_provider()
+        .ok_or_else(|| WarmupError("no credentials provider configured".to_string()))?;
+    let creds = provider
+        .provide_credentials()
+        .await
+        .map_err(|err| WarmupError(DisplayErrorContext(err).to_string()))?;
+    Ok(Warmed {
+        source: crate::credential_source(
+            &crate::provider_name(&creds),
+            &crate::profile_name(options),
+        ),
+        expiry: creds.expiry(),
+    })
+}
+
+/// Describes when credentials expire, e.g. "expire at 2026-10-14T18:30:00Z (in 7h 58m)".
+pub fn describe_expiry(expiry: Option<SystemTime>, now: SystemTime) -> String {
+    let Some(expiry) = expiry else {
+        return "don't expire (long-lived keys)".to_string();
+    };
+    let at = aws_smithy_types::DateTime::from(expiry)
+        .fmt(aws_smithy_types::date_time::Format::DateTime)
+        .unwrap_or_else(|_| format!("{:?}", expiry));
+    match expiry.duration_since(now) {
+        Ok(left) => format!("expire at {} (in {})", at, hours_minutes(left)),
+        Err(_) => format!("expired at {}", at),
+    }
+}
+
+fn hours_minutes(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    match minutes / 60 {
+        0 => format!("{}m", minutes),
+        hours => format!("{}h {}m", hours, minutes % 60),
+    }
+}
+
+fn model_access_hint(err: &SdkError<ConverseError>) -> &'static str {
+    match err.as_service_error() {
+        Some(ConverseError::AccessDeniedException(_)) => {
+            "request model access in the Bedrock console and grant the caller bedrock:InvokeModel: https://docs.aws.amazon.com/bedrock/latest/userguide/model-access.html"
+        }
+        Some(ConverseError::ResourceNotFoundException(_))
+        | Some(ConverseError::ValidationException(_)) => {
+            "check the model id; some models are only reachable through an inference profile id (e.g. us.amazon.nova-lite-v1:0)"
+        }
+        Some(ConverseError::ThrottlingException(_)) => {
+            "the model is reachable but throttled; try again shortly"
+        }
+        _ => "see the error detail above",
+    }
+}
+
+#[test]
+fn credential_expiry() {
+    let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_791_979_200);
+    assert_eq!("don't expire (long-lived keys)", describe_expiry(None, now));
+    assert_eq!(
+        "expire at 2026-10-14T20:00:00Z (in 8h 0m)",
+        describe_expiry(Some(now + Duration::from_secs(8 * 3600)), now)
+    );
+    assert_eq!(
+        "expire at 2026-10-14T12:45:30Z (in 45m)",
+        describe_expiry(Some(now + Duration::from_secs(45 * 60 + 30)), now)
+    );
+    assert_eq!(
+        "expired at 2026-10-14T11:00:00Z",
+        describe_expiry(Some(now - Duration::from_secs(3600)), now)
+    );
+}
@@ -0,0 +1,126 @@
+//! Text embeddings with Amazon Titan Text Embeddings V2, via InvokeModel
+//!
+//! See:
+//! https://docs.aws.amazon.com/bedrock/latest/userguide/titan-embedding-models.html
+//! https://docs.aws.amazon.com/bedrock/latest/userguide/model-parameters-titan-embed-text.html
+
+use aws_sdk_bedrockruntime::operation::RequestId;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::{recording, TraceId};
+
+pub const DEFAULT_MODEL_ID: &str = "amazon.titan-embed-text-v2:0";
+
+/// Titan V2's limit on input length, in characters (the token limit is 8k)
+pub const MAX_INPUT_CHARS: usize = 50_000;
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct EmbeddingRequest<'a> {
+    input_text: &'a str,
+    /// 256, 512, or 1024
+    dimensions: u32,
+    normalize: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct EmbeddingResponse {
+    embedding: Vec<f32>,
+    input_text_token_count: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Embedding {
+    pub vector: Vec<f32>,
+    /// Number of tokens the input text counted as
+    pub token_count: u32,
+}
+
+#[derive(Debug)]
+pub struct EmbeddingError(pub String);
+
+/// Embeds the text as a normalized vector of `dimensions` (256, 512, or 1024) floats.
+pub async fn embed_text(
+    client: &aws_sdk_bedrockruntime::Client,
+    model_id: &str,
+    text: &str,
+    dimensions: u32,
+) -> Result<(TraceId, Embedding), EmbeddingError> {
+    if text.chars().count() > MAX_INPUT_CHARS {
+        return Err(EmbeddingError(format!(
+            "input is over the {} character limit",
+            MAX_INPUT_CHARS
+        )));
+    }
+    let request = EmbeddingRequest {
+        input_text: text,
+        dimensions,
+        normalize: true,
+    };
+    let request_body = serde_json::to_string(&request).unwrap();
+    debug!("model-id: {}", model_id);
+
+    let (trace_id, body) = match recording::replay(model_id, request_body.as_bytes()) {
+        Some(body) => (TraceId("REPLAY".to_string()), body),
+        None => {
+            let output = client
+                .invoke_model()
+                .content_type("application/json")
+                .accept("application/json")
+                .model_id(model_id)
+                .body(request_body.clone().into_bytes().into())
+                .send()
+                .await
+                .map_err(|err| match crate::is_credentials_error(&err) {
+                    true => EmbeddingError(crate::credentials_hint()),
+                    false => EmbeddingError(format!("InvokeModelError:\n{:#?}", err)),
+                })?;
+            let trace_id = TraceId(output.request_id().unwrap_or("UNKNOWN").to_string());
+            let body = output.body.into_inner();
+            recording::record(model_id, request_body.as_bytes(), &body);
+            (trace_id, body)
+        }
+    };
+
+    let rsp: EmbeddingResponse = serde_json::from_slice(&body)
+        .map_err(|err| EmbeddingError(format!("malformed embedding response: {}", err)))?;
+    Ok((
+        trace_id,
+        Embedding {
+            vector: rsp.embedding,
+            token_count: rsp.input_text_token_count,
+        },
+    ))
+}
+
+/// Cosine similarity of two vectors, from -1 (opposite) to 1 (same direction).
+///
+/// 0 if either vector is all zeros, or they differ in length.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+#[test]
+fn similarity() {
+    assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+    assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]).abs() < 1e-6);
+    assert!((cosine_similarity(&[1.0, 1.0], &[-1.0, -1.0]) + 1.0).abs() < 1e-6);
+    assert_eq!(0.0, cosine_similarity(&[0.0, 0.0], &[1.0, 1.0]));
+    assert_eq!(0.0, cosine_similarity(&[1.0], &[1.0, 1.0]));
+
+    let body = r#"{"embedding": [0.5, -0.5], "inputTextTokenCount": 3}"#;
+    let rsp: EmbeddingResponse = serde_json::from_str(body).unwrap();
+    assert_eq!(3, rsp.input_text_token_count);
+}
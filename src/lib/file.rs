@@ -1,22 +1,67 @@
 use base64::prelude::*;
+use base64::{alphabet, engine};
 use shellexpand;
 use std::ffi::OsStr;
 use std::fs;
 use std::path::Path;
 
-/// Wrapper around an RFC4648 Base64 encoded String, accessible via as_ref().
-pub struct Base64Encoding(String);
+/// Url safe base64 (RFC4648 section 5), written without padding but read with or without
+const BASE64_URL_SAFE_ANY_PAD: engine::GeneralPurpose = engine::GeneralPurpose::new(
+    &alphabet::URL_SAFE,
+    engine::general_purpose::NO_PAD
+        .with_decode_padding_mode(engine::DecodePaddingMode::Indifferent),
+);
+
+/// Which RFC4648 alphabet an encoding uses
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Alphabet {
+    Standard,
+    UrlSafe,
+}
+
+/// Wrapper around an RFC4648 Base64 encoded String.  unwrap() returns the String, and
+/// try_decode() (or decode(), which panics on invalid base64) the bytes.
+///
+/// Encodings use the standard alphabet unless made with the `_urlsafe` functions, and
+/// decode with the alphabet they were made with.
+pub struct Base64Encoding(String, Alphabet);
 impl Base64Encoding {
     pub fn new(input: String) -> Self {
-        Self(input)
+        Self(input, Alphabet::Standard)
+    }
+
+    /// Wraps a url safe encoding, e.g. from a url or a JWT
+    pub fn new_urlsafe(input: String) -> Self {
+        Self(input, Alphabet::UrlSafe)
     }
 
     pub fn encode(data: Vec<u8>) -> Self {
-        Self(BASE64_STANDARD.encode(data))
+        Self(BASE64_STANDARD.encode(data), Alphabet::Standard)
+    }
+
+    /// Encodes with the url safe alphabet (- and _ for + and /), without padding
+    pub fn encode_urlsafe(data: Vec<u8>) -> Self {
+        Self(BASE64_URL_SAFE_ANY_PAD.encode(data), Alphabet::UrlSafe)
     }
 
-    fn decode(self) -> Vec<u8> {
-        BASE64_STANDARD.decode(self.0).unwrap()
+    pub fn decode(self) -> Vec<u8> {
+        self.try_decode().unwrap()
+    }
+
+    pub fn try_decode(self) -> Result<Vec<u8>, base64::DecodeError> {
+        match self.1 {
+            Alphabet::Standard => BASE64_STANDARD.decode(self.0),
+            Alphabet::UrlSafe => BASE64_URL_SAFE_ANY_PAD.decode(self.0),
+        }
+    }
+
+    /// Decodes a url safe encoding, the counterpart to `encode_urlsafe`
+    pub fn decode_urlsafe(input: String) -> Result<Vec<u8>, base64::DecodeError> {
+        Self::new_urlsafe(input).try_decode()
+    }
+
+    pub fn is_urlsafe(&self) -> bool {
+        self.1 == Alphabet::UrlSafe
     }
 
     pub fn unwrap(self) -> String {
@@ -67,6 +112,16 @@ pub fn read_base64(filename: &str) -> Base64Encoding {
     Base64Encoding::encode(contents)
 }
 
+/// Reads the contents of the specified file into an RFC4648 url safe base64 encoded
+/// string (without padding)
+///
+/// Filenames support ~ and env variables
+pub fn read_base64_urlsafe(filename: &str) -> Base64Encoding {
+    let expanded = expand(filename);
+    let contents = fs::read(Path::new(expanded.as_str())).unwrap();
+    Base64Encoding::encode_urlsafe(contents)
+}
+
 /// Reads the contents of the specified file into an RFC4648 base64 encoded string
 ///
 /// Filenames support ~ and env variables
@@ -75,6 +130,25 @@ pub fn read(filename: &str) -> Vec<u8> {
     fs::read(Path::new(expanded.as_str())).unwrap()
 }
 
+#[derive(Debug, PartialEq)]
+pub struct NotText(pub String);
+
+/// Reads the specified file as utf-8 text, for splicing into a prompt
+///
+/// Filenames support ~ and env variables
+pub fn read_text(filename: &str) -> Result<String, NotText> {
+    let expanded = expand(filename);
+    let bytes = fs::read(Path::new(expanded.as_str()))
+        .map_err(|err| NotText(format!("couldn't read {}: {}", filename, err)))?;
+    String::from_utf8(bytes).map_err(|err| {
+        NotText(format!(
+            "{} isn't utf-8 text (invalid at byte {}), attach it with --attach instead",
+            filename,
+            err.utf8_error().valid_up_to()
+        ))
+    })
+}
+
 /// Writes the binary decoding of the supplied RFC4648 base64 encoded string to the
 /// specified file.
 ///
@@ -85,12 +159,44 @@ pub fn write_base64(filename: &str, contents: Base64Encoding) {
     fs::write(Path::new(expanded.as_str()), decoded).unwrap();
 }
 
+/// Writes the supplied bytes to the specified file
+///
+/// Filenames support ~ and env variables
+pub fn write_bytes(filename: &str, contents: Vec<u8>) {
+    let expanded = expand(filename);
+    fs::write(Path::new(expanded.as_str()), contents).unwrap();
+}
+
 /// Writes the supplied utf-8 string to the specified file
 ///
 /// Filenames support ~ and env variables
-pub fn write_string(filename: &str, contents: String) {
+pub fn write_string(filename: &str, contents: String) -> std::io::Result<()> {
+    let expanded = expand(filename);
+    fs::write(Path::new(expanded.as_str()), contents)
+}
+
+/// Opens the specified file for appending, creating it if needed.
+///
+/// Works for named pipes (FIFOs) too, in which case this blocks until a reader opens
+/// the other end.
+///
+/// Filenames support ~ and env variables
+pub fn open_append(filename: &str) -> fs::File {
     let expanded = expand(filename);
-    let _ = fs::write(Path::new(expanded.as_str()), contents);
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(Path::new(expanded.as_str()))
+        .unwrap()
+}
+
+/// The directory for the CLIs' saved settings: $XDG_CONFIG_HOME/rusty-bedrock, or
+/// ~/.config/rusty-bedrock.  Not created until something is saved there.
+pub fn config_dir() -> String {
+    match std::env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => format!("{}/rusty-bedrock", dir),
+        _ => expand("~/.config/rusty-bedrock"),
+    }
 }
 
 pub enum Location {
@@ -98,6 +204,59 @@ pub enum Location {
     S3,
 }
 
+/// The bucket and key of an `s3://bucket/key` uri
+#[derive(Debug, PartialEq)]
+pub struct S3Uri {
+    pub bucket: String,
+    pub key: String,
+}
+impl std::fmt::Display for S3Uri {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "s3://{}/{}", self.bucket, self.key)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct InvalidS3Uri(pub String);
+
+/// Parses an s3 uri into its bucket and key, rejecting malformed uris (e.g. `s3:/bucket`,
+/// or `s3://bucket` without a key) that the service would fail less clearly.
+///
+/// The scheme is matched case insensitively, so `S3://` is normalized to `s3://`.
+pub fn s3_uri(uri: &str) -> Result<S3Uri, InvalidS3Uri> {
+    let invalid = |why: &str| {
+        InvalidS3Uri(format!(
+            "{} isn't a valid s3 uri ({}), expected s3://bucket/key",
+            uri, why
+        ))
+    };
+    let rest = match uri.get(..5) {
+        Some(scheme) if scheme.eq_ignore_ascii_case("s3://") => &uri[5..],
+        _ => return Err(invalid("it doesn't start with s3://")),
+    };
+    let (bucket, key) = rest.split_once('/').unwrap_or((rest, ""));
+    if bucket.is_empty() {
+        return Err(invalid("no bucket"));
+    }
+    // https://docs.aws.amazon.com/AmazonS3/latest/userguide/bucketnamingrules.html
+    let valid_bucket = (3..=63).contains(&bucket.len())
+        && bucket
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'.' || b == b'-')
+        && bucket.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && bucket.ends_with(|c: char| c.is_ascii_alphanumeric());
+    if !valid_bucket {
+        return Err(invalid("not a valid bucket name"));
+    }
+    if key.is_empty() || key.ends_with('/') {
+        return Err(invalid("no object key"));
+    }
+    Ok(S3Uri {
+        bucket: bucket.to_string(),
+        key: key.to_string(),
+    })
+}
+
 pub enum Type {
     Image,
     Video,
@@ -113,12 +272,28 @@ pub struct FileReference {
     pub path: String,
     pub stem: FileStem,
     pub extension: FileExtension,
+    /// Unparsed `#bytes=...` / `#pages=...` suffix, see `Range::parse`
+    pub range: Option<String>,
+    /// Document name given with a trailing `=name`, used instead of the stem
+    pub name: Option<String>,
 }
 
 impl From<String> for FileReference {
     fn from(value: String) -> Self {
-        // Determine location based on path prefix
-        let location = if value.starts_with("s3://") {
+        // A document can be named with a trailing `=name`, e.g. `report.pdf=Q3 Financials`
+        let (value, name) = split_name(&value);
+
+        // Part of a file can be selected with a trailing `#bytes=0-1000` or `#pages=1-5`
+        let (value, range) = split_range(&value);
+
+        // An explicit format can be appended as `@format`, for files with a missing or
+        // misleading extension, e.g. `~/image@jpeg`
+        let (value, format) = split_format_override(&value);
+
+        // Determine location based on path prefix.  Any s3: prefix counts, so a
+        // malformed uri (s3:/bucket/key) is rejected by `s3_uri` rather than read as
+        // a local path.
+        let location = if value.get(..3).is_some_and(|s| s.eq_ignore_ascii_case("s3:")) {
             Location::S3
         } else {
             Location::Local
@@ -126,22 +301,13 @@ impl From<String> for FileReference {
 
         // Get file stem and extension
         let stem = FileStem(get_file_stem(&value).to_lowercase());
-        let extension = FileExtension(get_extension_from_filename(&value));
+        let extension =
+            FileExtension(format.unwrap_or_else(|| get_extension_from_filename(&value)));
 
         // Determine file type based on extension
-        let file_type = match extension.0.to_lowercase().as_str() {
-            // Image formats
-            "png" | "jpg" | "jpeg" | "gif" | "webp" => Type::Image,
-
-            // Video formats
-            "mp4" | "mov" | "webm" | "mpeg" | "mpg" | "m4v" | "avi" => Type::Video,
-
-            // Document formats
-            "csv" | "doc" | "docx" | "html" | "md" | "pdf" | "txt" | "xls" | "xlsx" => {
-                Type::Document
-            }
-
-            _ => panic!("Unsupported file type {}", value),
+        let file_type = match file_type(&extension.0) {
+            Some(file_type) => file_type,
+            None => panic!("Unsupported file type {}", value),
         };
 
         FileReference {
@@ -150,10 +316,185 @@ impl From<String> for FileReference {
             path: value,
             stem,
             extension,
+            range,
+            name,
+        }
+    }
+}
+
+/// Video extensions Bedrock doesn't take, still typed as videos so attaching one is an
+/// `InvalidPath` error rather than an unsupported file type
+const UNSENT_VIDEO_FORMATS: &[&str] = &["avi", "m4v"];
+
+fn file_type(extension: &str) -> Option<Type> {
+    use crate::converse::modalities;
+
+    let extension = extension.to_lowercase();
+    let is = |formats: &[&str]| formats.contains(&extension.as_str());
+    if is(modalities::supported_image_formats()) {
+        Some(Type::Image)
+    } else if is(modalities::supported_video_formats()) || is(UNSENT_VIDEO_FORMATS) {
+        Some(Type::Video)
+    } else if is(modalities::supported_document_formats()) {
+        Some(Type::Document)
+    } else {
+        None
+    }
+}
+
+/// Splits a trailing `@format` off the path, if the suffix is a known format.
+///
+/// Anything else after an `@` is treated as part of the filename.
+fn split_format_override(value: &str) -> (String, Option<String>) {
+    if let Some((path, format)) = value.rsplit_once('@') {
+        if !path.is_empty() && file_type(format).is_some() {
+            return (path.to_string(), Some(format.to_lowercase()));
+        }
+    }
+    (value.to_string(), None)
+}
+
+/// Splits a trailing `=name` off the path, if what's before it is an attachment path.
+///
+/// An `=` in a range (`#bytes=0-100`) or in the filename itself (`a=b.pdf`) isn't a name.
+fn split_name(value: &str) -> (String, Option<String>) {
+    if let Some((path, name)) = value.rsplit_once('=') {
+        let (file, _) = split_range(path);
+        let (file, format) = split_format_override(&file);
+        let extension = format.unwrap_or_else(|| get_extension_from_filename(&file));
+        let is_range = path.ends_with("#bytes") || path.ends_with("#pages");
+        if !is_range && !name.trim().is_empty() && file_type(&extension).is_some() {
+            return (path.to_string(), Some(name.to_string()));
+        }
+    }
+    (value.to_string(), None)
+}
+
+/// Splits a trailing `#bytes=...` or `#pages=...` off the path.
+///
+/// Anything else after a `#` is treated as part of the filename.
+fn split_range(value: &str) -> (String, Option<String>) {
+    if let Some((path, range)) = value.rsplit_once('#') {
+        if !path.is_empty() && (range.starts_with("bytes=") || range.starts_with("pages=")) {
+            return (path.to_string(), Some(range.to_string()));
+        }
+    }
+    (value.to_string(), None)
+}
+
+/// Part of a file to attach instead of the whole thing
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Range {
+    /// `bytes=<start>-<end>`: byte offsets, end exclusive, e.g. bytes=0-1000 is the
+    /// first 1000 bytes
+    Bytes { start: u64, end: u64 },
+    /// `pages=<first>-<last>`: page numbers of a pdf, counting from 1, inclusive, e.g.
+    /// pages=1-5 or pages=3
+    Pages { first: u32, last: u32 },
+}
+impl Range {
+    pub fn parse(range: &str) -> Result<Self, String> {
+        let bounds = |spec: &str| -> Result<(u64, u64), String> {
+            let (start, end) = spec.split_once('-').unwrap_or((spec, spec));
+            let parse = |n: &str| {
+                n.trim()
+                    .parse::<u64>()
+                    .map_err(|_| format!("malformed range {}", range))
+            };
+            Ok((parse(start)?, parse(end)?))
+        };
+
+        if let Some(spec) = range.strip_prefix("bytes=") {
+            let (start, end) = bounds(spec)?;
+            if start >= end {
+                return Err(format!("empty byte range {}, the end is exclusive", range));
+            }
+            Ok(Range::Bytes { start, end })
+        } else if let Some(spec) = range.strip_prefix("pages=") {
+            let (first, last) = bounds(spec)?;
+            let page = |n: u64| {
+                u32::try_from(n).map_err(|_| format!("page {} is out of range in {}", n, range))
+            };
+            let (first, last) = (page(first)?, page(last)?);
+            if first == 0 || first > last {
+                return Err(format!(
+                    "invalid page range {}, pages count from 1 and first <= last",
+                    range
+                ));
+            }
+            Ok(Range::Pages { first, last })
+        } else {
+            Err(format!("unknown range {}, expected bytes= or pages=", range))
+        }
+    }
+
+    /// Suffix for the document name, which must differ between parts of the same file
+    pub fn name_suffix(&self) -> String {
+        match self {
+            Range::Bytes { start, end } => format!("bytes {}-{}", start, end),
+            Range::Pages { first, last } => format!("pages {}-{}", first, last),
+        }
+    }
+}
+
+/// Reads just the range of the file, checking it lies within the file.
+///
+/// Page ranges are extracted into a new pdf, which needs the `pdf` feature.
+///
+/// Filenames support ~ and env variables
+pub fn read_range(filename: &str, range: &Range) -> Result<Vec<u8>, String> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let expanded = expand(filename);
+    match *range {
+        Range::Bytes { start, end } => {
+            let mut file = fs::File::open(&expanded)
+                .map_err(|err| format!("couldn't open {}: {}", filename, err))?;
+            let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+            if end > len {
+                return Err(format!(
+                    "byte range {}-{} is past the end of {} ({} bytes)",
+                    start, end, filename, len
+                ));
+            }
+            let mut bytes = vec![];
+            file.seek(SeekFrom::Start(start))
+                .and_then(|_| file.take(end - start).read_to_end(&mut bytes))
+                .map_err(|err| format!("couldn't read {}: {}", filename, err))?;
+            Ok(bytes)
         }
+        Range::Pages { first, last } => read_pages(&expanded, first, last),
     }
 }
 
+#[cfg(feature = "pdf")]
+fn read_pages(filename: &str, first: u32, last: u32) -> Result<Vec<u8>, String> {
+    let mut pdf = lopdf::Document::load(filename)
+        .map_err(|err| format!("couldn't read pdf {}: {}", filename, err))?;
+    let count = pdf.get_pages().len() as u32;
+    if last > count {
+        return Err(format!(
+            "page range {}-{} is past the end of {} ({} pages)",
+            first, last, filename, count
+        ));
+    }
+    let others = (1..=count)
+        .filter(|page| !(first..=last).contains(page))
+        .collect::<Vec<_>>();
+    pdf.delete_pages(&others);
+    pdf.prune_objects();
+
+    let mut bytes = vec![];
+    pdf.save_to(&mut bytes)
+        .map_err(|err| format!("couldn't write pages of {}: {}", filename, err))?;
+    Ok(bytes)
+}
+
+#[cfg(not(feature = "pdf"))]
+fn read_pages(_filename: &str, _first: u32, _last: u32) -> Result<Vec<u8>, String> {
+    Err("page ranges need the pdf feature, rebuild with --features pdf".to_string())
+}
+
 #[test]
 fn extension() {
     let file = "/tmp/foo.bar";
@@ -173,6 +514,58 @@ fn extension() {
     assert_eq!("file", get_file_stem(file));
 }
 
+#[test]
+fn base64_alphabets() {
+    let data = vec![0xfb, 0xff, 0xbf, 0x01];
+    let standard = Base64Encoding::encode(data.clone());
+    assert!(!standard.is_urlsafe());
+    assert_eq!("+/+/AQ==", standard.unwrap());
+
+    let urlsafe = Base64Encoding::encode_urlsafe(data.clone());
+    assert!(urlsafe.is_urlsafe());
+    assert_eq!("-_-_AQ", urlsafe.unwrap());
+    assert_eq!(data, Base64Encoding::encode_urlsafe(data.clone()).decode());
+
+    // url safe strings decode with or without padding, but not as standard base64
+    assert_eq!(data, Base64Encoding::new_urlsafe("-_-_AQ==".to_string()).decode());
+    assert!(Base64Encoding::new("-_-_AQ==".to_string()).try_decode().is_err());
+    assert_eq!(Ok(data), Base64Encoding::decode_urlsafe("-_-_AQ".to_string()));
+}
+
+#[test]
+fn s3_uris() {
+    assert_eq!(
+        Ok(S3Uri {
+            bucket: "my-bucket.v2".to_string(),
+            key: "videos/clip one.mp4".to_string(),
+        }),
+        s3_uri("s3://my-bucket.v2/videos/clip one.mp4")
+    );
+    assert_eq!(
+        "s3://bucket/clip.mp4",
+        s3_uri("S3://bucket/clip.mp4").unwrap().to_string()
+    );
+
+    // missing keys
+    assert!(s3_uri("s3://bucket").is_err());
+    assert!(s3_uri("s3://bucket/").is_err());
+    assert!(s3_uri("s3://bucket/videos/").is_err());
+
+    // malformed
+    assert!(s3_uri("s3:/bucket/clip.mp4").is_err());
+    assert!(s3_uri("s3://").is_err());
+    assert!(s3_uri("s3:///clip.mp4").is_err());
+    assert!(s3_uri("s3://My_Bucket/clip.mp4").is_err());
+    assert!(s3_uri("https://bucket.s3.amazonaws.com/clip.mp4").is_err());
+    assert_eq!(
+        Err(InvalidS3Uri(
+            "s3://bucket isn't a valid s3 uri (no object key), expected s3://bucket/key"
+                .to_string()
+        )),
+        s3_uri("s3://bucket")
+    );
+}
+
 #[test]
 fn test_sanitize() {
     assert_eq!(
@@ -180,3 +573,90 @@ fn test_sanitize() {
         sanitize("sdf/../../.fo./.o/.../(*S&DFsdhfj.txt".to_string())
     );
 }
+
+#[test]
+fn format_override() {
+    let file_ref: FileReference = "/tmp/image@jpeg".to_string().into();
+    assert_eq!("/tmp/image", file_ref.path);
+    assert_eq!("jpeg", file_ref.extension.0);
+    assert!(matches!(file_ref.file_type, Type::Image));
+
+    let file_ref: FileReference = "s3://bucket/clip.bin@MP4".to_string().into();
+    assert_eq!("s3://bucket/clip.bin", file_ref.path);
+    assert_eq!("mp4", file_ref.extension.0);
+    assert!(matches!(file_ref.location, Location::S3));
+
+    // an @ that isn't followed by a format is part of the filename
+    let file_ref: FileReference = "/tmp/me@home.png".to_string().into();
+    assert_eq!("/tmp/me@home.png", file_ref.path);
+    assert_eq!("png", file_ref.extension.0);
+}
+
+#[test]
+fn ranges() {
+    let file_ref: FileReference = "/tmp/notes@txt#bytes=0-100".to_string().into();
+    assert_eq!("/tmp/notes", file_ref.path);
+    assert_eq!("txt", file_ref.extension.0);
+    assert_eq!(Some("bytes=0-100".to_string()), file_ref.range);
+
+    let file_ref: FileReference = "/tmp/issue#12.pdf".to_string().into();
+    assert_eq!("/tmp/issue#12.pdf", file_ref.path);
+    assert_eq!(None, file_ref.range);
+    assert_eq!(None, file_ref.name);
+
+    assert_eq!(
+        Ok(Range::Bytes { start: 0, end: 100 }),
+        Range::parse("bytes=0-100")
+    );
+    assert_eq!(
+        Ok(Range::Pages { first: 3, last: 3 }),
+        Range::parse("pages=3")
+    );
+    assert!(Range::parse("bytes=100-100").is_err());
+    assert!(Range::parse("pages=0-2").is_err());
+    assert!(Range::parse("pages=a-b").is_err());
+    // would wrap to pages=1-1 as a u32
+    assert!(Range::parse("pages=1-4294967297").is_err());
+
+    let path = std::env::temp_dir().join("bedrock-lib-range.txt");
+    fs::write(&path, "0123456789").unwrap();
+    let path = path.to_str().unwrap();
+    assert_eq!(
+        b"234".to_vec(),
+        read_range(path, &Range::Bytes { start: 2, end: 5 }).unwrap()
+    );
+    assert!(read_range(path, &Range::Bytes { start: 2, end: 50 }).is_err());
+}
+
+#[test]
+fn document_names() {
+    let file_ref: FileReference = "/tmp/report.pdf=Q3 Financials".to_string().into();
+    assert_eq!("/tmp/report.pdf", file_ref.path);
+    assert_eq!(Some("Q3 Financials".to_string()), file_ref.name);
+    assert_eq!("report", file_ref.stem.0);
+
+    let file_ref: FileReference = "/tmp/notes@txt#bytes=0-100=Intro".to_string().into();
+    assert_eq!("/tmp/notes", file_ref.path);
+    assert_eq!(Some("bytes=0-100".to_string()), file_ref.range);
+    assert_eq!(Some("Intro".to_string()), file_ref.name);
+
+    // an = in a range or the filename isn't a name
+    let file_ref: FileReference = "/tmp/notes.txt#bytes=0-100".to_string().into();
+    assert_eq!(Some("bytes=0-100".to_string()), file_ref.range);
+    assert_eq!(None, file_ref.name);
+    let file_ref: FileReference = "/tmp/a=b.pdf".to_string().into();
+    assert_eq!("/tmp/a=b.pdf", file_ref.path);
+    assert_eq!(None, file_ref.name);
+}
+
+#[test]
+fn text_files() {
+    let path = std::env::temp_dir().join("bedrock-lib-include.rs");
+    std::fs::write(&path, "fn main() {}\n").unwrap();
+    assert_eq!(Ok("fn main() {}\n".to_string()), read_text(path.to_str().unwrap()));
+
+    std::fs::write(&path, b"ok\xff").unwrap();
+    let NotText(msg) = read_text(path.to_str().unwrap()).unwrap_err();
+    assert!(msg.contains("invalid at byte 2"), "{}", msg);
+    assert!(read_text("/nonexistent/file.rs").is_err());
+}
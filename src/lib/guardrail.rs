@@ -0,0 +1,296 @@
+//! Bedrock Guardrails, applied to text without invoking a model
+//!
+//! ApplyGuardrail runs a guardrail's policies (denied topics, content filters, word
+//! filters, sensitive information, contextual grounding) over some content, a cheap way
+//! to pre-screen prompts before paying for inference.
+//!
+//! `list` finds the guardrails in the account (and their versions), to look up the id
+//! and version to pass to the above, or to `converse --guardrail-id`.
+//!
+//! See:
+//! https://docs.aws.amazon.com/bedrock/latest/userguide/guardrails-use-independent-api.html
+//! https://docs.aws.amazon.com/bedrock/latest/APIReference/API_runtime_ApplyGuardrail.html
+
+use aws_sdk_bedrock::types::GuardrailSummary;
+use aws_sdk_bedrockruntime::types::{
+    GuardrailAction, GuardrailAssessment, GuardrailContentBlock, GuardrailContentSource,
+    GuardrailTextBlock, GuardrailTraceAssessment,
+};
+use log::debug;
+
+/// What a guardrail made of some content
+#[derive(Debug, Clone)]
+pub struct GuardrailCheck {
+    /// Whether the guardrail would block or mask the content
+    pub intervened: bool,
+    /// One line per policy match, e.g. "topic Politics: BLOCKED"
+    pub findings: Vec<String>,
+    /// The guardrail's replacement text (blocked message, or content with PII masked)
+    pub outputs: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct GuardrailError(pub String);
+
+/// Applies the guardrail to `text`, as a model input or (with `as_output`) as a model's
+/// response, which some policies treat differently.
+///
+/// `version` is a version number, or "DRAFT" for the working draft.
+pub async fn check_text(
+    client: &aws_sdk_bedrockruntime::Client,
+    guardrail_id: &str,
+    version: &str,
+    text: &str,
+    as_output: bool,
+) -> Result<GuardrailCheck, GuardrailError> {
+    let source = match as_output {
+        true => GuardrailContentSource::Output,
+        false => GuardrailContentSource::Input,
+    };
+    let content =
+        GuardrailContentBlock::Text(GuardrailTextBlock::builder().text(text).build().unwrap());
+    let output = client
+        .apply_guardrail()
+        .guardrail_identifier(guardrail_id)
+        .guardrail_version(version)
+        .source(source)
+        .content(content)
+        .send()
+        .await
+        .map_err(|err| match crate::is_credentials_error(&err) {
+            true => GuardrailError(crate::credentials_hint()),
+            false => GuardrailError(format!(
+                "ApplyGuardrail failed: {}",
+                aws_sdk_bedrockruntime::error::DisplayErrorContext(&err)
+            )),
+        })?;
+    debug!("{}", crate::redact::debug(&output));
+
+    Ok(GuardrailCheck {
+        intervened: *output.action() == GuardrailAction::GuardrailIntervened,
+        findings: output.assessments().iter().flat_map(findings).collect(),
+        outputs: output
+            .outputs()
+            .iter()
+            .filter_map(|output| output.text())
+            .map(str::to_string)
+            .collect(),
+    })
+}
+
+/// Lists the account's guardrails (their DRAFT versions), or with `guardrail_id` every
+/// version of that guardrail.
+pub async fn list(
+    client: &aws_sdk_bedrock::Client,
+    guardrail_id: Option<&str>,
+) -> Result<Vec<GuardrailSummary>, GuardrailError> {
+    client
+        .list_guardrails()
+        .set_guardrail_identifier(guardrail_id.map(str::to_string))
+        .into_paginator()
+        .items()
+        .send()
+        .try_collect()
+        .await
+        .map_err(|err| match crate::is_credentials_error(&err) {
+            true => GuardrailError(crate::credentials_hint()),
+            false => GuardrailError(format!(
+                "ListGuardrails failed: {}",
+                aws_sdk_bedrock::error::DisplayErrorContext(&err)
+            )),
+        })
+}
+
+/// One line describing the guardrail, e.g.
+/// "pii-screen | id: gr-abc123 | version: 2 | status: READY"
+pub fn summary_line(guardrail: &GuardrailSummary) -> String {
+    format!(
+        "{} | id: {} | version: {} | status: {}",
+        guardrail.name(),
+        guardrail.id(),
+        guardrail.version(),
+        guardrail.status().as_str()
+    )
+}
+
+/// Describes a Converse response's guardrail trace, one line per policy match, each
+/// prefixed with whether it was in the input (the prompt) or the model's output.
+pub fn trace_findings(trace: &GuardrailTraceAssessment) -> Vec<String> {
+    let mut lines = vec![];
+    let mut inputs = trace
+        .input_assessment()
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    inputs.sort_by_key(|(guardrail, _)| *guardrail);
+    for (_, assessment) in inputs {
+        lines.extend(
+            findings(assessment)
+                .into_iter()
+                .map(|finding| format!("input: {}", finding)),
+        );
+    }
+    let mut outputs = trace
+        .output_assessments()
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    outputs.sort_by_key(|(guardrail, _)| *guardrail);
+    for assessment in outputs.into_iter().flat_map(|(_, assessments)| assessments) {
+        lines.extend(
+            findings(assessment)
+                .into_iter()
+                .map(|finding| format!("output: {}", finding)),
+        );
+    }
+    if let Some(reason) = trace.action_reason() {
+        lines.push(format!("reason: {}", reason));
+    }
+    lines
+}
+
+/// Describes each policy match in the assessment, one per line.
+pub fn findings(assessment: &GuardrailAssessment) -> Vec<String> {
+    let mut findings = vec![];
+    if let Some(policy) = assessment.topic_policy() {
+        for topic in policy.topics() {
+            findings.push(format!("topic {}: {}", topic.name(), topic.action()));
+        }
+    }
+    if let Some(policy) = assessment.content_policy() {
+        for filter in policy.filters() {
+            findings.push(format!(
+                "content {} ({} confidence): {}",
+                filter.r#type(),
+                filter.confidence(),
+                filter.action()
+            ));
+        }
+    }
+    if let Some(policy) = assessment.word_policy() {
+        for word in policy.custom_words() {
+            findings.push(format!("word \"{}\": {}", word.r#match(), word.action()));
+        }
+        for word in policy.managed_word_lists() {
+            findings.push(format!(
+                "word \"{}\" ({}): {}",
+                word.r#match(),
+                word.r#type(),
+                word.action()
+            ));
+        }
+    }
+    if let Some(policy) = assessment.sensitive_information_policy() {
+        for entity in policy.pii_entities() {
+            findings.push(format!(
+                "pii {} \"{}\": {}",
+                entity.r#type(),
+                entity.r#match(),
+                entity.action()
+            ));
+        }
+        for regex in policy.regexes() {
+            findings.push(format!(
+                "regex {} \"{}\": {}",
+                regex.name().unwrap_or("(unnamed)"),
+                regex.r#match().unwrap_or_default(),
+                regex.action()
+            ));
+        }
+    }
+    if let Some(policy) = assessment.contextual_grounding_policy() {
+        for filter in policy.filters() {
+            findings.push(format!(
+                "grounding {} (score {:.2}, threshold {:.2}): {}",
+                filter.r#type(),
+                filter.score(),
+                filter.threshold(),
+                filter.action()
+            ));
+        }
+    }
+    findings
+}
+
+#[test]
+fn assessment_findings() {
+    use aws_sdk_bedrockruntime::types::{
+        GuardrailPiiEntityFilter, GuardrailPiiEntityType,
+        GuardrailSensitiveInformationPolicyAction, GuardrailSensitiveInformationPolicyAssessment,
+        GuardrailTopic, GuardrailTopicPolicyAction, GuardrailTopicPolicyAssessment,
+        GuardrailTopicType,
+    };
+
+    let topic = GuardrailTopic::builder()
+        .name("Politics")
+        .r#type(GuardrailTopicType::Deny)
+        .action(GuardrailTopicPolicyAction::Blocked)
+        .build()
+        .unwrap();
+    let email = GuardrailPiiEntityFilter::builder()
+        .r#match("a@example.com")
+        .r#type(GuardrailPiiEntityType::Email)
+        .action(GuardrailSensitiveInformationPolicyAction::Anonymized)
+        .build()
+        .unwrap();
+    let assessment = GuardrailAssessment::builder()
+        .topic_policy(
+            GuardrailTopicPolicyAssessment::builder()
+                .topics(topic)
+                .build()
+                .unwrap(),
+        )
+        .sensitive_information_policy(
+            GuardrailSensitiveInformationPolicyAssessment::builder()
+                .pii_entities(email)
+                .set_regexes(Some(vec![]))
+                .build()
+                .unwrap(),
+        )
+        .build();
+
+    assert_eq!(
+        vec![
+            "topic Politics: BLOCKED",
+            "pii EMAIL \"a@example.com\": ANONYMIZED"
+        ],
+        findings(&assessment)
+    );
+    assert!(findings(&GuardrailAssessment::builder().build()).is_empty());
+
+    let trace = GuardrailTraceAssessment::builder()
+        .input_assessment("gr-123", assessment.clone())
+        .output_assessments("gr-123", vec![assessment])
+        .action_reason("Guardrail blocked.")
+        .build();
+    assert_eq!(
+        vec![
+            "input: topic Politics: BLOCKED",
+            "input: pii EMAIL \"a@example.com\": ANONYMIZED",
+            "output: topic Politics: BLOCKED",
+            "output: pii EMAIL \"a@example.com\": ANONYMIZED",
+            "reason: Guardrail blocked.",
+        ],
+        trace_findings(&trace)
+    );
+}
+
+#[test]
+fn guardrail_summaries() {
+    use aws_sdk_bedrock::types::GuardrailStatus;
+
+    let guardrail = GuardrailSummary::builder()
+        .id("gr-abc123")
+        .arn("arn:aws:bedrock:us-east-1:123456789012:guardrail/gr-abc123")
+        .name("pii-screen")
+        .version("2")
+        .status(GuardrailStatus::Ready)
+        .created_at(aws_smithy_types::DateTime::from_secs(0))
+        .updated_at(aws_smithy_types::DateTime::from_secs(0))
+        .build()
+        .unwrap();
+    assert_eq!(
+        "pii-screen | id: gr-abc123 | version: 2 | status: READY",
+        summary_line(&guardrail)
+    );
+}
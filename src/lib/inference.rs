@@ -0,0 +1,294 @@
+//! Valid inference parameter ranges and defaults, per model family
+//!
+//! Bedrock rejects out-of-range values with a generic ValidationException, and the
+//! ranges differ by provider (Nova's temperature is 0-1, Jamba's 0-2, Cohere's 0-5), so
+//! requests are checked here first to say which limit was broken and for which model.
+//!
+//! See:
+//! https://docs.aws.amazon.com/bedrock/latest/userguide/model-parameters.html
+
+use crate::amazon_nova::text::json;
+
+/// An inclusive range and the value the model uses when none is given
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParamRange {
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+}
+impl ParamRange {
+    const fn new(min: f32, max: f32, default: f32) -> Self {
+        Self { min, max, default }
+    }
+
+    fn check(&self, family: &str, name: &str, value: f32) -> Result<(), OutOfRange> {
+        if (self.min..=self.max).contains(&value) {
+            return Ok(());
+        }
+        Err(OutOfRange(format!(
+            "{} {} is out of range for {} models: {} to {} (default {})",
+            name, value, family, self.min, self.max, self.default
+        )))
+    }
+}
+
+/// The inference parameters a model family accepts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelParams {
+    /// e.g. "Amazon Nova", for messages
+    pub family: &'static str,
+    pub temperature: ParamRange,
+    pub top_p: ParamRange,
+    /// Largest top_k, for families that take one
+    pub max_top_k: Option<u32>,
+    /// Largest max_tokens, where it's the same across the family
+    pub max_tokens: Option<u32>,
+    /// Most stop sequences the family takes, where documented
+    pub max_stop_sequences: Option<usize>,
+}
+
+/// Model id fragments (matched anywhere in the id, so inference profile ids and ARNs
+/// match too) and the parameters of their family.  First match wins.
+const REGISTRY: &[(&str, ModelParams)] = &[
+    (
+        "amazon.nova-",
+        ModelParams {
+            family: "Amazon Nova",
+            temperature: ParamRange::new(0.0, 1.0, 0.7),
+            top_p: ParamRange::new(0.0, 1.0, 0.9),
+            max_top_k: Some(128),
+            max_tokens: Some(10_000),
+            max_stop_sequences: Some(4),
+        },
+    ),
+    (
+        "amazon.titan-text",
+        ModelParams {
+            family: "Amazon Titan Text",
+            temperature: ParamRange::new(0.0, 1.0, 0.7),
+            top_p: ParamRange::new(0.0, 1.0, 0.9),
+            max_top_k: None,
+            max_tokens: None,
+            max_stop_sequences: None,
+        },
+    ),
+    (
+        "anthropic.claude",
+        ModelParams {
+            family: "Anthropic Claude",
+            temperature: ParamRange::new(0.0, 1.0, 1.0),
+            top_p: ParamRange::new(0.0, 1.0, 0.999),
+            max_top_k: Some(500),
+            max_tokens: None,
+            max_stop_sequences: Some(8191),
+        },
+    ),
+    (
+        "meta.llama",
+        ModelParams {
+            family: "Meta Llama",
+            temperature: ParamRange::new(0.0, 1.0, 0.5),
+            top_p: ParamRange::new(0.0, 1.0, 0.9),
+            max_top_k: None,
+            max_tokens: None,
+            max_stop_sequences: None,
+        },
+    ),
+    (
+        "mistral.",
+        ModelParams {
+            family: "Mistral",
+            temperature: ParamRange::new(0.0, 1.0, 0.5),
+            top_p: ParamRange::new(0.0, 1.0, 0.9),
+            max_top_k: Some(200),
+            max_tokens: None,
+            max_stop_sequences: Some(10),
+        },
+    ),
+    (
+        "cohere.command",
+        ModelParams {
+            family: "Cohere Command",
+            temperature: ParamRange::new(0.0, 5.0, 0.3),
+            top_p: ParamRange::new(0.01, 0.99, 0.75),
+            max_top_k: Some(500),
+            max_tokens: None,
+            max_stop_sequences: Some(4),
+        },
+    ),
+    (
+        "ai21.jamba",
+        ModelParams {
+            family: "AI21 Jamba",
+            temperature: ParamRange::new(0.0, 2.0, 1.0),
+            top_p: ParamRange::new(0.0, 1.0, 1.0),
+            max_top_k: None,
+            max_tokens: None,
+            max_stop_sequences: None,
+        },
+    ),
+    (
+        "openai.gpt-oss",
+        ModelParams {
+            family: "OpenAI gpt-oss",
+            temperature: ParamRange::new(0.0, 2.0, 1.0),
+            top_p: ParamRange::new(0.0, 1.0, 1.0),
+            max_top_k: None,
+            max_tokens: None,
+            max_stop_sequences: None,
+        },
+    ),
+];
+
+/// Looks up the parameters for a model, inference profile id, or ARN.  Models outside
+/// the registry (including prompt routers) aren't checked.
+pub fn params_for(model_id: &str) -> Option<&'static ModelParams> {
+    REGISTRY
+        .iter()
+        .find(|(fragment, _)| model_id.contains(fragment))
+        .map(|(_, params)| params)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct OutOfRange(pub String);
+
+/// Checks each parameter that's set against the model's ranges.
+pub fn check(model_id: &str, config: &json::InferenceConfig) -> Result<(), OutOfRange> {
+    check_with_max_tokens(model_id, config, config.max_new_tokens.map(i32::from))
+}
+
+/// Like `check`, but with the max tokens that's sent in place of the config's, for
+/// Converse's max_tokens, which isn't limited to the u16 Nova's config holds.
+pub fn check_with_max_tokens(
+    model_id: &str,
+    config: &json::InferenceConfig,
+    max_tokens: Option<i32>,
+) -> Result<(), OutOfRange> {
+    if max_tokens.is_some_and(|max_tokens| max_tokens < 1) {
+        return Err(OutOfRange("max tokens must be greater than 0".to_string()));
+    }
+    if config.stop_sequences.iter().any(String::is_empty) {
+        return Err(OutOfRange("stop sequences can't be empty".to_string()));
+    }
+    let Some(params) = params_for(model_id) else {
+        return Ok(());
+    };
+    if let Some(temperature) = config.temperature {
+        params
+            .temperature
+            .check(params.family, "temperature", temperature)?;
+    }
+    if let Some(top_p) = config.top_p {
+        params.top_p.check(params.family, "top_p", top_p)?;
+    }
+    match (config.top_k, params.max_top_k) {
+        (Some(_), None) => {
+            return Err(OutOfRange(format!(
+                "{} models don't take a top_k",
+                params.family
+            )))
+        }
+        (Some(top_k), Some(max)) if top_k > max => {
+            return Err(OutOfRange(format!(
+                "top_k {} is out of range for {} models: 0 to {}",
+                top_k, params.family, max
+            )))
+        }
+        _ => {}
+    }
+    match (config.stop_sequences.len(), params.max_stop_sequences) {
+        (count, Some(max)) if count > max => {
+            return Err(OutOfRange(format!(
+                "{} stop sequences is too many for {} models, which take up to {}",
+                count, params.family, max
+            )))
+        }
+        _ => {}
+    }
+    match (max_tokens, params.max_tokens) {
+        (Some(max_tokens), Some(max)) if i64::from(max_tokens) > i64::from(max) => {
+            Err(OutOfRange(format!(
+                "max tokens {} is out of range for {} models: 1 to {}",
+                max_tokens, params.family, max
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks a CLI flag value, exiting with the model-specific message when it's out of
+/// range.
+pub fn check_or_exit(model_id: &str, config: &json::InferenceConfig) {
+    if let Err(OutOfRange(msg)) = check(model_id, config) {
+        eprintln!("{}", msg);
+        std::process::exit(1);
+    }
+}
+
+#[test]
+fn model_ranges() {
+    let temperature = |temperature| json::InferenceConfig {
+        temperature: Some(temperature),
+        ..Default::default()
+    };
+    assert_eq!(
+        "Amazon Nova",
+        params_for("us.amazon.nova-lite-v1:0").unwrap().family
+    );
+    assert!(params_for("amazon.titan-embed-text-v2:0").is_none());
+
+    assert_eq!(Ok(()), check("us.amazon.nova-lite-v1:0", &temperature(1.0)));
+    assert_eq!(
+        Err(OutOfRange(
+            "temperature 1.5 is out of range for Amazon Nova models: 0 to 1 (default 0.7)"
+                .to_string()
+        )),
+        check("us.amazon.nova-lite-v1:0", &temperature(1.5))
+    );
+    assert_eq!(Ok(()), check("ai21.jamba-1-5-mini-v1:0", &temperature(1.5)));
+    assert_eq!(Ok(()), check("some.new-model-v1:0", &temperature(9.0)));
+
+    let top_k = json::InferenceConfig {
+        top_k: Some(50),
+        ..Default::default()
+    };
+    assert!(check("meta.llama3-8b-instruct-v1:0", &top_k).is_err());
+    assert_eq!(Ok(()), check("anthropic.claude-3-haiku", &top_k));
+
+    let max_tokens = |max_tokens| json::InferenceConfig {
+        max_new_tokens: Some(max_tokens),
+        ..Default::default()
+    };
+    assert!(check("some.new-model-v1:0", &max_tokens(0)).is_err());
+    assert!(check("amazon.nova-pro-v1:0", &max_tokens(20_000)).is_err());
+    assert_eq!(
+        Ok(()),
+        check("anthropic.claude-3-haiku", &max_tokens(20_000))
+    );
+    let none = json::InferenceConfig::default();
+    assert!(check_with_max_tokens("some.new-model-v1:0", &none, Some(-5)).is_err());
+    assert_eq!(
+        Err(OutOfRange(
+            "max tokens 100000 is out of range for Amazon Nova models: 1 to 10000".to_string()
+        )),
+        check_with_max_tokens("amazon.nova-pro-v1:0", &none, Some(100_000))
+    );
+    assert_eq!(
+        Ok(()),
+        check_with_max_tokens("anthropic.claude-3-haiku", &none, Some(100_000))
+    );
+
+    let stop = |stop: &[&str]| json::InferenceConfig {
+        stop_sequences: stop.iter().map(|s| s.to_string()).collect(),
+        ..Default::default()
+    };
+    let five = ["a", "b", "c", "d", "e"];
+    assert_eq!(
+        Err(OutOfRange(
+            "5 stop sequences is too many for Amazon Nova models, which take up to 4".to_string()
+        )),
+        check("us.amazon.nova-pro-v1:0", &stop(&five))
+    );
+    assert_eq!(Ok(()), check("anthropic.claude-3-haiku", &stop(&five)));
+    assert!(check("some.new-model-v1:0", &stop(&["END", ""])).is_err());
+}
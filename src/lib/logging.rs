@@ -0,0 +1,157 @@
+//! Logger setup for the CLIs: `--log-format plain` (stderrlog's lines) or `json`
+//!
+//! JSON logs are one object per line on stderr, for container log collectors:
+//!
+//! ```text
+//! {"timestamp":"2025-03-01T17:04:05.123Z","level":"WARN","target":"nova","message":"...","model":"us.amazon.nova-pro-v1:0","trace_id":"..."}
+//! ```
+//!
+//! Besides the record itself, each line carries the context fields set with
+//! `set_field`: the CLIs set `model` once it's resolved, and `chat` sets `trace_id`
+//! to the latest response's.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde_json::{json, Value};
+
+#[derive(clap::ArgEnum, Debug, Clone, Copy, Default, PartialEq)]
+pub enum LogFormat {
+    #[default]
+    Plain,
+    Json,
+}
+
+static FIELDS: Mutex<BTreeMap<&'static str, String>> = Mutex::new(BTreeMap::new());
+
+/// Sets up logging to stderr: info and up, or debug and up when verbose.
+pub fn init(verbose: bool, format: LogFormat) {
+    // the loggers take debug and up, and the max level filters out debug unless verbose
+    match format {
+        LogFormat::Plain => stderrlog::new().verbosity(3).init().unwrap(),
+        LogFormat::Json => log::set_boxed_logger(Box::new(JsonLogger)).unwrap(),
+    }
+    set_verbose(verbose);
+}
+
+/// Changes the level logged from here on: debug and up when verbose, or info and up.
+pub fn set_verbose(verbose: bool) {
+    log::set_max_level(match verbose {
+        true => LevelFilter::Debug,
+        false => LevelFilter::Info,
+    });
+}
+
+/// Adds a field to every later json log line, replacing any earlier value.
+pub fn set_field(name: &'static str, value: impl Into<String>) {
+    FIELDS.lock().unwrap().insert(name, value.into());
+}
+
+struct JsonLogger;
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = line(
+            SystemTime::now(),
+            record.level(),
+            record.target(),
+            &record.args().to_string(),
+            &FIELDS.lock().unwrap(),
+        );
+        let _ = writeln!(std::io::stderr(), "{}", line);
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stderr().flush();
+    }
+}
+
+fn line(
+    time: SystemTime,
+    level: Level,
+    target: &str,
+    message: &str,
+    fields: &BTreeMap<&'static str, String>,
+) -> Value {
+    let mut line = json!({
+        "timestamp": timestamp(time),
+        "level": level.as_str(),
+        "target": target,
+        "message": message,
+    });
+    for (name, value) in fields {
+        line[*name] = json!(value);
+    }
+    line
+}
+
+/// RFC 3339 UTC, to the millisecond
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let secs_of_day = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Converts days since 1970-01-01 into a (year, month, day).
+///
+/// See: https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[test]
+fn json_lines() {
+    assert_eq!((1970, 1, 1), civil_from_days(0));
+    assert_eq!((2000, 2, 29), civil_from_days(11_016));
+    assert_eq!((2024, 12, 31), civil_from_days(20_088));
+
+    let time = UNIX_EPOCH + std::time::Duration::from_millis(1_735_689_599_250);
+    assert_eq!("2024-12-31T23:59:59.250Z", timestamp(time));
+
+    let fields = BTreeMap::from([("model", "us.amazon.nova-pro-v1:0".to_string())]);
+    assert_eq!(
+        json!({
+            "timestamp": "2024-12-31T23:59:59.250Z",
+            "level": "WARN",
+            "target": "nova",
+            "message": "model timed out, retrying once",
+            "model": "us.amazon.nova-pro-v1:0",
+        }),
+        line(
+            time,
+            Level::Warn,
+            "nova",
+            "model timed out, retrying once",
+            &fields
+        )
+    );
+}
@@ -0,0 +1,142 @@
+//! Renders model output markdown for the terminal (`--pretty`)
+//!
+//! Prose (headings, lists, emphasis, tables) is rendered with termimad, and fenced code
+//! blocks are syntax highlighted with syntect, using the fence's language tag.  Either
+//! way, long output goes through the `pager` when it's enabled (`--pager`), and tags
+//! named with `--strip-tags` are taken out first.
+//!
+//! See:
+//! https://docs.rs/termimad/latest/termimad/
+//! https://docs.rs/syntect/latest/syntect/
+
+use std::io::IsTerminal;
+
+use syntect::easy::HighlightLines;
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::SyntaxSet;
+use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
+
+use crate::{pager, tags};
+
+/// Prints the text, rendered as markdown when `pretty` is set and the terminal allows,
+/// and without any tags stripped with `--strip-tags`.
+pub fn print(text: &str, pretty: bool) {
+    let text = tags::strip(text);
+    if pretty && should_render() {
+        pager::print(&render(&text));
+    } else {
+        pager::print(&format!("{}\n", text));
+    }
+}
+
+/// Rendering is skipped when stdout isn't a terminal, or NO_COLOR is set.
+///
+/// See: https://no-color.org/
+pub fn should_render() -> bool {
+    std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+}
+
+/// Renders markdown into a string with terminal escape codes.
+pub fn render(text: &str) -> String {
+    let skin = termimad::MadSkin::default();
+    let syntaxes = SyntaxSet::load_defaults_newlines();
+    let themes = ThemeSet::load_defaults();
+    let theme = &themes.themes["base16-ocean.dark"];
+
+    let mut out = String::new();
+    for segment in segments(text) {
+        match segment {
+            Segment::Prose(prose) => out.push_str(&skin.term_text(prose).to_string()),
+            Segment::Code { lang, code } => {
+                let syntax = syntaxes
+                    .find_syntax_by_token(lang)
+                    .unwrap_or_else(|| syntaxes.find_syntax_plain_text());
+                let mut highlighter = HighlightLines::new(syntax, theme);
+                for line in LinesWithEndings::from(code) {
+                    match highlighter.highlight_line(line, &syntaxes) {
+                        Ok(ranges) => out.push_str(&as_24_bit_terminal_escaped(&ranges, false)),
+                        Err(_) => out.push_str(line),
+                    }
+                }
+                // reset colors after the block
+                out.push_str("\x1b[0m");
+            }
+        }
+    }
+    out
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum Segment<'a> {
+    Prose(&'a str),
+    Code { lang: &'a str, code: &'a str },
+}
+
+/// Splits markdown into prose and fenced (```) code blocks.
+///
+/// An unterminated fence runs to the end of the text, as it would in a renderer.
+fn segments(text: &str) -> Vec<Segment<'_>> {
+    let mut segments = vec![];
+    let mut start = 0;
+    let mut pos = 0;
+    let mut fence: Option<(&str, usize)> = None; // language and start of the code
+
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim();
+        match fence {
+            None if trimmed.starts_with("```") => {
+                if pos > start {
+                    segments.push(Segment::Prose(&text[start..pos]));
+                }
+                fence = Some((trimmed.trim_start_matches('`').trim(), pos + line.len()));
+            }
+            Some((lang, code_start)) if trimmed == "```" => {
+                segments.push(Segment::Code {
+                    lang,
+                    code: &text[code_start..pos],
+                });
+                start = pos + line.len();
+                fence = None;
+            }
+            _ => {}
+        }
+        pos += line.len();
+    }
+
+    match fence {
+        Some((lang, code_start)) => segments.push(Segment::Code {
+            lang,
+            code: &text[code_start.min(pos)..],
+        }),
+        None if pos > start => segments.push(Segment::Prose(&text[start..])),
+        None => {}
+    }
+    segments
+}
+
+#[test]
+fn fenced_segments() {
+    let text = "# Title\n\nSome code:\n```rust\nfn main() {}\n```\nDone.\n";
+    assert_eq!(
+        vec![
+            Segment::Prose("# Title\n\nSome code:\n"),
+            Segment::Code {
+                lang: "rust",
+                code: "fn main() {}\n"
+            },
+            Segment::Prose("Done.\n"),
+        ],
+        segments(text)
+    );
+
+    assert_eq!(
+        vec![Segment::Code {
+            lang: "",
+            code: "x\n"
+        }],
+        segments("```\nx\n")
+    );
+    assert_eq!(vec![Segment::Prose("plain")], segments("plain"));
+
+    assert!(render(text).contains("main"));
+}
@@ -0,0 +1,321 @@
+//! `--metrics-file`: usage metrics in Prometheus text format
+//!
+//! For the CLIs run from monitored jobs (cron, CI), each run adds its requests, errors,
+//! token counts, retries, and latency to a file in Prometheus' text exposition format,
+//! labeled by model, e.g. for node_exporter's textfile collector:
+//!
+//! ```text
+//! # TYPE bedrock_input_tokens_total counter
+//! bedrock_input_tokens_total{model="us.amazon.nova-pro-v1:0"} 1520
+//! ```
+//!
+//! The collector needs each series once per file, so rather than appending lines, a run
+//! adds to the totals already in the file and rewrites it (through a temporary file and
+//! a rename, so a scrape never sees it half written).
+//!
+//! See: https://prometheus.io/docs/instrumenting/exposition_formats/
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use aws_sdk_bedrockruntime::config::interceptors::{
+    BeforeSerializationInterceptorContextRef, BeforeTransmitInterceptorContextRef,
+};
+use aws_sdk_bedrockruntime::config::{ConfigBag, Intercept, RuntimeComponents};
+use aws_sdk_bedrockruntime::operation::converse::ConverseInput;
+use aws_sdk_bedrockruntime::operation::converse_stream::ConverseStreamInput;
+use aws_sdk_bedrockruntime::operation::invoke_model::InvokeModelInput;
+use aws_sdk_bedrockruntime::operation::invoke_model_with_response_stream::InvokeModelWithResponseStreamInput;
+use aws_smithy_types::config_bag::{Storable, StoreReplace};
+
+use crate::chat::ChatResponse;
+use crate::file;
+
+/// (name, help, type) of each metric, in the order they're written
+const METRICS: &[(&str, &str, &str)] = &[
+    ("bedrock_requests_total", "Model calls made", "counter"),
+    ("bedrock_errors_total", "Model calls that failed", "counter"),
+    ("bedrock_input_tokens_total", "Input tokens used", "counter"),
+    (
+        "bedrock_output_tokens_total",
+        "Output tokens generated",
+        "counter",
+    ),
+    (
+        "bedrock_retries_total",
+        "Attempts the SDK retried (e.g. when throttled)",
+        "counter",
+    ),
+    (
+        "bedrock_latency_seconds_total",
+        "Time spent waiting on model calls",
+        "counter",
+    ),
+    (
+        "bedrock_last_run_latency_seconds",
+        "Time the latest run spent waiting on model calls",
+        "gauge",
+    ),
+];
+
+// ===========================
+// Retry counting
+// ===========================
+
+static CALLS: AtomicU64 = AtomicU64::new(0);
+static ATTEMPTS: AtomicU64 = AtomicU64::new(0);
+/// (calls, attempts) of model calls, by model id
+static BY_MODEL: Mutex<BTreeMap<String, (u64, u64)>> = Mutex::new(BTreeMap::new());
+
+/// The model a call is for, kept from `read_before_execution` for its attempts
+#[derive(Debug)]
+struct CallModel(String);
+impl Storable for CallModel {
+    type Storer = StoreReplace<Self>;
+}
+
+/// Counts SDK calls and their attempts, whose difference is the number of retries, in
+/// all and for each model.  Added to every runtime client by `new_runtime_client`.
+#[derive(Debug)]
+pub(crate) struct CountAttempts;
+impl Intercept for CountAttempts {
+    fn name(&self) -> &'static str {
+        "CountAttempts"
+    }
+
+    fn read_before_execution(
+        &self,
+        context: &BeforeSerializationInterceptorContextRef<'_>,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        CALLS.fetch_add(1, Ordering::Relaxed);
+        if let Some(model) = model_id(context) {
+            BY_MODEL
+                .lock()
+                .unwrap()
+                .entry(model.to_string())
+                .or_default()
+                .0 += 1;
+            cfg.interceptor_state()
+                .store_put(CallModel(model.to_string()));
+        }
+        Ok(())
+    }
+
+    fn read_before_attempt(
+        &self,
+        _context: &BeforeTransmitInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        ATTEMPTS.fetch_add(1, Ordering::Relaxed);
+        if let Some(CallModel(model)) = cfg.load::<CallModel>() {
+            BY_MODEL.lock().unwrap().entry(model.clone()).or_default().1 += 1;
+        }
+        Ok(())
+    }
+}
+
+/// The model id of a model call's input
+fn model_id<'a>(context: &'a BeforeSerializationInterceptorContextRef<'a>) -> Option<&'a str> {
+    let input = context.input();
+    if let Some(input) = input.downcast_ref::<ConverseInput>() {
+        return input.model_id();
+    }
+    if let Some(input) = input.downcast_ref::<ConverseStreamInput>() {
+        return input.model_id();
+    }
+    if let Some(input) = input.downcast_ref::<InvokeModelInput>() {
+        return input.model_id();
+    }
+    input
+        .downcast_ref::<InvokeModelWithResponseStreamInput>()?
+        .model_id()
+}
+
+/// Attempts the SDK has retried so far in this process
+pub fn retries() -> u64 {
+    let calls = CALLS.load(Ordering::Relaxed);
+    ATTEMPTS.load(Ordering::Relaxed).saturating_sub(calls)
+}
+
+/// Attempts the SDK has retried so far in this process, in calls to `model`
+pub fn model_retries(model: &str) -> u64 {
+    match BY_MODEL.lock().unwrap().get(model) {
+        Some((calls, attempts)) => attempts.saturating_sub(*calls),
+        None => 0,
+    }
+}
+
+// ===========================
+// Runs
+// ===========================
+
+/// What a run did, added up over its requests
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Run {
+    pub requests: u64,
+    pub errors: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub latency: Duration,
+}
+impl Run {
+    /// Adds a request, with its response (None if it failed).
+    pub fn record(&mut self, latency: Duration, response: Option<&ChatResponse>) {
+        self.requests += 1;
+        self.latency += latency;
+        match response {
+            Some(response) => {
+                self.input_tokens += u64::from(response.input_tokens);
+                self.output_tokens += u64::from(response.output_tokens);
+            }
+            None => self.errors += 1,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MetricsError(pub String);
+
+/// Adds the run (and the SDK's retries of calls to the model) to the totals in `path`
+/// for this model, creating the file if needed.
+///
+/// Filenames support ~ and env variables
+pub fn write(path: &str, model: &str, run: &Run) -> Result<(), MetricsError> {
+    let expanded = file::expand(path);
+    let existing = match std::fs::read_to_string(&expanded) {
+        Ok(existing) => existing,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(MetricsError(format!("couldn't read {}: {}", path, err))),
+    };
+    let mut values = parse(&existing);
+    add(&mut values, model, run, model_retries(model));
+
+    let tmp = format!("{}.tmp", expanded);
+    std::fs::write(&tmp, format(&values))
+        .and_then(|_| std::fs::rename(&tmp, &expanded))
+        .map_err(|err| MetricsError(format!("couldn't write {}: {}", path, err)))
+}
+
+/// Values keyed by (metric name, model)
+type Values = BTreeMap<(String, String), f64>;
+
+fn add(values: &mut Values, model: &str, run: &Run, retries: u64) {
+    let latency = run.latency.as_secs_f64();
+    for (name, value) in [
+        ("bedrock_requests_total", run.requests as f64),
+        ("bedrock_errors_total", run.errors as f64),
+        ("bedrock_input_tokens_total", run.input_tokens as f64),
+        ("bedrock_output_tokens_total", run.output_tokens as f64),
+        ("bedrock_retries_total", retries as f64),
+        ("bedrock_latency_seconds_total", latency),
+    ] {
+        *values
+            .entry((name.to_string(), model.to_string()))
+            .or_default() += value;
+    }
+    values.insert(
+        (
+            "bedrock_last_run_latency_seconds".to_string(),
+            model.to_string(),
+        ),
+        latency,
+    );
+}
+
+/// Reads the samples of a file written by `format`.  Anything else is dropped.
+fn parse(text: &str) -> Values {
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (series, value) = line.rsplit_once(' ')?;
+            let (name, labels) = series.split_once("{model=\"")?;
+            let model = unescape(labels.strip_suffix("\"}")?);
+            Some(((name.to_string(), model), value.parse().ok()?))
+        })
+        .collect()
+}
+
+fn format(values: &Values) -> String {
+    let mut out = String::new();
+    for (name, help, kind) in METRICS {
+        out.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} {}\n",
+            name, help, name, kind
+        ));
+        for ((_, model), value) in values.iter().filter(|((metric, _), _)| metric == name) {
+            out.push_str(&format!(
+                "{}{{model=\"{}\"}} {}\n",
+                name,
+                escape(model),
+                value
+            ));
+        }
+    }
+    out
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn unescape(label: &str) -> String {
+    let mut out = String::new();
+    let mut chars = label.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+    out
+}
+
+#[test]
+fn metrics_accumulate() {
+    let run = Run {
+        requests: 2,
+        errors: 1,
+        input_tokens: 300,
+        output_tokens: 40,
+        latency: Duration::from_millis(1500),
+    };
+    let mut values = Values::new();
+    add(&mut values, "us.amazon.nova-pro-v1:0", &run, 1);
+    let text = format(&values);
+    assert!(text.contains("# TYPE bedrock_input_tokens_total counter\n"));
+    assert!(text.contains("bedrock_input_tokens_total{model=\"us.amazon.nova-pro-v1:0\"} 300\n"));
+    assert!(
+        text.contains("bedrock_last_run_latency_seconds{model=\"us.amazon.nova-pro-v1:0\"} 1.5\n")
+    );
+
+    // a second run adds to the counters, and replaces the gauge
+    let mut values = parse(&text);
+    let faster = Run {
+        latency: Duration::from_millis(500),
+        ..run.clone()
+    };
+    add(&mut values, "us.amazon.nova-pro-v1:0", &faster, 0);
+    add(&mut values, "odd \"model\"", &run, 0);
+    let text = format(&values);
+    assert!(text.contains("bedrock_input_tokens_total{model=\"us.amazon.nova-pro-v1:0\"} 600\n"));
+    assert!(text.contains("bedrock_retries_total{model=\"us.amazon.nova-pro-v1:0\"} 1\n"));
+    assert!(text.contains("bedrock_latency_seconds_total{model=\"us.amazon.nova-pro-v1:0\"} 2\n"));
+    assert!(
+        text.contains("bedrock_last_run_latency_seconds{model=\"us.amazon.nova-pro-v1:0\"} 0.5\n")
+    );
+    assert!(text.contains("bedrock_errors_total{model=\"odd \\\"model\\\"\"} 1\n"));
+    assert_eq!(values, parse(&text));
+}
@@ -1,12 +1,41 @@
 pub mod amazon_nova;
+pub mod chat;
+pub mod cli;
 pub mod converse;
+pub mod diff;
+pub mod doctor;
+pub mod embeddings;
 pub mod file;
+pub mod guardrail;
+pub mod inference;
+pub mod logging;
+pub mod markdown;
+pub mod metrics;
+pub mod pager;
+pub mod progress;
+pub mod rate_limit;
+pub mod recording;
+pub mod redact;
+pub mod request_file;
+pub mod retry;
+pub mod snippets;
+pub mod tags;
+pub mod tokens;
 
-use std::{collections::HashMap, fmt::Display};
+use std::{collections::HashMap, fmt::Display, str::FromStr, sync::OnceLock};
 
 pub use amazon_nova as nova;
-use aws_sdk_bedrock::types::InferenceType;
+use aws_credential_types::provider::error::CredentialsError;
+use aws_sdk_bedrock::operation::get_foundation_model_availability::GetFoundationModelAvailabilityOutput;
+use aws_sdk_bedrock::types::{
+    AgreementStatus, AuthorizationStatus, EntitlementAvailability, FoundationModelLifecycleStatus,
+    FoundationModelSummary, InferenceProfileSummary, InferenceType, RegionAvailability,
+};
+use aws_sdk_bedrockruntime::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_bedrockruntime::types::PerformanceConfigLatency;
+use log::{debug, warn};
 
+#[derive(Debug, Clone)]
 pub struct TraceId(String);
 impl AsRef<str> for TraceId {
     fn as_ref(&self) -> &str {
@@ -19,7 +48,169 @@ impl Display for TraceId {
     }
 }
 
-pub async fn new_runtime_client(aws_profile: Option<String>) -> aws_sdk_bedrockruntime::Client {
+/// Shown when a request still hits `ModelTimeoutException` after being retried
+pub const MODEL_TIMEOUT_HINT: &str = "The model timed out twice processing this request.  \
+Large multimodal requests are the usual cause: try fewer or smaller attachments (e.g. \
+shorter or lower resolution videos, or splitting documents).";
+
+/// Whether the error is a `ModelTimeoutException`.
+///
+/// Unlike throttling, a model timeout is often resolved by a single retry, so callers
+/// retry once (`retry::Policy::MODEL_TIMEOUT`) and then surface `MODEL_TIMEOUT_HINT`.
+pub fn is_model_timeout<E: ProvideErrorMetadata, R>(err: &SdkError<E, R>) -> bool {
+    err.code() == Some("ModelTimeoutException")
+}
+
+// ===========================
+// Credential errors, the most common first-run failure
+// ===========================
+
+/// Profile and region of the first loaded config, named in `credentials_hint`
+static LOADED_FROM: OnceLock<(String, Option<String>)> = OnceLock::new();
+
+/// Whether the request failed for want of valid credentials: none could be resolved
+/// (e.g. an expired SSO session), or Bedrock rejected them as expired or invalid.
+pub fn is_credentials_error<E, R>(err: &SdkError<E, R>) -> bool
+where
+    E: ProvideErrorMetadata + std::error::Error + 'static,
+    R: std::fmt::Debug,
+{
+    if matches!(
+        err.code(),
+        Some(
+            "ExpiredTokenException"
+                | "ExpiredToken"
+                | "UnrecognizedClientException"
+                | "InvalidClientTokenId"
+                | "InvalidSignatureException"
+        )
+    ) {
+        return true;
+    }
+    // resolution failures arrive as a dispatch failure, caused by a CredentialsError
+    let mut source = std::error::Error::source(err);
+    while let Some(err) = source {
+        if err.is::<CredentialsError>() {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// What to do about a credentials error, naming the profile and region in use.
+pub fn credentials_hint() -> String {
+    let (profile, region) = LOADED_FROM
+        .get()
+        .cloned()
+        .unwrap_or_else(|| ("default".to_string(), None));
+    format!(
+        "AWS credentials are missing, expired, or invalid (profile: {}, region: {}).\n\
+        If the profile uses IAM Identity Center (SSO), run `aws sso login --profile {}`.  \
+        Otherwise check ~/.aws/credentials and ~/.aws/config, or pick another profile with --aws-profile.\n\
+        `doctor` diagnoses credential, region, and model access problems.",
+        profile,
+        region.as_deref().unwrap_or("not set"),
+        profile
+    )
+}
+
+/// Bedrock's `performanceConfig.latency` setting
+///
+/// Optimized latency is only offered for some models, see:
+/// https://docs.aws.amazon.com/bedrock/latest/userguide/latency-optimized-inference.html
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Latency {
+    #[default]
+    Standard,
+    Optimized,
+}
+impl Latency {
+    /// The value to send for this model, if any.
+    ///
+    /// Standard is the service default so it's never sent.  Optimized is dropped with a
+    /// warning for models that don't support it, rather than failing the request.
+    pub fn for_model(self, model_id: &str) -> Option<PerformanceConfigLatency> {
+        match self {
+            Latency::Standard => None,
+            Latency::Optimized if supports_optimized_latency(model_id) => {
+                Some(PerformanceConfigLatency::Optimized)
+            }
+            Latency::Optimized => {
+                warn!(
+                    "{} doesn't support optimized latency, using standard",
+                    model_id
+                );
+                None
+            }
+        }
+    }
+}
+impl FromStr for Latency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "standard" => Ok(Latency::Standard),
+            "optimized" => Ok(Latency::Optimized),
+            _ => Err(format!("expected optimized or standard, got {}", s)),
+        }
+    }
+}
+
+fn supports_optimized_latency(model_id: &str) -> bool {
+    [
+        "amazon.nova-pro",
+        "anthropic.claude-3-5-haiku",
+        "meta.llama3-1-70b",
+        "meta.llama3-1-405b",
+    ]
+    .iter()
+    .any(|supported| model_id.contains(supported))
+}
+
+/// Overrides for how the SDK clients are configured, see `load_sdk_config`
+#[derive(Debug, Clone, Default)]
+pub struct ClientOptions {
+    /// Named profile to read from ~/.aws/config and ~/.aws/credentials
+    pub aws_profile: Option<String>,
+    /// Region, taking precedence over the profile's or environment's region
+    pub region: Option<String>,
+    /// The SDK's retry and timeout behavior
+    pub retry: RetryOptions,
+    /// Bedrock Runtime endpoint override, e.g. a VPC (PrivateLink) endpoint or a local
+    /// mock.  The control plane client keeps its regular endpoint.
+    pub endpoint_url: Option<String>,
+    /// Identifies the calling application in the SDK's user agent (and so in CloudTrail),
+    /// taking precedence over AWS_SDK_UA_APP_ID or the profile's sdk_ua_app_id
+    pub app_name: Option<aws_config::AppName>,
+    /// Proxy for every request (e.g. from `cli::proxy`), taking precedence over
+    /// HTTPS_PROXY, see `proxied_http_client`
+    pub proxy: Option<aws_smithy_http_client::proxy::ProxyConfig>,
+}
+
+/// Overrides for the SDK's own retries, which otherwise come from the profile or the
+/// environment (AWS_RETRY_MODE, AWS_MAX_ATTEMPTS), defaulting to standard mode with 3
+/// attempts.
+///
+/// These retries happen inside each SDK call, so they multiply with the crate's: a
+/// `ModelTimeoutException` is retried once by the CLIs, and each of those calls may
+/// itself be attempted `max_attempts` times.  Adaptive mode also paces requests
+/// client-side once throttled, on top of any `rate_limit::RateLimiter`.
+///
+/// See: https://docs.aws.amazon.com/sdkref/latest/guide/feature-retry-behavior.html
+#[derive(Debug, Clone, Default)]
+pub struct RetryOptions {
+    /// standard, or adaptive (client-side rate limiting when throttled)
+    pub mode: Option<aws_config::retry::RetryMode>,
+    /// Attempts per call including the first, so 1 disables retries
+    pub max_attempts: Option<u32>,
+    /// Time limit for a whole call, retries included
+    pub operation_timeout: Option<std::time::Duration>,
+}
+
+/// Loads the shared AWS SdkConfig, optionally overriding the profile.
+pub async fn load_sdk_config(options: &ClientOptions) -> aws_config::SdkConfig {
     // Wire up SdkConfig:
     // https://docs.rs/aws-config/latest/aws_config/
     // https://docs.aws.amazon.com/cli/v1/userguide/cli-configure-files.html
@@ -28,54 +219,190 @@ pub async fn new_runtime_client(aws_profile: Option<String>) -> aws_sdk_bedrockr
     // https://docs.aws.amazon.com/sdk-for-rust/latest/dg/credproviders.html
     // https://docs.rs/aws-config/latest/aws_config/profile/credentials/struct.ProfileFileCredentialsProvider.html
     // https://docs.rs/aws-config/latest/aws_config/profile/struct.ProfileFileRegionProvider.html
-    let config = if let Some(profile) = aws_profile.clone() {
-        aws_config::from_env()
+    let mut loader = aws_config::from_env();
+    if let Some(profile) = &options.aws_profile {
+        loader = loader
             .credentials_provider(
                 aws_config::profile::ProfileFileCredentialsProvider::builder()
-                    .profile_name(profile.clone())
+                    .profile_name(profile)
                     .build(),
             )
             .region(
                 aws_config::profile::ProfileFileRegionProvider::builder()
                     .profile_name(profile)
                     .build(),
-            )
-            .load()
-            .await
-    } else {
-        aws_config::load_from_env().await
+            );
+    }
+    if let Some(region) = &options.region {
+        loader = loader.region(aws_config::Region::new(region.clone()));
+    }
+    if let Some(app_name) = &options.app_name {
+        loader = loader.app_name(app_name.clone());
+    }
+    if let Some(http_client) = proxied_http_client(options.proxy.as_ref()) {
+        loader = loader.http_client(http_client);
+    }
+    let config = with_retry_options(loader.load().await, &options.retry);
+
+    let profile = profile_name(options);
+    let region = config.region().map(|r| r.to_string());
+    if LOADED_FROM.set((profile.clone(), region.clone())).is_ok()
+        && log::log_enabled!(log::Level::Debug)
+    {
+        log_resolved(&config, &profile, region.as_deref()).await;
+    }
+    config
+}
+
+/// The config with the retry flags applied over the retry and timeout settings it loaded
+/// from the profile or environment, changing only the fields that were given.
+fn with_retry_options(
+    config: aws_config::SdkConfig,
+    retry: &RetryOptions,
+) -> aws_config::SdkConfig {
+    if retry.mode.is_none() && retry.max_attempts.is_none() && retry.operation_timeout.is_none() {
+        return config;
+    }
+    let mut retry_config = config
+        .retry_config()
+        .cloned()
+        .unwrap_or_else(aws_config::retry::RetryConfig::standard);
+    if let Some(mode) = retry.mode {
+        retry_config = retry_config.with_retry_mode(mode);
+    }
+    if let Some(max_attempts) = retry.max_attempts {
+        retry_config = retry_config.with_max_attempts(max_attempts);
+    }
+    let mut timeout_config = config
+        .timeout_config()
+        .map(|timeouts| timeouts.to_builder())
+        .unwrap_or_default();
+    if let Some(timeout) = retry.operation_timeout {
+        timeout_config = timeout_config.operation_timeout(timeout);
+    }
+    config
+        .to_builder()
+        .retry_config(retry_config)
+        .timeout_config(timeout_config.build())
+        .build()
+}
+
+/// The profile the SDK reads: --aws-profile, else AWS_PROFILE, else "default"
+pub(crate) fn profile_name(options: &ClientOptions) -> String {
+    options
+        .aws_profile
+        .clone()
+        .or_else(|| std::env::var("AWS_PROFILE").ok())
+        .unwrap_or_else(|| "default".to_string())
+}
+
+/// The environment variables `proxied_http_client` reads a proxy from
+const PROXY_VARS: [&str; 6] = [
+    "HTTPS_PROXY",
+    "https_proxy",
+    "HTTP_PROXY",
+    "http_proxy",
+    "ALL_PROXY",
+    "all_proxy",
+];
+
+/// An HTTP client that connects through `proxy`, or else the proxy in HTTPS_PROXY (or
+/// ALL_PROXY; HTTP_PROXY for plain http endpoints), for networks where Bedrock is only
+/// reachable through one.  Hosts in NO_PROXY connect directly.  None when there's no
+/// proxy, so the SDK's default client is used.
+///
+/// Requests are tunneled through the proxy with CONNECT, so TLS still runs end to end
+/// with Bedrock and the proxy only sees the host name.  An https:// proxy URL adds TLS
+/// between the client and the proxy too.  Proxies that intercept TLS re-sign traffic
+/// with their own CA, which has to be in the system's trust store: certificates are
+/// always verified, against the platform's roots.
+fn proxied_http_client(
+    proxy: Option<&aws_smithy_http_client::proxy::ProxyConfig>,
+) -> Option<aws_sdk_bedrockruntime::config::SharedHttpClient> {
+    use aws_smithy_http_client::{proxy::ProxyConfig, tls, Connector};
+
+    let proxy = match proxy {
+        Some(proxy) => proxy.clone(),
+        None if PROXY_VARS.iter().any(|var| {
+            std::env::var_os(var).is_some_and(|value| !value.is_empty())
+        }) =>
+        {
+            ProxyConfig::from_env()
+        }
+        None => return None,
     };
+    let client = aws_smithy_http_client::Builder::new().build_with_connector_fn(
+        move |settings, components| {
+            let mut builder = Connector::builder().proxy_config(proxy.clone());
+            builder.set_connector_settings(settings.cloned());
+            // for the connect and read timeouts
+            builder.set_sleep_impl(components.and_then(|components| components.sleep_impl()));
+            builder
+                .tls_provider(tls::Provider::Rustls(
+                    tls::rustls_provider::CryptoMode::AwsLc,
+                ))
+                .build()
+        },
+    );
+    Some(client)
+}
+
+/// Logs the region and where the credentials came from (for --verbose), as the
+/// precedence between env variables, --aws-profile, and the profile files is easy to
+/// lose track of.  The credentials are resolved early to find out.
+async fn log_resolved(config: &aws_config::SdkConfig, profile: &str, region: Option<&str>) {
+    use aws_credential_types::provider::ProvideCredentials;
+
+    let source = match config.credentials_provider() {
+        Some(provider) => match provider.provide_credentials().await {
+            Ok(creds) => credential_source(&provider_name(&creds), profile),
+            Err(err) => format!("none resolved ({})", err),
+        },
+        None => "no credentials provider".to_string(),
+    };
+    debug!(
+        "region: {}, credentials: {}",
+        region.unwrap_or("not set"),
+        source
+    );
+}
+
+/// The name of the provider that resolved the credentials, e.g. "ProfileFile".  The SDK
+/// only exposes it in the credentials' Debug output.
+pub(crate) fn provider_name(creds: &aws_credential_types::Credentials) -> String {
+    let debug = format!("{:?}", creds);
+    debug
+        .split_once("provider_name: \"")
+        .and_then(|(_, rest)| rest.split_once('"'))
+        .map_or_else(|| "unknown".to_string(), |(name, _)| name.to_string())
+}
+
+/// Describes a credentials provider name, e.g. "SSO (profile dev)".
+pub(crate) fn credential_source(provider_name: &str, profile: &str) -> String {
+    match provider_name {
+        "EnvironmentVariable" => "environment variables (AWS_ACCESS_KEY_ID)".to_string(),
+        "ProfileFile" => format!("profile {}", profile),
+        "SSO" => format!("SSO (profile {})", profile),
+        "IMDSv2" => "EC2 instance metadata".to_string(),
+        "EcsContainer" => "ECS container credentials".to_string(),
+        other => format!("{} (profile {})", other, profile),
+    }
+}
+
+pub async fn new_runtime_client(options: &ClientOptions) -> aws_sdk_bedrockruntime::Client {
+    let config = load_sdk_config(options).await;
 
     // https://docs.rs/aws-sdk-bedrockruntime/latest/aws_sdk_bedrockruntime/
-    aws_sdk_bedrockruntime::Client::new(&config)
+    let mut builder = aws_sdk_bedrockruntime::config::Builder::from(&config)
+        .interceptor(metrics::CountAttempts);
+    if let Some(endpoint_url) = &options.endpoint_url {
+        builder = builder.endpoint_url(endpoint_url);
+    }
+    aws_sdk_bedrockruntime::Client::from_conf(builder.build())
 }
 
-pub async fn new_controlplane_client(aws_profile: Option<String>) -> aws_sdk_bedrock::Client {
-    // Wire up SdkConfig:
-    // https://docs.rs/aws-config/latest/aws_config/
-    // https://docs.aws.amazon.com/cli/v1/userguide/cli-configure-files.html
-    // https://docs.aws.amazon.com/sdk-for-rust/latest/dg/configure.html
-    // https://docs.aws.amazon.com/sdkref/latest/guide/file-format.html
-    // https://docs.aws.amazon.com/sdk-for-rust/latest/dg/credproviders.html
-    // https://docs.rs/aws-config/latest/aws_config/profile/credentials/struct.ProfileFileCredentialsProvider.html
-    // https://docs.rs/aws-config/latest/aws_config/profile/struct.ProfileFileRegionProvider.html
-    let config = if let Some(profile) = aws_profile.clone() {
-        aws_config::from_env()
-            .credentials_provider(
-                aws_config::profile::ProfileFileCredentialsProvider::builder()
-                    .profile_name(profile.clone())
-                    .build(),
-            )
-            .region(
-                aws_config::profile::ProfileFileRegionProvider::builder()
-                    .profile_name(profile)
-                    .build(),
-            )
-            .load()
-            .await
-    } else {
-        aws_config::load_from_env().await
-    };
+pub async fn new_controlplane_client(options: &ClientOptions) -> aws_sdk_bedrock::Client {
+    let config = load_sdk_config(options).await;
 
     // https://docs.rs/aws-sdk-bedrock/latest/aws_sdk_bedrock/
     aws_sdk_bedrock::Client::new(&config)
@@ -86,23 +413,57 @@ pub async fn list_models(
     client: &aws_sdk_bedrock::Client,
     by_provider: Option<String>,
 ) -> Vec<ModelDetails> {
+    try_list_models(client, by_provider)
+        .await
+        .unwrap_or_else(|err| panic!("{}", err.0))
+}
+
+#[derive(Debug)]
+pub struct ListModelsError(pub String);
+
+/// Like `list_models`, but returns an error rather than panicking if a list call fails
+pub async fn try_list_models(
+    client: &aws_sdk_bedrock::Client,
+    by_provider: Option<String>,
+) -> Result<Vec<ModelDetails>, ListModelsError> {
     let models = client
         .list_foundation_models()
         .by_inference_type(InferenceType::OnDemand)
         .set_by_provider(by_provider)
         .send()
         .await
-        .unwrap()
+        .map_err(|err| match is_credentials_error(&err) {
+            true => ListModelsError(credentials_hint()),
+            false => ListModelsError(format!("ListFoundationModels failed: {:#?}", err)),
+        })?
         .model_summaries
-        .unwrap();
+        .unwrap_or_default();
 
+    // profiles only fill in the profile-ids, so without ListInferenceProfiles permission
+    // the models are still listed
     let profiles = client
         .list_inference_profiles()
         .send()
         .await
-        .unwrap()
-        .inference_profile_summaries
-        .unwrap();
+        .map(|output| output.inference_profile_summaries.unwrap_or_default())
+        .map_err(|err| aws_sdk_bedrock::error::DisplayErrorContext(&err).to_string());
+
+    Ok(model_details(models, profiles))
+}
+
+/// The models with their inference profiles, or with none (and a warning) if the
+/// profiles couldn't be listed.
+fn model_details(
+    models: Vec<FoundationModelSummary>,
+    profiles: Result<Vec<InferenceProfileSummary>, String>,
+) -> Vec<ModelDetails> {
+    let profiles = profiles.unwrap_or_else(|err| {
+        warn!(
+            "ListInferenceProfiles failed, listing models without their profile-ids: {}",
+            err
+        );
+        vec![]
+    });
 
     let mut model_map = HashMap::new();
     for model in models {
@@ -120,6 +481,9 @@ pub async fn list_models(
             .iter()
             .map(|m| m.to_string())
             .collect();
+        let lifecycle_status = model
+            .model_lifecycle()
+            .map(|lifecycle| lifecycle.status().to_string());
         let details = ModelDetails {
             provider,
             name,
@@ -127,6 +491,7 @@ pub async fn list_models(
             input,
             output,
             inference_profiles: Vec::new(),
+            lifecycle_status,
         };
         model_map.insert(arn, details);
     }
@@ -142,10 +507,312 @@ pub async fn list_models(
     let mut vec = model_map
         .values().cloned()
         .collect::<Vec<_>>();
-    vec.sort_by_key(|a| format!("{}{}", a.provider, a.name).to_string());
+    sort_models(&mut vec, SortBy::Provider);
     vec
 }
 
+/// Filters the models down to those this account can invoke, dropping ones whose model
+/// access hasn't been granted (which would otherwise fail with AccessDeniedException).
+///
+/// Costs one GetFoundationModelAvailability call per model, made one at a time, so it's
+/// noticeably slower than the listing itself.  A model whose availability can't be read
+/// is kept, with a warning.
+pub async fn accessible_models(
+    client: &aws_sdk_bedrock::Client,
+    models: Vec<ModelDetails>,
+) -> Vec<ModelDetails> {
+    let mut accessible = vec![];
+    for model in models {
+        match client
+            .get_foundation_model_availability()
+            .model_id(&model.model_id)
+            .send()
+            .await
+        {
+            Ok(availability) if !is_invocable(&availability) => {
+                debug!("not accessible: {} {:?}", model.model_id, availability);
+            }
+            Ok(_) => accessible.push(model),
+            Err(err) => {
+                warn!(
+                    "couldn't read availability of {}, keeping it: {}",
+                    model.model_id,
+                    aws_sdk_bedrock::error::DisplayErrorContext(&err)
+                );
+                accessible.push(model);
+            }
+        }
+    }
+    accessible
+}
+
+/// Access is granted, the model is entitled and offered in the region, and any
+/// end user license agreement has been accepted.
+fn is_invocable(availability: &GetFoundationModelAvailabilityOutput) -> bool {
+    *availability.authorization_status() == AuthorizationStatus::Authorized
+        && *availability.entitlement_availability() == EntitlementAvailability::Available
+        && *availability.region_availability() == RegionAvailability::Available
+        && availability
+            .agreement_availability()
+            .is_none_or(|agreement| *agreement.status() == AgreementStatus::Available)
+}
+
+// ===========================
+// Cross-region inference profile geographies (the us./eu./apac. id prefixes)
+// ===========================
+
+/// Geography of a cross-region inference profile, the prefix of ids like
+/// `eu.amazon.nova-lite-v1:0`.
+///
+/// See: https://docs.aws.amazon.com/bedrock/latest/userguide/inference-profiles-support.html
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Geo {
+    Us,
+    Eu,
+    Apac,
+}
+impl Geo {
+    const ALL: [Geo; 3] = [Geo::Us, Geo::Eu, Geo::Apac];
+
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Geo::Us => "us",
+            Geo::Eu => "eu",
+            Geo::Apac => "apac",
+        }
+    }
+
+    /// The geography whose profiles are offered in this region, e.g. eu-west-1 is Eu.
+    pub fn for_region(region: &str) -> Option<Geo> {
+        match region.split('-').next() {
+            Some("us") | Some("ca") => Some(Geo::Us),
+            Some("eu") => Some(Geo::Eu),
+            Some("ap") => Some(Geo::Apac),
+            _ => None,
+        }
+    }
+
+    /// Swaps a geo-prefixed profile id over to this geography.  Ids without a geo
+    /// prefix (plain model ids, ARNs) are returned unchanged.
+    pub fn apply(self, model_id: &str) -> String {
+        Geo::ALL
+            .iter()
+            .find_map(|geo| model_id.strip_prefix(&format!("{}.", geo.prefix())))
+            .map(|rest| format!("{}.{}", self.prefix(), rest))
+            .unwrap_or_else(|| model_id.to_string())
+    }
+}
+impl FromStr for Geo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Geo::ALL
+            .into_iter()
+            .find(|geo| geo.prefix() == s.to_lowercase())
+            .ok_or_else(|| format!("expected us, eu, or apac, got {}", s))
+    }
+}
+
+/// Picks the inference profile id for the active region's geography (or `geo`, when
+/// given), so the CLIs' `us.` defaults work from EU and APAC regions too.
+///
+/// When the id changes, checks the new profile exists with ListInferenceProfiles.  If
+/// the profiles can't be listed (e.g. no `bedrock:ListInferenceProfiles` permission) the
+/// new id is used anyway, with a warning.
+///
+/// ARNs are used as given, once `check_arn_region` has checked their region.
+pub async fn profile_for_region(
+    options: &ClientOptions,
+    region: Option<&str>,
+    model_id: &str,
+    geo: Option<Geo>,
+) -> Result<String, String> {
+    check_arn_region(model_id, region)?;
+    let geo = match geo.or_else(|| region.and_then(Geo::for_region)) {
+        Some(geo) => geo,
+        None => return Ok(model_id.to_string()),
+    };
+    let profile_id = geo.apply(model_id);
+    if profile_id == model_id {
+        return Ok(profile_id);
+    }
+    debug!("using {} for {} in {:?}", profile_id, model_id, region);
+
+    let client = new_controlplane_client(options).await;
+    let profiles = client
+        .list_inference_profiles()
+        .into_paginator()
+        .items()
+        .send()
+        .try_collect()
+        .await;
+    match profiles {
+        Ok(profiles) if profiles.iter().any(|p| p.inference_profile_id() == profile_id) => {
+            Ok(profile_id)
+        }
+        Ok(_) => Err(format!(
+            "no inference profile {} in {} (for {}); pick another --geo or a model id for this region",
+            profile_id,
+            region.unwrap_or("this region"),
+            model_id
+        )),
+        Err(err) => {
+            warn!(
+                "couldn't list inference profiles to check {}: {}",
+                profile_id,
+                aws_sdk_bedrock::error::DisplayErrorContext(&err)
+            );
+            Ok(profile_id)
+        }
+    }
+}
+
+// ===========================
+// Model ARNs: application inference profiles, prompt routers, and custom (imported,
+// fine-tuned) models
+// ===========================
+
+/// The region of a Bedrock ARN, e.g. us-west-2 in
+/// arn:aws:bedrock:us-west-2:123456789012:imported-model/abc123.  None for plain ids.
+pub fn arn_region(model_id: &str) -> Option<&str> {
+    let mut parts = model_id.strip_prefix("arn:")?.split(':');
+    let (_partition, _service, region) = (parts.next()?, parts.next()?, parts.next()?);
+    Some(region).filter(|region| !region.is_empty())
+}
+
+/// Whether the ARN is a model imported with Custom Model Import, or a custom (fine
+/// tuned) model.  These take whatever request schema the model was built with, which the
+/// CLIs don't know, so they're sent through Converse as given.
+///
+/// See: https://docs.aws.amazon.com/bedrock/latest/userguide/model-customization-import-model.html
+pub fn is_custom_model(model_id: &str) -> bool {
+    model_id.starts_with("arn:")
+        && (model_id.contains(":imported-model/") || model_id.contains(":custom-model/"))
+}
+
+/// An ARN can only be invoked from its own region, which Bedrock reports as a confusing
+/// ValidationException, so the mismatch is caught before sending.
+pub fn check_arn_region(model_id: &str, region: Option<&str>) -> Result<(), String> {
+    match (arn_region(model_id), region) {
+        (Some(arn_region), Some(region)) if arn_region != region => Err(format!(
+            "{} is in {}, but requests would go to {}; pass --region {}",
+            model_id, arn_region, region, arn_region
+        )),
+        _ => Ok(()),
+    }
+}
+
+// ===========================
+// Model id suggestions, for when a model id is mistyped
+// ===========================
+
+/// Whether the error means the model id doesn't exist (or isn't valid).
+///
+/// Bedrock answers a mistyped id with either `ResourceNotFoundException` or a
+/// `ValidationException` about the model identifier, depending on the operation.
+pub fn is_model_not_found<E: ProvideErrorMetadata, R>(err: &SdkError<E, R>) -> bool {
+    match err.code() {
+        Some("ResourceNotFoundException") => true,
+        Some("ValidationException") => err
+            .message()
+            .is_some_and(|msg| msg.to_lowercase().contains("model identifier")),
+        _ => false,
+    }
+}
+
+/// A request failed because the model id doesn't exist, see `is_model_not_found`
+#[derive(Debug)]
+pub struct ModelNotFound(pub String);
+
+/// Builds a "did you mean ...?" hint with the model ids closest to `model_id`.
+///
+/// Lists the available models, so only call this once a request has already failed.
+/// Returns None if no id is close, or if the models can't be listed.
+pub async fn did_you_mean(client: &aws_sdk_bedrock::Client, model_id: &str) -> Option<String> {
+    let models = match try_list_models(client, None).await {
+        Ok(models) => models,
+        Err(err) => {
+            warn!("Couldn't list models for suggestions: {}", err.0);
+            return None;
+        }
+    };
+
+    let suggestions = closest_model_ids(&models, model_id, 3);
+    if suggestions.is_empty() {
+        return None;
+    }
+    Some(format!("did you mean {}?", suggestions.join(" or ")))
+}
+
+/// Up to `max` model and inference profile ids closest to `wanted`, case-insensitively.
+///
+/// Model names count as well as ids, so "nova lite" finds the nova lite ids.
+pub fn closest_model_ids(models: &[ModelDetails], wanted: &str, max: usize) -> Vec<String> {
+    let wanted = wanted.to_lowercase();
+
+    let mut scored = vec![];
+    for model in models {
+        let name_distance = edit_distance(&wanted, &model.name.to_lowercase());
+        for id in std::iter::once(&model.model_id).chain(&model.inference_profiles) {
+            let distance = edit_distance(&wanted, &id.to_lowercase()).min(name_distance);
+            scored.push((distance, id.clone()));
+        }
+    }
+    scored.sort();
+
+    // anything further away than this is more of a different model than a typo
+    let threshold = (wanted.chars().count() / 3).max(3);
+    scored
+        .into_iter()
+        .filter(|(distance, _)| *distance <= threshold)
+        .map(|(_, id)| id)
+        .take(max)
+        .collect()
+}
+
+/// Levenshtein distance between two strings, in chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                prev
+            } else {
+                1 + prev.min(row[j]).min(current)
+            };
+            prev = current;
+        }
+    }
+    row[b.len()]
+}
+
+/// The input modalities (e.g. TEXT, IMAGE, VIDEO) ListFoundationModels reports for a
+/// model or inference profile id.  None if the model isn't listed (e.g. an ARN, or a
+/// model only offered through profiles) or the models can't be listed.
+pub async fn model_inputs(options: &ClientOptions, model_id: &str) -> Option<Vec<String>> {
+    let client = new_controlplane_client(options).await;
+    match try_list_models(&client, None).await {
+        Ok(models) => find_inputs(&models, model_id),
+        Err(err) => {
+            debug!("couldn't list models to check modalities: {}", err.0);
+            None
+        }
+    }
+}
+
+fn find_inputs(models: &[ModelDetails], model_id: &str) -> Option<Vec<String>> {
+    models
+        .iter()
+        .find(|model| {
+            model.model_id == model_id || model.inference_profiles.iter().any(|id| id == model_id)
+        })
+        .map(|model| model.input.clone())
+}
+
 #[derive(Debug, Clone)]
 pub struct ModelDetails {
     pub provider: String,
@@ -154,18 +821,330 @@ pub struct ModelDetails {
     pub input: Vec<String>,
     pub output: Vec<String>,
     pub inference_profiles: Vec<String>,
+    /// ACTIVE, or LEGACY for a model slated for retirement (None if Bedrock didn't say)
+    pub lifecycle_status: Option<String>,
+}
+impl ModelDetails {
+    /// Whether Bedrock marks the model LEGACY, to be retired and not for new work
+    pub fn is_legacy(&self) -> bool {
+        self.lifecycle_status.as_deref() == Some(FoundationModelLifecycleStatus::Legacy.as_str())
+    }
 }
 impl Display for ModelDetails {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{} | {} | model-id: {} | profile-ids: {} | inputs: {} | outputs: {}",
+            "{} | {} | model-id: {} | profile-ids: {} | inputs: {} | outputs: {} | lifecycle: {}",
             self.provider,
             self.name,
             self.model_id,
             self.inference_profiles.join(", "),
             self.input.join(", "),
-            self.output.join(", ")
+            self.output.join(", "),
+            self.lifecycle_status.as_deref().unwrap_or("unknown")
         )
     }
 }
+
+/// Orders of `models --sort-by`
+#[derive(clap::ArgEnum, Debug, Clone, Copy, Default, PartialEq)]
+pub enum SortBy {
+    /// By provider, then name (the order `list_models` returns)
+    #[default]
+    Provider,
+    /// By name, then provider
+    Name,
+    /// Grouped by output modality, e.g. EMBEDDING, IMAGE, TEXT, then by provider and name
+    Modality,
+}
+
+/// The output modalities a model is grouped under for `SortBy::Modality`, e.g. "TEXT"
+pub fn output_modality(model: &ModelDetails) -> String {
+    let mut output = model.output.clone();
+    output.sort();
+    output.join(", ")
+}
+
+pub fn sort_models(models: &mut [ModelDetails], sort_by: SortBy) {
+    match sort_by {
+        SortBy::Provider => models.sort_by_key(|a| format!("{}{}", a.provider, a.name)),
+        SortBy::Name => models.sort_by(|a, b| (&a.name, &a.provider).cmp(&(&b.name, &b.provider))),
+        SortBy::Modality => models.sort_by_cached_key(|model| {
+            (output_modality(model), model.provider.clone(), model.name.clone())
+        }),
+    }
+}
+
+#[test]
+fn retry_options_keep_loaded_settings() {
+    use aws_config::retry::{RetryConfig, RetryMode};
+    use std::time::Duration;
+
+    let loaded = aws_config::SdkConfig::builder()
+        .retry_config(RetryConfig::standard().with_max_attempts(5))
+        .timeout_config(
+            aws_config::timeout::TimeoutConfig::builder()
+                .connect_timeout(Duration::from_secs(2))
+                .build(),
+        )
+        .build();
+    let options = RetryOptions {
+        mode: Some(RetryMode::Adaptive),
+        operation_timeout: Some(Duration::from_secs(30)),
+        ..Default::default()
+    };
+    let config = with_retry_options(loaded, &options);
+    let retry = config.retry_config().unwrap();
+    assert_eq!(RetryMode::Adaptive, retry.mode());
+    assert_eq!(5, retry.max_attempts());
+    let timeouts = config.timeout_config().unwrap();
+    assert_eq!(Some(Duration::from_secs(2)), timeouts.connect_timeout());
+    assert_eq!(Some(Duration::from_secs(30)), timeouts.operation_timeout());
+}
+
+#[test]
+fn latency_for_model() {
+    assert_eq!(None, Latency::Standard.for_model("us.amazon.nova-pro-v1:0"));
+    assert_eq!(
+        Some(PerformanceConfigLatency::Optimized),
+        Latency::Optimized.for_model("us.amazon.nova-pro-v1:0")
+    );
+    assert_eq!(None, Latency::Optimized.for_model("us.amazon.nova-lite-v1:0"));
+    assert_eq!(Ok(Latency::Optimized), "Optimized".parse());
+}
+
+#[test]
+fn model_suggestions() {
+    assert_eq!(0, edit_distance("abc", "abc"));
+    assert_eq!(1, edit_distance("abc", "abd"));
+    assert_eq!(3, edit_distance("", "abc"));
+    assert_eq!(2, edit_distance("lite", "tile"));
+
+    let model = |name: &str, model_id: &str, profile: &str| ModelDetails {
+        provider: "Amazon".to_string(),
+        name: name.to_string(),
+        model_id: model_id.to_string(),
+        input: vec![],
+        output: vec![],
+        inference_profiles: vec![profile.to_string()],
+        lifecycle_status: None,
+    };
+    let models = vec![
+        model("Nova Lite", "amazon.nova-lite-v1:0", "us.amazon.nova-lite-v1:0"),
+        model("Nova Pro", "amazon.nova-pro-v1:0", "us.amazon.nova-pro-v1:0"),
+    ];
+
+    assert_eq!(
+        vec!["us.amazon.nova-lite-v1:0"],
+        closest_model_ids(&models, "us.amazon.nova-litte-v1:0", 1)
+    );
+    assert_eq!(
+        vec!["us.amazon.nova-pro-v1:0"],
+        closest_model_ids(&models, "US.Amazon.Nova-Pro-v1:0", 1)
+    );
+    assert_eq!(
+        "amazon.nova-lite-v1:0",
+        closest_model_ids(&models, "nova lite", 3)[0]
+    );
+    assert!(closest_model_ids(&models, "anthropic.claude-3-haiku", 3).is_empty());
+}
+
+#[test]
+fn geo_prefixes() {
+    assert_eq!(Some(Geo::Eu), Geo::for_region("eu-central-1"));
+    assert_eq!(Some(Geo::Apac), Geo::for_region("ap-northeast-1"));
+    assert_eq!(Some(Geo::Us), Geo::for_region("us-west-2"));
+    assert_eq!(None, Geo::for_region("sa-east-1"));
+
+    assert_eq!("eu.amazon.nova-lite-v1:0", Geo::Eu.apply("us.amazon.nova-lite-v1:0"));
+    assert_eq!("us.amazon.nova-lite-v1:0", Geo::Us.apply("apac.amazon.nova-lite-v1:0"));
+    assert_eq!("amazon.nova-lite-v1:0", Geo::Eu.apply("amazon.nova-lite-v1:0"));
+    let arn = "arn:aws:bedrock:us-east-1:123456789012:application-inference-profile/a1b2";
+    assert_eq!(arn, Geo::Apac.apply(arn));
+
+    assert_eq!(Ok(Geo::Apac), "APAC".parse());
+    assert!("mars".parse::<Geo>().is_err());
+}
+
+#[test]
+fn invocable_models() {
+    let availability = |authorization, agreement: Option<AgreementStatus>| {
+        GetFoundationModelAvailabilityOutput::builder()
+            .model_id("amazon.nova-lite-v1:0")
+            .authorization_status(authorization)
+            .entitlement_availability(EntitlementAvailability::Available)
+            .region_availability(RegionAvailability::Available)
+            .set_agreement_availability(agreement.map(|status| {
+                aws_sdk_bedrock::types::AgreementAvailability::builder()
+                    .status(status)
+                    .build()
+                    .unwrap()
+            }))
+            .build()
+            .unwrap()
+    };
+    assert!(is_invocable(&availability(AuthorizationStatus::Authorized, None)));
+    assert!(is_invocable(&availability(
+        AuthorizationStatus::Authorized,
+        Some(AgreementStatus::Available)
+    )));
+    assert!(!is_invocable(&availability(
+        AuthorizationStatus::NotAuthorized,
+        None
+    )));
+    assert!(!is_invocable(&availability(
+        AuthorizationStatus::Authorized,
+        Some(AgreementStatus::Pending)
+    )));
+}
+
+#[test]
+fn credentials_errors() {
+    use aws_sdk_bedrockruntime::error::ErrorMetadata;
+    use aws_sdk_bedrockruntime::operation::converse::ConverseError;
+
+    let service_error = |code: &str| {
+        let err = ConverseError::generic(ErrorMetadata::builder().code(code).build());
+        SdkError::<ConverseError, ()>::service_error(err, ())
+    };
+    assert!(is_credentials_error(&service_error("ExpiredTokenException")));
+    assert!(is_credentials_error(&service_error("UnrecognizedClientException")));
+    assert!(!is_credentials_error(&service_error("ThrottlingException")));
+
+    let unresolved = SdkError::<ConverseError, ()>::construction_failure(
+        CredentialsError::not_loaded("no profile"),
+    );
+    assert!(is_credentials_error(&unresolved));
+}
+
+#[test]
+fn credential_sources() {
+    let creds = |provider| {
+        aws_credential_types::Credentials::new("AKID", "secret", None, None, provider)
+    };
+    assert_eq!("ProfileFile", provider_name(&creds("ProfileFile")));
+    assert_eq!("SSO", provider_name(&creds("SSO")));
+
+    assert_eq!("SSO (profile dev)", credential_source("SSO", "dev"));
+    assert_eq!("profile default", credential_source("ProfileFile", "default"));
+    assert_eq!(
+        "environment variables (AWS_ACCESS_KEY_ID)",
+        credential_source("EnvironmentVariable", "default")
+    );
+}
+
+#[test]
+fn model_arns() {
+    let imported = "arn:aws:bedrock:us-west-2:123456789012:imported-model/abc123def456";
+    assert_eq!(Some("us-west-2"), arn_region(imported));
+    assert!(is_custom_model(imported));
+    assert!(!is_custom_model(
+        "arn:aws:bedrock:us-east-1:123456789012:application-inference-profile/a1b2"
+    ));
+    assert_eq!(None, arn_region("us.amazon.nova-lite-v1:0"));
+    assert_eq!(None, arn_region("arn:aws:bedrock"));
+
+    assert_eq!(Ok(()), check_arn_region(imported, Some("us-west-2")));
+    assert_eq!(Ok(()), check_arn_region(imported, None));
+    assert_eq!(
+        Err(format!(
+            "{} is in us-west-2, but requests would go to us-east-1; pass --region us-west-2",
+            imported
+        )),
+        check_arn_region(imported, Some("us-east-1"))
+    );
+    assert_eq!(
+        Ok(()),
+        check_arn_region("amazon.nova-lite-v1:0", Some("us-east-1"))
+    );
+}
+
+#[test]
+fn model_input_lookup() {
+    let models = vec![ModelDetails {
+        provider: "Amazon".to_string(),
+        name: "Nova Micro".to_string(),
+        model_id: "amazon.nova-micro-v1:0".to_string(),
+        input: vec!["TEXT".to_string()],
+        output: vec!["TEXT".to_string()],
+        inference_profiles: vec!["us.amazon.nova-micro-v1:0".to_string()],
+        lifecycle_status: None,
+    }];
+    let text = Some(vec!["TEXT".to_string()]);
+    assert_eq!(text, find_inputs(&models, "amazon.nova-micro-v1:0"));
+    assert_eq!(text, find_inputs(&models, "us.amazon.nova-micro-v1:0"));
+    assert_eq!(None, find_inputs(&models, "us.amazon.nova-lite-v1:0"));
+}
+
+#[test]
+fn model_sort_orders() {
+    let model = |provider: &str, name: &str, output: &[&str]| ModelDetails {
+        provider: provider.to_string(),
+        name: name.to_string(),
+        model_id: name.to_lowercase(),
+        input: vec![],
+        output: output.iter().map(|o| o.to_string()).collect(),
+        inference_profiles: vec![],
+        lifecycle_status: None,
+    };
+    let mut models = vec![
+        model("Cohere", "Embed English", &["EMBEDDING"]),
+        model("Amazon", "Nova Pro", &["TEXT"]),
+        model("Amazon", "Nova Canvas", &["IMAGE"]),
+        model("Anthropic", "Claude", &["TEXT"]),
+    ];
+    let names = |models: &[ModelDetails]| models.iter().map(|m| m.name.clone()).collect::<Vec<_>>();
+
+    sort_models(&mut models, SortBy::Provider);
+    assert_eq!(vec!["Nova Canvas", "Nova Pro", "Claude", "Embed English"], names(&models));
+    sort_models(&mut models, SortBy::Name);
+    assert_eq!(vec!["Claude", "Embed English", "Nova Canvas", "Nova Pro"], names(&models));
+    sort_models(&mut models, SortBy::Modality);
+    assert_eq!(vec!["Embed English", "Nova Canvas", "Nova Pro", "Claude"], names(&models));
+}
+
+#[test]
+fn models_listed_without_profiles() {
+    use aws_sdk_bedrock::types::{
+        FoundationModelLifecycle, InferenceProfileModel, InferenceProfileStatus, InferenceProfileType,
+    };
+
+    let arn = "arn:aws:bedrock:us-east-1::foundation-model/amazon.nova-pro-v1:0";
+    let models = || {
+        vec![FoundationModelSummary::builder()
+            .model_arn(arn)
+            .model_id("amazon.nova-pro-v1:0")
+            .model_name("Nova Pro")
+            .provider_name("Amazon")
+            .input_modalities(aws_sdk_bedrock::types::ModelModality::Text)
+            .output_modalities(aws_sdk_bedrock::types::ModelModality::Text)
+            .model_lifecycle(
+                FoundationModelLifecycle::builder()
+                    .status(FoundationModelLifecycleStatus::Legacy)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap()]
+    };
+    let profile = InferenceProfileSummary::builder()
+        .inference_profile_name("US Nova Pro")
+        .inference_profile_arn("arn:aws:bedrock:us-east-1::inference-profile/us.amazon.nova-pro-v1:0")
+        .inference_profile_id("us.amazon.nova-pro-v1:0")
+        .models(InferenceProfileModel::builder().model_arn(arn).build())
+        .status(InferenceProfileStatus::Active)
+        .r#type(InferenceProfileType::SystemDefined)
+        .build()
+        .unwrap();
+
+    let listed = model_details(models(), Ok(vec![profile]));
+    assert_eq!(vec!["us.amazon.nova-pro-v1:0"], listed[0].inference_profiles);
+    assert_eq!(Some("LEGACY"), listed[0].lifecycle_status.as_deref());
+    assert!(listed[0].is_legacy());
+    assert!(listed[0].to_string().ends_with("| lifecycle: LEGACY"));
+
+    let listed = model_details(models(), Err("AccessDeniedException".to_string()));
+    assert_eq!(1, listed.len());
+    assert_eq!("amazon.nova-pro-v1:0", listed[0].model_id);
+    assert!(listed[0].inference_profiles.is_empty());
+}
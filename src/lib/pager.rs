@@ -0,0 +1,69 @@
+//! Pages long responses (`--pager`)
+//!
+//! Output taller than the terminal is piped through $PAGER (`less -R`, which keeps
+//! `--pretty` colors, when unset), and shorter output is printed as usual.  Like
+//! `redact`, the setting is process wide: CLIs call `set_enabled(true)` for --pager,
+//! and `markdown::print` consults it.
+//!
+//! Paging only happens when stdout is a terminal, so piped or redirected output is
+//! unchanged.  Streamed output is printed as it arrives, and isn't paged.
+
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+const DEFAULT_PAGER: &str = "less -R";
+
+static ENABLED: OnceLock<bool> = OnceLock::new();
+
+/// Turns paging on or off for the rest of the process (it's off by default).  Only the
+/// first call has an effect.
+pub fn set_enabled(enabled: bool) {
+    let _ = ENABLED.set(enabled);
+}
+
+pub fn enabled() -> bool {
+    *ENABLED.get_or_init(|| false)
+}
+
+/// Prints the text, through the pager if it's enabled and the text is taller than the
+/// terminal.  If the pager can't be run, the text is printed instead.
+pub fn print(text: &str) {
+    if enabled() && std::io::stdout().is_terminal() {
+        let (_, rows) = termimad::terminal_size();
+        if text.lines().count() >= usize::from(rows) && page(text) {
+            return;
+        }
+    }
+    print!("{}", text);
+    let _ = std::io::stdout().flush();
+}
+
+/// Runs the pager on the text, returning whether it could be run.
+fn page(text: &str) -> bool {
+    let pager = std::env::var("PAGER")
+        .ok()
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_PAGER.to_string());
+    let mut words = pager.split_whitespace();
+    let Some(program) = words.next() else {
+        return false;
+    };
+    let child = Command::new(program)
+        .args(words)
+        .stdin(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(err) => {
+            log::warn!("couldn't run pager {:?}: {}", pager, err);
+            return false;
+        }
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // the pager closing early (e.g. q in less) is a broken pipe, not an error
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    let _ = child.wait();
+    true
+}
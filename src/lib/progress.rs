@@ -0,0 +1,41 @@
+//! An elapsed-time spinner on stderr while waiting on a non-streaming call
+//!
+//! Canvas generations and long Nova responses take several seconds with no output,
+//! which looks frozen.  `spin` redraws a one-line indicator until the call finishes,
+//! then erases it, so stdout (and anything piped from it) is untouched.
+
+use std::future::Future;
+use std::io::{IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+const FRAMES: &[char] = &['|', '/', '-', '\\'];
+
+/// Awaits the future, showing "<label> 3.2s" on stderr in the meantime.
+///
+/// Nothing is shown unless `enabled` (callers turn it off for --quiet or --verbose,
+/// whose logs would interleave with it) and stderr is a terminal.
+pub async fn spin<F: Future>(enabled: bool, label: &str, future: F) -> F::Output {
+    if !enabled || !std::io::stderr().is_terminal() {
+        return future.await;
+    }
+
+    let start = Instant::now();
+    let mut ticker = tokio::time::interval(Duration::from_millis(100));
+    tokio::pin!(future);
+    let mut frame = 0;
+    let output = loop {
+        tokio::select! {
+            output = &mut future => break output,
+            _ = ticker.tick() => {
+                let elapsed = start.elapsed().as_secs_f32();
+                eprint!("\r{} {} {:.1}s", FRAMES[frame % FRAMES.len()], label, elapsed);
+                let _ = std::io::stderr().flush();
+                frame += 1;
+            }
+        }
+    };
+    // \x1b[2K erases the line
+    eprint!("\r\x1b[2K");
+    let _ = std::io::stderr().flush();
+    output
+}
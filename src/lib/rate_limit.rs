@@ -0,0 +1,52 @@
+//! Paces requests to a target rate, shared by every request in a batch
+//!
+//! Bedrock's quotas are per account and model, so a batch of requests should back off
+//! together rather than each on its own.  A `RateLimiter` is shared between the
+//! callers (e.g. behind an `Arc`), and each waits its turn with `acquire` before
+//! sending.
+//!
+//! Retries are already budgeted across requests by the SDK: clients cloned from one
+//! another share the standard retry mode's retry quota, so a throttling storm drains the
+//! quota and stops retrying, instead of multiplying retries per request.
+
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+
+pub struct RateLimiter {
+    interval: Duration,
+    /// When the next request may be sent
+    next: Mutex<Instant>,
+}
+impl RateLimiter {
+    /// A limiter allowing `rpm` requests per minute, evenly spaced.
+    pub fn per_minute(rpm: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(60) / rpm.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits until the caller may send its request.
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut next = self.next.lock().await;
+            let now = Instant::now();
+            let slot = (*next).max(now);
+            *next = slot + self.interval;
+            slot - now
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+#[tokio::test]
+async fn pacing() {
+    let limiter = RateLimiter::per_minute(6000); // every 10ms
+    let start = Instant::now();
+    for _ in 0..3 {
+        limiter.acquire().await;
+    }
+    // the first request goes straight away, each later one waits an interval
+    assert!(start.elapsed() >= Duration::from_millis(20));
+}
@@ -0,0 +1,108 @@
+//! Records InvokeModel response bodies, and replays them in place of calling Bedrock
+//!
+//! Useful for demos, documentation examples, and tests without AWS access.  Responses are
+//! saved as `<dir>/<key>.json`, keyed by a hash of the model id and request body, so the
+//! same request replays the same response.
+//!
+//! The mode is process wide: CLIs call `set_mode` once while parsing args, and the invoke
+//! calls in the library (`amazon_nova::text`, `amazon_nova::canvas`) consult it.
+//! Converse requests aren't recorded.
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use log::debug;
+
+use crate::file;
+
+#[derive(Debug, Clone, Default)]
+pub enum Mode {
+    /// Call Bedrock as normal
+    #[default]
+    Live,
+    /// Call Bedrock, saving each response body into the directory
+    Record(String),
+    /// Answer from the directory's saved responses instead of calling Bedrock
+    Replay(String),
+}
+impl Mode {
+    /// The mode for a pair of `--record <dir>` / `--replay <dir>` args
+    pub fn from_args(record: Option<String>, replay: Option<String>) -> Self {
+        match (record, replay) {
+            (_, Some(dir)) => Mode::Replay(dir),
+            (Some(dir), None) => Mode::Record(dir),
+            (None, None) => Mode::Live,
+        }
+    }
+}
+
+static MODE: OnceLock<Mode> = OnceLock::new();
+
+/// Sets the mode for the rest of the process.  Only the first call has an effect.
+pub fn set_mode(mode: Mode) {
+    let _ = MODE.set(mode);
+}
+
+pub fn mode() -> &'static Mode {
+    MODE.get_or_init(Mode::default)
+}
+
+/// The saved response for this request, when replaying.
+///
+/// Panics when replaying a request that was never recorded, rather than quietly
+/// calling Bedrock.
+pub fn replay(model_id: &str, request: &[u8]) -> Option<Vec<u8>> {
+    let Mode::Replay(dir) = mode() else {
+        return None;
+    };
+    let path = recording_path(dir, model_id, request);
+    debug!("replaying {}", path.display());
+    match std::fs::read(&path) {
+        Ok(body) => Some(body),
+        Err(err) => panic!(
+            "no recorded response for this request: {} ({})\nRecord one first with --record {}",
+            path.display(),
+            err,
+            dir
+        ),
+    }
+}
+
+/// Saves the response for this request, when recording.
+pub fn record(model_id: &str, request: &[u8], response: &[u8]) {
+    let Mode::Record(dir) = mode() else {
+        return;
+    };
+    std::fs::create_dir_all(file::expand(dir))
+        .unwrap_or_else(|err| panic!("couldn't create recording dir {}: {}", dir, err));
+    let path = recording_path(dir, model_id, request);
+    debug!("recording {}", path.display());
+    std::fs::write(&path, response)
+        .unwrap_or_else(|err| panic!("couldn't write {}: {}", path.display(), err));
+}
+
+fn recording_path(dir: &str, model_id: &str, request: &[u8]) -> PathBuf {
+    PathBuf::from(file::expand(dir)).join(format!("{}.json", request_key(model_id, request)))
+}
+
+/// Stable hash of the model id and request body.
+///
+/// FNV-1a rather than std's hasher, whose output isn't guaranteed across Rust releases
+/// and would orphan existing recordings.
+pub fn request_key(model_id: &str, request: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in model_id.as_bytes().iter().chain(b"\n").chain(request) {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+#[test]
+fn keys() {
+    let key = request_key("us.amazon.nova-lite-v1:0", b"{}");
+    assert_eq!(16, key.len());
+    assert_eq!(key, request_key("us.amazon.nova-lite-v1:0", b"{}"));
+    assert_ne!(key, request_key("us.amazon.nova-pro-v1:0", b"{}"));
+    assert_ne!(key, request_key("us.amazon.nova-lite-v1:0", b"{ }"));
+}
@@ -0,0 +1,199 @@
+//! `--request-file`: a whole request in one json document
+//!
+//! Complex requests (a long system prompt, several attachments, tuned inference
+//! parameters) are hard to reproduce from shell history.  A request file holds all of
+//! it, so it can be version controlled and re-run exactly, e.g. for prompt regression
+//! tests:
+//!
+//! ```text
+//! {
+//!   "model": "us.amazon.nova-pro-v1:0",
+//!   "system": "You are a careful proofreader.",
+//!   "prompt": "List the typos in this document.",
+//!   "attachments": ["draft.md"],
+//!   "inference_config": {"max_tokens": 2000, "temperature": 0.2}
+//! }
+//! ```
+//!
+//! Every field is optional.  Relative attachment paths are relative to the request
+//! file, so a request and its attachments can be checked in side by side.
+//!
+//! `--inference-config` reads just the inference config from its own file, e.g. a
+//! team's `creative.json` and `deterministic.json`, in the same format as the request
+//! file's `inference_config`.
+
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::amazon_nova::text::json;
+use crate::file;
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RequestFile {
+    pub model: Option<String>,
+    pub system: Option<String>,
+    pub prompt: Option<String>,
+    pub prefill: Option<String>,
+    /// Attachment paths, as for --attach (including #pages=, @format, and s3://)
+    #[serde(default)]
+    pub attachments: Vec<String>,
+    #[serde(default)]
+    pub inference_config: RequestInferenceConfig,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RequestInferenceConfig {
+    pub max_tokens: Option<u16>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub top_k: Option<u32>,
+    #[serde(default)]
+    pub stop_sequences: Vec<String>,
+}
+impl RequestInferenceConfig {
+    pub fn to_nova(&self) -> json::InferenceConfig {
+        let mut config = json::InferenceConfig::default();
+        self.apply_to(&mut config);
+        config
+    }
+
+    /// Sets the fields this config has, leaving the rest of `config` as it was.
+    pub fn apply_to(&self, config: &mut json::InferenceConfig) {
+        config.max_new_tokens = self.max_tokens.or(config.max_new_tokens);
+        config.temperature = self.temperature.or(config.temperature);
+        config.top_p = self.top_p.or(config.top_p);
+        config.top_k = self.top_k.or(config.top_k);
+        if !self.stop_sequences.is_empty() {
+            config.stop_sequences = self.stop_sequences.clone();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RequestFileError(pub String);
+
+/// Reads and validates a request file, resolving its attachment paths.
+///
+/// Filenames support ~ and env variables
+pub fn load(filename: &str) -> Result<RequestFile, RequestFileError> {
+    let expanded = file::expand(filename);
+    let body = std::fs::read(&expanded)
+        .map_err(|err| RequestFileError(format!("couldn't read {}: {}", filename, err)))?;
+    let mut request = parse(&body).map_err(|RequestFileError(msg)| {
+        RequestFileError(format!("invalid request file {}: {}", filename, msg))
+    })?;
+    let dir = Path::new(&expanded).parent().unwrap_or(Path::new(""));
+    request.attachments = request
+        .attachments
+        .iter()
+        .map(|path| relative_to(dir, path))
+        .collect();
+    Ok(request)
+}
+
+/// Reads an inference config file (`--inference-config`), e.g.
+/// {"temperature": 0.9, "top_p": 0.95}
+///
+/// Filenames support ~ and env variables
+pub fn load_inference_config(filename: &str) -> Result<RequestInferenceConfig, RequestFileError> {
+    let body = std::fs::read(file::expand(filename))
+        .map_err(|err| RequestFileError(format!("couldn't read {}: {}", filename, err)))?;
+    serde_json::from_slice(&body)
+        .map_err(|err| RequestFileError(format!("invalid inference config {}: {}", filename, err)))
+}
+
+fn parse(body: &[u8]) -> Result<RequestFile, RequestFileError> {
+    let request: RequestFile =
+        serde_json::from_slice(body).map_err(|err| RequestFileError(err.to_string()))?;
+    if request.prompt.is_none() && request.attachments.is_empty() {
+        return Err(RequestFileError(
+            "needs a prompt or attachments".to_string(),
+        ));
+    }
+    if request.model.as_deref() == Some("") {
+        return Err(RequestFileError("model is empty".to_string()));
+    }
+    Ok(request)
+}
+
+/// Local relative paths are resolved against `dir`.  Absolute paths, s3 uris, and
+/// paths starting with ~ or an env variable are left alone.
+fn relative_to(dir: &Path, path: &str) -> String {
+    if path.starts_with("s3://")
+        || path.starts_with('~')
+        || path.starts_with('$')
+        || Path::new(path).is_absolute()
+    {
+        return path.to_string();
+    }
+    dir.join(path).to_string_lossy().to_string()
+}
+
+/// Picks a command line value over the request file's, which is picked over the
+/// default (or env variable) the command line would otherwise fall back to.
+pub fn merge<T>(from_command_line: bool, cli: T, request: Option<T>) -> T {
+    match (from_command_line, request) {
+        (false, Some(request)) => request,
+        _ => cli,
+    }
+}
+
+#[test]
+fn request_files() {
+    let request = parse(
+        br#"{
+            "model": "us.amazon.nova-pro-v1:0",
+            "prompt": "what's this?",
+            "attachments": ["a.png", "/tmp/b.png", "s3://bucket/c.mp4"],
+            "inference_config": {"max_tokens": 100, "temperature": 0.2}
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(Some("us.amazon.nova-pro-v1:0"), request.model.as_deref());
+    assert_eq!(Some(100), request.inference_config.to_nova().max_new_tokens);
+
+    let dir = Path::new("/prompts");
+    let resolved = request
+        .attachments
+        .iter()
+        .map(|path| relative_to(dir, path))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        vec!["/prompts/a.png", "/tmp/b.png", "s3://bucket/c.mp4"],
+        resolved
+    );
+
+    // typos are caught rather than ignored
+    assert!(parse(br#"{"prompt": "hi", "temprature": 0.2}"#).is_err());
+    assert!(parse(br#"{"prompt": "hi", "inference_config": {"temprature": 0.2}}"#).is_err());
+    assert!(parse(br#"{"system": "be brief"}"#).is_err());
+
+    assert_eq!("cli", merge(true, "cli", Some("file")));
+    assert_eq!("file", merge(false, "default", Some("file")));
+    assert_eq!("default", merge(false, "default", None));
+}
+
+#[test]
+fn inference_config_files() {
+    let path = std::env::temp_dir().join("bedrock-lib-creative.json");
+    std::fs::write(&path, r#"{"temperature": 0.9, "stop_sequences": ["END"]}"#).unwrap();
+    let creative = load_inference_config(path.to_str().unwrap()).unwrap();
+
+    // fields the file doesn't set are kept
+    let mut config = json::InferenceConfig {
+        max_new_tokens: Some(500),
+        temperature: Some(0.2),
+        ..Default::default()
+    };
+    creative.apply_to(&mut config);
+    assert_eq!(Some(500), config.max_new_tokens);
+    assert_eq!(Some(0.9), config.temperature);
+    assert_eq!(vec!["END".to_string()], config.stop_sequences);
+
+    std::fs::write(&path, r#"{"temprature": 0.9}"#).unwrap();
+    assert!(load_inference_config(path.to_str().unwrap()).is_err());
+    assert!(load_inference_config("/nonexistent/config.json").is_err());
+}
@@ -0,0 +1,173 @@
+//! Retries an async operation with exponential backoff and jitter
+//!
+//! The SDK already retries throttling and transient errors inside each call (see
+//! `RetryOptions`), so this is for errors it leaves to the caller, like
+//! `ModelTimeoutException`.  Which errors are worth retrying is up to the caller's
+//! predicate; anything else is returned straight away.
+//!
+//! Each retry waits twice as long as the one before, up to `max_delay`, with "equal
+//! jitter": half the delay is fixed and half is random, so callers that failed together
+//! don't all retry together.
+
+use std::future::Future;
+use std::time::Duration;
+
+/// How many times, and how far apart, to attempt an operation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Policy {
+    /// Attempts including the first, so 1 disables retries
+    pub max_attempts: u32,
+    /// The delay before the first retry, before jitter
+    pub base_delay: Duration,
+    /// The longest delay between attempts, before jitter
+    pub max_delay: Duration,
+}
+impl Policy {
+    /// One retry, soon after the first attempt, as model timeouts are often resolved by
+    /// a single retry.
+    pub const MODEL_TIMEOUT: Policy = Policy {
+        max_attempts: 2,
+        base_delay: Duration::from_millis(500),
+        max_delay: Duration::from_millis(500),
+    };
+
+    /// The delay before the given retry (1 for the first), with `jitter` (from 0 to 1)
+    /// choosing where it falls in the random half.
+    fn delay(&self, retry: u32, jitter: f64) -> Duration {
+        let doubled = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)));
+        let delay = doubled.min(self.max_delay);
+        delay / 2 + (delay / 2).mul_f64(jitter.clamp(0.0, 1.0))
+    }
+}
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            max_attempts: 3,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(20),
+        }
+    }
+}
+
+/// Runs `op` until it succeeds, fails with an error `retryable` rejects, or runs out of
+/// attempts, returning its last result.
+///
+/// e.g. `with_backoff(|| request.clone().send(), &Policy::MODEL_TIMEOUT, is_model_timeout)`
+pub async fn with_backoff<T, E, F, Fut>(
+    op: F,
+    policy: &Policy,
+    retryable: impl Fn(&E) -> bool,
+) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    run(op, policy, retryable, tokio::time::sleep, fastrand::f64).await
+}
+
+/// `with_backoff` with the clock and randomness passed in, so tests can fake them.
+async fn run<T, E, F, Fut, S, SleepFut>(
+    mut op: F,
+    policy: &Policy,
+    retryable: impl Fn(&E) -> bool,
+    mut sleep: S,
+    mut jitter: impl FnMut() -> f64,
+) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    S: FnMut(Duration) -> SleepFut,
+    SleepFut: Future<Output = ()>,
+{
+    let mut attempt = 1;
+    loop {
+        match op().await {
+            Err(err) if attempt < policy.max_attempts && retryable(&err) => {
+                let delay = policy.delay(attempt, jitter());
+                log::warn!(
+                    "attempt {} of {} failed, retrying in {:.1}s",
+                    attempt,
+                    policy.max_attempts,
+                    delay.as_secs_f64()
+                );
+                sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[tokio::test]
+async fn backoff() {
+    use std::cell::RefCell;
+
+    let policy = Policy {
+        max_attempts: 4,
+        base_delay: Duration::from_secs(1),
+        max_delay: Duration::from_secs(3),
+    };
+    assert_eq!(Duration::from_millis(500), policy.delay(1, 0.0));
+    assert_eq!(Duration::from_secs(1), policy.delay(1, 1.0));
+    assert_eq!(Duration::from_secs(3), policy.delay(5, 1.0));
+
+    // fails with a retryable error until the given attempt
+    let attempts = RefCell::new(0);
+    let slept = RefCell::new(vec![]);
+    let op = |succeed_on: u32| {
+        let attempts = &attempts;
+        move || {
+            *attempts.borrow_mut() += 1;
+            let attempt = *attempts.borrow();
+            async move {
+                match attempt >= succeed_on {
+                    true => Ok(attempt),
+                    false => Err("timeout"),
+                }
+            }
+        }
+    };
+    let sleep = |delay| {
+        slept.borrow_mut().push(delay);
+        async {}
+    };
+    let retryable = |err: &&str| *err == "timeout";
+
+    let result = run(op(3), &policy, retryable, sleep, || 1.0).await;
+    assert_eq!(Ok(3), result);
+    assert_eq!(
+        vec![Duration::from_secs(1), Duration::from_secs(2)],
+        *slept.borrow()
+    );
+
+    // out of attempts, the last error is returned
+    *attempts.borrow_mut() = 0;
+    slept.borrow_mut().clear();
+    let result = run(op(10), &policy, retryable, sleep, || 0.0).await;
+    assert_eq!(Err("timeout"), result);
+    assert_eq!(4, *attempts.borrow());
+    assert_eq!(
+        vec![
+            Duration::from_millis(500),
+            Duration::from_secs(1),
+            Duration::from_millis(1500)
+        ],
+        *slept.borrow()
+    );
+
+    // other errors aren't retried
+    *attempts.borrow_mut() = 0;
+    slept.borrow_mut().clear();
+    let result: Result<u32, &str> = run(
+        || async { Err("access denied") },
+        &policy,
+        retryable,
+        sleep,
+        || 1.0,
+    )
+    .await;
+    assert_eq!(Err("access denied"), result);
+    assert!(slept.borrow().is_empty());
+}
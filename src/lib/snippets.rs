@@ -0,0 +1,79 @@
+//! Named prompt snippets, for instructions that get repeated across conversations
+//!
+//! e.g. "json" for "Respond only with valid JSON, no prose."  Snippets are saved as a
+//! json object of name to text, in prompts.json under `file::config_dir()`, so every
+//! session (and every converse) sees the same library.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::file;
+
+#[derive(Debug)]
+pub struct SnippetError(pub String);
+
+/// Snippets by name
+pub type Snippets = BTreeMap<String, String>;
+
+/// Where the snippets are saved
+pub fn path() -> String {
+    format!("{}/prompts.json", file::config_dir())
+}
+
+/// Reads the saved snippets.  None saved yet is an empty library.
+pub fn load() -> Result<Snippets, SnippetError> {
+    load_from(&path())
+}
+
+/// Saves a snippet, replacing any of the same name.
+pub fn save(name: &str, text: &str) -> Result<(), SnippetError> {
+    save_to(&path(), name, text)
+}
+
+fn load_from(path: &str) -> Result<Snippets, SnippetError> {
+    match std::fs::read(path) {
+        Ok(body) => serde_json::from_slice(&body)
+            .map_err(|err| SnippetError(format!("invalid snippets file {}: {}", path, err))),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Snippets::new()),
+        Err(err) => Err(SnippetError(format!("couldn't read {}: {}", path, err))),
+    }
+}
+
+fn save_to(path: &str, name: &str, text: &str) -> Result<(), SnippetError> {
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(SnippetError(format!(
+            "snippet names can't be empty or contain spaces: {:?}",
+            name
+        )));
+    }
+    let mut snippets = load_from(path)?;
+    snippets.insert(name.to_string(), text.to_string());
+
+    if let Some(dir) = Path::new(path).parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|err| SnippetError(format!("couldn't create {}: {}", dir.display(), err)))?;
+    }
+    let json = serde_json::to_string_pretty(&snippets).unwrap();
+    std::fs::write(path, json)
+        .map_err(|err| SnippetError(format!("couldn't write {}: {}", path, err)))
+}
+
+#[test]
+fn saved_snippets() {
+    let dir = std::env::temp_dir().join(format!("snippets-{}", std::process::id()));
+    let path = dir.join("prompts.json").to_string_lossy().to_string();
+
+    assert_eq!(Snippets::new(), load_from(&path).unwrap());
+    save_to(&path, "json", "Respond only in JSON.").unwrap();
+    save_to(&path, "brief", "Answer in one sentence.").unwrap();
+    save_to(&path, "json", "Respond only with valid JSON.").unwrap();
+    let snippets = load_from(&path).unwrap();
+    assert_eq!(
+        vec!["brief", "json"],
+        snippets.keys().map(String::as_str).collect::<Vec<_>>()
+    );
+    assert_eq!("Respond only with valid JSON.", snippets["json"]);
+
+    assert!(save_to(&path, "two words", "text").is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+}
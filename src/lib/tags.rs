@@ -0,0 +1,192 @@
+//! Hides the content of named XML-like tags in printed output (`--strip-tags`)
+//!
+//! For prompts that ask the model to think out loud first, e.g. in
+//! `<thinking>...</thinking>`, ahead of its answer: with `--strip-tags thinking` only
+//! the answer is shown.  Only what's printed is stripped, the history and saved
+//! responses keep the tags.  Like `pager`, the setting is process wide: CLIs call
+//! `set_stripped` once, and `markdown::print` consults it.
+//!
+//! Streamed output goes through a `Stripper`, which holds back text that might be the
+//! start of a tag until the next chunk shows whether it is.
+
+use std::sync::OnceLock;
+
+static STRIPPED: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Sets the tags to strip, e.g. ["thinking"], for the rest of the process (none by
+/// default).  Only the first call has an effect.
+pub fn set_stripped(tags: Vec<String>) {
+    let _ = STRIPPED.set(tags);
+}
+
+pub fn stripped() -> &'static [String] {
+    STRIPPED.get_or_init(Vec::new)
+}
+
+/// The text without the stripped tags and their content.
+pub fn strip(text: &str) -> String {
+    if stripped().is_empty() {
+        return text.to_string();
+    }
+    strip_tags(text, stripped())
+}
+
+fn strip_tags(text: &str, tags: &[String]) -> String {
+    let mut stripper = Stripper::with_tags(tags);
+    let mut out = stripper.push(text);
+    out.push_str(&stripper.finish());
+    out
+}
+
+/// Strips tags from text arriving in chunks, as it's streamed.
+///
+/// A tag left open at the end hides everything after it, as an answer cut off
+/// mid-thought has nothing to show yet.
+#[derive(Debug)]
+pub struct Stripper {
+    tags: Vec<String>,
+    /// Text not yet shown or hidden, that could be the start of a tag
+    pending: String,
+    /// The tag whose content is being hidden
+    inside: Option<String>,
+    /// Whether a tag was just closed, so whitespace after it is dropped too
+    after_tag: bool,
+}
+impl Stripper {
+    /// Strips the tags set with `set_stripped`.
+    pub fn new() -> Stripper {
+        Stripper::with_tags(stripped())
+    }
+
+    fn with_tags(tags: &[String]) -> Stripper {
+        Stripper {
+            tags: tags.to_vec(),
+            pending: String::new(),
+            inside: None,
+            after_tag: false,
+        }
+    }
+
+    /// Takes the next chunk, returning the text that can be shown now.
+    pub fn push(&mut self, chunk: &str) -> String {
+        if self.tags.is_empty() {
+            return chunk.to_string();
+        }
+        self.pending.push_str(chunk);
+        let mut out = String::new();
+        loop {
+            match self.inside.clone() {
+                None => {
+                    let opening = self
+                        .tags
+                        .iter()
+                        .filter_map(|tag| {
+                            let open = format!("<{}>", tag);
+                            self.pending.find(&open).map(|at| (at, open.len(), tag))
+                        })
+                        .min_by_key(|(at, _, _)| *at);
+                    match opening {
+                        Some((at, len, tag)) => {
+                            let tag = tag.clone();
+                            let before = self.pending[..at].to_string();
+                            self.show(&before, &mut out);
+                            self.pending.drain(..at + len);
+                            self.inside = Some(tag);
+                        }
+                        None => {
+                            let keep = self.partial_len(|tag| format!("<{}>", tag));
+                            let shown = self.pending[..self.pending.len() - keep].to_string();
+                            self.show(&shown, &mut out);
+                            self.pending.drain(..self.pending.len() - keep);
+                            return out;
+                        }
+                    }
+                }
+                Some(tag) => {
+                    let close = format!("</{}>", tag);
+                    match self.pending.find(&close) {
+                        Some(at) => {
+                            self.pending.drain(..at + close.len());
+                            self.inside = None;
+                            self.after_tag = true;
+                        }
+                        None => {
+                            let keep = self.partial_len(|_| close.clone());
+                            self.pending.drain(..self.pending.len() - keep);
+                            return out;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the text held back at the end of the stream.
+    pub fn finish(&mut self) -> String {
+        let mut out = String::new();
+        if self.inside.is_none() {
+            let rest = std::mem::take(&mut self.pending);
+            self.show(&rest, &mut out);
+        }
+        self.pending.clear();
+        out
+    }
+
+    fn show(&mut self, text: &str, out: &mut String) {
+        let text = match self.after_tag {
+            true => text.trim_start(),
+            false => text,
+        };
+        if !text.is_empty() {
+            self.after_tag = false;
+            out.push_str(text);
+        }
+    }
+
+    /// The length of the longest end of the pending text that starts one of the markers.
+    fn partial_len(&self, marker: impl Fn(&str) -> String) -> usize {
+        self.tags
+            .iter()
+            .map(|tag| marker(tag))
+            .filter_map(|marker| {
+                (1..marker.len()).rev().find(|len| {
+                    marker.is_char_boundary(*len) && self.pending.ends_with(&marker[..*len])
+                })
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+impl Default for Stripper {
+    fn default() -> Self {
+        Stripper::new()
+    }
+}
+
+#[test]
+fn stripped_tags() {
+    let tags = ["thinking".to_string(), "scratchpad".to_string()];
+    assert_eq!(
+        "The answer is 4.",
+        strip_tags("<thinking>2 + 2 is 4</thinking>\n\nThe answer is 4.", &tags)
+    );
+    assert_eq!(
+        "a b",
+        strip_tags("a <scratchpad>x</scratchpad>b<thinking>y</thinking>", &tags)
+    );
+    assert_eq!(
+        "a <other>kept</other>",
+        strip_tags("a <other>kept</other>", &tags)
+    );
+    assert_eq!("answer", strip_tags("answer<thinking>cut off", &tags));
+    assert_eq!("1 < 2", strip_tags("1 < 2", &tags));
+
+    // a tag split across chunks
+    let mut stripper = Stripper::with_tags(&tags);
+    let shown = ["<thi", "nking>hidden</th", "inking>\nshown <", "b>"]
+        .iter()
+        .map(|chunk| stripper.push(chunk))
+        .collect::<String>()
+        + &stripper.finish();
+    assert_eq!("shown <b>", shown);
+}
@@ -0,0 +1,88 @@
+//! Approximate token counts, for fitting long input into a budget (`--max-input-tokens`)
+//!
+//! Bedrock only counts tokens once a request is made, and each model has its own
+//! tokenizer, so these are estimates from the text's length: about 4 characters a
+//! token, as for English prose.  Code, other languages, and unusual text can run to
+//! more tokens, so leave some headroom under a hard limit.
+
+/// Characters per token in the estimate
+pub const CHARS_PER_TOKEN: usize = 4;
+
+/// The approximate number of tokens in the text
+pub fn estimate(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Which part of a too long text `truncate_to_budget` keeps
+#[derive(clap::ArgEnum, Debug, Clone, Copy, Default, PartialEq)]
+pub enum Keep {
+    /// The beginning, dropping the end
+    #[default]
+    Head,
+    /// The end, dropping the beginning
+    Tail,
+}
+
+/// The text cut down to about `max_tokens`, keeping its beginning or end.  Text within
+/// the budget is returned whole.
+pub fn truncate_to_budget(text: &str, max_tokens: usize, keep: Keep) -> &str {
+    let max_chars = max_tokens.saturating_mul(CHARS_PER_TOKEN);
+    let chars = text.chars().count();
+    if chars <= max_chars {
+        return text;
+    }
+    match keep {
+        Keep::Head => {
+            let end = text
+                .char_indices()
+                .nth(max_chars)
+                .map_or(text.len(), |(at, _)| at);
+            &text[..end]
+        }
+        Keep::Tail => {
+            let start = text
+                .char_indices()
+                .nth(chars - max_chars)
+                .map_or(text.len(), |(at, _)| at);
+            &text[start..]
+        }
+    }
+}
+
+/// Like `truncate_to_budget`, logging a warning with how much was dropped.
+pub fn fit(text: String, max_tokens: usize, keep: Keep) -> String {
+    let kept = truncate_to_budget(&text, max_tokens, keep);
+    if kept.len() == text.len() {
+        return text;
+    }
+    log::warn!(
+        "prompt is ~{} tokens, over --max-input-tokens {}: dropped ~{} tokens from its {}",
+        estimate(&text),
+        max_tokens,
+        estimate(&text) - estimate(kept),
+        match keep {
+            Keep::Head => "end",
+            Keep::Tail => "beginning",
+        }
+    );
+    kept.to_string()
+}
+
+#[test]
+fn token_budgets() {
+    assert_eq!(0, estimate(""));
+    assert_eq!(1, estimate("abc"));
+    assert_eq!(3, estimate("hello world"));
+
+    let text = "one two three four";
+    assert_eq!(text, truncate_to_budget(text, 5, Keep::Head));
+    assert_eq!("one two ", truncate_to_budget(text, 2, Keep::Head));
+    assert_eq!("ree four", truncate_to_budget(text, 2, Keep::Tail));
+    assert_eq!("", truncate_to_budget(text, 0, Keep::Tail));
+
+    // cut on character boundaries
+    assert_eq!("héllo wö", truncate_to_budget("héllo wörld", 2, Keep::Head));
+    assert_eq!("lo wörld", truncate_to_budget("héllo wörld", 2, Keep::Tail));
+
+    assert_eq!("one two ", fit(text.to_string(), 2, Keep::Head));
+}
@@ -0,0 +1,30 @@
+//! Every binary prints a completion script, whatever args or subcommands it requires
+
+use std::process::Command;
+
+#[test]
+fn completion_scripts() {
+    let binaries = [
+        env!("CARGO_BIN_EXE_nova"),
+        env!("CARGO_BIN_EXE_canvas"),
+        env!("CARGO_BIN_EXE_converse"),
+        env!("CARGO_BIN_EXE_models"),
+        env!("CARGO_BIN_EXE_doctor"),
+        env!("CARGO_BIN_EXE_embed"),
+        env!("CARGO_BIN_EXE_guardrail"),
+    ];
+    for binary in binaries {
+        let output = Command::new(binary)
+            .args(["--completions", "bash"])
+            .output()
+            .unwrap();
+        let script = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            output.status.success(),
+            "{}: {}",
+            binary,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert!(script.contains("complete -F"), "{}: {}", binary, script);
+    }
+}
@@ -0,0 +1,134 @@
+//! `converse --sse` keeps stdout to Server-Sent Events, against a mock Bedrock endpoint
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::process::{Command, Stdio};
+
+use aws_smithy_eventstream::frame::write_message_to;
+use aws_smithy_types::event_stream::{Header, HeaderValue, Message};
+
+/// A ConverseStream event frame
+fn event(event_type: &'static str, payload: &str) -> Vec<u8> {
+    let message = Message::new(payload.to_string().into_bytes())
+        .add_header(Header::new(
+            ":message-type",
+            HeaderValue::String("event".into()),
+        ))
+        .add_header(Header::new(
+            ":event-type",
+            HeaderValue::String(event_type.into()),
+        ))
+        .add_header(Header::new(
+            ":content-type",
+            HeaderValue::String("application/json".into()),
+        ));
+    let mut frame = vec![];
+    write_message_to(&message, &mut frame).unwrap();
+    frame
+}
+
+/// Answers the first request with a complete stream, stopped at the token limit, and the
+/// second with a stream cut off part way.
+fn mock_bedrock() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for (request, stream) in listener.incoming().enumerate() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                if line.trim().is_empty() {
+                    break;
+                }
+            }
+            reader.read_exact(&mut vec![0; length]).unwrap();
+
+            let mut body = [
+                event("messageStart", r#"{"role":"assistant"}"#),
+                event(
+                    "contentBlockDelta",
+                    r#"{"contentBlockIndex":0,"delta":{"text":"Hello"}}"#,
+                ),
+            ]
+            .concat();
+            if request == 0 {
+                body.extend(event("contentBlockStop", r#"{"contentBlockIndex":0}"#));
+                body.extend(event("messageStop", r#"{"stopReason":"max_tokens"}"#));
+                body.extend(event(
+                    "metadata",
+                    r#"{"usage":{"inputTokens":3,"outputTokens":1,"totalTokens":4},
+                        "metrics":{"latencyMs":1},
+                        "trace":{"guardrail":{},"promptRouter":{"invokedModelId":"routed"}}}"#,
+                ));
+            } else {
+                body.extend(b"not an event stream frame");
+            }
+            let headers = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/vnd.amazon.eventstream\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                body.len()
+            );
+            let _ = stream.write_all(headers.as_bytes());
+            let _ = stream.write_all(&body);
+        }
+    });
+    address
+}
+
+#[test]
+fn sse_stdout_has_only_events() {
+    let mut converse = Command::new(env!("CARGO_BIN_EXE_converse"))
+        .args(["--sse", "--model", "amazon.nova-lite-v1:0"])
+        .args(["--guardrail-id", "mock", "--guardrail-trace"])
+        .args(["--endpoint-url", &mock_bedrock()])
+        .env("AWS_ACCESS_KEY_ID", "AKIDEXAMPLE")
+        .env("AWS_SECRET_ACCESS_KEY", "secret")
+        .env("AWS_REGION", "us-east-1")
+        .env("AWS_CONFIG_FILE", "/dev/null")
+        .env("AWS_SHARED_CREDENTIALS_FILE", "/dev/null")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    converse
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"say \"first\"\nsay \"second\"\n")
+        .unwrap();
+    let output = converse.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    for line in stdout.lines() {
+        assert!(
+            line.is_empty() || line.starts_with("event:") || line.starts_with("data:"),
+            "not an event: {:?}\nstdout:\n{}",
+            line,
+            stdout
+        );
+    }
+    assert!(
+        stdout.contains(r#""stopReason":"max_tokens""#),
+        "{}",
+        stdout
+    );
+    assert_eq!(2, stdout.matches("data: [DONE]").count(), "{}", stdout);
+    // the status lines go to stderr instead
+    assert!(stderr.contains("-- routed to routed --"), "{}", stderr);
+    assert!(stderr.contains("-- guardrail"), "{}", stderr);
+    assert!(stderr.contains("-- the response was cut off"), "{}", stderr);
+    assert!(
+        stderr.contains("-- the stream was interrupted"),
+        "{}",
+        stderr
+    );
+}